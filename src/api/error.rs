@@ -14,6 +14,8 @@ pub enum ApiError {
     TokenNotMonitored(String),
     #[error("{0}")]
     InvalidInput(String),
+    #[error("{0}")]
+    NotFound(String),
 }
 
 #[derive(Serialize)]
@@ -28,6 +30,7 @@ impl IntoResponse for ApiError {
             ApiError::DatabaseError(_) => StatusCode::SERVICE_UNAVAILABLE,
             ApiError::TokenNotMonitored(_) => StatusCode::NOT_FOUND,
             ApiError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
         };
 
         let body = Json(ErrorResponse {
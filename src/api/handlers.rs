@@ -1,39 +1,188 @@
 use axum::{
-    extract::{State, Path},
+    body::Body,
+    extract::{State, Path, Query},
+    http::header,
+    response::{IntoResponse, Response},
     Json,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::sync::Arc;
+use futures::StreamExt;
+use std::str::FromStr;
+use solana_sdk::pubkey::Pubkey;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use governor::{RateLimiter, state::{NotKeyed, InMemoryState}, clock::DefaultClock};
 use crate::db::operations::structure_token_stats;
-use crate::services::token::{get_token_metrics, get_token_price};
-use clickhouse::Client;
+use crate::services::token::get_token_price;
 use super::error::ApiError;
-use crate::services::excluded_accounts::check_new_token_exclusions;
-
-pub type AppState = (
-    Arc<RpcClient>,
-    Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
-    Client,
-);
+use super::state::AppState;
+use crate::services::export::{ExportDataset, ExportFormat, ExportFormatUnavailable, ExportRangeTooLarge};
+use crate::services::thresholds::normalize_thresholds;
+use crate::services::concentration::normalize_buckets;
+use crate::services::distribution::{MAX_BINS_PER_DECADE, MAX_LORENZ_POINTS};
+use crate::db::models::SnapshotRecord;
+use crate::services::jobs::{request_refresh, JOB_STATUSES, MAX_JOBS_PAGE};
+use crate::services::lifecycle::{add_token, validate_status, AddedToken, BULK_CONCURRENCY, MAX_BULK_TOKENS};
+use crate::services::sharding::leader;
+use crate::services::overlap::{MAX_OVERLAP_HOLDERS, MAX_OVERLAP_MINTS};
+use crate::services::holders::{decode_cursor, MAX_HOLDERS_PAGE, MAX_PORTFOLIO_SNAPSHOTS, MAX_WALLET_SNAPSHOTS};
+use crate::db::store::Store;
+use crate::config::ExclusionsConfig;
+use crate::types::models::{MonitoredToken, SnapshotJob, Worker, TokenStatus, HolderAge, WalletLabel, WalletPortfolio, MultiTokenWallets, WalletOverlap, HolderList, WalletHolding, BalanceHistogram, LorenzCurve, ConcentrationBucketConfig, Resolution, ThresholdConfig, TokenConcentration, TokenHistory};
 
 #[derive(Deserialize)]
 pub struct CreateTokenRequest {
     pub mint_address: String,
 }
 
+#[derive(Deserialize)]
+pub struct BulkCreateTokensRequest {
+    pub mint_addresses: Vec<String>,
+}
+
 #[derive(Serialize)]
 pub struct CreateTokenResponse {
     status: String,
     message: String,
+    // First snapshot job, poll GET /jobs/:id to know when data is available
+    #[serde(skip_serializing_if = "Option::is_none")]
+    job_id: Option<String>,
 }
 
+// One per distinct mint of a bulk request, in request order. status is one of
+// the single-token statuses, or 'invalid' / 'failed' with the reason in message
 #[derive(Serialize)]
-pub struct TokenListItem {
+pub struct BulkTokenResult {
     mint_address: String,
-    last_stats_update: String,
-    last_metrics_update: String,
+    status: String,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    job_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TokenListParams {
+    // 'all' or one status, archived tokens are left out by default
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct JobListParams {
+    pub status: Option<String>,
+    pub mint_address: Option<String>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateTokenRequest {
+    pub status: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct HistoryParams {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub resolution: Option<Resolution>,
+}
+
+#[derive(Deserialize)]
+pub struct ExportParams {
+    // csv (default), ndjson or parquet
+    pub format: Option<String>,
+    // Unix seconds, defaults to the whole history
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct ThresholdsRequest {
+    pub thresholds: Vec<f64>,
+}
+
+#[derive(Deserialize)]
+pub struct ConcentrationBucketsRequest {
+    pub buckets: Vec<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct ConcentrationParams {
+    // Comma separated top-N values, defaults to the token's configured buckets
+    pub n: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct LorenzParams {
+    // Unix seconds, the newest snapshot at or before it is used (default: latest)
+    pub timestamp: Option<i64>,
+    pub points: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct HistogramParams {
+    pub timestamp: Option<i64>,
+    pub bins_per_decade: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct HolderAgeParams {
+    pub timestamp: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct HoldersParams {
+    pub limit: Option<u64>,
+    // next_cursor of the previous page
+    pub cursor: Option<String>,
+    pub min_usd: Option<f64>,
+}
+
+#[derive(Deserialize)]
+pub struct WalletHistoryParams {
+    // Number of most recent snapshots to include
+    pub limit: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct OverlapParams {
+    // Comma separated mint addresses
+    pub mints: String,
+    pub limit: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct MultiTokenWalletsParams {
+    pub min_tokens: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct PortfolioParams {
+    // Number of recent snapshots of each token to include as history
+    pub snapshots: Option<u64>,
+}
+
+async fn ensure_monitored(store: &dyn Store, mint_address: &str) -> Result<(), ApiError> {
+    let status = store.token_status(mint_address)
+        .await
+        .map_err(|e| {
+            tracing::error!("Database error checking monitored status: {}", e);
+            ApiError::DatabaseError(e.to_string())
+        })?;
+
+    match status {
+        Some(_) => Ok(()),
+        None => Err(ApiError::TokenNotMonitored(mint_address.to_string())),
+    }
+}
+
+async fn snapshot_at(store: &dyn Store, mint_address: &str, timestamp: Option<i64>) -> Result<SnapshotRecord, ApiError> {
+    let at = timestamp.unwrap_or_else(|| chrono::Utc::now().timestamp());
+    store.find_snapshot(mint_address, at)
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?
+        .ok_or_else(|| ApiError::NotFound(format!("No snapshot of {} at or before {}", mint_address, at)))
 }
 
 async fn validate_token_with_jupiter(mint_address: &str) -> Result<(), ApiError> {
@@ -44,7 +193,7 @@ async fn validate_token_with_jupiter(mint_address: &str) -> Result<(), ApiError>
 }
 
 pub async fn create_token_monitor(
-    State((_rpc_client, rate_limiter, db)): State<AppState>,
+    State((rpc_client, rate_limiter, store, config)): State<AppState>,
     Json(params): Json<CreateTokenRequest>,
 ) -> Result<Json<CreateTokenResponse>, ApiError> {
     rate_limiter.until_ready().await;
@@ -55,48 +204,102 @@ pub async fn create_token_monitor(
     validate_token_with_jupiter(&params.mint_address).await?;
     tracing::info!("Token validation successful, proceeding with monitoring setup");
     
-    // Check if token is already monitored
-    let is_monitored = db.query(
-        "SELECT mint_address FROM monitored_tokens WHERE mint_address = ? LIMIT 1"
-    )
-        .bind(&params.mint_address)
-        .fetch_optional::<String>()
+    // Paused and archived tokens are resumed
+    let added = add_token(&rpc_client, &rate_limiter, store.as_ref(), &params.mint_address, "POST /tokens", &config.exclusions)
         .await
         .map_err(|e| {
-            tracing::error!("Database error checking monitored status: {}", e);
+            tracing::error!("Failed to add token {}: {}", params.mint_address, e);
             ApiError::DatabaseError(e.to_string())
         })?;
 
-    if is_monitored.is_some() {
-        return Ok(Json(CreateTokenResponse {
-            status: "already_monitored".to_string(),
-            message: "Token is already being monitored".to_string(),
-        }));
+    let job_id = match added {
+        AddedToken::AlreadyMonitored => {
+            return Ok(Json(CreateTokenResponse {
+                status: "already_monitored".to_string(),
+                message: "Token is already being monitored".to_string(),
+                job_id: None,
+            }));
+        }
+        AddedToken::Resumed { job_id } => {
+            return Ok(Json(CreateTokenResponse {
+                status: "monitoring_resumed".to_string(),
+                message: "Token monitoring has been resumed.".to_string(),
+                job_id: Some(job_id),
+            }));
+        }
+        AddedToken::Started { job_id } => job_id,
+    };
+
+    // Insiders and snipers are labelled by the workers' launch analysis
+    Ok(Json(CreateTokenResponse {
+        status: "monitoring_started".to_string(),
+        message: "Token has been added to monitoring. Data will be available once the snapshot job is done.".to_string(),
+        job_id: Some(job_id),
+    }))
+}
+
+// Mints are validated and added a few at a time, one bad mint doesn't fail the
+// others. Launch analysis is left to the scheduled service.
+pub async fn bulk_create_token_monitors(
+    State((rpc_client, rate_limiter, store, config)): State<AppState>,
+    Json(params): Json<BulkCreateTokensRequest>,
+) -> Result<Json<Vec<BulkTokenResult>>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    let mut seen = HashSet::new();
+    let mints: Vec<String> = params.mint_addresses
+        .into_iter()
+        .map(|mint| mint.trim().to_string())
+        .filter(|mint| !mint.is_empty() && seen.insert(mint.clone()))
+        .collect();
+    if mints.is_empty() || mints.len() > MAX_BULK_TOKENS {
+        return Err(ApiError::InvalidInput(format!("mint_addresses needs between 1 and {} distinct mint addresses", MAX_BULK_TOKENS)));
     }
+    tracing::info!("Received request to monitor {} tokens", mints.len());
 
-    // Add token to monitoring
-    db.query(
-        "INSERT INTO monitored_tokens (mint_address, last_stats_update, last_metrics_update) 
-         VALUES (?, toDateTime('1970-01-01 00:00:00'), toDateTime('1970-01-01 00:00:00'))"
-    )
-        .bind(&params.mint_address)
-        .execute()
-        .await
-        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+    let results = futures::stream::iter(mints)
+        .map(|mint_address| bulk_add_token(&rpc_client, &rate_limiter, store.as_ref(), mint_address, &config.exclusions))
+        .buffered(BULK_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    Ok(Json(results))
+}
 
-    // Check for excluded accounts for this new token
-    if let Err(e) = check_new_token_exclusions(&_rpc_client, &rate_limiter, &db, &params.mint_address).await {
-        tracing::error!("Failed to check excluded accounts for new token: {}", e);
+async fn bulk_add_token(
+    rpc_client: &Arc<RpcClient>,
+    rate_limiter: &Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    store: &dyn Store,
+    mint_address: String,
+    config: &ExclusionsConfig,
+) -> BulkTokenResult {
+    let result = |status: &str, message: String, job_id: Option<String>| BulkTokenResult {
+        mint_address: mint_address.clone(),
+        status: status.to_string(),
+        message,
+        job_id,
+    };
+
+    if Pubkey::from_str(&mint_address).is_err() {
+        return result("invalid", "Invalid mint address".to_string(), None);
+    }
+    if validate_token_with_jupiter(&mint_address).await.is_err() {
+        return result("invalid", "Invalid token address, Jupiter has no price for it".to_string(), None);
     }
 
-    Ok(Json(CreateTokenResponse {
-        status: "monitoring_started".to_string(),
-        message: "Token has been added to monitoring. Data will be available soon.".to_string(),
-    }))
+    match add_token(rpc_client, rate_limiter, store, &mint_address, "POST /tokens/bulk", config).await {
+        Ok(AddedToken::AlreadyMonitored) => result("already_monitored", "Token is already being monitored".to_string(), None),
+        Ok(AddedToken::Resumed { job_id }) => result("monitoring_resumed", "Token monitoring has been resumed.".to_string(), Some(job_id)),
+        Ok(AddedToken::Started { job_id }) => result("monitoring_started", "Token has been added to monitoring.".to_string(), Some(job_id)),
+        Err(e) => {
+            tracing::error!("Failed to add token {}: {}", mint_address, e);
+            result("failed", e.to_string(), None)
+        }
+    }
 }
 
 pub async fn get_token_stats(
-    State((_rpc_client, rate_limiter, db)): State<AppState>,
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
     Path(mint_address): Path<String>,
 ) -> Result<Json<serde_json::Value>, ApiError> {
     rate_limiter.until_ready().await;
@@ -104,22 +307,9 @@ pub async fn get_token_stats(
     tracing::info!("Retrieving stats for token: {}", mint_address);
     
     // Check if token is monitored
-    let is_monitored = db.query(
-        "SELECT mint_address FROM monitored_tokens WHERE mint_address = ? LIMIT 1"
-    )
-        .bind(&mint_address)
-        .fetch_optional::<String>()
-        .await
-        .map_err(|e| {
-            tracing::error!("Database error checking monitored status: {}", e);
-            ApiError::DatabaseError(e.to_string())
-        })?;
-
-    if is_monitored.is_none() {
-        return Err(ApiError::TokenNotMonitored(mint_address));
-    }
+    ensure_monitored(store.as_ref(), &mint_address).await?;
 
-    match get_token_metrics(&db, &mint_address).await {
+    match store.token_metrics(&mint_address).await {
         Ok(stats) => {
             tracing::info!("Successfully retrieved stats for {}", mint_address);
             Ok(Json(structure_token_stats(stats)))
@@ -132,34 +322,633 @@ pub async fn get_token_stats(
 }
 
 pub async fn get_all_tokens(
-    State((_rpc_client, rate_limiter, db)): State<AppState>,
-) -> Result<Json<Vec<TokenListItem>>, ApiError> {
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Query(params): Query<TokenListParams>,
+) -> Result<Json<Vec<MonitoredToken>>, ApiError> {
     rate_limiter.until_ready().await;
     
     tracing::info!("Retrieving all monitored tokens");
+
+    let statuses: Vec<String> = match params.status.as_deref() {
+        None | Some("") => vec!["active".to_string(), "paused".to_string()],
+        Some("all") => vec!["active".to_string(), "paused".to_string(), "archived".to_string()],
+        Some(status) => {
+            validate_status(status).map_err(ApiError::InvalidInput)?;
+            vec![status.to_string()]
+        }
+    };
     
-    let tokens = db.query(
-        "SELECT 
-            mint_address,
-            toString(last_stats_update) as last_stats_update,
-            toString(last_metrics_update) as last_metrics_update
-         FROM monitored_tokens
-         ORDER BY last_stats_update DESC"
-    )
-        .fetch_all::<(String, String, String)>()
+    let token_list = store.monitored_tokens(&statuses)
         .await
         .map_err(|e| {
             tracing::error!("Database error fetching monitored tokens: {}", e);
             ApiError::DatabaseError(e.to_string())
         })?;
 
-    let token_list = tokens.into_iter()
-        .map(|(mint_address, last_stats_update, last_metrics_update)| TokenListItem {
-            mint_address,
-            last_stats_update,
-            last_metrics_update,
-        })
+    Ok(Json(token_list))
+}
+
+pub async fn update_token_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path(mint_address): Path<String>,
+    Json(params): Json<UpdateTokenRequest>,
+) -> Result<Json<TokenStatus>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    ensure_monitored(store.as_ref(), &mint_address).await?;
+    validate_status(&params.status).map_err(ApiError::InvalidInput)?;
+
+    let reason = params.reason.unwrap_or_else(|| "updated via API".to_string());
+    store.set_token_status(&mint_address, &params.status, &reason)
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+
+    token_status(store.as_ref(), &mint_address).await
+}
+
+// Stops monitoring but keeps the token's data, POST /tokens resumes it
+pub async fn delete_token_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path(mint_address): Path<String>,
+) -> Result<Json<TokenStatus>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    ensure_monitored(store.as_ref(), &mint_address).await?;
+
+    store.set_token_status(&mint_address, "archived", "deleted via API")
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+
+    token_status(store.as_ref(), &mint_address).await
+}
+
+// Takes a snapshot now instead of waiting for the next monitoring cycle
+pub async fn refresh_token_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path(mint_address): Path<String>,
+) -> Result<Json<SnapshotJob>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    ensure_monitored(store.as_ref(), &mint_address).await?;
+
+    let job_id = request_refresh(store.as_ref(), &mint_address)
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+    snapshot_job(store.as_ref(), &job_id).await
+}
+
+pub async fn get_job_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path(job_id): Path<String>,
+) -> Result<Json<SnapshotJob>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    snapshot_job(store.as_ref(), &job_id).await
+}
+
+pub async fn list_jobs_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Query(params): Query<JobListParams>,
+) -> Result<Json<Vec<SnapshotJob>>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    let status = params.status.as_deref().filter(|s| !s.is_empty());
+    if let Some(status) = status {
+        if !JOB_STATUSES.contains(&status) {
+            return Err(ApiError::InvalidInput(format!(
+                "Unknown status {}, expected one of {}", status, JOB_STATUSES.join(", ")
+            )));
+        }
+    }
+    let limit = params.limit.unwrap_or(100);
+    if limit == 0 || limit > MAX_JOBS_PAGE {
+        return Err(ApiError::InvalidInput(format!("limit must be between 1 and {}", MAX_JOBS_PAGE)));
+    }
+
+    let jobs = store.jobs(status, params.mint_address.as_deref(), limit)
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+    Ok(Json(jobs))
+}
+
+// Workers currently in the ring, with the one running the global jobs marked as leader
+pub async fn list_workers_handler(
+    State((_rpc_client, rate_limiter, store, config)): State<AppState>,
+) -> Result<Json<Vec<Worker>>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    let workers = store.live_workers(config.workers.timeout_secs)
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+    let ids: Vec<String> = workers.iter().map(|(worker_id, _, _)| worker_id.clone()).collect();
+    let leader = leader(&ids).map(str::to_string);
+
+    Ok(Json(workers.into_iter().map(|(worker_id, mode, heartbeat_at)| Worker {
+        leader: leader.as_deref() == Some(worker_id.as_str()),
+        worker_id,
+        mode,
+        heartbeat_at,
+    }).collect()))
+}
+
+async fn snapshot_job(store: &dyn Store, job_id: &str) -> Result<Json<SnapshotJob>, ApiError> {
+    store.job(job_id)
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Job {} not found", job_id)))
+}
+
+async fn token_status(store: &dyn Store, mint_address: &str) -> Result<Json<TokenStatus>, ApiError> {
+    store.token_status(mint_address)
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?
+        .map(Json)
+        .ok_or_else(|| ApiError::TokenNotMonitored(mint_address.to_string()))
+}
+
+pub async fn get_token_history_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path(mint_address): Path<String>,
+    Query(params): Query<HistoryParams>,
+) -> Result<Json<TokenHistory>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    ensure_monitored(store.as_ref(), &mint_address).await?;
+
+    let to = params.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+    let from = params.from.unwrap_or(to - 24 * 3600);
+    if from >= to {
+        return Err(ApiError::InvalidInput("from must be before to".to_string()));
+    }
+
+    let history = store.history(&mint_address, from, to, params.resolution.unwrap_or(Resolution::Auto))
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching history for {}: {}", mint_address, e);
+            ApiError::DatabaseError(e.to_string())
+        })?;
+
+    Ok(Json(history))
+}
+
+// Streamed from ClickHouse as it produces the rows
+pub async fn export_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path((mint_address, dataset)): Path<(String, String)>,
+    Query(params): Query<ExportParams>,
+) -> Result<Response, ApiError> {
+    rate_limiter.until_ready().await;
+
+    ensure_monitored(store.as_ref(), &mint_address).await?;
+
+    let dataset = ExportDataset::from_str(&dataset).map_err(ApiError::InvalidInput)?;
+    let format = match params.format.as_deref() {
+        None | Some("") => ExportFormat::Csv,
+        Some(format) => ExportFormat::from_str(format).map_err(ApiError::InvalidInput)?,
+    };
+    let to = params.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+    let from = params.from.unwrap_or(0);
+    if from >= to {
+        return Err(ApiError::InvalidInput("from must be before to".to_string()));
+    }
+
+    let stream = store.export(dataset, format, &mint_address, from, to)
+        .await
+        .map_err(|e| {
+            if e.is::<ExportRangeTooLarge>() || e.is::<ExportFormatUnavailable>() {
+                return ApiError::InvalidInput(e.to_string());
+            }
+            tracing::error!("Error exporting {} of {}: {}", dataset.name(), mint_address, e);
+            ApiError::DatabaseError(e.to_string())
+        })?;
+
+    let filename = format!("{}-{}.{}", mint_address, dataset.name(), format.extension());
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        Body::from_stream(stream),
+    ).into_response())
+}
+
+pub async fn get_default_thresholds_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+) -> Result<Json<ThresholdConfig>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    let config = store.default_thresholds()
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+
+    Ok(Json(config))
+}
+
+pub async fn set_default_thresholds_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Json(params): Json<ThresholdsRequest>,
+) -> Result<Json<ThresholdConfig>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    let thresholds = normalize_thresholds(params.thresholds).map_err(ApiError::InvalidInput)?;
+    if thresholds.is_empty() {
+        return Err(ApiError::InvalidInput("The default needs at least one threshold".to_string()));
+    }
+
+    tracing::info!("Setting default USD thresholds to {:?}", thresholds);
+    store.set_thresholds(None, &thresholds)
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+
+    let config = store.default_thresholds()
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+
+    Ok(Json(config))
+}
+
+pub async fn get_token_thresholds_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path(mint_address): Path<String>,
+) -> Result<Json<ThresholdConfig>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    ensure_monitored(store.as_ref(), &mint_address).await?;
+
+    let config = store.token_thresholds(&mint_address)
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+
+    Ok(Json(config))
+}
+
+pub async fn set_token_thresholds_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path(mint_address): Path<String>,
+    Json(params): Json<ThresholdsRequest>,
+) -> Result<Json<ThresholdConfig>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    ensure_monitored(store.as_ref(), &mint_address).await?;
+    let thresholds = normalize_thresholds(params.thresholds).map_err(ApiError::InvalidInput)?;
+
+    tracing::info!("Setting USD thresholds for {} to {:?}", mint_address, thresholds);
+    store.set_thresholds(Some(&mint_address), &thresholds)
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+
+    let config = store.token_thresholds(&mint_address)
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+
+    Ok(Json(config))
+}
+
+pub async fn get_default_buckets_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+) -> Result<Json<ConcentrationBucketConfig>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    let config = store.default_buckets()
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+
+    Ok(Json(config))
+}
+
+pub async fn set_default_buckets_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Json(params): Json<ConcentrationBucketsRequest>,
+) -> Result<Json<ConcentrationBucketConfig>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    let buckets = normalize_buckets(params.buckets).map_err(ApiError::InvalidInput)?;
+    if buckets.is_empty() {
+        return Err(ApiError::InvalidInput("The default needs at least one bucket".to_string()));
+    }
+
+    tracing::info!("Setting default top-N buckets to {:?}", buckets);
+    store.set_buckets(None, &buckets)
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+
+    let config = store.default_buckets()
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+
+    Ok(Json(config))
+}
+
+pub async fn get_token_buckets_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path(mint_address): Path<String>,
+) -> Result<Json<ConcentrationBucketConfig>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    ensure_monitored(store.as_ref(), &mint_address).await?;
+
+    let config = store.token_buckets(&mint_address)
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+
+    Ok(Json(config))
+}
+
+pub async fn set_token_buckets_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path(mint_address): Path<String>,
+    Json(params): Json<ConcentrationBucketsRequest>,
+) -> Result<Json<ConcentrationBucketConfig>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    ensure_monitored(store.as_ref(), &mint_address).await?;
+    let buckets = normalize_buckets(params.buckets).map_err(ApiError::InvalidInput)?;
+
+    tracing::info!("Setting top-N buckets for {} to {:?}", mint_address, buckets);
+    store.set_buckets(Some(&mint_address), &buckets)
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+
+    let config = store.token_buckets(&mint_address)
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+
+    Ok(Json(config))
+}
+
+pub async fn get_token_concentration_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path(mint_address): Path<String>,
+    Query(params): Query<ConcentrationParams>,
+) -> Result<Json<TokenConcentration>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    ensure_monitored(store.as_ref(), &mint_address).await?;
+
+    let top_n = match params.n {
+        Some(n) => {
+            let top_n = n.split(',')
+                .map(|v| v.trim().parse::<u32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| ApiError::InvalidInput(format!("Invalid top-N list {}", n)))?;
+            normalize_buckets(top_n).map_err(ApiError::InvalidInput)?
+        }
+        None => store.token_buckets(&mint_address)
+            .await
+            .map_err(|e| ApiError::DatabaseError(e.to_string()))?
+            .buckets,
+    };
+    if top_n.is_empty() {
+        return Err(ApiError::InvalidInput("n needs at least one value".to_string()));
+    }
+
+    let concentration = store.concentration(&mint_address, &top_n)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error computing concentration for {}: {}", mint_address, e);
+            ApiError::DatabaseError(e.to_string())
+        })?;
+
+    Ok(Json(concentration))
+}
+
+pub async fn get_lorenz_curve_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path(mint_address): Path<String>,
+    Query(params): Query<LorenzParams>,
+) -> Result<Json<LorenzCurve>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    ensure_monitored(store.as_ref(), &mint_address).await?;
+
+    let points = params.points.unwrap_or(100);
+    if points == 0 || points > MAX_LORENZ_POINTS {
+        return Err(ApiError::InvalidInput(format!("points must be between 1 and {}", MAX_LORENZ_POINTS)));
+    }
+
+    let snapshot = snapshot_at(store.as_ref(), &mint_address, params.timestamp).await?;
+    let curve = store.lorenz_curve(&mint_address, &snapshot, points)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error computing Lorenz curve for {}: {}", mint_address, e);
+            ApiError::DatabaseError(e.to_string())
+        })?;
+
+    Ok(Json(curve))
+}
+
+pub async fn get_balance_histogram_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path(mint_address): Path<String>,
+    Query(params): Query<HistogramParams>,
+) -> Result<Json<BalanceHistogram>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    ensure_monitored(store.as_ref(), &mint_address).await?;
+
+    let bins_per_decade = params.bins_per_decade.unwrap_or(1);
+    if bins_per_decade == 0 || bins_per_decade > MAX_BINS_PER_DECADE {
+        return Err(ApiError::InvalidInput(format!("bins_per_decade must be between 1 and {}", MAX_BINS_PER_DECADE)));
+    }
+
+    let snapshot = snapshot_at(store.as_ref(), &mint_address, params.timestamp).await?;
+    let histogram = store.balance_histogram(&mint_address, &snapshot, bins_per_decade)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error computing balance histogram for {}: {}", mint_address, e);
+            ApiError::DatabaseError(e.to_string())
+        })?;
+
+    Ok(Json(histogram))
+}
+
+// Holders of a snapshot by how long they have held the token without selling out.
+// A wallet that left and came back is counted from its return.
+pub async fn get_holder_age_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path(mint_address): Path<String>,
+    Query(params): Query<HolderAgeParams>,
+) -> Result<Json<HolderAge>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    ensure_monitored(store.as_ref(), &mint_address).await?;
+
+    let snapshot = snapshot_at(store.as_ref(), &mint_address, params.timestamp).await?;
+    let age = store.holder_age(&mint_address, &snapshot)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error computing holder age for {}: {}", mint_address, e);
+            ApiError::DatabaseError(e.to_string())
+        })?;
+
+    Ok(Json(age))
+}
+
+pub async fn get_holders_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path(mint_address): Path<String>,
+    Query(params): Query<HoldersParams>,
+) -> Result<Json<HolderList>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    ensure_monitored(store.as_ref(), &mint_address).await?;
+
+    let limit = params.limit.unwrap_or(100);
+    if limit == 0 || limit > MAX_HOLDERS_PAGE {
+        return Err(ApiError::InvalidInput(format!("limit must be between 1 and {}", MAX_HOLDERS_PAGE)));
+    }
+    let min_usd = params.min_usd.unwrap_or(0.0);
+    if !min_usd.is_finite() || min_usd < 0.0 {
+        return Err(ApiError::InvalidInput("min_usd must be a positive amount".to_string()));
+    }
+
+    // A cursor pins the page to the snapshot the first page was read from
+    let (snapshot, after_rank) = match params.cursor {
+        Some(cursor) => {
+            let (timestamp, rank) = decode_cursor(&cursor)
+                .ok_or_else(|| ApiError::InvalidInput(format!("Invalid cursor {}", cursor)))?;
+            let snapshot = snapshot_at(store.as_ref(), &mint_address, Some(timestamp)).await?;
+            if snapshot.timestamp != timestamp {
+                return Err(ApiError::NotFound(format!("Snapshot {} of the cursor no longer exists", timestamp)));
+            }
+            (snapshot, rank)
+        }
+        None => (snapshot_at(store.as_ref(), &mint_address, None).await?, 0),
+    };
+
+    let holders = store.holders(&mint_address, &snapshot, after_rank, limit, min_usd)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching holders for {}: {}", mint_address, e);
+            ApiError::DatabaseError(e.to_string())
+        })?;
+
+    Ok(Json(holders))
+}
+
+pub async fn get_wallet_holding_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path((mint_address, wallet)): Path<(String, String)>,
+    Query(params): Query<WalletHistoryParams>,
+) -> Result<Json<WalletHolding>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    ensure_monitored(store.as_ref(), &mint_address).await?;
+    if Pubkey::from_str(&wallet).is_err() {
+        return Err(ApiError::InvalidInput(format!("Invalid wallet address {}", wallet)));
+    }
+
+    let limit = params.limit.unwrap_or(100);
+    if limit == 0 || limit > MAX_WALLET_SNAPSHOTS {
+        return Err(ApiError::InvalidInput(format!("limit must be between 1 and {}", MAX_WALLET_SNAPSHOTS)));
+    }
+
+    let holding = store.wallet_holding(&mint_address, &wallet, limit)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching wallet {} for {}: {}", wallet, mint_address, e);
+            ApiError::DatabaseError(e.to_string())
+        })?;
+
+    Ok(Json(holding))
+}
+
+pub async fn get_wallet_overlap_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Query(params): Query<OverlapParams>,
+) -> Result<Json<WalletOverlap>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    let mut mints: Vec<String> = params.mints.split(',')
+        .map(|m| m.trim().to_string())
+        .filter(|m| !m.is_empty())
         .collect();
+    mints.sort();
+    mints.dedup();
+    if mints.len() < 2 || mints.len() > MAX_OVERLAP_MINTS {
+        return Err(ApiError::InvalidInput(format!("mints needs between 2 and {} distinct mint addresses", MAX_OVERLAP_MINTS)));
+    }
+    for mint in &mints {
+        ensure_monitored(store.as_ref(), mint).await?;
+    }
 
-    Ok(Json(token_list))
-}
\ No newline at end of file
+    let limit = params.limit.unwrap_or(100);
+    if limit == 0 || limit > MAX_OVERLAP_HOLDERS {
+        return Err(ApiError::InvalidInput(format!("limit must be between 1 and {}", MAX_OVERLAP_HOLDERS)));
+    }
+
+    let overlap = store.wallet_overlap(&mints, limit)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error computing overlap of {:?}: {}", mints, e);
+            ApiError::DatabaseError(e.to_string())
+        })?;
+
+    Ok(Json(overlap))
+}
+
+pub async fn get_multi_token_wallets_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Query(params): Query<MultiTokenWalletsParams>,
+) -> Result<Json<MultiTokenWallets>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    let min_tokens = params.min_tokens.unwrap_or(2);
+    if min_tokens < 2 {
+        return Err(ApiError::InvalidInput("min_tokens must be at least 2".to_string()));
+    }
+    let limit = params.limit.unwrap_or(100);
+    if limit == 0 || limit > MAX_OVERLAP_HOLDERS {
+        return Err(ApiError::InvalidInput(format!("limit must be between 1 and {}", MAX_OVERLAP_HOLDERS)));
+    }
+
+    let wallets = store.multi_token_wallets(min_tokens, limit)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching multi-token wallets: {}", e);
+            ApiError::DatabaseError(e.to_string())
+        })?;
+
+    Ok(Json(wallets))
+}
+
+pub async fn get_wallet_portfolio_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path(address): Path<String>,
+    Query(params): Query<PortfolioParams>,
+) -> Result<Json<WalletPortfolio>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    if Pubkey::from_str(&address).is_err() {
+        return Err(ApiError::InvalidInput(format!("Invalid wallet address {}", address)));
+    }
+    let snapshots = params.snapshots.unwrap_or(10);
+    if snapshots == 0 || snapshots > MAX_PORTFOLIO_SNAPSHOTS {
+        return Err(ApiError::InvalidInput(format!("snapshots must be between 1 and {}", MAX_PORTFOLIO_SNAPSHOTS)));
+    }
+
+    let portfolio = store.wallet_portfolio(&address, snapshots)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching portfolio of {}: {}", address, e);
+            ApiError::DatabaseError(e.to_string())
+        })?;
+
+    Ok(Json(portfolio))
+}
+
+pub async fn get_wallet_labels_handler(
+    State((_rpc_client, rate_limiter, store, _config)): State<AppState>,
+    Path(mint_address): Path<String>,
+) -> Result<Json<Vec<WalletLabel>>, ApiError> {
+    rate_limiter.until_ready().await;
+
+    ensure_monitored(store.as_ref(), &mint_address).await?;
+
+    let labels = store.wallet_labels(&mint_address)
+        .await
+        .map_err(|e| ApiError::DatabaseError(e.to_string()))?;
+
+    Ok(Json(labels))
+}
@@ -2,7 +2,38 @@ use axum::{
     routing::{get, post},
     Router,
 };
-use super::handlers::{get_token_stats, create_token_monitor, get_all_tokens};
+use super::handlers::{
+    get_token_stats,
+    update_token_handler,
+    delete_token_handler,
+    refresh_token_handler,
+    get_job_handler,
+    list_jobs_handler,
+    list_workers_handler,
+    create_token_monitor,
+    bulk_create_token_monitors,
+    get_all_tokens,
+    get_token_history_handler,
+    export_handler,
+    get_default_thresholds_handler,
+    set_default_thresholds_handler,
+    get_token_thresholds_handler,
+    set_token_thresholds_handler,
+    get_default_buckets_handler,
+    set_default_buckets_handler,
+    get_token_buckets_handler,
+    set_token_buckets_handler,
+    get_token_concentration_handler,
+    get_lorenz_curve_handler,
+    get_balance_histogram_handler,
+    get_holder_age_handler,
+    get_holders_handler,
+    get_wallet_holding_handler,
+    get_wallet_overlap_handler,
+    get_multi_token_wallets_handler,
+    get_wallet_portfolio_handler,
+    get_wallet_labels_handler,
+};
 use super::state::AppState;
 use tower_http::cors::{CorsLayer, Any};
 
@@ -13,9 +44,30 @@ pub fn create_router(state: AppState) -> Router {
         .allow_headers(Any);
 
     Router::new()
-        .route("/tokens/:mint_address", get(get_token_stats))
+        .route("/tokens/:mint_address", get(get_token_stats).patch(update_token_handler).delete(delete_token_handler))
+        .route("/tokens/:mint_address/refresh", post(refresh_token_handler))
+        .route("/tokens/:mint_address/history", get(get_token_history_handler))
+        .route("/tokens/:mint_address/export/:dataset", get(export_handler))
+        .route("/tokens/:mint_address/thresholds", get(get_token_thresholds_handler).put(set_token_thresholds_handler))
+        .route("/tokens/:mint_address/holders", get(get_holders_handler))
+        .route("/tokens/:mint_address/holders/:wallet", get(get_wallet_holding_handler))
+        .route("/tokens/:mint_address/labels", get(get_wallet_labels_handler))
+        .route("/tokens/:mint_address/lorenz", get(get_lorenz_curve_handler))
+        .route("/tokens/:mint_address/histogram", get(get_balance_histogram_handler))
+        .route("/tokens/:mint_address/age", get(get_holder_age_handler))
+        .route("/tokens/:mint_address/concentration", get(get_token_concentration_handler))
+        .route("/tokens/:mint_address/concentration/buckets", get(get_token_buckets_handler).put(set_token_buckets_handler))
+        .route("/concentration/buckets", get(get_default_buckets_handler).put(set_default_buckets_handler))
+        .route("/wallets/:address", get(get_wallet_portfolio_handler))
+        .route("/overlap", get(get_wallet_overlap_handler))
+        .route("/overlap/wallets", get(get_multi_token_wallets_handler))
+        .route("/thresholds", get(get_default_thresholds_handler).put(set_default_thresholds_handler))
+        .route("/jobs", get(list_jobs_handler))
+        .route("/jobs/:job_id", get(get_job_handler))
+        .route("/workers", get(list_workers_handler))
         .route("/tokens", get(get_all_tokens))
         .route("/tokens", post(create_token_monitor))
+        .route("/tokens/bulk", post(bulk_create_token_monitors))
         .layer(cors)
         .with_state(state)
 }
\ No newline at end of file
@@ -1,11 +1,13 @@
 use std::sync::Arc;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use governor::{RateLimiter, state::{NotKeyed, InMemoryState}, clock::DefaultClock};
-use clickhouse::Client;
+use crate::config::Config;
+use crate::db::store::Store;
 
 pub type AppState = (
     Arc<RpcClient>,
     Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
-    Client,
+    Arc<dyn Store>,
+    Arc<Config>,
 );
 
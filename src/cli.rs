@@ -0,0 +1,160 @@
+use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand};
+use crate::services::export::{ExportDataset, ExportFormat};
+use crate::services::replay::DumpFormat;
+
+#[derive(Parser)]
+#[command(name = "memespread", about = "Track holders & concentration of Solana tokens")]
+pub struct Cli {
+    /// TOML config file (default: $MEMESPREAD_CONFIG, or memespread.toml if it exists)
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the API, frontend and monitoring services (default)
+    Serve,
+    /// Run only the API and frontend
+    Api,
+    /// Run only the monitoring services, taking a shard of the monitored tokens
+    Worker,
+    /// Start monitoring a token, or resume it if it was paused or archived
+    Add {
+        mint_address: String,
+    },
+    /// Take a snapshot of a monitored token and print its metrics as JSON
+    Snapshot {
+        mint_address: String,
+        /// Seconds to wait for a worker to finish the snapshot job before giving up
+        #[arg(long, default_value_t = 600)]
+        timeout: u64,
+    },
+    /// Recompute the metrics of stored snapshots with the current exclusions and config
+    Recompute {
+        /// Only recompute this token
+        #[arg(long = "mint")]
+        mint_address: Option<String>,
+        /// Unix timestamp of the oldest snapshot to recompute (default: all)
+        #[arg(long)]
+        from: Option<i64>,
+        /// Unix timestamp of the newest snapshot to recompute (default: now)
+        #[arg(long)]
+        to: Option<i64>,
+    },
+    /// Write the holders of a token's snapshot as CSV, or stream its raw holder
+    /// rows, stats or metrics history over a time range
+    Export(ExportArgs),
+    /// Run a dump of a token's accounts through the snapshot pipeline without RPC,
+    /// printing its metrics as JSON or storing it as a snapshot
+    Replay(ReplayArgs),
+    /// Recompute a stored snapshot's metrics in Rust and compare them with the views' results
+    CheckMetrics {
+        mint_address: String,
+        /// Unix timestamp, the newest snapshot taken at or before it is checked (default: latest)
+        #[arg(long)]
+        at: Option<i64>,
+        /// Largest accepted difference, relative to the larger value
+        #[arg(long, default_value_t = 1e-9)]
+        tolerance: f64,
+    },
+    /// Load historical holder snapshots of a token with their original timestamps
+    ImportSnapshots {
+        mint_address: String,
+        /// One JSON snapshot per line, `-` reads stdin
+        file: PathBuf,
+        /// Import snapshots older than a retention TTL anyway, the TTL drops them again
+        #[arg(long)]
+        force: bool,
+    },
+    /// Manage the accounts left out of the metrics
+    Exclusions {
+        #[command(subcommand)]
+        action: ExclusionsAction,
+    },
+    /// Apply pending schema migrations
+    Migrate {
+        /// Print the statements of pending migrations without running them
+        #[arg(long)]
+        dry_run: bool,
+        #[command(subcommand)]
+        action: Option<MigrateAction>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum MigrateAction {
+    /// List every migration and when it was applied
+    Status,
+}
+
+#[derive(Subcommand)]
+pub enum ExclusionsAction {
+    /// Add accounts from a file of `address,category[,description]` lines, `-` reads stdin
+    Import {
+        file: PathBuf,
+    },
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    pub mint_address: String,
+    /// Unix timestamp, the newest snapshot taken at or before it is used (default: latest)
+    #[arg(long, conflicts_with = "dataset")]
+    pub at: Option<i64>,
+    /// holders, stats or metrics, streamed from ClickHouse instead of the ranked holders of one snapshot
+    #[arg(long = "data")]
+    pub dataset: Option<ExportDataset>,
+    /// With --data, csv, ndjson or parquet (default: csv)
+    #[arg(long, requires = "dataset")]
+    pub format: Option<ExportFormat>,
+    /// With --data, unix timestamp of the oldest row (default: all)
+    #[arg(long, requires = "dataset")]
+    pub from: Option<i64>,
+    /// With --data, unix timestamp of the newest row (default: now)
+    #[arg(long, requires = "dataset")]
+    pub to: Option<i64>,
+    /// File to write to (default: stdout)
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ReplayArgs {
+    /// getProgramAccounts output of the token program, `-` reads stdin
+    pub file: PathBuf,
+    /// json, csv or base64 (default: from the file extension)
+    #[arg(long)]
+    pub format: Option<DumpFormat>,
+    /// Mint of the accounts, needed when the dump holds several
+    #[arg(long = "mint")]
+    pub mint_address: Option<String>,
+    /// USD price of one token
+    #[arg(long)]
+    pub price: f64,
+    #[arg(long)]
+    pub decimals: u8,
+    /// Raw supply (default: the sum of the dumped accounts)
+    #[arg(long)]
+    pub supply: Option<u64>,
+    /// Unix timestamp of the snapshot (default: now)
+    #[arg(long)]
+    pub at: Option<i64>,
+    /// Store the snapshot instead of only printing its metrics
+    #[arg(long)]
+    pub write: bool,
+    /// Compute the metrics in Rust without connecting to ClickHouse
+    #[arg(long, conflicts_with = "write")]
+    pub offline: bool,
+    /// With --offline, `address,category[,description]` lines of accounts to leave out
+    #[arg(long, requires = "offline")]
+    pub exclusions: Option<PathBuf>,
+    /// With --offline, USD thresholds (default: 10,100,1000,10000,100000)
+    #[arg(long, value_delimiter = ',', requires = "offline")]
+    pub thresholds: Vec<f64>,
+    /// With --offline, top-N buckets (default: 1,10,25,50,100,250)
+    #[arg(long, value_delimiter = ',', requires = "offline")]
+    pub buckets: Vec<u32>,
+}
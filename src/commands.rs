@@ -0,0 +1,376 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use anyhow::Result;
+use chrono::Utc;
+use clickhouse::Client;
+use futures::TryStreamExt;
+use solana_sdk::pubkey::Pubkey;
+use tokio::time::Duration;
+use crate::cli::{ExportArgs, MigrateAction, ReplayArgs};
+use crate::config::Config;
+use crate::db::init::init_database;
+use crate::db::migrations::{migration_status, run_migrations};
+use crate::db::operations::structure_token_stats;
+use crate::db::queries::find_snapshot;
+use crate::db::store::{ClickHouseStore, MemoryStore, Store};
+use crate::services::export::ExportFormat;
+use crate::services::import::{parse_snapshot_import, write_historical_snapshots};
+use crate::services::excluded_accounts::parse_exclusions;
+use crate::services::holders::MAX_HOLDERS_PAGE;
+use crate::services::jobs::request_refresh;
+use crate::services::lifecycle::{add_token, AddedToken};
+use crate::services::concentration::normalize_buckets;
+use crate::services::metrics::{compare_with_stored, DEFAULT_BUCKETS, DEFAULT_THRESHOLDS};
+use crate::services::recompute::recompute_metrics;
+use crate::services::thresholds::normalize_thresholds;
+use crate::services::replay::{dump_mint, dump_supply, parse_dump, replay_snapshot, DumpFormat};
+use crate::services::snapshots::SnapshotCache;
+use crate::services::token::{decode_holders, get_token_price, snapshot_stats, update_token_metrics, write_snapshot_at};
+use crate::{connect_rpc, connect_to_clickhouse};
+
+// One-shot commands for scripting, they share the services with the API and
+// print their results to stdout. Logs go to stderr.
+
+async fn connect(config: &Config) -> Result<Client> {
+    let client = connect_to_clickhouse(&config.clickhouse).await?;
+    init_database(&client, config.clickhouse.auto_migrate).await?;
+    Ok(client)
+}
+
+fn parse_mint(mint_address: &str) -> Result<()> {
+    Pubkey::from_str(mint_address)
+        .map(|_| ())
+        .map_err(|_| anyhow::anyhow!("Invalid mint address {}", mint_address))
+}
+
+pub async fn add(config: &Config, mint_address: &str) -> Result<()> {
+    parse_mint(mint_address)?;
+    get_token_price(mint_address)
+        .await
+        .map_err(|_| anyhow::anyhow!("Invalid token address {}, Jupiter has no price for it", mint_address))?;
+
+    let store = ClickHouseStore::new(connect(config).await?, config.clickhouse.clone());
+    let (rpc_client, rate_limiter) = connect_rpc(&config.rpc)?;
+    // Insiders and snipers are labelled by the workers' launch analysis
+    match add_token(&rpc_client, &rate_limiter, &store, mint_address, "memespread add", &config.exclusions).await? {
+        AddedToken::AlreadyMonitored => println!("{} is already monitored", mint_address),
+        AddedToken::Resumed { job_id } => println!("Resumed {}, snapshot job {}", mint_address, job_id),
+        AddedToken::Started { job_id } => println!("Monitoring {}, snapshot job {}", mint_address, job_id),
+    }
+    Ok(())
+}
+
+// With workers running the snapshot is queued as a job for the mint's owner, so
+// its cached holder set stays in sync. Otherwise it is taken here as a checkpoint.
+pub async fn snapshot(config: &Config, mint_address: &str, timeout_secs: u64) -> Result<()> {
+    parse_mint(mint_address)?;
+    let store = ClickHouseStore::new(connect(config).await?, config.clickhouse.clone());
+    if store.token_status(mint_address).await?.is_none() {
+        return Err(anyhow::anyhow!("{} is not monitored, add it with `memespread add` first", mint_address));
+    }
+
+    if store.live_workers(config.workers.timeout_secs).await?.is_empty() {
+        let (rpc_client, rate_limiter) = connect_rpc(&config.rpc)?;
+        let cache = SnapshotCache::new(1);
+        update_token_metrics(&rpc_client, &rate_limiter, mint_address, &store, &cache).await?;
+    } else {
+        let job_id = request_refresh(&store, mint_address).await?;
+        tracing::info!("Waiting for snapshot job {}", job_id);
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(timeout_secs);
+        loop {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            let job = store.job(&job_id)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Snapshot job {} disappeared", job_id))?;
+            match job.status.as_str() {
+                "done" => break,
+                "failed" => {
+                    return Err(anyhow::anyhow!(
+                        "Snapshot job {} failed: {}",
+                        job_id,
+                        job.error.unwrap_or_default()
+                    ));
+                }
+                _ => {}
+            }
+            // The job stays queued, a worker may still pick it up later
+            if tokio::time::Instant::now() >= deadline {
+                return Err(anyhow::anyhow!(
+                    "Snapshot job {} still {} after {}s ({} of {} attempts{}), check GET /jobs/{}",
+                    job_id,
+                    job.status,
+                    timeout_secs,
+                    job.attempts,
+                    job.max_attempts,
+                    job.error.map(|e| format!(", last error: {}", e)).unwrap_or_default(),
+                    job_id
+                ));
+            }
+        }
+    }
+
+    let stats = store.token_metrics(mint_address).await?;
+    println!("{}", serde_json::to_string_pretty(&structure_token_stats(stats))?);
+    Ok(())
+}
+
+pub async fn recompute(config: &Config, mint_address: Option<&str>, from: Option<i64>, to: Option<i64>) -> Result<()> {
+    if let Some(mint_address) = mint_address {
+        parse_mint(mint_address)?;
+    }
+    let client = connect(config).await?;
+    let snapshots = recompute_metrics(
+        &client,
+        mint_address,
+        from.unwrap_or(0),
+        to.unwrap_or_else(|| Utc::now().timestamp()),
+    ).await?;
+    println!("Recomputed {} snapshots", snapshots);
+    Ok(())
+}
+
+// Created once there is something to write, so a failed export leaves no file behind
+fn open_output(path: Option<&Path>) -> Result<Box<dyn Write>> {
+    match path {
+        Some(path) => Ok(Box::new(BufWriter::new(File::create(path)
+            .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", path.display(), e))?))),
+        None => Ok(Box::new(std::io::stdout().lock())),
+    }
+}
+
+pub async fn export(config: &Config, args: ExportArgs) -> Result<()> {
+    let mint_address = args.mint_address.as_str();
+    parse_mint(mint_address)?;
+    if let Some(dataset) = args.dataset {
+        let to = args.to.unwrap_or_else(|| Utc::now().timestamp());
+        let store = ClickHouseStore::new(connect(config).await?, config.clickhouse.clone());
+        let stream = store.export(
+            dataset,
+            args.format.unwrap_or(ExportFormat::Csv),
+            mint_address,
+            args.from.unwrap_or(0),
+            to,
+        ).await?;
+        let mut out = open_output(args.output.as_deref())?;
+        futures::pin_mut!(stream);
+        while let Some(chunk) = stream.try_next().await? {
+            out.write_all(&chunk)?;
+        }
+        out.flush()?;
+        return Ok(());
+    }
+
+    let store = ClickHouseStore::new(connect(config).await?, config.clickhouse.clone());
+    let snapshot = store.find_snapshot(mint_address, args.at.unwrap_or_else(|| Utc::now().timestamp()))
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("No snapshot of {} found", mint_address))?;
+
+    let mut out = open_output(args.output.as_deref())?;
+    writeln!(out, "rank,holder_address,balance,usd_value,share_of_supply,excluded_category")?;
+    let mut after_rank = 0;
+    loop {
+        let page = store.holders(mint_address, &snapshot, after_rank, MAX_HOLDERS_PAGE, 0.0).await?;
+        for holder in &page.holders {
+            writeln!(
+                out,
+                "{},{},{},{},{},{}",
+                holder.rank,
+                holder.holder_address,
+                holder.balance,
+                holder.usd_value,
+                holder.share_of_supply,
+                holder.excluded_category.as_deref().unwrap_or_default()
+            )?;
+        }
+        match page.holders.last() {
+            Some(last) if page.next_cursor.is_some() => after_rank = last.rank,
+            _ => break,
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+// Decoding goes through the same code as RPC snapshots. Without --write the
+// metrics are computed in a ClickHouse session and nothing is stored.
+pub async fn replay(config: &Config, args: ReplayArgs) -> Result<()> {
+    let format = args.format
+        .or_else(|| DumpFormat::from_path(&args.file))
+        .ok_or_else(|| anyhow::anyhow!("Can't tell the format of {}, pass --format", args.file.display()))?;
+    if !(args.price.is_finite() && args.price > 0.0) {
+        return Err(anyhow::anyhow!("--price must be positive"));
+    }
+    let text = read_input(&args.file)?;
+    let accounts = parse_dump(&text, format).map_err(|e| anyhow::anyhow!(e))?;
+
+    let mint_pubkey = match &args.mint_address {
+        Some(mint_address) => {
+            parse_mint(mint_address)?;
+            Pubkey::from_str(mint_address)?
+        }
+        None => dump_mint(&accounts).map_err(|e| anyhow::anyhow!(e))?,
+    };
+    let mint_address = mint_pubkey.to_string();
+    let supply = args.supply.unwrap_or_else(|| dump_supply(&accounts, &mint_pubkey));
+    let holders = decode_holders(accounts, &mint_pubkey, 1);
+    tracing::info!("Decoded {} holders of {}", holders.len(), mint_address);
+
+    let at = args.at.unwrap_or_else(|| Utc::now().timestamp());
+    let timestamp = chrono::DateTime::from_timestamp(at, 0)
+        .ok_or_else(|| anyhow::anyhow!("Invalid timestamp {}", at))?
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+    let stats = snapshot_stats(args.price, supply, args.decimals);
+
+    if args.offline {
+        let excluded = match &args.exclusions {
+            Some(file) => parse_exclusions(&read_input(file)?).map_err(|e| anyhow::anyhow!(e))?,
+            None => Vec::new(),
+        };
+        let thresholds = match args.thresholds {
+            thresholds if thresholds.is_empty() => DEFAULT_THRESHOLDS.to_vec(),
+            thresholds => normalize_thresholds(thresholds).map_err(|e| anyhow::anyhow!(e))?,
+        };
+        let buckets = match args.buckets {
+            buckets if buckets.is_empty() => DEFAULT_BUCKETS.to_vec(),
+            buckets => normalize_buckets(buckets).map_err(|e| anyhow::anyhow!(e))?,
+        };
+        // The same write path as a stored snapshot, kept in memory
+        let store = MemoryStore::new(&excluded, thresholds, buckets);
+        write_snapshot_at(&store, &SnapshotCache::new(1), &mint_address, &holders, &stats, &timestamp).await?;
+        println!("{}", serde_json::to_string_pretty(&structure_token_stats(store.token_metrics(&mint_address).await?))?);
+        return Ok(());
+    }
+
+    let client = connect(config).await?;
+    if args.write {
+        let store = ClickHouseStore::new(client, config.clickhouse.clone());
+        // A worker's next delta would build on the replayed rows
+        if store.token_status(&mint_address).await?.is_some() {
+            return Err(anyhow::anyhow!("{} is monitored, replayed snapshots can only be stored for other tokens", mint_address));
+        }
+        let summary = write_snapshot_at(&store, &SnapshotCache::new(1), &mint_address, &holders, &stats, &timestamp).await?;
+        println!("Stored snapshot of {} at {}, {} holders", mint_address, summary.timestamp, summary.holders);
+    } else {
+        let metrics = replay_snapshot(&client, &mint_address, &holders, &stats, &timestamp).await?;
+        println!("{}", serde_json::to_string_pretty(&structure_token_stats(metrics))?);
+    }
+    Ok(())
+}
+
+pub async fn check_metrics(config: &Config, mint_address: &str, at: Option<i64>, tolerance: f64) -> Result<()> {
+    parse_mint(mint_address)?;
+    let client = connect(config).await?;
+    let snapshot = find_snapshot(&client, mint_address, at.unwrap_or_else(|| Utc::now().timestamp()))
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("No snapshot of {} found", mint_address))?;
+
+    let mismatches = compare_with_stored(&client, mint_address, &snapshot, tolerance).await?;
+    for mismatch in &mismatches {
+        println!("{}", mismatch);
+    }
+    if !mismatches.is_empty() {
+        return Err(anyhow::anyhow!("{} metrics of the snapshot at {} differ", mismatches.len(), snapshot.timestamp));
+    }
+    println!("Metrics of the snapshot at {} match", snapshot.timestamp);
+    Ok(())
+}
+
+fn read_input(file: &Path) -> Result<String> {
+    if file == Path::new("-") {
+        Ok(std::io::read_to_string(std::io::stdin())?)
+    } else {
+        std::fs::read_to_string(file)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", file.display(), e))
+    }
+}
+
+pub async fn import_exclusions(config: &Config, file: &Path) -> Result<()> {
+    let text = read_input(file)?;
+    let accounts = parse_exclusions(&text).map_err(|e| anyhow::anyhow!(e))?;
+
+    let store = ClickHouseStore::new(connect(config).await?, config.clickhouse.clone());
+    store.insert_excluded_accounts(&accounts).await?;
+    println!("Imported {} excluded accounts, run `memespread recompute` to apply them to stored snapshots", accounts.len());
+    Ok(())
+}
+
+// Live snapshots after the token was added are deltas on top of a checkpoint,
+// and imported rows inside that range would be counted into them, so only
+// older snapshots can be imported
+pub async fn import_snapshots(config: &Config, mint_address: &str, file: &Path, force: bool) -> Result<()> {
+    parse_mint(mint_address)?;
+    let snapshots = parse_snapshot_import(&read_input(file)?).map_err(|e| anyhow::anyhow!(e))?;
+    let (Some(first), Some(last)) = (snapshots.first(), snapshots.last()) else {
+        return Err(anyhow::anyhow!("{} holds no snapshots", file.display()));
+    };
+
+    let store = ClickHouseStore::new(connect(config).await?, config.clickhouse.clone());
+    let before = store.monitoring_start(mint_address).await?.unwrap_or_else(|| Utc::now().timestamp());
+    if last.timestamp >= before {
+        return Err(anyhow::anyhow!(
+            "Snapshots must be older than {}, when monitoring of {} started, the last one is at {}",
+            before, mint_address, last.timestamp
+        ));
+    }
+    // A snapshot is only whole while every table it is written to keeps it
+    let retention = &config.retention;
+    let shortest_ttl = [
+        ("token_holders", retention.token_holders_days),
+        ("token_holder_balances", retention.token_holder_balances_days),
+        ("token_stats", retention.token_stats_days),
+        ("token_thresholds", retention.token_thresholds_days),
+        ("token_holder_counts", retention.token_holder_counts_days),
+        ("token_concentration", retention.token_concentration_days),
+        ("token_distribution", retention.token_distribution_days),
+        ("token_label_holdings", retention.token_label_holdings_days),
+    ]
+    .into_iter()
+    .filter(|(_, days)| *days > 0)
+    .min_by_key(|(_, days)| *days);
+    if let Some((table, ttl_days)) = shortest_ttl {
+        if first.timestamp < Utc::now().timestamp() - ttl_days as i64 * 86400 {
+            let message = format!(
+                "Snapshots from before {} days ago are dropped by the {} TTL, the first one is at {}",
+                ttl_days, table, first.timestamp
+            );
+            if !force {
+                return Err(anyhow::anyhow!("{}. Raise the TTLs in [retention] or pass --force to import them anyway", message));
+            }
+            tracing::warn!("{}", message);
+        }
+    }
+
+    let rows = write_historical_snapshots(&store, mint_address, &snapshots).await?;
+    println!("Imported {} snapshots of {} from {} to {}, {} holder rows", snapshots.len(), mint_address, first.timestamp, last.timestamp, rows);
+    Ok(())
+}
+
+pub async fn migrate(config: &Config, dry_run: bool, action: Option<MigrateAction>) -> Result<()> {
+    let client = connect_to_clickhouse(&config.clickhouse).await?;
+
+    match action {
+        Some(MigrateAction::Status) => {
+            for m in migration_status(&client).await? {
+                println!(
+                    "{:>4}  {:<28} {}",
+                    m.version,
+                    m.name,
+                    m.applied_at.as_deref().unwrap_or("pending")
+                );
+            }
+        }
+        None => {
+            let applied = run_migrations(&client, dry_run).await?;
+            if dry_run {
+                println!("{} migrations pending", applied.len());
+            } else {
+                println!("Applied {} migrations", applied.len());
+            }
+        }
+    }
+
+    Ok(())
+}
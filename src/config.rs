@@ -0,0 +1,529 @@
+use std::path::Path;
+use std::str::FromStr;
+use anyhow::Result;
+use serde::Deserialize;
+
+// Settings of every service, read from a TOML file and overridden by
+// environment variables. Missing keys take the defaults below, unknown keys are
+// rejected so typos don't go unnoticed. See memespread.example.toml.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub api: ApiConfig,
+    pub frontend: FrontendConfig,
+    pub rpc: RpcConfig,
+    pub clickhouse: ClickHouseConfig,
+    pub monitor: MonitorConfig,
+    pub geyser: GeyserConfig,
+    pub jobs: JobsConfig,
+    pub workers: WorkersConfig,
+    pub exclusions: ExclusionsConfig,
+    pub launch: LaunchConfig,
+    pub archive: ArchiveConfig,
+    pub retention: RetentionConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiConfig {
+    pub port: u16,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self { port: 8000 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FrontendConfig {
+    pub port: u16,
+    // Where the dashboard and its form submissions reach the API
+    pub api_url: String,
+}
+
+impl Default for FrontendConfig {
+    fn default() -> Self {
+        Self {
+            port: 3000,
+            api_url: "http://localhost:8000".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcConfig {
+    // Any Solana RPC endpoint, takes precedence over helius_api_key
+    pub url: Option<String>,
+    pub helius_api_key: Option<String>,
+    pub requests_per_second: u32,
+    pub timeout_secs: u64,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            helius_api_key: None,
+            requests_per_second: 5,
+            timeout_secs: 60,
+        }
+    }
+}
+
+impl RpcConfig {
+    pub fn endpoint(&self) -> Result<String> {
+        match (&self.url, &self.helius_api_key) {
+            (Some(url), _) => Ok(url.clone()),
+            (None, Some(key)) => Ok(format!("https://rpc.helius.xyz/?api-key={}", key)),
+            (None, None) => Err(anyhow::anyhow!("Set rpc.url or rpc.helius_api_key (HELIUS_API_KEY)")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClickHouseConfig {
+    pub url: String,
+    pub user: String,
+    pub password: String,
+    pub database: String,
+    pub connect_retries: u32,
+    // When off, pending migrations have to be run with `memespread migrate` first
+    pub auto_migrate: bool,
+}
+
+impl Default for ClickHouseConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:8123".to_string(),
+            user: "default".to_string(),
+            password: String::new(),
+            database: "default".to_string(),
+            connect_retries: 5,
+            auto_migrate: true,
+        }
+    }
+}
+
+// Where holder snapshots come from: per-token getProgramAccounts polling, or a
+// single Yellowstone gRPC subscription covering every monitored mint
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IngestMode {
+    Rpc,
+    Geyser,
+}
+
+impl FromStr for IngestMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rpc" => Ok(IngestMode::Rpc),
+            "geyser" => Ok(IngestMode::Geyser),
+            other => Err(format!("unknown mode {}, expected rpc or geyser", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MonitorConfig {
+    pub ingest_mode: IngestMode,
+    // Tokens not snapshotted for this long are queued as `stats` jobs, also the
+    // interval of Geyser snapshots
+    pub stats_interval_secs: u64,
+    // Tokens not snapshotted for this long are queued as `metrics` jobs
+    pub metrics_interval_secs: u64,
+    // Every Nth snapshot of a mint is a full checkpoint, the others only hold changes
+    pub checkpoint_every: u32,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self {
+            ingest_mode: IngestMode::Rpc,
+            stats_interval_secs: 60,
+            metrics_interval_secs: 4 * 60 * 60,
+            checkpoint_every: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeyserConfig {
+    pub endpoint: Option<String>,
+    pub x_token: Option<String>,
+    // Token account closures can't be observed through the account filters,
+    // so every mint is re-seeded from RPC on this interval
+    pub resync_secs: u64,
+}
+
+impl Default for GeyserConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            x_token: None,
+            resync_secs: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobsConfig {
+    // Jobs run at once by one worker, the RPC rate limit is shared anyway
+    pub concurrency: usize,
+    pub poll_interval_secs: u64,
+    // Renewed while the job runs, so it only expires when the worker is gone
+    pub lease_secs: u64,
+    // Claimable jobs read per poll before keeping the ones in this worker's shard
+    pub claim_scan_limit: u64,
+    // Failed jobs are retried after this, doubling up to max_backoff_secs
+    pub base_backoff_secs: u64,
+    pub max_backoff_secs: u64,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 2,
+            poll_interval_secs: 2,
+            lease_secs: 120,
+            claim_scan_limit: 500,
+            base_backoff_secs: 30,
+            max_backoff_secs: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkersConfig {
+    // Identifies this process in heartbeats, claims and leases. Defaults to the
+    // hostname and process id, so it has to be unique when set.
+    pub id: String,
+    pub heartbeat_secs: u64,
+    // Workers that haven't sent a heartbeat for this long are dropped from the ring
+    pub timeout_secs: u64,
+}
+
+impl Default for WorkersConfig {
+    fn default() -> Self {
+        let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "worker".to_string());
+        Self {
+            id: format!("{}-{}", host, std::process::id()),
+            heartbeat_secs: 10,
+            timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExclusionsConfig {
+    pub update_interval_secs: u64,
+    // Largest holders checked for program ownership, across all tokens and for each new token
+    pub top_holders: u64,
+    // Accounts per getMultipleAccounts call
+    pub batch_size: usize,
+}
+
+impl Default for ExclusionsConfig {
+    fn default() -> Self {
+        Self {
+            update_interval_secs: 24 * 60 * 60,
+            top_holders: 300,
+            batch_size: 25,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LaunchConfig {
+    pub check_interval_secs: u64,
+    // Buys landing within this many slots of the creation count as sniped
+    pub slot_window: u64,
+}
+
+impl Default for LaunchConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_secs: 10 * 60,
+            slot_window: 5,
+        }
+    }
+}
+
+// Floors below which active tokens are archived. Unset floors are not checked,
+// and with neither set auto-archival is off.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArchiveConfig {
+    pub min_market_cap: Option<f64>,
+    // Wallets that started holding over the window, from token_holder_spells
+    pub min_new_holders: Option<u64>,
+    // Also the grace period before a newly added token can be archived
+    pub window_hours: u32,
+    pub check_interval_secs: u64,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            min_market_cap: None,
+            min_new_holders: None,
+            window_hours: 24,
+            check_interval_secs: 60 * 60,
+        }
+    }
+}
+
+impl ArchiveConfig {
+    pub fn enabled(&self) -> bool {
+        self.min_market_cap.is_some() || self.min_new_holders.is_some()
+    }
+}
+
+// TTLs in days, 0 keeps rows forever. The holder TTLs have to be at least the
+// stats TTL and longer than the checkpoint interval, otherwise stored snapshots
+// and the current holder set can't be rebuilt.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    pub token_holders_days: u32,
+    pub token_holder_balances_days: u32,
+    pub token_stats_days: u32,
+    pub token_thresholds_days: u32,
+    pub token_holder_counts_days: u32,
+    pub token_concentration_days: u32,
+    pub token_distribution_days: u32,
+    pub token_label_holdings_days: u32,
+    pub hourly_rollup_days: u32,
+    pub daily_rollup_days: u32,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            token_holders_days: 90,
+            token_holder_balances_days: 90,
+            token_stats_days: 90,
+            token_thresholds_days: 90,
+            token_holder_counts_days: 90,
+            token_concentration_days: 90,
+            token_distribution_days: 90,
+            token_label_holdings_days: 90,
+            hourly_rollup_days: 365,
+            daily_rollup_days: 0,
+        }
+    }
+}
+
+fn env_override<T: FromStr>(value: &mut T, var: &str) -> Result<()>
+where
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(var) {
+        Ok(v) if !v.is_empty() => {
+            *value = v.parse::<T>().map_err(|e| anyhow::anyhow!("Invalid {} {}: {}", var, v, e))?;
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn env_override_opt<T: FromStr>(value: &mut Option<T>, var: &str) -> Result<()>
+where
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(var) {
+        Ok(v) if !v.is_empty() => {
+            *value = Some(v.parse::<T>().map_err(|e| anyhow::anyhow!("Invalid {} {}: {}", var, v, e))?);
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn ensure(ok: bool, message: &str) -> Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Invalid config: {}", message))
+    }
+}
+
+impl Config {
+    // Reads `path`, or memespread.toml in the working directory if it exists,
+    // then applies the environment overrides and validates the result
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let default_path = Path::new("memespread.toml");
+        let path = match path {
+            Some(path) => Some(path),
+            None if default_path.exists() => Some(default_path),
+            None => None,
+        };
+
+        let mut config = match path {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| anyhow::anyhow!("Failed to read config {}: {}", path.display(), e))?;
+                toml::from_str(&text)
+                    .map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))?
+            }
+            None => Config::default(),
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_env(&mut self) -> Result<()> {
+        env_override(&mut self.api.port, "API_PORT")?;
+        env_override(&mut self.frontend.port, "FRONTEND_PORT")?;
+        env_override(&mut self.frontend.api_url, "FRONTEND_API_URL")?;
+
+        env_override_opt(&mut self.rpc.url, "RPC_URL")?;
+        env_override_opt(&mut self.rpc.helius_api_key, "HELIUS_API_KEY")?;
+        env_override(&mut self.rpc.requests_per_second, "RPC_REQUESTS_PER_SECOND")?;
+        env_override(&mut self.rpc.timeout_secs, "RPC_TIMEOUT_SECS")?;
+
+        env_override(&mut self.clickhouse.url, "CLICKHOUSE_URL")?;
+        env_override(&mut self.clickhouse.user, "CLICKHOUSE_USER")?;
+        env_override(&mut self.clickhouse.password, "CLICKHOUSE_PASSWORD")?;
+        env_override(&mut self.clickhouse.database, "CLICKHOUSE_DATABASE")?;
+        env_override(&mut self.clickhouse.connect_retries, "CLICKHOUSE_CONNECT_RETRIES")?;
+        env_override(&mut self.clickhouse.auto_migrate, "AUTO_MIGRATE")?;
+
+        env_override(&mut self.monitor.ingest_mode, "INGEST_MODE")?;
+        env_override(&mut self.monitor.stats_interval_secs, "MONITOR_STATS_INTERVAL_SECS")?;
+        env_override(&mut self.monitor.metrics_interval_secs, "MONITOR_METRICS_INTERVAL_SECS")?;
+        env_override(&mut self.monitor.checkpoint_every, "SNAPSHOT_CHECKPOINT_EVERY")?;
+
+        env_override_opt(&mut self.geyser.endpoint, "GEYSER_ENDPOINT")?;
+        env_override_opt(&mut self.geyser.x_token, "GEYSER_X_TOKEN")?;
+        env_override(&mut self.geyser.resync_secs, "GEYSER_RESYNC_SECS")?;
+
+        env_override(&mut self.jobs.concurrency, "JOBS_CONCURRENCY")?;
+        env_override(&mut self.jobs.poll_interval_secs, "JOBS_POLL_INTERVAL_SECS")?;
+        env_override(&mut self.jobs.lease_secs, "JOBS_LEASE_SECS")?;
+        env_override(&mut self.jobs.claim_scan_limit, "JOBS_CLAIM_SCAN_LIMIT")?;
+        env_override(&mut self.jobs.base_backoff_secs, "JOBS_BASE_BACKOFF_SECS")?;
+        env_override(&mut self.jobs.max_backoff_secs, "JOBS_MAX_BACKOFF_SECS")?;
+
+        env_override(&mut self.workers.id, "WORKER_ID")?;
+        env_override(&mut self.workers.heartbeat_secs, "WORKER_HEARTBEAT_SECS")?;
+        env_override(&mut self.workers.timeout_secs, "WORKER_TIMEOUT_SECS")?;
+
+        env_override(&mut self.exclusions.update_interval_secs, "EXCLUSIONS_UPDATE_INTERVAL_SECS")?;
+        env_override(&mut self.exclusions.top_holders, "EXCLUSIONS_TOP_HOLDERS")?;
+        env_override(&mut self.exclusions.batch_size, "EXCLUSIONS_BATCH_SIZE")?;
+
+        env_override(&mut self.launch.check_interval_secs, "LAUNCH_CHECK_INTERVAL_SECS")?;
+        env_override(&mut self.launch.slot_window, "LAUNCH_SLOT_WINDOW")?;
+
+        env_override_opt(&mut self.archive.min_market_cap, "ARCHIVE_MIN_MARKET_CAP")?;
+        env_override_opt(&mut self.archive.min_new_holders, "ARCHIVE_MIN_NEW_HOLDERS")?;
+        env_override(&mut self.archive.window_hours, "ARCHIVE_WINDOW_HOURS")?;
+        env_override(&mut self.archive.check_interval_secs, "ARCHIVE_CHECK_INTERVAL_SECS")?;
+
+        let retention = &mut self.retention;
+        env_override(&mut retention.token_holders_days, "TTL_TOKEN_HOLDERS_DAYS")?;
+        env_override(&mut retention.token_holder_balances_days, "TTL_TOKEN_HOLDER_BALANCES_DAYS")?;
+        env_override(&mut retention.token_stats_days, "TTL_TOKEN_STATS_DAYS")?;
+        env_override(&mut retention.token_thresholds_days, "TTL_TOKEN_THRESHOLDS_DAYS")?;
+        env_override(&mut retention.token_holder_counts_days, "TTL_TOKEN_HOLDER_COUNTS_DAYS")?;
+        env_override(&mut retention.token_concentration_days, "TTL_TOKEN_CONCENTRATION_DAYS")?;
+        env_override(&mut retention.token_distribution_days, "TTL_TOKEN_DISTRIBUTION_DAYS")?;
+        env_override(&mut retention.token_label_holdings_days, "TTL_TOKEN_LABEL_HOLDINGS_DAYS")?;
+        env_override(&mut retention.hourly_rollup_days, "TTL_HOURLY_ROLLUP_DAYS")?;
+        env_override(&mut retention.daily_rollup_days, "TTL_DAILY_ROLLUP_DAYS")?;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure(self.api.port != 0, "api.port must be set")?;
+        ensure(self.frontend.port != 0, "frontend.port must be set")?;
+        ensure(self.api.port != self.frontend.port, "api.port and frontend.port must differ")?;
+        ensure(
+            self.frontend.api_url.starts_with("http://") || self.frontend.api_url.starts_with("https://"),
+            "frontend.api_url must be an http(s) URL",
+        )?;
+
+        ensure(self.rpc.requests_per_second > 0, "rpc.requests_per_second must be at least 1")?;
+        ensure(self.rpc.timeout_secs > 0, "rpc.timeout_secs must be at least 1")?;
+        ensure(!self.clickhouse.database.is_empty(), "clickhouse.database must be set")?;
+        ensure(self.clickhouse.connect_retries > 0, "clickhouse.connect_retries must be at least 1")?;
+
+        ensure(self.monitor.stats_interval_secs > 0, "monitor.stats_interval_secs must be at least 1")?;
+        ensure(self.monitor.metrics_interval_secs > 0, "monitor.metrics_interval_secs must be at least 1")?;
+        ensure(self.monitor.checkpoint_every > 0, "monitor.checkpoint_every must be at least 1")?;
+        if self.monitor.ingest_mode == IngestMode::Geyser {
+            ensure(self.geyser.endpoint.is_some(), "geyser.endpoint (GEYSER_ENDPOINT) must be set when ingest_mode is geyser")?;
+        }
+        ensure(self.geyser.resync_secs > 0, "geyser.resync_secs must be at least 1")?;
+
+        ensure(self.jobs.concurrency > 0, "jobs.concurrency must be at least 1")?;
+        ensure(self.jobs.poll_interval_secs > 0, "jobs.poll_interval_secs must be at least 1")?;
+        ensure(self.jobs.lease_secs >= 3, "jobs.lease_secs must be at least 3")?;
+        ensure(
+            self.jobs.claim_scan_limit >= self.jobs.concurrency as u64,
+            "jobs.claim_scan_limit must be at least jobs.concurrency",
+        )?;
+        ensure(
+            self.jobs.base_backoff_secs <= self.jobs.max_backoff_secs,
+            "jobs.base_backoff_secs must not exceed jobs.max_backoff_secs",
+        )?;
+
+        ensure(!self.workers.id.is_empty(), "workers.id must not be empty")?;
+        ensure(self.workers.heartbeat_secs > 0, "workers.heartbeat_secs must be at least 1")?;
+        ensure(
+            self.workers.timeout_secs >= 2 * self.workers.heartbeat_secs,
+            "workers.timeout_secs must be at least twice workers.heartbeat_secs",
+        )?;
+
+        ensure(self.exclusions.update_interval_secs > 0, "exclusions.update_interval_secs must be at least 1")?;
+        ensure(self.exclusions.batch_size > 0, "exclusions.batch_size must be at least 1")?;
+        ensure(self.exclusions.batch_size <= 100, "exclusions.batch_size must be at most 100")?;
+
+        ensure(self.launch.check_interval_secs > 0, "launch.check_interval_secs must be at least 1")?;
+        ensure(self.archive.window_hours > 0, "archive.window_hours must be at least 1")?;
+        ensure(self.archive.check_interval_secs > 0, "archive.check_interval_secs must be at least 1")?;
+
+        // Every stored snapshot is rebuilt from the holder rows since its
+        // checkpoint, so those have to outlive both the snapshot and a checkpoint interval
+        let retention = &self.retention;
+        let checkpoint_secs = self.monitor.checkpoint_every as u64 * self.monitor.stats_interval_secs;
+        for (key, days) in [
+            ("token_holders_days", retention.token_holders_days),
+            ("token_holder_balances_days", retention.token_holder_balances_days),
+        ] {
+            ensure(
+                ttl_secs(days) >= ttl_secs(retention.token_stats_days),
+                &format!("retention.{} must be at least retention.token_stats_days (0 keeps rows forever)", key),
+            )?;
+            ensure(
+                ttl_secs(days) > checkpoint_secs,
+                &format!(
+                    "retention.{} must be longer than the checkpoint interval of {}s (monitor.checkpoint_every * monitor.stats_interval_secs)",
+                    key, checkpoint_secs
+                ),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// A TTL in seconds, 0 days being forever
+fn ttl_secs(days: u32) -> u64 {
+    match days {
+        0 => u64::MAX,
+        days => days as u64 * 24 * 60 * 60,
+    }
+}
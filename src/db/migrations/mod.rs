@@ -0,0 +1,495 @@
+use std::time::Duration;
+use anyhow::Result;
+use clickhouse::Client;
+use crate::db::schema::{SCHEMA_MIGRATIONS_SQL, SCHEMA_MIGRATION_CLAIMS_SQL};
+
+mod v01_baseline;
+mod v02_delta_snapshots;
+mod v03_partitions_and_rollups;
+mod v04_configurable_thresholds;
+mod v05_configurable_concentration;
+mod v06_distribution_statistics;
+mod v07_holder_address_index;
+mod v08_wallet_labels;
+mod v09_launch_analysis;
+mod v10_holder_age;
+mod v11_token_status;
+mod v12_snapshot_jobs;
+mod v13_job_queue;
+mod v14_worker_heartbeats;
+mod v15_exclusive_job_claims;
+mod v16_holder_balances_address_index;
+mod v17_token_holders_account_key;
+mod v18_holder_spells;
+
+// One unit of a migration. Every step has to be safe to re-run, since a
+// migration is only recorded once all of its steps went through.
+#[derive(Debug, Clone)]
+pub enum Step {
+    Sql(String),
+    // Recreate a table from the given definition and copy the rows over, for
+    // sorting/partition key or key column type changes that ALTER can't make.
+    // Skipped when the keys already match.
+    RebuildTable { table: String, create_sql: String },
+    // Drop and recreate a materialized view from the given definition
+    RecreateView { name: String, create_sql: String },
+    // Replay a view's SELECT over the existing source rows into its target table
+    Backfill { target: String, view_sql: String },
+}
+
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub steps: fn() -> Vec<Step>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub version: u32,
+    pub name: &'static str,
+    pub applied_at: Option<String>,
+}
+
+// Ordered by version, append only. Each migration lives in its own module with
+// the SQL as it was when the migration was written, so it creates the same
+// schema on every database no matter how far behind it is. Never edit an applied
+// migration or build one from db::schema, add a new one recreating what changed.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, name: "baseline", steps: v01_baseline::steps },
+    Migration { version: 2, name: "delta_snapshots", steps: v02_delta_snapshots::steps },
+    Migration { version: 3, name: "partitions_and_rollups", steps: v03_partitions_and_rollups::steps },
+    Migration { version: 4, name: "configurable_thresholds", steps: v04_configurable_thresholds::steps },
+    Migration { version: 5, name: "configurable_concentration", steps: v05_configurable_concentration::steps },
+    Migration { version: 6, name: "distribution_statistics", steps: v06_distribution_statistics::steps },
+    Migration { version: 7, name: "holder_address_index", steps: v07_holder_address_index::steps },
+    Migration { version: 8, name: "wallet_labels", steps: v08_wallet_labels::steps },
+    Migration { version: 9, name: "launch_analysis", steps: v09_launch_analysis::steps },
+    Migration { version: 10, name: "holder_age", steps: v10_holder_age::steps },
+    Migration { version: 11, name: "token_status", steps: v11_token_status::steps },
+    Migration { version: 12, name: "snapshot_jobs", steps: v12_snapshot_jobs::steps },
+    Migration { version: 13, name: "job_queue", steps: v13_job_queue::steps },
+    Migration { version: 14, name: "worker_heartbeats", steps: v14_worker_heartbeats::steps },
+    Migration { version: 15, name: "exclusive_job_claims", steps: v15_exclusive_job_claims::steps },
+    Migration { version: 16, name: "holder_balances_address_index", steps: v16_holder_balances_address_index::steps },
+    Migration { version: 17, name: "token_holders_account_key", steps: v17_token_holders_account_key::steps },
+    Migration { version: 18, name: "holder_spells", steps: v18_holder_spells::steps },
+];
+
+// From this version on token_holders keeps every token account of an owner
+pub const TOKEN_ACCOUNT_KEY_VERSION: u32 = 17;
+
+impl Step {
+    // SQL shown by `migrate --dry-run`
+    pub fn describe(&self) -> String {
+        match self {
+            Step::Sql(sql) => sql.trim().to_string(),
+            Step::RebuildTable { table, create_sql } => format!(
+                "-- rebuild {} if its sorting or partition key or their column types changed\n{}",
+                table,
+                create_sql.trim()
+            ),
+            Step::RecreateView { name, create_sql } => format!(
+                "DROP VIEW IF EXISTS {}\n{}",
+                name,
+                create_sql.trim()
+            ),
+            Step::Backfill { target, view_sql } => format!(
+                "INSERT INTO {} {}",
+                target,
+                view_select(view_sql).trim()
+            ),
+        }
+    }
+
+    async fn apply(&self, client: &Client) -> Result<()> {
+        match self {
+            Step::Sql(sql) => client.query(sql).execute().await?,
+            Step::RebuildTable { table, create_sql } => rebuild_table(client, table, create_sql).await?,
+            Step::RecreateView { name, create_sql } => {
+                client.query(&format!("DROP VIEW IF EXISTS {}", name)).execute().await?;
+                client.query(create_sql).execute().await?;
+            }
+            Step::Backfill { target, view_sql } => {
+                client
+                    .query(&format!("INSERT INTO {} {}", target, view_select(view_sql)))
+                    .execute()
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The SELECT part of a `CREATE MATERIALIZED VIEW ... TO ... AS SELECT` statement
+pub fn view_select(view_sql: &str) -> &str {
+    match view_sql.find("\nAS") {
+        Some(idx) => &view_sql[idx + 3..],
+        None => view_sql,
+    }
+}
+
+// Sorting key, partition key and the types of the columns in them
+async fn table_keys(client: &Client, table: &str) -> Result<Option<(String, String, Vec<(String, String)>)>> {
+    let keys: Option<(String, String)> = client
+        .query("SELECT sorting_key, partition_key FROM system.tables WHERE database = currentDatabase() AND name = ?")
+        .bind(table)
+        .fetch_optional()
+        .await?;
+    let Some((sorting_key, partition_key)) = keys else {
+        return Ok(None);
+    };
+
+    let key_columns: Vec<(String, String)> = client
+        .query("
+            SELECT name, type FROM system.columns
+            WHERE database = currentDatabase() AND table = ?
+                AND (is_in_sorting_key OR is_in_partition_key)
+            ORDER BY name
+        ")
+        .bind(table)
+        .fetch_all()
+        .await?;
+    Ok(Some((sorting_key, partition_key, key_columns)))
+}
+
+async fn rebuild_table(client: &Client, table: &str, create_sql: &str) -> Result<()> {
+    let rebuild = format!("{}__rebuild", table);
+    let rebuild_sql = create_sql.replacen(
+        &format!("IF NOT EXISTS {} (", table),
+        &format!("IF NOT EXISTS {} (", rebuild),
+        1,
+    );
+    if rebuild_sql == create_sql {
+        return Err(anyhow::anyhow!("Can't derive rebuild table from the definition of {}", table));
+    }
+
+    // Leftovers of an interrupted rebuild are either partial copies or the old
+    // table after the exchange went through, both safe to drop
+    client.query(&format!("DROP TABLE IF EXISTS {}", rebuild)).execute().await?;
+    client.query(&rebuild_sql).execute().await?;
+
+    let current = table_keys(client, table).await?;
+    let wanted = table_keys(client, &rebuild).await?;
+    if current.is_none() || current == wanted {
+        client.query(&format!("DROP TABLE IF EXISTS {}", rebuild)).execute().await?;
+        if current.is_none() {
+            client.query(create_sql).execute().await?;
+        }
+        tracing::info!("Table {} already has the wanted keys, skipping rebuild", table);
+        return Ok(());
+    }
+
+    let columns: Vec<String> = client
+        .query("
+            SELECT name FROM system.columns
+            WHERE database = currentDatabase() AND table = ?
+                AND name IN (SELECT name FROM system.columns WHERE database = currentDatabase() AND table = ?)
+            ORDER BY position
+        ")
+        .bind(&rebuild)
+        .bind(table)
+        .fetch_all()
+        .await?;
+    let columns = columns.join(", ");
+
+    tracing::info!("Rebuilding {} with new keys, copying rows...", table);
+    client
+        .query(&format!("INSERT INTO {} ({}) SELECT {} FROM {}", rebuild, columns, columns, table))
+        .execute()
+        .await?;
+    client.query(&format!("EXCHANGE TABLES {} AND {}", table, rebuild)).execute().await?;
+    client.query(&format!("DROP TABLE {}", rebuild)).execute().await?;
+    tracing::info!("Rebuilt {}", table);
+
+    Ok(())
+}
+
+async fn applied_migrations(client: &Client) -> Result<Vec<(u32, String)>> {
+    client.query(SCHEMA_MIGRATIONS_SQL).execute().await?;
+    let applied: Vec<(u32, String)> = client
+        .query("SELECT version, toString(max(applied_at)) FROM schema_migrations GROUP BY version ORDER BY version")
+        .fetch_all()
+        .await?;
+    Ok(applied)
+}
+
+// When a migration was applied, as unix seconds
+pub async fn applied_at(client: &Client, version: u32) -> Result<Option<i64>> {
+    let applied = applied_migrations(client).await?;
+    let applied_at = applied.into_iter().find(|(applied, _)| *applied == version).map(|(_, applied_at)| applied_at);
+    match applied_at {
+        Some(applied_at) => {
+            let at = chrono::NaiveDateTime::parse_from_str(&applied_at, "%Y-%m-%d %H:%M:%S")?;
+            Ok(Some(at.and_utc().timestamp()))
+        }
+        None => Ok(None),
+    }
+}
+
+pub async fn migration_status(client: &Client) -> Result<Vec<MigrationStatus>> {
+    let applied = applied_migrations(client).await?;
+    Ok(MIGRATIONS.iter()
+        .map(|m| MigrationStatus {
+            version: m.version,
+            name: m.name,
+            applied_at: applied.iter()
+                .find(|(version, _)| *version == m.version)
+                .map(|(_, applied_at)| applied_at.clone()),
+        })
+        .collect())
+}
+
+pub async fn pending_migrations(client: &Client) -> Result<Vec<&'static Migration>> {
+    let applied = applied_migrations(client).await?;
+    Ok(MIGRATIONS.iter()
+        .filter(|m| !applied.iter().any(|(version, _)| *version == m.version))
+        .collect())
+}
+
+// A claim left by a process that died while applying a migration keeps the
+// others waiting this long before they take the migration over
+const MIGRATION_CLAIM_TIMEOUT_SECS: i64 = 6 * 3600;
+const MIGRATION_POLL_SECS: u64 = 2;
+
+async fn is_applied(client: &Client, version: u32) -> Result<bool> {
+    let applied: u64 = client
+        .query("SELECT count() FROM schema_migrations WHERE version = ?")
+        .bind(version)
+        .fetch_one()
+        .await?;
+    Ok(applied > 0)
+}
+
+// Latest attempt at applying a migration as (attempt, owner, released, seconds
+// since it was claimed)
+async fn latest_claim(client: &Client, version: u32) -> Result<Option<(u32, String, u8, i64)>> {
+    let claim = client
+        .query("
+            SELECT
+                attempt,
+                argMinIf(owner, claimed_at, released = 0) AS owner,
+                max(released) AS released,
+                toInt64(dateDiff('second', minIf(claimed_at, released = 0), now64(6, 'UTC'))) AS age_secs
+            FROM schema_migration_claims
+            WHERE version = ?
+            GROUP BY attempt
+            ORDER BY attempt DESC
+            LIMIT 1
+        ")
+        .bind(version)
+        .fetch_optional()
+        .await?;
+    Ok(claim)
+}
+
+// Waits until this process holds the migration, returning the attempt, or until
+// another process applied it. Claims of an attempt share a deduplication token,
+// so ClickHouse stores the first one and the stored row names the only process
+// that may apply the migration. The others wait for it to be recorded, and
+// claim the next attempt once the owner released it after a failure or its
+// claim timed out.
+async fn claim_migration(client: &Client, version: u32, owner: &str) -> Result<Option<u32>> {
+    let mut waiting = false;
+    loop {
+        if is_applied(client, version).await? {
+            return Ok(None);
+        }
+        let attempt = match latest_claim(client, version).await? {
+            None => 1,
+            Some((attempt, _, released, age_secs)) if released > 0 || age_secs > MIGRATION_CLAIM_TIMEOUT_SECS => attempt + 1,
+            Some((_, claim_owner, ..)) => {
+                if !waiting {
+                    tracing::info!("Waiting for {} to apply migration {}", claim_owner, version);
+                    waiting = true;
+                }
+                tokio::time::sleep(Duration::from_secs(MIGRATION_POLL_SECS)).await;
+                continue;
+            }
+        };
+
+        client
+            .clone()
+            .with_option("insert_deduplicate", "1")
+            .with_option("insert_deduplication_token", format!("migration:{}:{}", version, attempt))
+            .query("INSERT INTO schema_migration_claims (version, attempt, owner) VALUES (?, ?, ?)")
+            .bind(version)
+            .bind(attempt)
+            .bind(owner)
+            .execute()
+            .await?;
+        let claim_owner: String = client
+            .query("
+                SELECT argMin(owner, claimed_at)
+                FROM schema_migration_claims
+                WHERE version = ? AND attempt = ? AND released = 0
+            ")
+            .bind(version)
+            .bind(attempt)
+            .fetch_one()
+            .await?;
+        if claim_owner == owner {
+            // Another process may have applied it between the check and the claim
+            if is_applied(client, version).await? {
+                release_migration(client, version, attempt, owner).await?;
+                return Ok(None);
+            }
+            return Ok(Some(attempt));
+        }
+    }
+}
+
+async fn release_migration(client: &Client, version: u32, attempt: u32, owner: &str) -> Result<()> {
+    client
+        .query("INSERT INTO schema_migration_claims (version, attempt, owner, released) VALUES (?, ?, ?, 1)")
+        .bind(version)
+        .bind(attempt)
+        .bind(owner)
+        .execute()
+        .await?;
+    Ok(())
+}
+
+// Applies every pending migration in order and returns the versions applied.
+// With dry_run the statements are only logged. Processes starting together
+// each claim a migration before applying it, so only one of them runs it while
+// the others wait for it to be recorded, see claim_migration.
+pub async fn run_migrations(client: &Client, dry_run: bool) -> Result<Vec<u32>> {
+    let pending = pending_migrations(client).await?;
+    if pending.is_empty() {
+        tracing::info!("Schema is up to date");
+    }
+    if !dry_run && !pending.is_empty() {
+        client.query(SCHEMA_MIGRATION_CLAIMS_SQL).execute().await?;
+    }
+    let owner = format!("{}:{}", std::process::id(), chrono::Utc::now().timestamp_micros());
+
+    let mut applied = Vec::new();
+    for migration in pending {
+        let steps = (migration.steps)();
+        if dry_run {
+            tracing::info!("Would apply migration {} ({}) in {} steps", migration.version, migration.name, steps.len());
+            for step in &steps {
+                tracing::info!("{}", step.describe());
+            }
+            applied.push(migration.version);
+            continue;
+        }
+
+        let Some(attempt) = claim_migration(client, migration.version, &owner).await? else {
+            tracing::info!("Migration {} ({}) was applied by another process", migration.version, migration.name);
+            continue;
+        };
+        tracing::info!("Applying migration {} ({})...", migration.version, migration.name);
+        for step in &steps {
+            if let Err(e) = step.apply(client).await {
+                tracing::error!("Migration {} failed. Error: {}", migration.version, e);
+                tracing::error!("Failed step: {}", step.describe());
+                if let Err(release_error) = release_migration(client, migration.version, attempt, &owner).await {
+                    tracing::error!("Failed to release migration {}: {}", migration.version, release_error);
+                }
+                return Err(e);
+            }
+        }
+        client
+            .query("INSERT INTO schema_migrations (version, name) VALUES (?, ?)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .execute()
+            .await?;
+        tracing::info!("Applied migration {} ({})", migration.version, migration.name);
+        applied.push(migration.version);
+    }
+
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+    use crate::db::schema::{TOKEN_HOLDER_BALANCES_MV_SQL, TOKEN_HOLDER_SPELLS_SQL};
+    use crate::services::recompute::METRIC_VIEWS;
+
+    // Definition of every view after applying all migrations in order
+    fn migrated_views() -> HashMap<String, String> {
+        let mut views = HashMap::new();
+        for migration in MIGRATIONS {
+            for step in (migration.steps)() {
+                match step {
+                    Step::RecreateView { name, create_sql } => {
+                        views.insert(name, create_sql);
+                    }
+                    Step::Sql(sql) => {
+                        if let Some(rest) = sql.trim().strip_prefix("CREATE MATERIALIZED VIEW IF NOT EXISTS ") {
+                            let name = rest.split_whitespace().next().unwrap().to_string();
+                            views.insert(name, sql);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        views
+    }
+
+    // Key columns of the last definition of a table
+    fn migrated_table_key(table: &str) -> Vec<String> {
+        let create = format!("CREATE TABLE IF NOT EXISTS {} (", table);
+        let create_sql = MIGRATIONS
+            .iter()
+            .flat_map(|migration| (migration.steps)())
+            .filter_map(|step| match step {
+                Step::RebuildTable { create_sql, .. } | Step::Sql(create_sql) if create_sql.contains(&create) => Some(create_sql),
+                _ => None,
+            })
+            .next_back()
+            .unwrap();
+        let key = create_sql.split("PRIMARY KEY (").nth(1).unwrap().split(')').next().unwrap();
+        key.split(',').map(|column| column.trim().to_string()).collect()
+    }
+
+    // An owner with two token accounts in one snapshot keeps both rows once
+    // ReplacingMergeTree collapses rows with equal keys
+    #[test]
+    fn token_holders_key_keeps_every_token_account() {
+        let key = migrated_table_key("token_holders");
+        let rows = [
+            [("mint_address", "mint"), ("token_account", "account-1"), ("holder_address", "owner"), ("amount", "300"), ("timestamp", "1718000000")],
+            [("mint_address", "mint"), ("token_account", "account-2"), ("holder_address", "owner"), ("amount", "100"), ("timestamp", "1718000000")],
+        ];
+        let mut merged: HashMap<Vec<&str>, u64> = HashMap::new();
+        for row in &rows {
+            let value = |column: &str| row.iter().find(|(name, _)| *name == column).unwrap().1;
+            let row_key = key.iter().map(|column| value(column)).collect();
+            merged.insert(row_key, value("amount").parse().unwrap());
+        }
+        assert_eq!(merged.values().sum::<u64>(), 400);
+    }
+
+    #[test]
+    fn versions_are_sequential() {
+        for (idx, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, idx + 1, "{}", migration.name);
+        }
+    }
+
+    #[test]
+    fn replayed_views_match_migrated_views() {
+        let views = migrated_views();
+        let mut replayed = vec![("token_holder_balances_mv".to_string(), TOKEN_HOLDER_BALANCES_MV_SQL)];
+        replayed.extend(METRIC_VIEWS.iter().map(|(table, sql)| (format!("{}_mv", table), *sql)));
+        for (name, sql) in replayed {
+            assert_eq!(views.get(&name).map(String::as_str), Some(sql), "{} differs from its latest migration", name);
+        }
+    }
+
+    #[test]
+    fn replayed_spells_match_the_backfill() {
+        let backfill = format!("INSERT INTO token_holder_spells {}", TOKEN_HOLDER_SPELLS_SQL.trim());
+        let backfilled = MIGRATIONS
+            .iter()
+            .flat_map(|migration| (migration.steps)())
+            .any(|step| matches!(step, Step::Sql(sql) if sql == backfill));
+        assert!(backfilled, "TOKEN_HOLDER_SPELLS_SQL differs from the holder_spells backfill");
+    }
+}
@@ -0,0 +1,185 @@
+use super::Step;
+use super::v01_baseline::TOKEN_HOLDER_BALANCES_MV_SQL;
+
+const TOKEN_HOLDER_BALANCES_TABLE_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS token_holder_balances (
+    mint_address String,
+    holder_address String,
+    balance Float64,
+    timestamp DateTime('UTC'),
+    PRIMARY KEY (mint_address, holder_address, timestamp)
+) ENGINE = ReplacingMergeTree
+"#;
+
+const TOKEN_THRESHOLDS_MV_SQL: &str = r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_thresholds_mv
+TO token_thresholds
+AS 
+SELECT 
+    ts.mint_address,
+    value as usd_threshold,
+    value / ts.price as token_amount,
+    ts.timestamp
+FROM token_stats ts
+ARRAY JOIN [10, 100, 1000, 10000, 100000] as value
+WHERE ts.price > 0
+"#;
+
+const TOKEN_HOLDER_COUNTS_MV_SQL: &str = r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_holder_counts_mv
+TO token_holder_counts
+AS 
+WITH snapshot_holders AS (
+    SELECT
+        ts.mint_address as mint_address,
+        ts.timestamp as timestamp,
+        any(ts.price) as price,
+        any(ts.decimals) as decimals,
+        any(ts.market_cap) as market_cap,
+        thb.holder_address as holder_address,
+        argMax(thb.balance, thb.timestamp) as balance
+    FROM token_stats ts
+    INNER JOIN token_holder_balances thb ON ts.mint_address = thb.mint_address
+    WHERE thb.timestamp >= ts.checkpoint_timestamp
+        AND thb.timestamp <= ts.timestamp
+        AND ts.price > 0
+    GROUP BY ts.mint_address, ts.timestamp, thb.holder_address
+    HAVING balance > 0
+)
+SELECT 
+    mint_address,
+    timestamp,
+    usd_threshold,
+    countIf(balance / pow(10, decimals) * price >= usd_threshold) AS holder_count,
+    count() AS total_holders,
+    coalesce((holder_count / nullIf(total_holders, 0)) * 100, 0) AS pct_total_holders,
+    coalesce((holder_count / nullIf(countIf(balance / pow(10, decimals) * price >= 10), 0)) * 100, 0) AS pct_of_10usd,
+    coalesce(any(market_cap) / nullIf(holder_count, 0), 0) AS mcap_per_holder,
+    sumIf(balance / pow(10, decimals) * price, balance / pow(10, decimals) * price >= usd_threshold) AS slice_value_usd
+FROM snapshot_holders
+ARRAY JOIN [10, 100, 1000, 10000, 100000] AS usd_threshold
+GROUP BY
+    mint_address,
+    timestamp,
+    usd_threshold
+"#;
+
+const TOKEN_CONCENTRATION_MV_SQL: &str = r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_concentration_mv
+TO token_concentration
+AS 
+WITH snapshot_holders AS (
+    SELECT
+        ts.mint_address as mint_address,
+        ts.timestamp as timestamp,
+        any(ts.supply) as supply,
+        thb.holder_address as holder_address,
+        argMax(thb.balance, thb.timestamp) as balance
+    FROM token_stats ts
+    INNER JOIN token_holder_balances thb ON ts.mint_address = thb.mint_address
+    WHERE thb.timestamp >= ts.checkpoint_timestamp
+        AND thb.timestamp <= ts.timestamp
+    GROUP BY ts.mint_address, ts.timestamp, thb.holder_address
+    HAVING balance > 0
+),
+ranked_holders AS (
+    SELECT
+        mint_address,
+        timestamp,
+        balance,
+        supply,
+        row_number() OVER (PARTITION BY mint_address, timestamp ORDER BY balance DESC) AS rank
+    FROM snapshot_holders
+)
+SELECT
+    rh.mint_address as mint_address,
+    rh.timestamp as timestamp,
+    t.top_n,
+    (sum(rh.balance) / max(rh.supply)) * 100 AS percentage
+FROM ranked_holders rh
+CROSS JOIN (
+    SELECT 1 AS top_n
+    UNION ALL SELECT 10
+    UNION ALL SELECT 25
+    UNION ALL SELECT 50
+    UNION ALL SELECT 100
+    UNION ALL SELECT 250
+) t
+WHERE rh.rank <= t.top_n
+GROUP BY
+    rh.mint_address,
+    rh.timestamp,
+    t.top_n
+"#;
+
+const TOKEN_DISTRIBUTION_MV_SQL: &str = r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_distribution_mv
+TO token_distribution
+AS 
+WITH holder_amounts AS (
+    SELECT
+        ts.mint_address as mint_address,
+        ts.timestamp as timestamp,
+        toFloat64(any(ts.supply)) as supply,
+        thb.holder_address as holder_address,
+        toFloat64(argMax(thb.balance, thb.timestamp)) as balance
+    FROM token_stats ts
+    INNER JOIN token_holder_balances thb ON ts.mint_address = thb.mint_address
+    WHERE thb.timestamp >= ts.checkpoint_timestamp
+        AND thb.timestamp <= ts.timestamp
+    GROUP BY ts.mint_address, ts.timestamp, thb.holder_address
+    HAVING balance > 0
+),
+ranked_amounts AS (
+    SELECT
+        mint_address,
+        timestamp,
+        balance,
+        supply,
+        row_number() OVER (PARTITION BY mint_address, timestamp ORDER BY balance) as rank,
+        count() OVER (PARTITION BY mint_address, timestamp) as total_count
+    FROM holder_amounts
+)
+SELECT
+    mint_address,
+    timestamp,
+    sum(pow((balance / supply) * 100, 2)) as hhi,
+    (1 - (
+        sum(balance * (rank - 1))
+        / 
+        (count() * sum(balance))
+    )) * 100 as distribution_score,
+    quantileExact(0.5)(balance) as median_balance,
+    any(total_count) as total_holders,
+    sum(balance) / any(total_count) as mean_balance
+FROM ranked_amounts
+GROUP BY
+    mint_address,
+    timestamp
+"#;
+
+// In dependency order
+pub(super) const VIEWS: &[(&str, &str)] = &[
+    ("token_holder_balances_mv", TOKEN_HOLDER_BALANCES_MV_SQL),
+    ("token_thresholds_mv", TOKEN_THRESHOLDS_MV_SQL),
+    ("token_holder_counts_mv", TOKEN_HOLDER_COUNTS_MV_SQL),
+    ("token_concentration_mv", TOKEN_CONCENTRATION_MV_SQL),
+    ("token_distribution_mv", TOKEN_DISTRIBUTION_MV_SQL),
+];
+
+// Holder balances keyed by timestamp and metric views that rebuild state from checkpoints
+pub(super) fn steps() -> Vec<Step> {
+    let mut steps: Vec<Step> = VIEWS.iter()
+        .map(|(name, _)| Step::Sql(format!("DROP VIEW IF EXISTS {}", name)))
+        .collect();
+    steps.push(Step::Sql(
+        "ALTER TABLE token_stats ADD COLUMN IF NOT EXISTS checkpoint_timestamp DateTime('UTC') DEFAULT timestamp".to_string(),
+    ));
+    steps.push(Step::RebuildTable {
+        table: "token_holder_balances".to_string(),
+        create_sql: TOKEN_HOLDER_BALANCES_TABLE_SQL.to_string(),
+    });
+    steps.extend(VIEWS.iter()
+        .map(|(name, sql)| Step::RecreateView { name: name.to_string(), create_sql: sql.to_string() }));
+    steps
+}
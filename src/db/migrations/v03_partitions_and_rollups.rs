@@ -0,0 +1,314 @@
+use super::Step;
+use super::v02_delta_snapshots::VIEWS;
+
+const TOKEN_STATS_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS token_stats (
+    mint_address String,
+    timestamp DateTime('UTC'),
+    price Float64,
+    supply Float64,
+    market_cap Float64,
+    decimals UInt8,
+    checkpoint_timestamp DateTime('UTC') DEFAULT timestamp,
+    PRIMARY KEY (mint_address, timestamp)
+) ENGINE = ReplacingMergeTree
+PARTITION BY toYYYYMM(timestamp)
+"#;
+
+const TOKEN_HOLDERS_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS token_holders (
+    mint_address String,
+    token_account String,
+    holder_address String,
+    amount UInt64,
+    timestamp DateTime('UTC'),
+    PRIMARY KEY (mint_address, holder_address, timestamp)
+) ENGINE = ReplacingMergeTree
+PARTITION BY toYYYYMM(timestamp)
+"#;
+
+const TOKEN_HOLDER_BALANCES_TABLE_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS token_holder_balances (
+    mint_address String,
+    holder_address String,
+    balance Float64,
+    timestamp DateTime('UTC'),
+    PRIMARY KEY (mint_address, holder_address, timestamp)
+) ENGINE = ReplacingMergeTree
+PARTITION BY toYYYYMM(timestamp)
+"#;
+
+const TOKEN_THRESHOLDS_TABLE_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS token_thresholds (
+    mint_address String,
+    usd_threshold Float64,
+    token_amount Float64,
+    timestamp DateTime('UTC'),
+    PRIMARY KEY (mint_address, usd_threshold, timestamp)
+) ENGINE = ReplacingMergeTree
+PARTITION BY toYYYYMM(timestamp)
+"#;
+
+const TOKEN_HOLDER_COUNTS_TABLE_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS token_holder_counts (
+    mint_address String,
+    timestamp DateTime('UTC'),
+    usd_threshold Float64,
+    holder_count UInt64,
+    total_holders UInt64,
+    pct_total_holders Float64 DEFAULT 0,
+    pct_of_10usd Float64 DEFAULT 0,
+    mcap_per_holder Float64 DEFAULT 0,
+    slice_value_usd Float64 DEFAULT 0,
+    PRIMARY KEY (mint_address, timestamp, usd_threshold)
+) ENGINE = ReplacingMergeTree
+PARTITION BY toYYYYMM(timestamp)
+"#;
+
+const TOKEN_CONCENTRATION_TABLE_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS token_concentration (
+    mint_address String,
+    timestamp DateTime('UTC'),
+    top_n UInt8,
+    percentage Float64,
+    PRIMARY KEY (mint_address, timestamp, top_n)
+) ENGINE = ReplacingMergeTree
+PARTITION BY toYYYYMM(timestamp)
+"#;
+
+const TOKEN_DISTRIBUTION_TABLE_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS token_distribution (
+    mint_address String,
+    timestamp DateTime('UTC'),
+    hhi Float64,
+    distribution_score Float64,
+    median_balance Float64,
+    total_holders UInt64,
+    mean_balance Float64,
+    PRIMARY KEY (mint_address, timestamp)
+) ENGINE = ReplacingMergeTree
+PARTITION BY toYYYYMM(timestamp)
+"#;
+
+// (rollup table, its definition, the view filling it)
+const ROLLUPS: &[(&str, &str, &str)] = &[
+    (
+        "token_stats_hourly",
+        r#"
+CREATE TABLE IF NOT EXISTS token_stats_hourly (
+    mint_address String,
+    bucket DateTime('UTC'),
+    snapshot_timestamp DateTime('UTC'),
+    price Float64, supply Float64, market_cap Float64, decimals UInt8,
+    PRIMARY KEY (mint_address, bucket)
+) ENGINE = ReplacingMergeTree(snapshot_timestamp)
+PARTITION BY toYYYYMM(bucket)
+"#,
+        r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_stats_hourly_mv
+TO token_stats_hourly
+AS
+SELECT
+    mint_address,
+    toStartOfHour(timestamp) AS bucket,
+    timestamp AS snapshot_timestamp,
+    price, supply, market_cap, decimals
+FROM token_stats
+"#,
+    ),
+    (
+        "token_stats_daily",
+        r#"
+CREATE TABLE IF NOT EXISTS token_stats_daily (
+    mint_address String,
+    bucket DateTime('UTC'),
+    snapshot_timestamp DateTime('UTC'),
+    price Float64, supply Float64, market_cap Float64, decimals UInt8,
+    PRIMARY KEY (mint_address, bucket)
+) ENGINE = ReplacingMergeTree(snapshot_timestamp)
+PARTITION BY toYYYYMM(bucket)
+"#,
+        r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_stats_daily_mv
+TO token_stats_daily
+AS
+SELECT
+    mint_address,
+    toStartOfDay(timestamp) AS bucket,
+    timestamp AS snapshot_timestamp,
+    price, supply, market_cap, decimals
+FROM token_stats
+"#,
+    ),
+    (
+        "token_distribution_hourly",
+        r#"
+CREATE TABLE IF NOT EXISTS token_distribution_hourly (
+    mint_address String,
+    bucket DateTime('UTC'),
+    snapshot_timestamp DateTime('UTC'),
+    hhi Float64, distribution_score Float64, median_balance Float64, total_holders UInt64, mean_balance Float64,
+    PRIMARY KEY (mint_address, bucket)
+) ENGINE = ReplacingMergeTree(snapshot_timestamp)
+PARTITION BY toYYYYMM(bucket)
+"#,
+        r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_distribution_hourly_mv
+TO token_distribution_hourly
+AS
+SELECT
+    mint_address,
+    toStartOfHour(timestamp) AS bucket,
+    timestamp AS snapshot_timestamp,
+    hhi, distribution_score, median_balance, total_holders, mean_balance
+FROM token_distribution
+"#,
+    ),
+    (
+        "token_distribution_daily",
+        r#"
+CREATE TABLE IF NOT EXISTS token_distribution_daily (
+    mint_address String,
+    bucket DateTime('UTC'),
+    snapshot_timestamp DateTime('UTC'),
+    hhi Float64, distribution_score Float64, median_balance Float64, total_holders UInt64, mean_balance Float64,
+    PRIMARY KEY (mint_address, bucket)
+) ENGINE = ReplacingMergeTree(snapshot_timestamp)
+PARTITION BY toYYYYMM(bucket)
+"#,
+        r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_distribution_daily_mv
+TO token_distribution_daily
+AS
+SELECT
+    mint_address,
+    toStartOfDay(timestamp) AS bucket,
+    timestamp AS snapshot_timestamp,
+    hhi, distribution_score, median_balance, total_holders, mean_balance
+FROM token_distribution
+"#,
+    ),
+    (
+        "token_concentration_hourly",
+        r#"
+CREATE TABLE IF NOT EXISTS token_concentration_hourly (
+    mint_address String,
+    bucket DateTime('UTC'),
+    snapshot_timestamp DateTime('UTC'),
+    top_n UInt8, percentage Float64,
+    PRIMARY KEY (mint_address, bucket, top_n)
+) ENGINE = ReplacingMergeTree(snapshot_timestamp)
+PARTITION BY toYYYYMM(bucket)
+"#,
+        r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_concentration_hourly_mv
+TO token_concentration_hourly
+AS
+SELECT
+    mint_address,
+    toStartOfHour(timestamp) AS bucket,
+    timestamp AS snapshot_timestamp,
+    top_n, percentage
+FROM token_concentration
+"#,
+    ),
+    (
+        "token_concentration_daily",
+        r#"
+CREATE TABLE IF NOT EXISTS token_concentration_daily (
+    mint_address String,
+    bucket DateTime('UTC'),
+    snapshot_timestamp DateTime('UTC'),
+    top_n UInt8, percentage Float64,
+    PRIMARY KEY (mint_address, bucket, top_n)
+) ENGINE = ReplacingMergeTree(snapshot_timestamp)
+PARTITION BY toYYYYMM(bucket)
+"#,
+        r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_concentration_daily_mv
+TO token_concentration_daily
+AS
+SELECT
+    mint_address,
+    toStartOfDay(timestamp) AS bucket,
+    timestamp AS snapshot_timestamp,
+    top_n, percentage
+FROM token_concentration
+"#,
+    ),
+    (
+        "token_holder_counts_hourly",
+        r#"
+CREATE TABLE IF NOT EXISTS token_holder_counts_hourly (
+    mint_address String,
+    bucket DateTime('UTC'),
+    snapshot_timestamp DateTime('UTC'),
+    usd_threshold Float64, holder_count UInt64, total_holders UInt64, pct_total_holders Float64, pct_of_10usd Float64, mcap_per_holder Float64, slice_value_usd Float64,
+    PRIMARY KEY (mint_address, bucket, usd_threshold)
+) ENGINE = ReplacingMergeTree(snapshot_timestamp)
+PARTITION BY toYYYYMM(bucket)
+"#,
+        r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_holder_counts_hourly_mv
+TO token_holder_counts_hourly
+AS
+SELECT
+    mint_address,
+    toStartOfHour(timestamp) AS bucket,
+    timestamp AS snapshot_timestamp,
+    usd_threshold, holder_count, total_holders, pct_total_holders, pct_of_10usd, mcap_per_holder, slice_value_usd
+FROM token_holder_counts
+"#,
+    ),
+    (
+        "token_holder_counts_daily",
+        r#"
+CREATE TABLE IF NOT EXISTS token_holder_counts_daily (
+    mint_address String,
+    bucket DateTime('UTC'),
+    snapshot_timestamp DateTime('UTC'),
+    usd_threshold Float64, holder_count UInt64, total_holders UInt64, pct_total_holders Float64, pct_of_10usd Float64, mcap_per_holder Float64, slice_value_usd Float64,
+    PRIMARY KEY (mint_address, bucket, usd_threshold)
+) ENGINE = ReplacingMergeTree(snapshot_timestamp)
+PARTITION BY toYYYYMM(bucket)
+"#,
+        r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_holder_counts_daily_mv
+TO token_holder_counts_daily
+AS
+SELECT
+    mint_address,
+    toStartOfDay(timestamp) AS bucket,
+    timestamp AS snapshot_timestamp,
+    usd_threshold, holder_count, total_holders, pct_total_holders, pct_of_10usd, mcap_per_holder, slice_value_usd
+FROM token_holder_counts
+"#,
+    ),
+];
+
+// Monthly partitions on the time-series tables, rollups backfilled from what's retained
+pub(super) fn steps() -> Vec<Step> {
+    let mut steps: Vec<Step> = VIEWS.iter()
+        .map(|(name, _)| Step::Sql(format!("DROP VIEW IF EXISTS {}", name)))
+        .collect();
+    for (table, create_sql) in [
+        ("token_stats", TOKEN_STATS_SQL),
+        ("token_holders", TOKEN_HOLDERS_SQL),
+        ("token_holder_balances", TOKEN_HOLDER_BALANCES_TABLE_SQL),
+        ("token_thresholds", TOKEN_THRESHOLDS_TABLE_SQL),
+        ("token_holder_counts", TOKEN_HOLDER_COUNTS_TABLE_SQL),
+        ("token_concentration", TOKEN_CONCENTRATION_TABLE_SQL),
+        ("token_distribution", TOKEN_DISTRIBUTION_TABLE_SQL),
+    ] {
+        steps.push(Step::RebuildTable { table: table.to_string(), create_sql: create_sql.to_string() });
+    }
+    for (table, create_sql, view_sql) in ROLLUPS {
+        steps.push(Step::Sql(create_sql.to_string()));
+        steps.push(Step::Backfill { target: table.to_string(), view_sql: view_sql.to_string() });
+    }
+    steps.extend(VIEWS.iter()
+        .map(|(name, sql)| Step::RecreateView { name: name.to_string(), create_sql: sql.to_string() }));
+    steps.extend(ROLLUPS.iter()
+        .map(|(table, _, view_sql)| Step::RecreateView { name: format!("{}_mv", table), create_sql: view_sql.to_string() }));
+    steps
+}
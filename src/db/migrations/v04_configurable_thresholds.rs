@@ -0,0 +1,115 @@
+use super::Step;
+
+const TOKEN_THRESHOLD_CONFIG_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS token_threshold_config (
+    mint_address String,
+    thresholds Array(Float64),
+    updated_at DateTime64(3, 'UTC') DEFAULT now64(3, 'UTC'),
+    PRIMARY KEY (mint_address)
+) ENGINE = ReplacingMergeTree(updated_at)
+"#;
+
+const DEFAULT_THRESHOLDS_SEED_SQL: &str = r#"
+INSERT INTO token_threshold_config (mint_address, thresholds)
+SELECT '', [10, 100, 1000, 10000, 100000]
+WHERE (SELECT count() FROM token_threshold_config WHERE mint_address = '') = 0
+"#;
+
+const TOKEN_THRESHOLDS_MV_SQL: &str = r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_thresholds_mv
+TO token_thresholds
+AS 
+SELECT 
+    mint_address,
+    usd_threshold,
+    usd_threshold / price as token_amount,
+    timestamp
+FROM (
+    SELECT
+        ts.mint_address as mint_address,
+        ts.timestamp as timestamp,
+        ts.price as price,
+        if(notEmpty(cfg.thresholds), cfg.thresholds, dflt.thresholds) as thresholds
+    FROM token_stats ts
+    LEFT JOIN (
+        SELECT mint_address, argMax(thresholds, updated_at) as thresholds
+        FROM token_threshold_config
+        WHERE mint_address != ''
+        GROUP BY mint_address
+    ) cfg ON ts.mint_address = cfg.mint_address
+    CROSS JOIN (
+        SELECT argMax(thresholds, updated_at) as thresholds
+        FROM token_threshold_config
+        WHERE mint_address = ''
+    ) dflt
+    WHERE ts.price > 0
+)
+ARRAY JOIN thresholds as usd_threshold
+"#;
+
+const TOKEN_HOLDER_COUNTS_MV_SQL: &str = r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_holder_counts_mv
+TO token_holder_counts
+AS 
+WITH snapshot_holders AS (
+    SELECT
+        ts.mint_address as mint_address,
+        ts.timestamp as timestamp,
+        any(ts.price) as price,
+        any(ts.decimals) as decimals,
+        any(ts.market_cap) as market_cap,
+        any(if(notEmpty(cfg.thresholds), cfg.thresholds, dflt.thresholds)) as thresholds,
+        thb.holder_address as holder_address,
+        argMax(thb.balance, thb.timestamp) as balance
+    FROM token_stats ts
+    INNER JOIN token_holder_balances thb ON ts.mint_address = thb.mint_address
+    LEFT JOIN (
+        SELECT mint_address, argMax(thresholds, updated_at) as thresholds
+        FROM token_threshold_config
+        WHERE mint_address != ''
+        GROUP BY mint_address
+    ) cfg ON ts.mint_address = cfg.mint_address
+    CROSS JOIN (
+        SELECT argMax(thresholds, updated_at) as thresholds
+        FROM token_threshold_config
+        WHERE mint_address = ''
+    ) dflt
+    WHERE thb.timestamp >= ts.checkpoint_timestamp
+        AND thb.timestamp <= ts.timestamp
+        AND ts.price > 0
+    GROUP BY ts.mint_address, ts.timestamp, thb.holder_address
+    HAVING balance > 0
+)
+SELECT 
+    mint_address,
+    timestamp,
+    usd_threshold,
+    countIf(balance / pow(10, decimals) * price >= usd_threshold) AS holder_count,
+    count() AS total_holders,
+    coalesce((holder_count / nullIf(total_holders, 0)) * 100, 0) AS pct_total_holders,
+    coalesce((holder_count / nullIf(countIf(balance / pow(10, decimals) * price >= arrayMin(thresholds)), 0)) * 100, 0) AS pct_of_10usd,
+    coalesce(any(market_cap) / nullIf(holder_count, 0), 0) AS mcap_per_holder,
+    sumIf(balance / pow(10, decimals) * price, balance / pow(10, decimals) * price >= usd_threshold) AS slice_value_usd
+FROM snapshot_holders
+ARRAY JOIN thresholds AS usd_threshold
+GROUP BY
+    mint_address,
+    timestamp,
+    usd_threshold
+"#;
+
+// USD thresholds read from token_threshold_config instead of a fixed list
+pub(super) fn steps() -> Vec<Step> {
+    vec![
+        Step::Sql(TOKEN_THRESHOLD_CONFIG_SQL.to_string()),
+        Step::Sql(DEFAULT_THRESHOLDS_SEED_SQL.to_string()),
+        Step::RecreateView {
+            name: "token_thresholds_mv".to_string(),
+            create_sql: TOKEN_THRESHOLDS_MV_SQL.to_string(),
+        },
+        Step::RecreateView {
+            name: "token_holder_counts_mv".to_string(),
+            create_sql: TOKEN_HOLDER_COUNTS_MV_SQL.to_string(),
+        },
+    ]
+}
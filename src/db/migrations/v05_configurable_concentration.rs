@@ -0,0 +1,151 @@
+use super::Step;
+
+const TOKEN_CONCENTRATION_CONFIG_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS token_concentration_config (
+    mint_address String,
+    buckets Array(UInt32),
+    updated_at DateTime64(3, 'UTC') DEFAULT now64(3, 'UTC'),
+    PRIMARY KEY (mint_address)
+) ENGINE = ReplacingMergeTree(updated_at)
+"#;
+
+const DEFAULT_CONCENTRATION_SEED_SQL: &str = r#"
+INSERT INTO token_concentration_config (mint_address, buckets)
+SELECT '', [1, 10, 25, 50, 100, 250]
+WHERE (SELECT count() FROM token_concentration_config WHERE mint_address = '') = 0
+"#;
+
+const TOKEN_CONCENTRATION_TABLE_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS token_concentration (
+    mint_address String,
+    timestamp DateTime('UTC'),
+    top_n UInt32,
+    percentage Float64,
+    PRIMARY KEY (mint_address, timestamp, top_n)
+) ENGINE = ReplacingMergeTree
+PARTITION BY toYYYYMM(timestamp)
+"#;
+
+const TOKEN_CONCENTRATION_MV_SQL: &str = r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_concentration_mv
+TO token_concentration
+AS 
+WITH snapshot_holders AS (
+    SELECT
+        ts.mint_address as mint_address,
+        ts.timestamp as timestamp,
+        any(ts.supply) as supply,
+        any(if(notEmpty(cfg.buckets), cfg.buckets, dflt.buckets)) as buckets,
+        thb.holder_address as holder_address,
+        argMax(thb.balance, thb.timestamp) as balance
+    FROM token_stats ts
+    INNER JOIN token_holder_balances thb ON ts.mint_address = thb.mint_address
+    LEFT JOIN (
+        SELECT mint_address, argMax(buckets, updated_at) as buckets
+        FROM token_concentration_config
+        WHERE mint_address != ''
+        GROUP BY mint_address
+    ) cfg ON ts.mint_address = cfg.mint_address
+    CROSS JOIN (
+        SELECT argMax(buckets, updated_at) as buckets
+        FROM token_concentration_config
+        WHERE mint_address = ''
+    ) dflt
+    WHERE thb.timestamp >= ts.checkpoint_timestamp
+        AND thb.timestamp <= ts.timestamp
+    GROUP BY ts.mint_address, ts.timestamp, thb.holder_address
+    HAVING balance > 0
+),
+sorted_balances AS (
+    SELECT
+        mint_address,
+        timestamp,
+        any(supply) as supply,
+        any(buckets) as buckets,
+        arrayReverseSort(groupArray(balance)) as balances
+    FROM snapshot_holders
+    GROUP BY mint_address, timestamp
+)
+SELECT
+    mint_address,
+    timestamp,
+    top_n,
+    (arraySum(arraySlice(balances, 1, top_n)) / supply) * 100 AS percentage
+FROM sorted_balances
+ARRAY JOIN buckets AS top_n
+"#;
+
+// (rollup table, its definition, the view filling it)
+const ROLLUPS: &[(&str, &str, &str)] = &[
+    (
+        "token_concentration_hourly",
+        r#"
+CREATE TABLE IF NOT EXISTS token_concentration_hourly (
+    mint_address String,
+    bucket DateTime('UTC'),
+    snapshot_timestamp DateTime('UTC'),
+    top_n UInt32, percentage Float64,
+    PRIMARY KEY (mint_address, bucket, top_n)
+) ENGINE = ReplacingMergeTree(snapshot_timestamp)
+PARTITION BY toYYYYMM(bucket)
+"#,
+        r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_concentration_hourly_mv
+TO token_concentration_hourly
+AS
+SELECT
+    mint_address,
+    toStartOfHour(timestamp) AS bucket,
+    timestamp AS snapshot_timestamp,
+    top_n, percentage
+FROM token_concentration
+"#,
+    ),
+    (
+        "token_concentration_daily",
+        r#"
+CREATE TABLE IF NOT EXISTS token_concentration_daily (
+    mint_address String,
+    bucket DateTime('UTC'),
+    snapshot_timestamp DateTime('UTC'),
+    top_n UInt32, percentage Float64,
+    PRIMARY KEY (mint_address, bucket, top_n)
+) ENGINE = ReplacingMergeTree(snapshot_timestamp)
+PARTITION BY toYYYYMM(bucket)
+"#,
+        r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_concentration_daily_mv
+TO token_concentration_daily
+AS
+SELECT
+    mint_address,
+    toStartOfDay(timestamp) AS bucket,
+    timestamp AS snapshot_timestamp,
+    top_n, percentage
+FROM token_concentration
+"#,
+    ),
+];
+
+// Top-N buckets read from token_concentration_config, top_n widened to UInt32
+pub(super) fn steps() -> Vec<Step> {
+    let mut steps = vec![Step::Sql("DROP VIEW IF EXISTS token_concentration_mv".to_string())];
+    for (table, _, _) in ROLLUPS {
+        steps.push(Step::Sql(format!("DROP VIEW IF EXISTS {}_mv", table)));
+    }
+    steps.push(Step::Sql(TOKEN_CONCENTRATION_CONFIG_SQL.to_string()));
+    steps.push(Step::Sql(DEFAULT_CONCENTRATION_SEED_SQL.to_string()));
+    steps.push(Step::RebuildTable {
+        table: "token_concentration".to_string(),
+        create_sql: TOKEN_CONCENTRATION_TABLE_SQL.to_string(),
+    });
+    for (table, create_sql, view_sql) in ROLLUPS {
+        steps.push(Step::RebuildTable { table: table.to_string(), create_sql: create_sql.to_string() });
+        steps.push(Step::RecreateView { name: format!("{}_mv", table), create_sql: view_sql.to_string() });
+    }
+    steps.push(Step::RecreateView {
+        name: "token_concentration_mv".to_string(),
+        create_sql: TOKEN_CONCENTRATION_MV_SQL.to_string(),
+    });
+    steps
+}
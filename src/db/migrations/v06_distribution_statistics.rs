@@ -0,0 +1,148 @@
+use super::Step;
+
+const TOKEN_DISTRIBUTION_MV_SQL: &str = r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_distribution_mv
+TO token_distribution
+AS 
+WITH holder_amounts AS (
+    SELECT
+        ts.mint_address as mint_address,
+        ts.timestamp as timestamp,
+        toFloat64(any(ts.supply)) as supply,
+        thb.holder_address as holder_address,
+        toFloat64(argMax(thb.balance, thb.timestamp)) as balance
+    FROM token_stats ts
+    INNER JOIN token_holder_balances thb ON ts.mint_address = thb.mint_address
+    WHERE thb.timestamp >= ts.checkpoint_timestamp
+        AND thb.timestamp <= ts.timestamp
+    GROUP BY ts.mint_address, ts.timestamp, thb.holder_address
+    HAVING balance > 0
+),
+ranked_amounts AS (
+    SELECT
+        mint_address,
+        timestamp,
+        balance,
+        supply,
+        row_number() OVER (PARTITION BY mint_address, timestamp ORDER BY balance) as rank,
+        count() OVER (PARTITION BY mint_address, timestamp) as total_count
+    FROM holder_amounts
+),
+snapshot_aggregates AS (
+    SELECT
+        mint_address,
+        timestamp,
+        sum(pow((balance / supply) * 100, 2)) as hhi,
+        (1 - (
+            sum(balance * (rank - 1))
+            / 
+            (count() * sum(balance))
+        )) * 100 as distribution_score,
+        quantileExact(0.5)(balance) as median_balance,
+        any(total_count) as total_holders,
+        sum(balance) / any(total_count) as mean_balance,
+        2 * sum(rank * balance) / (count() * sum(balance)) - (count() + 1) / count() as gini,
+        sum(balance * ln(balance)) / sum(balance) - ln(sum(balance) / count()) as theil_index,
+        quantilesExact(0.1, 0.25, 0.5, 0.75, 0.9, 0.95, 0.99)(balance) as percentiles,
+        arraySort(groupArray(balance)) as sorted_balances,
+        sum(balance) as held
+    FROM ranked_amounts
+    GROUP BY
+        mint_address,
+        timestamp
+)
+SELECT
+    mint_address,
+    timestamp,
+    hhi,
+    distribution_score,
+    median_balance,
+    total_holders,
+    mean_balance,
+    gini,
+    arrayFirstIndex(x -> x > held * 0.5, arrayCumSum(arrayReverse(sorted_balances))) as nakamoto_coefficient,
+    theil_index,
+    percentiles[1] as p10,
+    percentiles[2] as p25,
+    percentiles[3] as p50,
+    percentiles[4] as p75,
+    percentiles[5] as p90,
+    percentiles[6] as p95,
+    percentiles[7] as p99,
+    arrayMap(
+        k -> arraySum(arraySlice(sorted_balances, 1, intDiv(k * length(sorted_balances), 20))) / held,
+        range(21)
+    ) as lorenz_curve
+FROM snapshot_aggregates
+"#;
+
+const ROLLUP_VIEWS: &[(&str, &str)] = &[
+    (
+        "token_distribution_hourly_mv",
+        r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_distribution_hourly_mv
+TO token_distribution_hourly
+AS
+SELECT
+    mint_address,
+    toStartOfHour(timestamp) AS bucket,
+    timestamp AS snapshot_timestamp,
+    hhi, distribution_score, median_balance, total_holders, mean_balance, gini, nakamoto_coefficient, theil_index, p10, p25, p50, p75, p90, p95, p99, lorenz_curve
+FROM token_distribution
+"#,
+    ),
+    (
+        "token_distribution_daily_mv",
+        r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_distribution_daily_mv
+TO token_distribution_daily
+AS
+SELECT
+    mint_address,
+    toStartOfDay(timestamp) AS bucket,
+    timestamp AS snapshot_timestamp,
+    hhi, distribution_score, median_balance, total_holders, mean_balance, gini, nakamoto_coefficient, theil_index, p10, p25, p50, p75, p90, p95, p99, lorenz_curve
+FROM token_distribution
+"#,
+    ),
+];
+
+// Gini, Nakamoto, Theil, percentiles and Lorenz points on token_distribution and
+// its rollups. The rollup views are dropped and recreated with the new columns,
+// snapshots taken before this keep zeroes for them.
+pub(super) fn steps() -> Vec<Step> {
+    let columns = [
+        ("gini", "Float64"),
+        ("nakamoto_coefficient", "UInt64"),
+        ("theil_index", "Float64"),
+        ("p10", "Float64"),
+        ("p25", "Float64"),
+        ("p50", "Float64"),
+        ("p75", "Float64"),
+        ("p90", "Float64"),
+        ("p95", "Float64"),
+        ("p99", "Float64"),
+        ("lorenz_curve", "Array(Float64)"),
+    ];
+    let add_columns = columns.iter()
+        .map(|(name, ty)| format!("ADD COLUMN IF NOT EXISTS {} {}", name, ty))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut steps = vec![Step::Sql("DROP VIEW IF EXISTS token_distribution_mv".to_string())];
+    for (name, _) in ROLLUP_VIEWS {
+        steps.push(Step::Sql(format!("DROP VIEW IF EXISTS {}", name)));
+    }
+    steps.push(Step::Sql(format!("ALTER TABLE token_distribution {}", add_columns)));
+    for tier in ["hourly", "daily"] {
+        steps.push(Step::Sql(format!("ALTER TABLE token_distribution_{} {}", tier, add_columns)));
+    }
+    for (name, create_sql) in ROLLUP_VIEWS {
+        steps.push(Step::RecreateView { name: name.to_string(), create_sql: create_sql.to_string() });
+    }
+    steps.push(Step::RecreateView {
+        name: "token_distribution_mv".to_string(),
+        create_sql: TOKEN_DISTRIBUTION_MV_SQL.to_string(),
+    });
+    steps
+}
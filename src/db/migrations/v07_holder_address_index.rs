@@ -0,0 +1,11 @@
+use super::Step;
+
+// Skip index for wallet lookups, built for existing parts in the background
+pub(super) fn steps() -> Vec<Step> {
+    vec![
+        Step::Sql(
+            "ALTER TABLE token_holders ADD INDEX IF NOT EXISTS holder_address_idx holder_address TYPE bloom_filter(0.01) GRANULARITY 4".to_string(),
+        ),
+        Step::Sql("ALTER TABLE token_holders MATERIALIZE INDEX holder_address_idx".to_string()),
+    ]
+}
@@ -0,0 +1,127 @@
+use super::Step;
+
+const WALLET_LABELS_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS wallet_labels (
+    mint_address String,
+    address String,
+    label String,
+    reason String,
+    added_at DateTime('UTC') DEFAULT now('UTC'),
+    PRIMARY KEY (mint_address, label, address)
+) ENGINE = ReplacingMergeTree(added_at)
+"#;
+
+const TOKEN_LABEL_HOLDINGS_TABLE_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS token_label_holdings (
+    mint_address String,
+    timestamp DateTime('UTC'),
+    label String,
+    holders UInt64,
+    balance Float64,
+    supply_share Float64,
+    PRIMARY KEY (mint_address, timestamp, label)
+) ENGINE = ReplacingMergeTree
+PARTITION BY toYYYYMM(timestamp)
+"#;
+
+const TOKEN_LABEL_HOLDINGS_MV_SQL: &str = r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_label_holdings_mv
+TO token_label_holdings
+AS
+WITH snapshot_holders AS (
+    SELECT
+        ts.mint_address as mint_address,
+        ts.timestamp as timestamp,
+        any(ts.supply) as supply,
+        thb.holder_address as holder_address,
+        argMax(thb.balance, thb.timestamp) as balance
+    FROM token_stats ts
+    INNER JOIN token_holder_balances thb ON ts.mint_address = thb.mint_address
+    WHERE thb.timestamp >= ts.checkpoint_timestamp
+        AND thb.timestamp <= ts.timestamp
+        AND thb.holder_address IN (SELECT address FROM wallet_labels)
+    GROUP BY ts.mint_address, ts.timestamp, thb.holder_address
+    HAVING balance > 0
+)
+SELECT
+    sh.mint_address as mint_address,
+    sh.timestamp as timestamp,
+    wl.label as label,
+    count() as holders,
+    sum(sh.balance) as balance,
+    sum(sh.balance) / any(sh.supply) * 100 as supply_share
+FROM snapshot_holders sh
+INNER JOIN (
+    SELECT DISTINCT mint_address, address, label FROM wallet_labels
+) wl ON sh.mint_address = wl.mint_address AND sh.holder_address = wl.address
+GROUP BY sh.mint_address, sh.timestamp, wl.label
+"#;
+
+// (rollup table, its definition, the view filling it)
+const ROLLUPS: &[(&str, &str, &str)] = &[
+    (
+        "token_label_holdings_hourly",
+        r#"
+CREATE TABLE IF NOT EXISTS token_label_holdings_hourly (
+    mint_address String,
+    bucket DateTime('UTC'),
+    snapshot_timestamp DateTime('UTC'),
+    label String, holders UInt64, balance Float64, supply_share Float64,
+    PRIMARY KEY (mint_address, bucket, label)
+) ENGINE = ReplacingMergeTree(snapshot_timestamp)
+PARTITION BY toYYYYMM(bucket)
+"#,
+        r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_label_holdings_hourly_mv
+TO token_label_holdings_hourly
+AS
+SELECT
+    mint_address,
+    toStartOfHour(timestamp) AS bucket,
+    timestamp AS snapshot_timestamp,
+    label, holders, balance, supply_share
+FROM token_label_holdings
+"#,
+    ),
+    (
+        "token_label_holdings_daily",
+        r#"
+CREATE TABLE IF NOT EXISTS token_label_holdings_daily (
+    mint_address String,
+    bucket DateTime('UTC'),
+    snapshot_timestamp DateTime('UTC'),
+    label String, holders UInt64, balance Float64, supply_share Float64,
+    PRIMARY KEY (mint_address, bucket, label)
+) ENGINE = ReplacingMergeTree(snapshot_timestamp)
+PARTITION BY toYYYYMM(bucket)
+"#,
+        r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_label_holdings_daily_mv
+TO token_label_holdings_daily
+AS
+SELECT
+    mint_address,
+    toStartOfDay(timestamp) AS bucket,
+    timestamp AS snapshot_timestamp,
+    label, holders, balance, supply_share
+FROM token_label_holdings
+"#,
+    ),
+];
+
+// Wallet labels and the supply held per label with each snapshot
+pub(super) fn steps() -> Vec<Step> {
+    let mut steps = vec![
+        Step::Sql(WALLET_LABELS_SQL.to_string()),
+        Step::Sql(TOKEN_LABEL_HOLDINGS_TABLE_SQL.to_string()),
+        Step::RecreateView {
+            name: "token_label_holdings_mv".to_string(),
+            create_sql: TOKEN_LABEL_HOLDINGS_MV_SQL.to_string(),
+        },
+    ];
+    for (table, create_sql, view_sql) in ROLLUPS {
+        steps.push(Step::Sql(create_sql.to_string()));
+        steps.push(Step::RecreateView { name: format!("{}_mv", table), create_sql: view_sql.to_string() });
+    }
+    steps
+}
@@ -0,0 +1,20 @@
+use super::Step;
+
+const TOKEN_LAUNCH_ANALYSIS_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS token_launch_analysis (
+    mint_address String,
+    analyzed_at DateTime('UTC') DEFAULT now('UTC'),
+    creation_signature String,
+    launch_slot UInt64,
+    window_slots UInt64,
+    insiders UInt32,
+    snipers UInt32,
+    sniper_acquired Float64,
+    status String,
+    PRIMARY KEY (mint_address)
+) ENGINE = ReplacingMergeTree(analyzed_at)
+"#;
+
+pub(super) fn steps() -> Vec<Step> {
+    vec![Step::Sql(TOKEN_LAUNCH_ANALYSIS_SQL.to_string())]
+}
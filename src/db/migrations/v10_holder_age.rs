@@ -0,0 +1,40 @@
+use super::Step;
+
+const TOKEN_HOLDER_AGE_TABLE_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS token_holder_age (
+    mint_address String,
+    holder_address String,
+    first_seen SimpleAggregateFunction(min, DateTime('UTC')),
+    last_seen SimpleAggregateFunction(max, DateTime('UTC')),
+    PRIMARY KEY (mint_address, holder_address)
+) ENGINE = AggregatingMergeTree
+"#;
+
+const TOKEN_HOLDER_AGE_MV_SQL: &str = r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_holder_age_mv
+TO token_holder_age
+AS
+SELECT
+    mint_address,
+    holder_address,
+    min(timestamp) as first_seen,
+    max(timestamp) as last_seen
+FROM token_holder_balances
+WHERE balance > 0
+GROUP BY mint_address, holder_address
+"#;
+
+// First/last seen per holder, backfilled from the balances already stored
+pub(super) fn steps() -> Vec<Step> {
+    vec![
+        Step::Sql(TOKEN_HOLDER_AGE_TABLE_SQL.to_string()),
+        Step::RecreateView {
+            name: "token_holder_age_mv".to_string(),
+            create_sql: TOKEN_HOLDER_AGE_MV_SQL.to_string(),
+        },
+        Step::Backfill {
+            target: "token_holder_age".to_string(),
+            view_sql: TOKEN_HOLDER_AGE_MV_SQL.to_string(),
+        },
+    ]
+}
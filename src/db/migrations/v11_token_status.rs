@@ -0,0 +1,12 @@
+use super::Step;
+
+pub(super) fn steps() -> Vec<Step> {
+    [
+        "ALTER TABLE monitored_tokens ADD COLUMN IF NOT EXISTS status String DEFAULT 'active'",
+        "ALTER TABLE monitored_tokens ADD COLUMN IF NOT EXISTS status_reason String DEFAULT ''",
+        "ALTER TABLE monitored_tokens ADD COLUMN IF NOT EXISTS status_updated_at DateTime('UTC') DEFAULT now('UTC')",
+    ]
+    .iter()
+    .map(|sql| Step::Sql(sql.to_string()))
+    .collect()
+}
@@ -0,0 +1,23 @@
+use super::Step;
+
+const SNAPSHOT_JOBS_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS snapshot_jobs (
+    job_id String,
+    mint_address String,
+    status String,
+    created_at DateTime64(3, 'UTC'),
+    started_at DateTime64(3, 'UTC') DEFAULT 0,
+    finished_at DateTime64(3, 'UTC') DEFAULT 0,
+    holders UInt64 DEFAULT 0,
+    rows_written UInt64 DEFAULT 0,
+    snapshot_timestamp DateTime('UTC') DEFAULT 0,
+    error String DEFAULT '',
+    updated_at DateTime64(3, 'UTC') DEFAULT now64(3, 'UTC'),
+    PRIMARY KEY (job_id)
+) ENGINE = ReplacingMergeTree(updated_at)
+TTL toDateTime(created_at) + INTERVAL 7 DAY
+"#;
+
+pub(super) fn steps() -> Vec<Step> {
+    vec![Step::Sql(SNAPSHOT_JOBS_SQL.to_string())]
+}
@@ -0,0 +1,29 @@
+use super::Step;
+
+const SNAPSHOT_JOB_CLAIMS_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS snapshot_job_claims (
+    job_id String,
+    attempt UInt32,
+    worker_id String,
+    claimed_at DateTime64(6, 'UTC') DEFAULT now64(6, 'UTC'),
+    PRIMARY KEY (job_id, attempt)
+) ENGINE = MergeTree
+TTL toDateTime(claimed_at) + INTERVAL 7 DAY
+"#;
+
+// Leases, retries and claim rows for the snapshot jobs
+pub(super) fn steps() -> Vec<Step> {
+    let mut steps: Vec<Step> = [
+        "ALTER TABLE snapshot_jobs ADD COLUMN IF NOT EXISTS kind String DEFAULT 'refresh' AFTER mint_address",
+        "ALTER TABLE snapshot_jobs ADD COLUMN IF NOT EXISTS attempts UInt32 DEFAULT 0 AFTER finished_at",
+        "ALTER TABLE snapshot_jobs ADD COLUMN IF NOT EXISTS max_attempts UInt32 DEFAULT 3 AFTER attempts",
+        "ALTER TABLE snapshot_jobs ADD COLUMN IF NOT EXISTS run_after DateTime64(3, 'UTC') DEFAULT 0 AFTER max_attempts",
+        "ALTER TABLE snapshot_jobs ADD COLUMN IF NOT EXISTS lease_owner String DEFAULT '' AFTER run_after",
+        "ALTER TABLE snapshot_jobs ADD COLUMN IF NOT EXISTS lease_expires_at DateTime64(3, 'UTC') DEFAULT 0 AFTER lease_owner",
+    ]
+    .iter()
+    .map(|sql| Step::Sql(sql.to_string()))
+    .collect();
+    steps.push(Step::Sql(SNAPSHOT_JOB_CLAIMS_SQL.to_string()));
+    steps
+}
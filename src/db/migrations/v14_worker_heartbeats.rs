@@ -0,0 +1,16 @@
+use super::Step;
+
+const WORKER_HEARTBEATS_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS worker_heartbeats (
+    worker_id String,
+    mode String,
+    active UInt8 DEFAULT 1,
+    heartbeat_at DateTime64(3, 'UTC'),
+    PRIMARY KEY (worker_id)
+) ENGINE = ReplacingMergeTree(heartbeat_at)
+TTL toDateTime(heartbeat_at) + INTERVAL 1 DAY
+"#;
+
+pub(super) fn steps() -> Vec<Step> {
+    vec![Step::Sql(WORKER_HEARTBEATS_SQL.to_string())]
+}
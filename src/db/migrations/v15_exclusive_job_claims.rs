@@ -0,0 +1,9 @@
+use super::Step;
+
+// Claims of the same attempt share an insert_deduplication_token, which plain
+// MergeTree tables only honour with a deduplication window
+pub(super) fn steps() -> Vec<Step> {
+    vec![Step::Sql(
+        "ALTER TABLE snapshot_job_claims MODIFY SETTING non_replicated_deduplication_window = 10000".to_string(),
+    )]
+}
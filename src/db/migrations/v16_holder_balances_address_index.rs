@@ -0,0 +1,11 @@
+use super::Step;
+
+// The wallet portfolio reads token_holder_balances, same skip index as v7
+pub(super) fn steps() -> Vec<Step> {
+    vec![
+        Step::Sql(
+            "ALTER TABLE token_holder_balances ADD INDEX IF NOT EXISTS holder_address_idx holder_address TYPE bloom_filter(0.01) GRANULARITY 4".to_string(),
+        ),
+        Step::Sql("ALTER TABLE token_holder_balances MATERIALIZE INDEX holder_address_idx".to_string()),
+    ]
+}
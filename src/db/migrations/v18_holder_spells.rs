@@ -0,0 +1,146 @@
+use super::Step;
+
+// One row per stretch of consecutive snapshots a wallet held a token in. A
+// wallet that sells out and buys back starts a new spell.
+const TOKEN_HOLDER_SPELLS_TABLE_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS token_holder_spells (
+    mint_address String,
+    holder_address String,
+    first_seen DateTime('UTC'),
+    last_seen SimpleAggregateFunction(max, DateTime('UTC')),
+    PRIMARY KEY (mint_address, holder_address, first_seen)
+) ENGINE = AggregatingMergeTree
+"#;
+
+// Fires on token_stats like the metric views. Every holder of the snapshot
+// continues its latest spell when it was seen in the previous snapshot, which
+// moves last_seen up, or else starts a new one.
+const TOKEN_HOLDER_SPELLS_MV_SQL: &str = r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_holder_spells_mv
+TO token_holder_spells
+AS
+WITH snapshot_holders AS (
+    SELECT
+        ts.mint_address as mint_address,
+        ts.timestamp as timestamp,
+        thb.holder_address as holder_address,
+        argMax(thb.balance, thb.timestamp) as balance
+    FROM token_stats ts
+    INNER JOIN token_holder_balances thb ON ts.mint_address = thb.mint_address
+    WHERE thb.timestamp >= ts.checkpoint_timestamp
+        AND thb.timestamp <= ts.timestamp
+    GROUP BY ts.mint_address, ts.timestamp, thb.holder_address
+    HAVING balance > 0
+)
+SELECT
+    sh.mint_address as mint_address,
+    sh.holder_address as holder_address,
+    if(
+        spell.first_seen > toDateTime(0, 'UTC') AND spell.last_seen >= previous.timestamp,
+        spell.first_seen,
+        sh.timestamp
+    ) as first_seen,
+    sh.timestamp as last_seen
+FROM snapshot_holders sh
+ASOF LEFT JOIN (
+    SELECT DISTINCT mint_address, timestamp FROM token_stats
+) previous ON sh.mint_address = previous.mint_address AND sh.timestamp > previous.timestamp
+ASOF LEFT JOIN (
+    SELECT mint_address, holder_address, first_seen, max(last_seen) as last_seen
+    FROM token_holder_spells
+    GROUP BY mint_address, holder_address, first_seen
+) spell ON sh.mint_address = spell.mint_address
+    AND sh.holder_address = spell.holder_address
+    AND sh.timestamp > spell.first_seen
+"#;
+
+// Spells of the snapshots already stored. A balance starts one after a zero
+// row or after a checkpoint the wallet had no row in, and the spell lasts until
+// the snapshot before its next zero row or missed checkpoint.
+const TOKEN_HOLDER_SPELLS_SQL: &str = r#"
+WITH
+    checkpoints AS (
+        SELECT DISTINCT mint_address, checkpoint_timestamp FROM token_stats
+    ),
+    balance_rows AS (
+        SELECT
+            mint_address,
+            holder_address,
+            timestamp,
+            balance,
+            lagInFrame(timestamp) OVER w as previous_timestamp,
+            lagInFrame(balance) OVER w as previous_balance,
+            leadInFrame(timestamp) OVER w as next_timestamp
+        FROM token_holder_balances FINAL
+        WINDOW w AS (
+            PARTITION BY mint_address, holder_address ORDER BY timestamp
+            ROWS BETWEEN UNBOUNDED PRECEDING AND UNBOUNDED FOLLOWING
+        )
+    ),
+    spell_starts AS (
+        SELECT
+            br.mint_address as mint_address,
+            br.holder_address as holder_address,
+            br.timestamp as timestamp,
+            br.next_timestamp as next_timestamp,
+            br.previous_balance = 0
+                OR (cp.checkpoint_timestamp > toDateTime(0, 'UTC') AND cp.checkpoint_timestamp < br.timestamp) as starts
+        FROM balance_rows br
+        ASOF LEFT JOIN checkpoints cp ON br.mint_address = cp.mint_address
+            AND br.previous_timestamp < cp.checkpoint_timestamp
+        WHERE br.balance > 0
+    ),
+    spells AS (
+        SELECT
+            mint_address,
+            holder_address,
+            min(timestamp) as first_seen,
+            max(timestamp) as last_balance,
+            argMax(next_timestamp, timestamp) as next_timestamp
+        FROM (
+            SELECT
+                *,
+                sum(starts) OVER (PARTITION BY mint_address, holder_address ORDER BY timestamp ROWS UNBOUNDED PRECEDING) as spell
+            FROM spell_starts
+        )
+        GROUP BY mint_address, holder_address, spell
+    ),
+    spell_ends AS (
+        SELECT
+            s.mint_address as mint_address,
+            s.holder_address as holder_address,
+            s.first_seen as first_seen,
+            s.last_balance as last_balance,
+            arrayMin(arrayFilter(
+                t -> t > toDateTime(0, 'UTC'),
+                [s.next_timestamp, cp.checkpoint_timestamp, toDateTime(4294967295, 'UTC')]
+            )) as ends_before
+        FROM spells s
+        ASOF LEFT JOIN checkpoints cp ON s.mint_address = cp.mint_address
+            AND s.last_balance < cp.checkpoint_timestamp
+    )
+SELECT
+    se.mint_address as mint_address,
+    se.holder_address as holder_address,
+    se.first_seen as first_seen,
+    greatest(se.last_balance, snapshot.timestamp) as last_seen
+FROM spell_ends se
+ASOF LEFT JOIN (
+    SELECT DISTINCT mint_address, timestamp FROM token_stats
+) snapshot ON se.mint_address = snapshot.mint_address AND se.ends_before > snapshot.timestamp
+"#;
+
+// token_holder_age kept a wallet's first snapshot across sell-outs and only saw
+// balance changes and checkpoints, so it is replaced by holding spells
+pub(super) fn steps() -> Vec<Step> {
+    vec![
+        Step::Sql(TOKEN_HOLDER_SPELLS_TABLE_SQL.to_string()),
+        Step::RecreateView {
+            name: "token_holder_spells_mv".to_string(),
+            create_sql: TOKEN_HOLDER_SPELLS_MV_SQL.to_string(),
+        },
+        Step::Sql(format!("INSERT INTO token_holder_spells {}", TOKEN_HOLDER_SPELLS_SQL.trim())),
+        Step::Sql("DROP VIEW IF EXISTS token_holder_age_mv".to_string()),
+        Step::Sql("DROP TABLE IF EXISTS token_holder_age".to_string()),
+    ]
+}
@@ -2,4 +2,7 @@ pub mod schema;
 pub mod models;
 pub mod queries;
 pub mod operations;
-pub mod init;
\ No newline at end of file
+pub mod init;
+pub mod retention;
+pub mod migrations;
+pub mod store;
\ No newline at end of file
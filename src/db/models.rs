@@ -10,6 +10,16 @@ pub struct TokenStatsRecord {
     pub decimals: u8,
 }
 
+// One token_stats row, timestamps as unix seconds
+#[derive(Debug, Clone, Row, serde::Deserialize)]
+pub struct SnapshotRecord {
+    pub timestamp: i64,
+    pub checkpoint_timestamp: i64,
+    pub price: f64,
+    pub supply: f64,
+    pub decimals: u8,
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Row, serde::Deserialize)]
 pub struct TokenHolderThresholdRecord {
@@ -31,7 +41,7 @@ pub struct TokenConcentrationMetricRecord {
     pub mint_address: String,
     #[serde(with = "clickhouse::serde::time::datetime")]
     pub timestamp: OffsetDateTime,
-    pub top_n: u8,
+    pub top_n: u32,
     pub percentage: f64
 }
 
@@ -42,7 +52,161 @@ pub struct TokenDistributionMetricRecord {
     #[serde(with = "clickhouse::serde::time::datetime")]
     pub timestamp: OffsetDateTime,
     pub hhi: f64,
-    pub distribution_score: f64
+    pub distribution_score: f64,
+    pub median_balance: f64,
+    pub total_holders: u64,
+    pub mean_balance: f64,
+    pub gini: f64,
+    pub nakamoto_coefficient: u64,
+    pub theil_index: f64,
+    pub p10: f64,
+    pub p25: f64,
+    pub p50: f64,
+    pub p75: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub lorenz_curve: Vec<f64>,
+}
+
+
+
+#[derive(Debug, Row, serde::Deserialize)]
+pub struct TopHoldersShareRecord {
+    pub total_holders: u64,
+    pub percentages: Vec<f64>,
+    pub holders_for_50pct: u64,
+    pub holders_for_90pct: u64,
+}
+
+#[derive(Debug, Row, serde::Deserialize)]
+pub struct LorenzRecord {
+    pub total_holders: u64,
+    pub holder_shares: Vec<f64>,
+    pub supply_shares: Vec<f64>,
+}
+
+#[derive(Debug, Row, serde::Deserialize)]
+pub struct HolderRankRecord {
+    pub rank: u64,
+    pub holder_address: String,
+    pub balance: f64,
+    pub category: String,
+}
+
+#[derive(Debug, Row, serde::Deserialize)]
+pub struct WalletSnapshotRecord {
+    pub timestamp: i64,
+    pub price: f64,
+    pub supply: f64,
+    pub decimals: u8,
+    pub total_holders: u64,
+    pub balance: f64,
+    pub rank: u64,
+}
+
+// A wallet's balance and rank in the latest snapshot of one token
+#[derive(Debug, Row, serde::Deserialize)]
+pub struct PortfolioTokenRecord {
+    pub mint_address: String,
+    pub timestamp: i64,
+    pub price: f64,
+    pub supply: f64,
+    pub decimals: u8,
+    pub total_holders: u64,
+    pub balance: f64,
+    pub rank: u64,
+}
+
+// WalletSnapshotRecord of one of several tokens
+#[derive(Debug, Row, serde::Deserialize)]
+pub struct PortfolioSnapshotRecord {
+    pub mint_address: String,
+    pub timestamp: i64,
+    pub price: f64,
+    pub supply: f64,
+    pub decimals: u8,
+    pub total_holders: u64,
+    pub balance: f64,
+    pub rank: u64,
+}
+
+#[derive(Debug, Row, serde::Deserialize)]
+pub struct SharedHolderRecord {
+    pub holder_address: String,
+    pub mints: Vec<String>,
+    pub balances: Vec<f64>,
+    pub shares: Vec<f64>,
+}
+
+#[derive(Debug, Row, serde::Deserialize)]
+pub struct MultiTokenWalletRecord {
+    pub holder_address: String,
+    pub token_count: u64,
+    pub mints: Vec<String>,
 }
 
+#[derive(Debug, Clone, Row, serde::Deserialize)]
+pub struct LaunchAnalysisRecord {
+    pub analyzed_at: String,
+    pub status: String,
+    pub creation_signature: String,
+    pub launch_slot: u64,
+    pub window_slots: u64,
+    pub insiders: u32,
+    pub snipers: u32,
+    pub sniper_acquired: f64,
+}
+
+// Times as unix milliseconds, 0 when not reached yet
+#[derive(Debug, Clone, Row, serde::Deserialize)]
+pub struct SnapshotJobRecord {
+    pub job_id: String,
+    pub mint_address: String,
+    pub kind: String,
+    pub status: String,
+    pub created_at: i64,
+    pub started_at: i64,
+    pub finished_at: i64,
+    pub attempts: u32,
+    pub max_attempts: u32,
+    pub run_after: i64,
+    pub lease_owner: String,
+    pub lease_expires_at: i64,
+    pub holders: u64,
+    pub rows_written: u64,
+    pub snapshot_timestamp: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Row, serde::Deserialize)]
+pub struct ClaimableJobRecord {
+    pub job_id: String,
+    pub mint_address: String,
+    pub attempt: u32,
+}
+
+// The claim that won an attempt
+#[derive(Debug, Row, serde::Deserialize)]
+pub struct JobClaimRecord {
+    pub worker_id: String,
+    pub age_secs: i64,
+}
+
+// wallet_labels row written in batches, added_at is filled in by ClickHouse
+#[derive(Debug, Row, serde::Serialize)]
+pub struct WalletLabelRow<'a> {
+    pub mint_address: &'a str,
+    pub address: &'a str,
+    pub label: &'a str,
+    pub reason: &'a str,
+}
 
+// An active token checked for auto-archival, new_holders is NULL while it has
+// no holder spells
+#[derive(Debug, Row, serde::Deserialize)]
+pub struct ArchiveCandidateRecord {
+    pub mint_address: String,
+    pub market_cap: f64,
+    pub new_holders: Option<u64>,
+}
@@ -1,23 +1,29 @@
 use anyhow::Result;
 use clickhouse::Client;
-use crate::types::models::TokenHolderStats;
+use crate::types::models::{TokenHolderStats, TokenStats};
 use solana_sdk::pubkey::Pubkey;
 
 pub async fn insert_token_stats(
     client: &Client,
     mint_address: &str,
-    price: f64,
-    supply: f64,
-    market_cap: f64,
-    decimals: u8,
+    timestamp: &str,
+    checkpoint_timestamp: &str,
+    stats: &TokenStats,
 ) -> Result<(), anyhow::Error> {
-    // Get the timestamp that was just used
-    let timestamp: String = client
-        .query("SELECT toString(max(timestamp)) FROM token_holders WHERE mint_address = ?")
-        .bind(mint_address)
-        .fetch_one()
-        .await?;
+    insert_token_stats_row(client, mint_address, timestamp, checkpoint_timestamp, stats).await?;
+    update_monitored_token_timestamp(client, mint_address, timestamp).await?;
 
+    Ok(())
+}
+
+// Only the token_stats row, without touching the token's schedule
+pub async fn insert_token_stats_row(
+    client: &Client,
+    mint_address: &str,
+    timestamp: &str,
+    checkpoint_timestamp: &str,
+    stats: &TokenStats,
+) -> Result<(), anyhow::Error> {
     client
         .query(
             "INSERT INTO token_stats (
@@ -26,37 +32,44 @@ pub async fn insert_token_stats(
                 price,
                 supply,
                 market_cap,
-                decimals
-            ) VALUES (?, toDateTime(?, 'UTC'), ?, ?, ?, ?)"
+                decimals,
+                checkpoint_timestamp
+            ) VALUES (?, toDateTime(?, 'UTC'), ?, ?, ?, ?, toDateTime(?, 'UTC'))"
         )
         .bind(mint_address)
-        .bind(&timestamp)
-        .bind(price)
-        .bind(supply)
-        .bind(market_cap)
-        .bind(decimals)
+        .bind(timestamp)
+        .bind(stats.price)
+        .bind(stats.supply)
+        .bind(stats.market_cap)
+        .bind(stats.decimals)
+        .bind(checkpoint_timestamp)
         .execute()
         .await?;
 
-    update_monitored_token_timestamp(client, mint_address, &timestamp).await?;
-
     Ok(())
 }
 
+// Rows are either a full checkpoint or only the changed holders of a snapshot
 pub async fn insert_token_holders(
     client: &Client,
     mint_address: &str,
     holders: &[(String, u64, Pubkey)],
+    timestamp: &str,
 ) -> Result<(), anyhow::Error> {
+    if holders.is_empty() {
+        tracing::info!("No holder changes to insert for {}", mint_address);
+        return Ok(());
+    }
     tracing::info!("Starting to insert {} holders for {}", holders.len(), mint_address);
 
     let values = holders.iter()
         .map(|(token_account, amount, holder_address)| 
-            format!("('{}', '{}', '{}', {}, now())", 
+            format!("('{}', '{}', '{}', {}, toDateTime('{}', 'UTC'))", 
                 mint_address, 
                 token_account, 
-                holder_address.to_string(), 
-                amount
+                holder_address, 
+                amount,
+                timestamp
             )
         )
         .collect::<Vec<_>>()
@@ -111,7 +124,14 @@ pub fn structure_token_stats(data: TokenHolderStats) -> serde_json::Value {
             "hhi": (data.distribution_stats.hhi * 10000.0).round() / 10000.0,
             "median_balance": data.distribution_stats.median_balance,
             "mean_balance": data.distribution_stats.mean_balance,
-            "total_count": data.distribution_stats.total_count
+            "total_count": data.distribution_stats.total_count,
+            "gini": (data.distribution_stats.gini * 10000.0).round() / 10000.0,
+            "nakamoto_coefficient": data.distribution_stats.nakamoto_coefficient,
+            "theil_index": (data.distribution_stats.theil_index * 10000.0).round() / 10000.0,
+            "percentiles": data.distribution_stats.percentiles,
+            "lorenz_curve": data.distribution_stats.lorenz_curve.iter()
+                .map(|share| (share * 10000.0).round() / 10000.0)
+                .collect::<Vec<_>>()
         },
         "holder_thresholds": data.holder_thresholds.iter().map(|h| {
             serde_json::json!({
@@ -124,6 +144,9 @@ pub fn structure_token_stats(data: TokenHolderStats) -> serde_json::Value {
                 "slice_value_usd": (h.slice_value_usd * 100.0).round() / 100.0
             })
         }).collect::<Vec<_>>(),
+        "insider_holdings": data.insider_holdings,
+        "sniper_holdings": data.sniper_holdings,
+        "launch": data.launch,
         "concentration_metrics": data.concentration_metrics.iter().map(|m| {
             serde_json::json!({
                 "top_n": m.top_n,
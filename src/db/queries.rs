@@ -1,14 +1,128 @@
 use clickhouse::Client;
 use anyhow::Result;
+use crate::db::models::SnapshotRecord;
 
-pub async fn get_tokens_needing_stats_update(client: &Client) -> Result<Vec<String>> {
+// Current balance of every holder per mint, rebuilt from the newest checkpoint
+// onwards. Use as a subquery and filter on mint_address outside of it.
+pub const LATEST_HOLDER_BALANCES_SQL: &str = "
+    SELECT
+        thb.mint_address AS mint_address,
+        thb.holder_address AS holder_address,
+        argMax(thb.balance, thb.timestamp) AS balance
+    FROM token_holder_balances thb
+    INNER JOIN (
+        SELECT mint_address, max(checkpoint_timestamp) AS checkpoint_timestamp
+        FROM token_stats
+        GROUP BY mint_address
+    ) cp ON thb.mint_address = cp.mint_address
+    WHERE thb.timestamp >= cp.checkpoint_timestamp
+    GROUP BY thb.mint_address, thb.holder_address
+    HAVING balance > 0
+";
+
+// Supply and decimals of each mint's latest snapshot, filter on mint_address outside of it
+pub const LATEST_SUPPLY_SQL: &str = "
+    SELECT
+        ts.mint_address AS mint_address,
+        argMax(ts.supply, ts.timestamp) AS supply,
+        argMax(ts.decimals, ts.timestamp) AS decimals
+    FROM token_stats ts
+    GROUP BY ts.mint_address
+";
+
+// Balance of every holder as of one stored snapshot. Binds mint_address, the
+// snapshot's checkpoint_timestamp and its timestamp, both as unix seconds.
+pub const SNAPSHOT_HOLDER_BALANCES_SQL: &str = "
+    SELECT
+        thb.holder_address AS holder_address,
+        argMax(thb.balance, thb.timestamp) AS balance
+    FROM token_holder_balances thb
+    WHERE thb.mint_address = ?
+        AND thb.timestamp BETWEEN toDateTime(?, 'UTC') AND toDateTime(?, 'UTC')
+    GROUP BY thb.holder_address
+    HAVING balance > 0
+";
+
+// Same as SNAPSHOT_HOLDER_BALANCES_SQL but read from token_holders, so excluded
+// accounts are included. Amounts are summed over the owner's token accounts.
+pub const SNAPSHOT_OWNER_BALANCES_SQL: &str = "
+    SELECT
+        holder_address,
+        argMax(amount, timestamp) AS balance
+    FROM (
+        SELECT holder_address, timestamp, toFloat64(sum(amount)) AS amount
+        FROM token_holders
+        WHERE mint_address = ?
+            AND timestamp BETWEEN toDateTime(?, 'UTC') AND toDateTime(?, 'UTC')
+        GROUP BY holder_address, timestamp
+    )
+    GROUP BY holder_address
+    HAVING balance > 0
+";
+
+// Newest snapshot taken at or before `at` (unix seconds)
+pub async fn find_snapshot(client: &Client, mint_address: &str, at: i64) -> Result<Option<SnapshotRecord>> {
+    let snapshot = client
+        .query("
+            SELECT
+                toInt64(timestamp) AS timestamp,
+                toInt64(checkpoint_timestamp) AS checkpoint_timestamp,
+                price,
+                supply,
+                decimals
+            FROM token_stats
+            WHERE mint_address = ? AND timestamp <= toDateTime(?, 'UTC')
+            ORDER BY timestamp DESC
+            LIMIT 1
+        ")
+        .bind(mint_address)
+        .bind(at)
+        .fetch_optional::<SnapshotRecord>()
+        .await?;
+
+    Ok(snapshot)
+}
+
+// Snapshots taken between two unix timestamps, oldest first
+pub async fn snapshots_between(client: &Client, mint_address: &str, from: i64, to: i64, limit: u64) -> Result<Vec<SnapshotRecord>> {
+    let snapshots = client
+        .query("
+            SELECT
+                toInt64(timestamp) AS timestamp,
+                toInt64(checkpoint_timestamp) AS checkpoint_timestamp,
+                price,
+                supply,
+                decimals
+            FROM token_stats FINAL
+            WHERE mint_address = ?
+                AND timestamp BETWEEN toDateTime(?, 'UTC') AND toDateTime(?, 'UTC')
+            ORDER BY timestamp
+            LIMIT ?
+        ")
+        .bind(mint_address)
+        .bind(from)
+        .bind(to)
+        .bind(limit)
+        .fetch_all::<SnapshotRecord>()
+        .await?;
+
+    Ok(snapshots)
+}
+
+// Tokens not snapshotted within the interval. Tokens that already have a queued
+// or running snapshot job are left to that job.
+pub async fn get_tokens_needing_stats_update(client: &Client, interval_secs: u64) -> Result<Vec<String>> {
     let query = "
         SELECT mint_address 
         FROM monitored_tokens 
-        WHERE last_stats_update < subtractMinutes(now(), 1)
+        WHERE last_stats_update < subtractSeconds(now(), ?)
+            AND status = 'active'
+            AND mint_address NOT IN (
+                SELECT mint_address FROM snapshot_jobs FINAL WHERE status IN ('queued', 'running')
+            )
     ";
     
-    let mut cursor = client.query(query).fetch::<String>()?;
+    let mut cursor = client.query(query).bind(interval_secs).fetch::<String>()?;
     let mut results = Vec::new();
     
     while let Some(row) = cursor.next().await? {
@@ -18,14 +132,18 @@ pub async fn get_tokens_needing_stats_update(client: &Client) -> Result<Vec<Stri
     Ok(results)
 }
 
-pub async fn get_tokens_needing_metrics_update(client: &Client) -> Result<Vec<String>> {
+pub async fn get_tokens_needing_metrics_update(client: &Client, interval_secs: u64) -> Result<Vec<String>> {
     let query = "
         SELECT mint_address 
         FROM monitored_tokens 
-        WHERE last_metrics_update < subtractHours(now(), 4)
+        WHERE last_metrics_update < subtractSeconds(now(), ?)
+            AND status = 'active'
+            AND mint_address NOT IN (
+                SELECT mint_address FROM snapshot_jobs FINAL WHERE status IN ('queued', 'running')
+            )
     ";
     
-    let mut cursor = client.query(query).fetch::<String>()?;
+    let mut cursor = client.query(query).bind(interval_secs).fetch::<String>()?;
     let mut results = Vec::new();
     
     while let Some(row) = cursor.next().await? {
@@ -33,4 +151,15 @@ pub async fn get_tokens_needing_metrics_update(client: &Client) -> Result<Vec<St
     }
     
     Ok(results)
-}
\ No newline at end of file
+}
+
+// When the token was first added, None if it never was
+pub async fn get_monitoring_start(client: &Client, mint_address: &str) -> Result<Option<i64>> {
+    let start = client
+        .query("SELECT toInt64(created_at) FROM monitored_tokens FINAL WHERE mint_address = ? LIMIT 1")
+        .bind(mint_address)
+        .fetch_optional::<i64>()
+        .await?;
+
+    Ok(start)
+}
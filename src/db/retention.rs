@@ -0,0 +1,59 @@
+use anyhow::Result;
+use clickhouse::Client;
+use crate::config::RetentionConfig;
+use crate::db::schema::ROLLUP_SOURCES;
+
+// (table, TTL column, days). 0 days keeps rows forever.
+fn table_ttls(config: &RetentionConfig) -> Vec<(String, &'static str, u32)> {
+    let mut tables: Vec<(String, &'static str, u32)> = [
+        ("token_holders", "timestamp", config.token_holders_days),
+        ("token_holder_balances", "timestamp", config.token_holder_balances_days),
+        ("token_stats", "timestamp", config.token_stats_days),
+        ("token_thresholds", "timestamp", config.token_thresholds_days),
+        ("token_holder_counts", "timestamp", config.token_holder_counts_days),
+        ("token_concentration", "timestamp", config.token_concentration_days),
+        ("token_distribution", "timestamp", config.token_distribution_days),
+        ("token_label_holdings", "timestamp", config.token_label_holdings_days),
+    ]
+    .into_iter()
+    .map(|(table, column, days)| (table.to_string(), column, days))
+    .collect();
+    for (tier, days) in [("hourly", config.hourly_rollup_days), ("daily", config.daily_rollup_days)] {
+        for source in ROLLUP_SOURCES {
+            tables.push((format!("{}_{}", source, tier), "bucket", days));
+        }
+    }
+    tables
+}
+
+// Brings every table's TTL in line with the config. Tables that already carry the
+// wanted TTL are left alone, since MODIFY TTL rewrites existing parts.
+pub async fn apply_retention(client: &Client, config: &RetentionConfig) -> Result<()> {
+    for (table, column, days) in table_ttls(config) {
+        let engine: String = client
+            .query("SELECT engine_full FROM system.tables WHERE database = currentDatabase() AND name = ?")
+            .bind(&table)
+            .fetch_one()
+            .await?;
+
+        let has_ttl = engine.contains(" TTL ");
+        if days == 0 {
+            if has_ttl {
+                client.query(&format!("ALTER TABLE {} REMOVE TTL", table)).execute().await?;
+                tracing::info!("Removed TTL from {}", table);
+            }
+            continue;
+        }
+
+        if has_ttl && engine.contains(&format!("toIntervalDay({})", days)) {
+            continue;
+        }
+        client
+            .query(&format!("ALTER TABLE {} MODIFY TTL {} + INTERVAL {} DAY", table, column, days))
+            .execute()
+            .await?;
+        tracing::info!("Set TTL on {} to {} days", table, days);
+    }
+
+    Ok(())
+}
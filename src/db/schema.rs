@@ -1,111 +1,32 @@
-pub const MONITORED_TOKENS_SQL: &str = r#"
-CREATE TABLE IF NOT EXISTS monitored_tokens (
-    mint_address String,
-    last_stats_update DateTime('UTC'),
-    last_metrics_update DateTime('UTC'),
-    created_at DateTime('UTC') DEFAULT now('UTC'),
-    PRIMARY KEY (mint_address)
+// Applied migrations, see db::migrations
+pub const SCHEMA_MIGRATIONS_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS schema_migrations (
+    version UInt32,
+    name String,
+    applied_at DateTime('UTC') DEFAULT now('UTC'),
+    PRIMARY KEY (version)
 ) ENGINE = ReplacingMergeTree
 "#;
 
-pub const TOKEN_STATS_SQL: &str = r#"
-CREATE TABLE IF NOT EXISTS token_stats (
-    mint_address String,
-    timestamp DateTime('UTC'),
-    price Float64,
-    supply Float64,
-    market_cap Float64,
-    decimals UInt8,
-    PRIMARY KEY (mint_address, timestamp)
-) ENGINE = ReplacingMergeTree
-"#;
-
-// Raw holder data from every rpc call
-pub const TOKEN_HOLDERS_SQL: &str = r#"
-CREATE TABLE IF NOT EXISTS token_holders (
-    mint_address String,
-    token_account String,
-    holder_address String,
-    amount UInt64,
-    timestamp DateTime('UTC'),
-    PRIMARY KEY (mint_address, holder_address, timestamp)
-) ENGINE = ReplacingMergeTree
-"#;
-
-//accumulated exclusions list checked every 24 hrs
-pub const EXCLUDED_ACCOUNTS_SQL: &str = r#"
-CREATE TABLE IF NOT EXISTS excluded_accounts (
-    address String,
-    category String,
-    description String,
-    added_at DateTime('UTC') DEFAULT now('UTC'),
-    PRIMARY KEY (address)
-) ENGINE = ReplacingMergeTree
-"#;
-
-// Target tables for MVs
-//this is the cleaned up holder data - removing the exclusions
-pub const TOKEN_HOLDER_BALANCES_TABLE_SQL: &str = r#"
-CREATE TABLE IF NOT EXISTS token_holder_balances (
-    mint_address String,
-    holder_address String,
-    balance Float64,
-    timestamp DateTime('UTC'),
-    PRIMARY KEY (mint_address, holder_address)
-) ENGINE = ReplacingMergeTree
-"#;
-
-
-pub const TOKEN_THRESHOLDS_TABLE_SQL: &str = r#"
-CREATE TABLE IF NOT EXISTS token_thresholds (
-    mint_address String,
-    usd_threshold Float64,
-    token_amount Float64,
-    timestamp DateTime('UTC'),
-    PRIMARY KEY (mint_address, usd_threshold, timestamp)
-) ENGINE = ReplacingMergeTree
-"#;
-
-
-pub const TOKEN_HOLDER_COUNTS_TABLE_SQL: &str = r#"
-CREATE TABLE IF NOT EXISTS token_holder_counts (
-    mint_address String,
-    timestamp DateTime('UTC'),
-    usd_threshold Float64,
-    holder_count UInt64,
-    total_holders UInt64,
-    pct_total_holders Float64 DEFAULT 0,
-    pct_of_10usd Float64 DEFAULT 0,
-    mcap_per_holder Float64 DEFAULT 0,
-    slice_value_usd Float64 DEFAULT 0,
-    PRIMARY KEY (mint_address, timestamp, usd_threshold)
-) ENGINE = ReplacingMergeTree
-"#;
-
-pub const TOKEN_CONCENTRATION_TABLE_SQL: &str = r#"
-CREATE TABLE IF NOT EXISTS token_concentration (
-    mint_address String,
-    timestamp DateTime('UTC'),
-    top_n UInt8,
-    percentage Float64,
-    PRIMARY KEY (mint_address, timestamp, top_n)
-) ENGINE = ReplacingMergeTree
-"#;
-
-pub const TOKEN_DISTRIBUTION_TABLE_SQL: &str = r#"
-CREATE TABLE IF NOT EXISTS token_distribution (
-    mint_address String,
-    timestamp DateTime('UTC'),
-    hhi Float64,
-    distribution_score Float64,
-    median_balance Float64,
-    total_holders UInt64,
-    mean_balance Float64,
-    PRIMARY KEY (mint_address, timestamp)
-) ENGINE = ReplacingMergeTree
+// Claims on applying a migration, see db::migrations::run_migrations. Claims of
+// an attempt share an insert_deduplication_token, which plain MergeTree tables
+// only honour with a deduplication window. An owner whose migration failed
+// adds a released row to let the next attempt through.
+pub const SCHEMA_MIGRATION_CLAIMS_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS schema_migration_claims (
+    version UInt32,
+    attempt UInt32,
+    owner String,
+    released UInt8 DEFAULT 0,
+    claimed_at DateTime64(6, 'UTC') DEFAULT now64(6, 'UTC'),
+    PRIMARY KEY (version, attempt)
+) ENGINE = MergeTree
+SETTINGS non_replicated_deduplication_window = 10000
 "#;
 
-// Materialized Views in dependency order
+// Current definitions of the materialized views, replayed over stored snapshots
+// by recompute and replay. The views themselves are created by the frozen SQL in
+// db::migrations, so a change here needs a migration recreating the view with it.
 pub const TOKEN_HOLDER_BALANCES_MV_SQL: &str = r#"
 CREATE MATERIALIZED VIEW IF NOT EXISTS token_holder_balances_mv
 TO token_holder_balances
@@ -119,117 +40,162 @@ LEFT ANTI JOIN excluded_accounts ea ON th.holder_address = ea.address
 GROUP BY th.mint_address, th.holder_address, th.timestamp
 "#;
 
+// The metric views fire on token_stats, which is written after the snapshot's
+// holder rows, and rebuild the full holder set from the checkpoint onwards
 pub const TOKEN_THRESHOLDS_MV_SQL: &str = r#"
 CREATE MATERIALIZED VIEW IF NOT EXISTS token_thresholds_mv
 TO token_thresholds
 AS 
 SELECT 
-    ts.mint_address,
-    value as usd_threshold,
-    value / ts.price as token_amount,
-    ts.timestamp
-FROM token_stats ts
-INNER JOIN token_holder_balances thb 
-    ON ts.mint_address = thb.mint_address 
-    AND ts.timestamp = thb.timestamp
-ARRAY JOIN [10, 100, 1000, 10000, 100000] as value
-WHERE ts.price > 0
+    mint_address,
+    usd_threshold,
+    usd_threshold / price as token_amount,
+    timestamp
+FROM (
+    SELECT
+        ts.mint_address as mint_address,
+        ts.timestamp as timestamp,
+        ts.price as price,
+        if(notEmpty(cfg.thresholds), cfg.thresholds, dflt.thresholds) as thresholds
+    FROM token_stats ts
+    LEFT JOIN (
+        SELECT mint_address, argMax(thresholds, updated_at) as thresholds
+        FROM token_threshold_config
+        WHERE mint_address != ''
+        GROUP BY mint_address
+    ) cfg ON ts.mint_address = cfg.mint_address
+    CROSS JOIN (
+        SELECT argMax(thresholds, updated_at) as thresholds
+        FROM token_threshold_config
+        WHERE mint_address = ''
+    ) dflt
+    WHERE ts.price > 0
+)
+ARRAY JOIN thresholds as usd_threshold
 "#;
 
 
+// pct_of_10usd is relative to the lowest configured threshold, which is $10 by default
 pub const TOKEN_HOLDER_COUNTS_MV_SQL: &str = r#"
 CREATE MATERIALIZED VIEW IF NOT EXISTS token_holder_counts_mv
 TO token_holder_counts
 AS 
+WITH snapshot_holders AS (
+    SELECT
+        ts.mint_address as mint_address,
+        ts.timestamp as timestamp,
+        any(ts.price) as price,
+        any(ts.decimals) as decimals,
+        any(ts.market_cap) as market_cap,
+        any(if(notEmpty(cfg.thresholds), cfg.thresholds, dflt.thresholds)) as thresholds,
+        thb.holder_address as holder_address,
+        argMax(thb.balance, thb.timestamp) as balance
+    FROM token_stats ts
+    INNER JOIN token_holder_balances thb ON ts.mint_address = thb.mint_address
+    LEFT JOIN (
+        SELECT mint_address, argMax(thresholds, updated_at) as thresholds
+        FROM token_threshold_config
+        WHERE mint_address != ''
+        GROUP BY mint_address
+    ) cfg ON ts.mint_address = cfg.mint_address
+    CROSS JOIN (
+        SELECT argMax(thresholds, updated_at) as thresholds
+        FROM token_threshold_config
+        WHERE mint_address = ''
+    ) dflt
+    WHERE thb.timestamp >= ts.checkpoint_timestamp
+        AND thb.timestamp <= ts.timestamp
+        AND ts.price > 0
+    GROUP BY ts.mint_address, ts.timestamp, thb.holder_address
+    HAVING balance > 0
+)
 SELECT 
-    thb.mint_address as mint_address,
-    thb.timestamp as timestamp,
-    tt.usd_threshold,
-    countDistinct(multiIf(thb.balance / pow(10, ts.decimals) >= tt.token_amount, thb.holder_address, NULL)) AS holder_count,
-    countDistinct(thb.holder_address) AS total_holders,
+    mint_address,
+    timestamp,
+    usd_threshold,
+    countIf(balance / pow(10, decimals) * price >= usd_threshold) AS holder_count,
+    count() AS total_holders,
     coalesce((holder_count / nullIf(total_holders, 0)) * 100, 0) AS pct_total_holders,
-    coalesce((holder_count / nullIf(any(holder_count) OVER (PARTITION BY thb.mint_address, thb.timestamp ORDER BY tt.usd_threshold ASC), 0)) * 100, 0) AS pct_of_10usd,
-    coalesce(max(ts.market_cap) / nullIf(holder_count, 0), 0) AS mcap_per_holder,
-    coalesce(sum(multiIf(thb.balance / pow(10, ts.decimals) >= tt.token_amount, thb.balance / pow(10, ts.decimals) * ts.price, 0)), 0) AS slice_value_usd
-FROM token_holder_balances thb
-JOIN (
-    SELECT mint_address, max(timestamp) as max_ts
-    FROM token_stats
-    GROUP BY mint_address
-) latest_ts ON thb.mint_address = latest_ts.mint_address
-JOIN token_stats ts 
-    ON thb.mint_address = ts.mint_address 
-    AND ts.timestamp = latest_ts.max_ts
-JOIN token_thresholds tt 
-    ON thb.mint_address = tt.mint_address 
-    AND tt.timestamp = latest_ts.max_ts
-WHERE tt.usd_threshold IN (10, 100, 1000, 10000, 100000)
+    coalesce((holder_count / nullIf(countIf(balance / pow(10, decimals) * price >= arrayMin(thresholds)), 0)) * 100, 0) AS pct_of_10usd,
+    coalesce(any(market_cap) / nullIf(holder_count, 0), 0) AS mcap_per_holder,
+    sumIf(balance / pow(10, decimals) * price, balance / pow(10, decimals) * price >= usd_threshold) AS slice_value_usd
+FROM snapshot_holders
+ARRAY JOIN thresholds AS usd_threshold
 GROUP BY
-    thb.mint_address,
-    thb.timestamp,
-    tt.usd_threshold
+    mint_address,
+    timestamp,
+    usd_threshold
 "#;
 
 pub const TOKEN_CONCENTRATION_MV_SQL: &str = r#"
 CREATE MATERIALIZED VIEW IF NOT EXISTS token_concentration_mv
 TO token_concentration
 AS 
-WITH ranked_holders AS (
+WITH snapshot_holders AS (
     SELECT
-        thb.mint_address,
-        thb.timestamp,
-        thb.balance,
-        row_number() OVER (PARTITION BY thb.mint_address, thb.timestamp ORDER BY thb.balance DESC) AS rank
-    FROM token_holder_balances thb
+        ts.mint_address as mint_address,
+        ts.timestamp as timestamp,
+        any(ts.supply) as supply,
+        any(if(notEmpty(cfg.buckets), cfg.buckets, dflt.buckets)) as buckets,
+        thb.holder_address as holder_address,
+        argMax(thb.balance, thb.timestamp) as balance
+    FROM token_stats ts
+    INNER JOIN token_holder_balances thb ON ts.mint_address = thb.mint_address
+    LEFT JOIN (
+        SELECT mint_address, argMax(buckets, updated_at) as buckets
+        FROM token_concentration_config
+        WHERE mint_address != ''
+        GROUP BY mint_address
+    ) cfg ON ts.mint_address = cfg.mint_address
+    CROSS JOIN (
+        SELECT argMax(buckets, updated_at) as buckets
+        FROM token_concentration_config
+        WHERE mint_address = ''
+    ) dflt
+    WHERE thb.timestamp >= ts.checkpoint_timestamp
+        AND thb.timestamp <= ts.timestamp
+    GROUP BY ts.mint_address, ts.timestamp, thb.holder_address
+    HAVING balance > 0
+),
+sorted_balances AS (
+    SELECT
+        mint_address,
+        timestamp,
+        any(supply) as supply,
+        any(buckets) as buckets,
+        arrayReverseSort(groupArray(balance)) as balances
+    FROM snapshot_holders
+    GROUP BY mint_address, timestamp
 )
 SELECT
-    rh.mint_address as mint_address,
-    rh.timestamp as timestamp,
-    t.top_n,
-    (sum(rh.balance) / max(ts.supply)) * 100 AS percentage
-FROM ranked_holders rh
-JOIN (
-    SELECT mint_address, max(timestamp) as max_ts
-    FROM token_stats
-    GROUP BY mint_address
-) latest_ts ON rh.mint_address = latest_ts.mint_address
-JOIN token_stats ts 
-    ON rh.mint_address = ts.mint_address 
-    AND ts.timestamp = latest_ts.max_ts
-CROSS JOIN (
-    SELECT 1 AS top_n
-    UNION ALL SELECT 10
-    UNION ALL SELECT 25
-    UNION ALL SELECT 50
-    UNION ALL SELECT 100
-    UNION ALL SELECT 250
-) t
-WHERE rh.rank <= t.top_n
-GROUP BY
-    rh.mint_address,
-    rh.timestamp,
-    t.top_n
+    mint_address,
+    timestamp,
+    top_n,
+    (arraySum(arraySlice(balances, 1, top_n)) / supply) * 100 AS percentage
+FROM sorted_balances
+ARRAY JOIN buckets AS top_n
 "#;
 
+// Nakamoto is the fewest holders owning more than half of the non-excluded
+// balance. The Lorenz curve is the cumulative share of that balance held by the
+// poorest 0%, 5%, ..., 100% of holders.
 pub const TOKEN_DISTRIBUTION_MV_SQL: &str = r#"
 CREATE MATERIALIZED VIEW IF NOT EXISTS token_distribution_mv
 TO token_distribution
 AS 
 WITH holder_amounts AS (
     SELECT
-        thb.mint_address as mint_address,
-        thb.timestamp as timestamp,
-        toFloat64(thb.balance) as balance,
-        toFloat64(ts.supply) as supply
-    FROM token_holder_balances thb
-    JOIN (
-        SELECT mint_address, max(timestamp) as max_ts
-        FROM token_stats
-        GROUP BY mint_address
-    ) latest_ts ON thb.mint_address = latest_ts.mint_address
-    JOIN token_stats ts 
-        ON thb.mint_address = ts.mint_address 
-        AND ts.timestamp = latest_ts.max_ts
+        ts.mint_address as mint_address,
+        ts.timestamp as timestamp,
+        toFloat64(any(ts.supply)) as supply,
+        thb.holder_address as holder_address,
+        toFloat64(argMax(thb.balance, thb.timestamp)) as balance
+    FROM token_stats ts
+    INNER JOIN token_holder_balances thb ON ts.mint_address = thb.mint_address
+    WHERE thb.timestamp >= ts.checkpoint_timestamp
+        AND thb.timestamp <= ts.timestamp
+    GROUP BY ts.mint_address, ts.timestamp, thb.holder_address
+    HAVING balance > 0
 ),
 ranked_amounts AS (
     SELECT
@@ -240,21 +206,177 @@ ranked_amounts AS (
         row_number() OVER (PARTITION BY mint_address, timestamp ORDER BY balance) as rank,
         count() OVER (PARTITION BY mint_address, timestamp) as total_count
     FROM holder_amounts
+),
+snapshot_aggregates AS (
+    SELECT
+        mint_address,
+        timestamp,
+        sum(pow((balance / supply) * 100, 2)) as hhi,
+        (1 - (
+            sum(balance * (rank - 1))
+            / 
+            (count() * sum(balance))
+        )) * 100 as distribution_score,
+        quantileExact(0.5)(balance) as median_balance,
+        any(total_count) as total_holders,
+        sum(balance) / any(total_count) as mean_balance,
+        2 * sum(rank * balance) / (count() * sum(balance)) - (count() + 1) / count() as gini,
+        sum(balance * ln(balance)) / sum(balance) - ln(sum(balance) / count()) as theil_index,
+        quantilesExact(0.1, 0.25, 0.5, 0.75, 0.9, 0.95, 0.99)(balance) as percentiles,
+        arraySort(groupArray(balance)) as sorted_balances,
+        sum(balance) as held
+    FROM ranked_amounts
+    GROUP BY
+        mint_address,
+        timestamp
 )
 SELECT
     mint_address,
     timestamp,
-    sum(pow((balance / supply) * 100, 2)) as hhi,
-    (1 - (
-        sum(balance * (rank - 1))
-        / 
-        (count() * sum(balance))
-    )) * 100 as distribution_score,
-    quantileExact(0.5)(balance) as median_balance,
-    any(total_count) as total_holders,
-    sum(balance) / any(total_count) as mean_balance
-FROM ranked_amounts
-GROUP BY
-    mint_address,
-    timestamp
+    hhi,
+    distribution_score,
+    median_balance,
+    total_holders,
+    mean_balance,
+    gini,
+    arrayFirstIndex(x -> x > held * 0.5, arrayCumSum(arrayReverse(sorted_balances))) as nakamoto_coefficient,
+    theil_index,
+    percentiles[1] as p10,
+    percentiles[2] as p25,
+    percentiles[3] as p50,
+    percentiles[4] as p75,
+    percentiles[5] as p90,
+    percentiles[6] as p95,
+    percentiles[7] as p99,
+    arrayMap(
+        k -> arraySum(arraySlice(sorted_balances, 1, intDiv(k * length(sorted_balances), 20))) / held,
+        range(21)
+    ) as lorenz_curve
+FROM snapshot_aggregates
 "#;
+
+// Labelled wallets that left the token have no rows in the snapshot and count
+// as zero, a label without any holding wallet writes no row
+pub const TOKEN_LABEL_HOLDINGS_MV_SQL: &str = r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_label_holdings_mv
+TO token_label_holdings
+AS
+WITH snapshot_holders AS (
+    SELECT
+        ts.mint_address as mint_address,
+        ts.timestamp as timestamp,
+        any(ts.supply) as supply,
+        thb.holder_address as holder_address,
+        argMax(thb.balance, thb.timestamp) as balance
+    FROM token_stats ts
+    INNER JOIN token_holder_balances thb ON ts.mint_address = thb.mint_address
+    WHERE thb.timestamp >= ts.checkpoint_timestamp
+        AND thb.timestamp <= ts.timestamp
+        AND thb.holder_address IN (SELECT address FROM wallet_labels)
+    GROUP BY ts.mint_address, ts.timestamp, thb.holder_address
+    HAVING balance > 0
+)
+SELECT
+    sh.mint_address as mint_address,
+    sh.timestamp as timestamp,
+    wl.label as label,
+    count() as holders,
+    sum(sh.balance) as balance,
+    sum(sh.balance) / any(sh.supply) * 100 as supply_share
+FROM snapshot_holders sh
+INNER JOIN (
+    SELECT DISTINCT mint_address, address, label FROM wallet_labels
+) wl ON sh.mint_address = wl.mint_address AND sh.holder_address = wl.address
+GROUP BY sh.mint_address, sh.timestamp, wl.label
+"#;
+
+// Holding spells of the stored snapshots, see the holder_spells migration.
+// Replayed by recompute and after imports, since token_holder_spells_mv only
+// extends the spells of the snapshots written before.
+pub const TOKEN_HOLDER_SPELLS_SQL: &str = r#"
+WITH
+    checkpoints AS (
+        SELECT DISTINCT mint_address, checkpoint_timestamp FROM token_stats
+    ),
+    balance_rows AS (
+        SELECT
+            mint_address,
+            holder_address,
+            timestamp,
+            balance,
+            lagInFrame(timestamp) OVER w as previous_timestamp,
+            lagInFrame(balance) OVER w as previous_balance,
+            leadInFrame(timestamp) OVER w as next_timestamp
+        FROM token_holder_balances FINAL
+        WINDOW w AS (
+            PARTITION BY mint_address, holder_address ORDER BY timestamp
+            ROWS BETWEEN UNBOUNDED PRECEDING AND UNBOUNDED FOLLOWING
+        )
+    ),
+    spell_starts AS (
+        SELECT
+            br.mint_address as mint_address,
+            br.holder_address as holder_address,
+            br.timestamp as timestamp,
+            br.next_timestamp as next_timestamp,
+            br.previous_balance = 0
+                OR (cp.checkpoint_timestamp > toDateTime(0, 'UTC') AND cp.checkpoint_timestamp < br.timestamp) as starts
+        FROM balance_rows br
+        ASOF LEFT JOIN checkpoints cp ON br.mint_address = cp.mint_address
+            AND br.previous_timestamp < cp.checkpoint_timestamp
+        WHERE br.balance > 0
+    ),
+    spells AS (
+        SELECT
+            mint_address,
+            holder_address,
+            min(timestamp) as first_seen,
+            max(timestamp) as last_balance,
+            argMax(next_timestamp, timestamp) as next_timestamp
+        FROM (
+            SELECT
+                *,
+                sum(starts) OVER (PARTITION BY mint_address, holder_address ORDER BY timestamp ROWS UNBOUNDED PRECEDING) as spell
+            FROM spell_starts
+        )
+        GROUP BY mint_address, holder_address, spell
+    ),
+    spell_ends AS (
+        SELECT
+            s.mint_address as mint_address,
+            s.holder_address as holder_address,
+            s.first_seen as first_seen,
+            s.last_balance as last_balance,
+            arrayMin(arrayFilter(
+                t -> t > toDateTime(0, 'UTC'),
+                [s.next_timestamp, cp.checkpoint_timestamp, toDateTime(4294967295, 'UTC')]
+            )) as ends_before
+        FROM spells s
+        ASOF LEFT JOIN checkpoints cp ON s.mint_address = cp.mint_address
+            AND s.last_balance < cp.checkpoint_timestamp
+    )
+SELECT
+    se.mint_address as mint_address,
+    se.holder_address as holder_address,
+    se.first_seen as first_seen,
+    greatest(se.last_balance, snapshot.timestamp) as last_seen
+FROM spell_ends se
+ASOF LEFT JOIN (
+    SELECT DISTINCT mint_address, timestamp FROM token_stats
+) snapshot ON se.mint_address = snapshot.mint_address AND se.ends_before > snapshot.timestamp
+"#;
+
+// Metric tables with downsampled copies, created by db::migrations. Each snapshot
+// is written into its hour and day bucket and ReplacingMergeTree keeps the latest
+// one, so a bucket holds the last snapshot taken in it. They outlive the raw
+// tables' TTL.
+pub const ROLLUP_SOURCES: &[&str] = &[
+    "token_stats",
+    "token_distribution",
+    "token_concentration",
+    "token_holder_counts",
+    "token_label_holdings",
+];
+
+// Suffixes of the rollup tables
+pub const ROLLUP_TIERS: &[&str] = &["hourly", "daily"];
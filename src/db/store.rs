@@ -0,0 +1,1554 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use clickhouse::Client;
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::Mutex;
+use crate::config::ClickHouseConfig;
+use crate::db::models::{
+    ArchiveCandidateRecord,
+    ClaimableJobRecord,
+    HolderRankRecord,
+    LaunchAnalysisRecord,
+    PortfolioTokenRecord,
+    SharedHolderRecord,
+    SnapshotJobRecord,
+    SnapshotRecord,
+    WalletSnapshotRecord,
+};
+use crate::db::operations::{insert_token_holders, insert_token_stats, insert_token_stats_row};
+use crate::db::queries::{
+    find_snapshot,
+    get_monitoring_start,
+    get_tokens_needing_metrics_update,
+    get_tokens_needing_stats_update,
+    snapshots_between,
+    LATEST_HOLDER_BALANCES_SQL,
+};
+use crate::services::excluded_accounts::{get_largest_holders, import_excluded_accounts};
+use crate::services::export::{check_holder_export_range, export_rows, export_stream, ExportDataset, ExportFormat, ExportStream, MAX_HOLDER_EXPORT_SNAPSHOTS};
+use crate::services::history::{candidate_resolutions, get_token_history};
+use crate::services::launch::{
+    get_tokens_pending_launch_analysis,
+    get_wallet_labels,
+    label_holdings,
+    launch_info,
+    store_launch_analysis,
+    LaunchAnalysis,
+    INSIDER_LABEL,
+    SNIPER_LABEL,
+};
+use crate::services::lifecycle::{get_archive_candidates, get_token_status, set_token_status};
+use crate::services::metrics::{self, compute_metrics, owner_balances, SnapshotMetrics};
+use crate::services::recompute::rebuild_holder_spells;
+use crate::services::sharding::{live_workers, send_heartbeat};
+use crate::services::token::{get_token_metrics, SnapshotSummary};
+use crate::services::{concentration, distribution, holders, jobs, overlap, thresholds};
+use crate::types::models::{
+    BalanceHistogram,
+    ConcentrationBucketConfig,
+    ConfigSource,
+    HistogramBucket,
+    HistoryPoint,
+    HolderAge,
+    HolderCountPoint,
+    HolderList,
+    LabelHoldings,
+    LorenzCurve,
+    LorenzPoint,
+    MonitoredToken,
+    MultiTokenWallet,
+    MultiTokenWallets,
+    Resolution,
+    SnapshotJob,
+    ThresholdConfig,
+    TokenConcentration,
+    TokenHistory,
+    TokenHolderStats,
+    TokenStats,
+    TokenStatus,
+    WalletHolding,
+    WalletLabel,
+    WalletOverlap,
+    WalletPortfolio,
+};
+
+// Monitored tokens, snapshot writes and the metrics read back from them, launch
+// analysis, the snapshot job queue and the worker ring: the storage the API, the
+// services and the job workers depend on.
+// ClickHouseStore is what runs. MemoryStore keeps everything in the process and
+// computes what the views and queries do from the stored balances with the same
+// services, except for Parquet exports.
+// Timestamps are 'YYYY-MM-DD hh:mm:ss' in UTC, as ClickHouse formats them.
+#[async_trait]
+pub trait Store: Send + Sync {
+    // Tokens with one of the statuses, most recently snapshotted first
+    async fn monitored_tokens(&self, statuses: &[String]) -> Result<Vec<MonitoredToken>>;
+    async fn token_status(&self, mint_address: &str) -> Result<Option<TokenStatus>>;
+    // When monitoring of the token started, in unix seconds
+    async fn monitoring_start(&self, mint_address: &str) -> Result<Option<i64>>;
+    // New tokens are active and due for a snapshot
+    async fn insert_monitored_token(&self, mint_address: &str) -> Result<()>;
+    async fn set_token_status(&self, mint_address: &str, status: &str, reason: &str) -> Result<()>;
+    // Active tokens without a stats or metrics snapshot within the interval
+    async fn tokens_needing_stats_update(&self, interval_secs: u64) -> Result<Vec<String>>;
+    async fn tokens_needing_metrics_update(&self, interval_secs: u64) -> Result<Vec<String>>;
+    // Active tokens older than the window with a snapshot, see lifecycle::get_archive_candidates
+    async fn archive_candidates(&self, window_hours: u32) -> Result<Vec<ArchiveCandidateRecord>>;
+
+    // Holder rows of a checkpoint or delta, then its stats, which is what computes the metrics
+    async fn insert_snapshot(
+        &self,
+        mint_address: &str,
+        rows: &[(String, u64, Pubkey)],
+        stats: &TokenStats,
+        timestamp: &str,
+        checkpoint_timestamp: &str,
+    ) -> Result<()>;
+    // A historical snapshot as its own checkpoint, the token's monitoring schedule is left alone
+    async fn import_snapshot(&self, mint_address: &str, rows: &[(String, u64, Pubkey)], stats: &TokenStats, timestamp: &str) -> Result<()>;
+    // Holding spells only extend from the previous snapshot, so they are rebuilt once older ones were imported
+    async fn rebuild_holder_spells(&self, mint_address: &str) -> Result<()>;
+    // Newest snapshot taken at or before `at` (unix seconds)
+    async fn find_snapshot(&self, mint_address: &str, at: i64) -> Result<Option<SnapshotRecord>>;
+    // Stats and metrics of the latest snapshot
+    async fn token_metrics(&self, mint_address: &str) -> Result<TokenHolderStats>;
+    // Largest owners of the latest snapshot as (holder_address, balance)
+    async fn top_holders(&self, mint_address: &str, limit: u64) -> Result<Vec<(String, f64)>>;
+    // Largest owners summed over the latest snapshot of every token
+    async fn largest_holders(&self, limit: u64) -> Result<Vec<(String, f64)>>;
+    // (address, category, description), left out from the next snapshot on
+    async fn insert_excluded_accounts(&self, accounts: &[(String, String, String)]) -> Result<()>;
+
+    // USD thresholds and top-N buckets applied to new snapshots, a token's
+    // override or else the default. None sets the default.
+    async fn default_thresholds(&self) -> Result<ThresholdConfig>;
+    async fn token_thresholds(&self, mint_address: &str) -> Result<ThresholdConfig>;
+    async fn set_thresholds(&self, mint_address: Option<&str>, thresholds: &[f64]) -> Result<()>;
+    async fn default_buckets(&self) -> Result<ConcentrationBucketConfig>;
+    async fn token_buckets(&self, mint_address: &str) -> Result<ConcentrationBucketConfig>;
+    async fn set_buckets(&self, mint_address: Option<&str>, buckets: &[u32]) -> Result<()>;
+
+    // Launch analysis, see services::launch. Tokens given up on as history_too_long are pending again.
+    async fn tokens_pending_launch_analysis(&self) -> Result<Vec<String>>;
+    // Labels the insiders and snipers, then records the analysis
+    async fn insert_launch_analysis(&self, mint_address: &str, analysis: &LaunchAnalysis, slot_window: u64) -> Result<()>;
+
+    // Analytics over the stored snapshots, see the services of the same name
+    async fn history(&self, mint_address: &str, from: i64, to: i64, resolution: Resolution) -> Result<TokenHistory>;
+    async fn concentration(&self, mint_address: &str, top_n: &[u32]) -> Result<TokenConcentration>;
+    async fn lorenz_curve(&self, mint_address: &str, snapshot: &SnapshotRecord, points: u32) -> Result<LorenzCurve>;
+    async fn balance_histogram(&self, mint_address: &str, snapshot: &SnapshotRecord, bins_per_decade: u32) -> Result<BalanceHistogram>;
+    async fn holder_age(&self, mint_address: &str, snapshot: &SnapshotRecord) -> Result<HolderAge>;
+    async fn holders(&self, mint_address: &str, snapshot: &SnapshotRecord, after_rank: u64, limit: u64, min_usd: f64) -> Result<HolderList>;
+    async fn wallet_holding(&self, mint_address: &str, holder_address: &str, limit: u64) -> Result<WalletHolding>;
+    async fn wallet_overlap(&self, mints: &[String], limit: u64) -> Result<WalletOverlap>;
+    async fn multi_token_wallets(&self, min_tokens: u64, limit: u64) -> Result<MultiTokenWallets>;
+    async fn wallet_portfolio(&self, holder_address: &str, snapshots: u64) -> Result<WalletPortfolio>;
+    async fn wallet_labels(&self, mint_address: &str) -> Result<Vec<WalletLabel>>;
+    // A dataset between two unix timestamps in one of ClickHouse's output formats
+    async fn export(&self, dataset: ExportDataset, format: ExportFormat, mint_address: &str, from: i64, to: i64) -> Result<ExportStream>;
+
+    // Snapshot jobs, see services::jobs for their lifecycle. Jobs are claimed
+    // per attempt, claim_job returns true for exactly one worker per attempt, and
+    // only the lease owner of an attempt can update it.
+    async fn enqueue_job(&self, mint_address: &str, kind: &str) -> Result<String>;
+    async fn job(&self, job_id: &str) -> Result<Option<SnapshotJob>>;
+    // Newest first, None matches every status / mint
+    async fn jobs(&self, status: Option<&str>, mint_address: Option<&str>, limit: u64) -> Result<Vec<SnapshotJob>>;
+    async fn claimable_jobs(&self, limit: u64) -> Result<Vec<ClaimableJobRecord>>;
+    async fn claim_job(&self, job_id: &str, attempt: u32, worker_id: &str, lease_secs: u64) -> Result<bool>;
+    async fn renew_lease(&self, job_id: &str, attempt: u32, worker_id: &str, lease_secs: u64) -> Result<()>;
+    async fn finish_job(&self, job_id: &str, attempt: u32, worker_id: &str, summary: &SnapshotSummary) -> Result<()>;
+    async fn fail_job(&self, job_id: &str, attempt: u32, worker_id: &str, error: &str, retry_after_secs: u64) -> Result<()>;
+    async fn fail_exhausted_leases(&self) -> Result<()>;
+
+    // Worker ring, as (worker_id, mode, heartbeat_at) of the live workers
+    async fn send_heartbeat(&self, worker_id: &str, mode: &str, active: bool) -> Result<()>;
+    async fn live_workers(&self, timeout_secs: u64) -> Result<Vec<(String, String, String)>>;
+}
+
+#[derive(Clone)]
+pub struct ClickHouseStore {
+    client: Client,
+    // Exports are read over HTTP with the client's settings
+    config: ClickHouseConfig,
+}
+
+impl ClickHouseStore {
+    pub fn new(client: Client, config: ClickHouseConfig) -> Self {
+        Self { client, config }
+    }
+}
+
+#[async_trait]
+impl Store for ClickHouseStore {
+    async fn monitored_tokens(&self, statuses: &[String]) -> Result<Vec<MonitoredToken>> {
+        let tokens = self.client
+            .query(
+                "SELECT
+                    mint_address,
+                    status,
+                    toString(last_stats_update) as last_stats_update,
+                    toString(last_metrics_update) as last_metrics_update
+                 FROM monitored_tokens FINAL
+                 WHERE has(?, status)
+                 ORDER BY last_stats_update DESC"
+            )
+            .bind(statuses)
+            .fetch_all::<(String, String, String, String)>()
+            .await?;
+
+        Ok(tokens.into_iter()
+            .map(|(mint_address, status, last_stats_update, last_metrics_update)| MonitoredToken {
+                mint_address,
+                status,
+                last_stats_update,
+                last_metrics_update,
+            })
+            .collect())
+    }
+
+    async fn token_status(&self, mint_address: &str) -> Result<Option<TokenStatus>> {
+        get_token_status(&self.client, mint_address).await
+    }
+
+    async fn monitoring_start(&self, mint_address: &str) -> Result<Option<i64>> {
+        get_monitoring_start(&self.client, mint_address).await
+    }
+
+    async fn insert_monitored_token(&self, mint_address: &str) -> Result<()> {
+        self.client
+            .query(
+                "INSERT INTO monitored_tokens (mint_address, last_stats_update, last_metrics_update)
+                 VALUES (?, toDateTime('1970-01-01 00:00:00'), toDateTime('1970-01-01 00:00:00'))"
+            )
+            .bind(mint_address)
+            .execute()
+            .await?;
+        Ok(())
+    }
+
+    async fn set_token_status(&self, mint_address: &str, status: &str, reason: &str) -> Result<()> {
+        set_token_status(&self.client, mint_address, status, reason).await
+    }
+
+    async fn tokens_needing_stats_update(&self, interval_secs: u64) -> Result<Vec<String>> {
+        get_tokens_needing_stats_update(&self.client, interval_secs).await
+    }
+
+    async fn tokens_needing_metrics_update(&self, interval_secs: u64) -> Result<Vec<String>> {
+        get_tokens_needing_metrics_update(&self.client, interval_secs).await
+    }
+
+    async fn archive_candidates(&self, window_hours: u32) -> Result<Vec<ArchiveCandidateRecord>> {
+        get_archive_candidates(&self.client, window_hours).await
+    }
+
+    async fn insert_snapshot(
+        &self,
+        mint_address: &str,
+        rows: &[(String, u64, Pubkey)],
+        stats: &TokenStats,
+        timestamp: &str,
+        checkpoint_timestamp: &str,
+    ) -> Result<()> {
+        insert_token_holders(&self.client, mint_address, rows, timestamp).await?;
+        // The metric views fire on this insert
+        insert_token_stats(&self.client, mint_address, timestamp, checkpoint_timestamp, stats).await
+    }
+
+    // Holders first and then the stats row the metric views fire on, without
+    // touching monitored_tokens
+    async fn import_snapshot(&self, mint_address: &str, rows: &[(String, u64, Pubkey)], stats: &TokenStats, timestamp: &str) -> Result<()> {
+        insert_token_holders(&self.client, mint_address, rows, timestamp).await?;
+        insert_token_stats_row(&self.client, mint_address, timestamp, timestamp, stats).await
+    }
+
+    async fn rebuild_holder_spells(&self, mint_address: &str) -> Result<()> {
+        rebuild_holder_spells(&self.client, Some(mint_address)).await
+    }
+
+    async fn find_snapshot(&self, mint_address: &str, at: i64) -> Result<Option<SnapshotRecord>> {
+        find_snapshot(&self.client, mint_address, at).await
+    }
+
+    async fn token_metrics(&self, mint_address: &str) -> Result<TokenHolderStats> {
+        get_token_metrics(&self.client, mint_address).await
+    }
+
+    async fn top_holders(&self, mint_address: &str, limit: u64) -> Result<Vec<(String, f64)>> {
+        let holders = self.client
+            .query(&format!("
+                SELECT holder_address, balance
+                FROM ({})
+                WHERE mint_address = ?
+                ORDER BY balance DESC
+                LIMIT ?
+            ", LATEST_HOLDER_BALANCES_SQL))
+            .bind(mint_address)
+            .bind(limit)
+            .fetch_all()
+            .await?;
+        Ok(holders)
+    }
+
+    async fn largest_holders(&self, limit: u64) -> Result<Vec<(String, f64)>> {
+        get_largest_holders(&self.client, limit).await
+    }
+
+    async fn insert_excluded_accounts(&self, accounts: &[(String, String, String)]) -> Result<()> {
+        import_excluded_accounts(&self.client, accounts).await
+    }
+
+    async fn default_thresholds(&self) -> Result<ThresholdConfig> {
+        thresholds::get_default_thresholds(&self.client).await
+    }
+
+    async fn token_thresholds(&self, mint_address: &str) -> Result<ThresholdConfig> {
+        thresholds::get_token_thresholds(&self.client, mint_address).await
+    }
+
+    async fn set_thresholds(&self, mint_address: Option<&str>, thresholds: &[f64]) -> Result<()> {
+        thresholds::set_thresholds(&self.client, mint_address, thresholds).await
+    }
+
+    async fn default_buckets(&self) -> Result<ConcentrationBucketConfig> {
+        concentration::get_default_buckets(&self.client).await
+    }
+
+    async fn token_buckets(&self, mint_address: &str) -> Result<ConcentrationBucketConfig> {
+        concentration::get_token_buckets(&self.client, mint_address).await
+    }
+
+    async fn set_buckets(&self, mint_address: Option<&str>, buckets: &[u32]) -> Result<()> {
+        concentration::set_buckets(&self.client, mint_address, buckets).await
+    }
+
+    async fn tokens_pending_launch_analysis(&self) -> Result<Vec<String>> {
+        get_tokens_pending_launch_analysis(&self.client).await
+    }
+
+    async fn insert_launch_analysis(&self, mint_address: &str, analysis: &LaunchAnalysis, slot_window: u64) -> Result<()> {
+        store_launch_analysis(&self.client, mint_address, analysis, slot_window).await
+    }
+
+    async fn history(&self, mint_address: &str, from: i64, to: i64, resolution: Resolution) -> Result<TokenHistory> {
+        get_token_history(&self.client, mint_address, from, to, resolution).await
+    }
+
+    async fn concentration(&self, mint_address: &str, top_n: &[u32]) -> Result<TokenConcentration> {
+        concentration::get_concentration(&self.client, mint_address, top_n).await
+    }
+
+    async fn lorenz_curve(&self, mint_address: &str, snapshot: &SnapshotRecord, points: u32) -> Result<LorenzCurve> {
+        distribution::get_lorenz_curve(&self.client, mint_address, snapshot, points).await
+    }
+
+    async fn balance_histogram(&self, mint_address: &str, snapshot: &SnapshotRecord, bins_per_decade: u32) -> Result<BalanceHistogram> {
+        distribution::get_balance_histogram(&self.client, mint_address, snapshot, bins_per_decade).await
+    }
+
+    async fn holder_age(&self, mint_address: &str, snapshot: &SnapshotRecord) -> Result<HolderAge> {
+        distribution::get_holder_age(&self.client, mint_address, snapshot).await
+    }
+
+    async fn holders(&self, mint_address: &str, snapshot: &SnapshotRecord, after_rank: u64, limit: u64, min_usd: f64) -> Result<HolderList> {
+        holders::get_holders(&self.client, mint_address, snapshot, after_rank, limit, min_usd).await
+    }
+
+    async fn wallet_holding(&self, mint_address: &str, holder_address: &str, limit: u64) -> Result<WalletHolding> {
+        holders::get_wallet_holding(&self.client, mint_address, holder_address, limit).await
+    }
+
+    async fn wallet_overlap(&self, mints: &[String], limit: u64) -> Result<WalletOverlap> {
+        overlap::get_wallet_overlap(&self.client, mints, limit).await
+    }
+
+    async fn multi_token_wallets(&self, min_tokens: u64, limit: u64) -> Result<MultiTokenWallets> {
+        overlap::get_multi_token_wallets(&self.client, min_tokens, limit).await
+    }
+
+    async fn wallet_portfolio(&self, holder_address: &str, snapshots: u64) -> Result<WalletPortfolio> {
+        holders::get_wallet_portfolio(&self.client, holder_address, snapshots).await
+    }
+
+    async fn wallet_labels(&self, mint_address: &str) -> Result<Vec<WalletLabel>> {
+        get_wallet_labels(&self.client, mint_address).await
+    }
+
+    async fn export(&self, dataset: ExportDataset, format: ExportFormat, mint_address: &str, from: i64, to: i64) -> Result<ExportStream> {
+        let snapshots = match dataset {
+            ExportDataset::Holders => {
+                let limit = MAX_HOLDER_EXPORT_SNAPSHOTS as u64 + 1;
+                let snapshots = snapshots_between(&self.client, mint_address, from, to, limit).await?;
+                check_holder_export_range(mint_address, from, to, snapshots.len())?;
+                snapshots
+            }
+            ExportDataset::Stats | ExportDataset::Metrics => Vec::new(),
+        };
+        export_stream(&self.config, dataset, format, mint_address, (from, to), &snapshots).await
+    }
+
+    async fn enqueue_job(&self, mint_address: &str, kind: &str) -> Result<String> {
+        jobs::enqueue_snapshot_job(&self.client, mint_address, kind).await
+    }
+
+    async fn job(&self, job_id: &str) -> Result<Option<SnapshotJob>> {
+        jobs::get_snapshot_job(&self.client, job_id).await
+    }
+
+    async fn jobs(&self, status: Option<&str>, mint_address: Option<&str>, limit: u64) -> Result<Vec<SnapshotJob>> {
+        jobs::list_snapshot_jobs(&self.client, status, mint_address, limit).await
+    }
+
+    async fn claimable_jobs(&self, limit: u64) -> Result<Vec<ClaimableJobRecord>> {
+        jobs::claimable_jobs(&self.client, limit).await
+    }
+
+    async fn claim_job(&self, job_id: &str, attempt: u32, worker_id: &str, lease_secs: u64) -> Result<bool> {
+        jobs::claim_job(&self.client, job_id, attempt, worker_id, lease_secs).await
+    }
+
+    async fn renew_lease(&self, job_id: &str, attempt: u32, worker_id: &str, lease_secs: u64) -> Result<()> {
+        jobs::renew_lease(&self.client, job_id, attempt, worker_id, lease_secs).await
+    }
+
+    async fn finish_job(&self, job_id: &str, attempt: u32, worker_id: &str, summary: &SnapshotSummary) -> Result<()> {
+        jobs::mark_done(&self.client, job_id, attempt, worker_id, summary).await
+    }
+
+    async fn fail_job(&self, job_id: &str, attempt: u32, worker_id: &str, error: &str, retry_after_secs: u64) -> Result<()> {
+        jobs::mark_failed(&self.client, job_id, attempt, worker_id, error, retry_after_secs).await
+    }
+
+    async fn fail_exhausted_leases(&self) -> Result<()> {
+        jobs::fail_exhausted_leases(&self.client).await
+    }
+
+    async fn send_heartbeat(&self, worker_id: &str, mode: &str, active: bool) -> Result<()> {
+        send_heartbeat(&self.client, worker_id, mode, active).await
+    }
+
+    async fn live_workers(&self, timeout_secs: u64) -> Result<Vec<(String, String, String)>> {
+        live_workers(&self.client, timeout_secs).await
+    }
+}
+
+const NEVER: &str = "1970-01-01 00:00:00";
+
+#[derive(Debug, Clone)]
+struct MemoryToken {
+    status: String,
+    reason: String,
+    status_updated_at: String,
+    // Unix seconds
+    created_at: i64,
+    last_stats_update: String,
+    last_metrics_update: String,
+}
+
+#[derive(Debug)]
+struct MemorySnapshot {
+    record: SnapshotRecord,
+    // Owner balances with the excluded owners, as token_holders has them
+    owners: Vec<(String, f64)>,
+    // Owner balances the metrics were computed from, as token_holder_balances has them
+    balances: Vec<(String, f64)>,
+    stats: TokenStats,
+    metrics: SnapshotMetrics,
+    // (label, holders, balance, supply_share) of the labels held, like token_label_holdings
+    label_holdings: Vec<(String, u64, f64, f64)>,
+}
+
+#[derive(Debug)]
+struct MemoryWorker {
+    mode: String,
+    active: bool,
+    heartbeat_at: i64,
+}
+
+#[derive(Debug, Default)]
+struct MemoryData {
+    // Address to category
+    excluded: HashMap<String, String>,
+    // Keyed like token_threshold_config and token_concentration_config
+    thresholds: HashMap<String, Vec<f64>>,
+    buckets: HashMap<String, Vec<u32>>,
+    tokens: BTreeMap<String, MemoryToken>,
+    // Holder rows per mint as (timestamp, token_account, amount, owner)
+    holders: HashMap<String, Vec<(i64, String, u64, Pubkey)>>,
+    // Per mint, in timestamp order
+    snapshots: HashMap<String, Vec<MemorySnapshot>>,
+    // Per mint, one per label and address like wallet_labels
+    labels: HashMap<String, Vec<WalletLabel>>,
+    launches: HashMap<String, LaunchAnalysisRecord>,
+    // In creation order, times in unix milliseconds like snapshot_jobs
+    jobs: Vec<SnapshotJobRecord>,
+    workers: BTreeMap<String, MemoryWorker>,
+}
+
+impl MemoryData {
+    fn snapshots(&self, mint_address: &str) -> &[MemorySnapshot] {
+        self.snapshots.get(mint_address).map_or(&[], Vec::as_slice)
+    }
+
+    fn latest(&self, mint_address: &str) -> Result<&MemorySnapshot> {
+        self.snapshots(mint_address)
+            .last()
+            .ok_or_else(|| anyhow::anyhow!("No snapshot of {}", mint_address))
+    }
+
+    // The stored snapshot a record was found for
+    fn snapshot(&self, mint_address: &str, record: &SnapshotRecord) -> Result<&MemorySnapshot> {
+        self.snapshots(mint_address)
+            .iter()
+            .find(|snapshot| snapshot.record.timestamp == record.timestamp)
+            .ok_or_else(|| anyhow::anyhow!("No snapshot of {} at {}", mint_address, record.timestamp))
+    }
+
+    // Rebuilds the balances the way token_holder_balances_mv and the metric
+    // views do: per owner, summed over its token accounts at each timestamp,
+    // the newest value since the checkpoint, excluded owners left out
+    fn add_snapshot(
+        &mut self,
+        mint_address: &str,
+        rows: &[(String, u64, Pubkey)],
+        stats: &TokenStats,
+        at: i64,
+        checkpoint_at: i64,
+    ) {
+        let holders = self.holders.entry(mint_address.to_string()).or_default();
+        holders.extend(rows.iter().map(|(token_account, amount, owner)| (at, token_account.clone(), *amount, *owner)));
+        let current = rows_as_of(holders, checkpoint_at, at);
+        let excluded: HashSet<String> = self.excluded.keys().cloned().collect();
+        let owners = owner_balances(&current, &HashSet::new());
+        let holdings = owner_balances(&current, &excluded);
+        let balances: Vec<f64> = holdings.iter().map(|(_, balance)| *balance).collect();
+
+        let (thresholds, _) = token_config(&self.thresholds, mint_address);
+        let (buckets, _) = token_config(&self.buckets, mint_address);
+        let metrics = compute_metrics(&balances, stats, &thresholds, &buckets);
+        let label_holdings = labelled_holdings(self.labels.get(mint_address).map_or(&[], Vec::as_slice), &holdings, stats.supply);
+        let snapshots = self.snapshots.entry(mint_address.to_string()).or_default();
+        snapshots.retain(|snapshot| snapshot.record.timestamp != at);
+        snapshots.push(MemorySnapshot {
+            record: SnapshotRecord {
+                timestamp: at,
+                checkpoint_timestamp: checkpoint_at,
+                price: stats.price,
+                supply: stats.supply,
+                decimals: stats.decimals,
+            },
+            owners,
+            balances: holdings,
+            stats: stats.clone(),
+            metrics,
+            label_holdings,
+        });
+        snapshots.sort_by_key(|snapshot| snapshot.record.timestamp);
+    }
+
+    // Like get_label_holdings: the latest snapshot, and the newest snapshot of
+    // each hour over the last 7 days the label was held in
+    fn label_holdings(&self, mint_address: &str, label: &str, decimals: u8) -> LabelHoldings {
+        let labelled_wallets = self.labels
+            .get(mint_address)
+            .map_or(0, |labels| labels.iter().filter(|wallet| wallet.label == label).count() as u64);
+        let held = |snapshot: &MemorySnapshot| snapshot.label_holdings
+            .iter()
+            .find(|(held_label, ..)| held_label == label)
+            .map(|(_, holders, balance, supply_share)| (*holders, *balance, *supply_share));
+        let snapshots = self.snapshots(mint_address);
+
+        let since = Utc::now().timestamp() - 7 * 24 * 3600;
+        let mut hourly: BTreeMap<i64, (u64, f64, f64)> = BTreeMap::new();
+        for snapshot in snapshots {
+            let bucket = Resolution::Hourly.bucket_start(snapshot.record.timestamp);
+            if let Some(holdings) = held(snapshot).filter(|_| bucket >= since) {
+                hourly.insert(bucket, holdings);
+            }
+        }
+        let history = hourly.into_iter()
+            .map(|(bucket, (holders, balance, supply_share))| (format_unix(bucket), holders, balance, supply_share))
+            .collect();
+
+        let latest = snapshots.last().and_then(held).unwrap_or_default();
+        label_holdings(label, labelled_wallets, latest, history, decimals)
+    }
+}
+
+// Rows of each owner's newest timestamp between the checkpoint and `at`
+fn rows_as_of(holders: &[(i64, String, u64, Pubkey)], checkpoint_at: i64, at: i64) -> Vec<(String, u64, Pubkey)> {
+    let window: Vec<&(i64, String, u64, Pubkey)> = holders
+        .iter()
+        .filter(|(row_at, ..)| *row_at >= checkpoint_at && *row_at <= at)
+        .collect();
+    let mut latest: HashMap<Pubkey, i64> = HashMap::new();
+    for (row_at, _, _, owner) in &window {
+        let owner_at = latest.entry(*owner).or_insert(*row_at);
+        *owner_at = (*owner_at).max(*row_at);
+    }
+    window
+        .into_iter()
+        .filter(|(row_at, _, _, owner)| latest.get(owner) == Some(row_at))
+        .map(|(_, token_account, amount, owner)| (token_account.clone(), *amount, *owner))
+        .collect()
+}
+
+// Holders, balance and share of supply per label of the labelled owners, as token_label_holdings_mv
+fn labelled_holdings(labels: &[WalletLabel], balances: &[(String, f64)], supply: f64) -> Vec<(String, u64, f64, f64)> {
+    let balances: HashMap<&str, f64> = balances.iter().map(|(holder, balance)| (holder.as_str(), *balance)).collect();
+    let mut holdings: BTreeMap<&str, (u64, f64)> = BTreeMap::new();
+    for wallet in labels {
+        if let Some(balance) = balances.get(wallet.address.as_str()) {
+            let held = holdings.entry(wallet.label.as_str()).or_default();
+            held.0 += 1;
+            held.1 += balance;
+        }
+    }
+    holdings.into_iter()
+        .map(|(label, (holders, balance))| (label.to_string(), holders, balance, balance / supply * 100.0))
+        .collect()
+}
+
+// (holder, first snapshot) of every stretch of consecutive snapshots a holder
+// held a balance in, like token_holder_spells
+fn holder_spells(snapshots: &[MemorySnapshot]) -> Vec<(&str, i64)> {
+    let mut spells = Vec::new();
+    let mut previous: HashSet<&str> = HashSet::new();
+    for snapshot in snapshots {
+        let current: HashSet<&str> = snapshot.balances.iter().map(|(holder, _)| holder.as_str()).collect();
+        spells.extend(current.difference(&previous).map(|holder| (*holder, snapshot.record.timestamp)));
+        previous = current;
+    }
+    spells
+}
+
+// Largest balance first, ties by address, the order holders are ranked in
+fn largest_first(balances: &mut [(String, f64)]) {
+    balances.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+}
+
+// The wallet's balance and rank among the balances, a balance of 0 if it holds nothing
+fn wallet_rank(balances: &[(String, f64)], holder_address: &str) -> (f64, u64) {
+    let balance = balances.iter()
+        .find(|(holder, _)| holder == holder_address)
+        .map_or(0.0, |(_, balance)| *balance);
+    let ahead = balances.iter()
+        .filter(|(holder, other)| *other > balance || (*other == balance && holder.as_str() < holder_address))
+        .count();
+    (balance, ahead as u64 + 1)
+}
+
+// The wallet among the snapshot's balances, None if the snapshot has no holders
+fn wallet_snapshot(snapshot: &MemorySnapshot, balances: &[(String, f64)], holder_address: &str) -> Option<WalletSnapshotRecord> {
+    if balances.is_empty() {
+        return None;
+    }
+    let (balance, rank) = wallet_rank(balances, holder_address);
+    Some(WalletSnapshotRecord {
+        timestamp: snapshot.record.timestamp,
+        price: snapshot.record.price,
+        supply: snapshot.record.supply,
+        decimals: snapshot.record.decimals,
+        total_holders: balances.len() as u64,
+        balance,
+        rank,
+    })
+}
+
+fn last_snapshots(snapshots: &[MemorySnapshot], limit: u64) -> &[MemorySnapshot] {
+    &snapshots[snapshots.len().saturating_sub(limit as usize)..]
+}
+
+// snapshot_jobs defaults
+const MAX_ATTEMPTS: u32 = 3;
+
+// Config key of the global default
+const DEFAULT_KEY: &str = "";
+
+// Starts with the given (address, category, description) exclusions and default
+// thresholds and buckets
+#[derive(Debug)]
+pub struct MemoryStore {
+    data: Mutex<MemoryData>,
+}
+
+impl MemoryStore {
+    pub fn new(excluded: &[(String, String, String)], thresholds: Vec<f64>, buckets: Vec<u32>) -> Self {
+        Self {
+            data: Mutex::new(MemoryData {
+                excluded: excluded.iter()
+                    .map(|(address, category, _)| (address.clone(), category.clone()))
+                    .collect(),
+                thresholds: HashMap::from([(DEFAULT_KEY.to_string(), thresholds)]),
+                buckets: HashMap::from([(DEFAULT_KEY.to_string(), buckets)]),
+                ..MemoryData::default()
+            }),
+        }
+    }
+
+    async fn tokens_updated_before(&self, interval_secs: u64, last_update: impl Fn(&MemoryToken) -> &str) -> Result<Vec<String>> {
+        let cutoff = Utc::now().timestamp() - interval_secs as i64;
+        let data = self.data.lock().await;
+        let mut due = Vec::new();
+        for (mint_address, token) in &data.tokens {
+            let pending = data.jobs
+                .iter()
+                .any(|job| job.mint_address == *mint_address && (job.status == "queued" || job.status == "running"));
+            if token.status == "active" && !pending && parse_timestamp(last_update(token))? < cutoff {
+                due.push(mint_address.clone());
+            }
+        }
+        Ok(due)
+    }
+}
+
+fn parse_timestamp(timestamp: &str) -> Result<i64> {
+    Ok(NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S")
+        .map_err(|e| anyhow::anyhow!("Invalid timestamp {}: {}", timestamp, e))?
+        .and_utc()
+        .timestamp())
+}
+
+fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn format_unix(timestamp: i64) -> String {
+    format_timestamp(DateTime::from_timestamp(timestamp, 0).unwrap_or_default())
+}
+
+// The job's current attempt, if the worker holds its lease
+fn leased_job<'a>(jobs: &'a mut [SnapshotJobRecord], job_id: &str, attempt: u32, worker_id: &str) -> Option<&'a mut SnapshotJobRecord> {
+    jobs.iter_mut()
+        .find(|job| job.job_id == job_id && job.lease_owner == worker_id && job.attempts == attempt)
+}
+
+// A token's override, or else the default, like get_token_thresholds / get_token_buckets
+fn token_config<T: Clone>(config: &HashMap<String, Vec<T>>, mint_address: &str) -> (Vec<T>, ConfigSource) {
+    match config.get(mint_address).filter(|values| !values.is_empty()) {
+        Some(values) => (values.clone(), ConfigSource::Token),
+        None => (config.get(DEFAULT_KEY).cloned().unwrap_or_default(), ConfigSource::Default),
+    }
+}
+
+// Bins of balance * scale, like log_histogram
+fn log_histogram(balances: &[(String, f64)], scale: f64, bins_per_decade: u32) -> Vec<HistogramBucket> {
+    let mut bins: BTreeMap<i64, (u64, f64)> = BTreeMap::new();
+    for value in balances.iter().map(|(_, balance)| balance * scale).filter(|value| *value > 0.0) {
+        let bin = bins.entry(distribution::histogram_bin(value, bins_per_decade)).or_default();
+        bin.0 += 1;
+        bin.1 += value;
+    }
+    distribution::histogram_buckets(
+        bins.into_iter().map(|(bin, (holder_count, total))| (bin, holder_count, total)).collect(),
+        bins_per_decade,
+    )
+}
+
+fn is_claimable(job: &SnapshotJobRecord, now: i64) -> bool {
+    (job.status == "queued" && job.run_after <= now)
+        || (job.status == "running" && job.lease_expires_at < now && job.attempts < job.max_attempts)
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn monitored_tokens(&self, statuses: &[String]) -> Result<Vec<MonitoredToken>> {
+        let data = self.data.lock().await;
+        let mut tokens: Vec<MonitoredToken> = data.tokens
+            .iter()
+            .filter(|(_, token)| statuses.contains(&token.status))
+            .map(|(mint_address, token)| MonitoredToken {
+                mint_address: mint_address.clone(),
+                status: token.status.clone(),
+                last_stats_update: token.last_stats_update.clone(),
+                last_metrics_update: token.last_metrics_update.clone(),
+            })
+            .collect();
+        tokens.sort_by(|a, b| b.last_stats_update.cmp(&a.last_stats_update));
+        Ok(tokens)
+    }
+
+    async fn token_status(&self, mint_address: &str) -> Result<Option<TokenStatus>> {
+        let data = self.data.lock().await;
+        Ok(data.tokens.get(mint_address).map(|token| TokenStatus {
+            mint_address: mint_address.to_string(),
+            status: token.status.clone(),
+            reason: token.reason.clone(),
+            updated_at: token.status_updated_at.clone(),
+        }))
+    }
+
+    async fn monitoring_start(&self, mint_address: &str) -> Result<Option<i64>> {
+        let data = self.data.lock().await;
+        Ok(data.tokens.get(mint_address).map(|token| token.created_at))
+    }
+
+    async fn insert_monitored_token(&self, mint_address: &str) -> Result<()> {
+        let now = Utc::now();
+        let mut data = self.data.lock().await;
+        data.tokens.insert(mint_address.to_string(), MemoryToken {
+            status: "active".to_string(),
+            reason: String::new(),
+            status_updated_at: format_timestamp(now),
+            created_at: now.timestamp(),
+            last_stats_update: NEVER.to_string(),
+            last_metrics_update: NEVER.to_string(),
+        });
+        Ok(())
+    }
+
+    async fn set_token_status(&self, mint_address: &str, status: &str, reason: &str) -> Result<()> {
+        let mut data = self.data.lock().await;
+        if let Some(token) = data.tokens.get_mut(mint_address) {
+            token.status = status.to_string();
+            token.reason = reason.to_string();
+            token.status_updated_at = format_timestamp(Utc::now());
+        }
+        Ok(())
+    }
+
+    async fn tokens_needing_stats_update(&self, interval_secs: u64) -> Result<Vec<String>> {
+        self.tokens_updated_before(interval_secs, |token| &token.last_stats_update).await
+    }
+
+    async fn tokens_needing_metrics_update(&self, interval_secs: u64) -> Result<Vec<String>> {
+        self.tokens_updated_before(interval_secs, |token| &token.last_metrics_update).await
+    }
+
+    async fn archive_candidates(&self, window_hours: u32) -> Result<Vec<ArchiveCandidateRecord>> {
+        let cutoff = Utc::now().timestamp() - window_hours as i64 * 3600;
+        let data = self.data.lock().await;
+        Ok(data.tokens
+            .iter()
+            .filter(|(_, token)| token.status == "active" && token.created_at < cutoff)
+            .filter_map(|(mint_address, _)| {
+                let snapshots = data.snapshots(mint_address);
+                let latest = snapshots.last()?;
+                let spells = holder_spells(snapshots);
+                let new_holders: HashSet<&str> = spells.iter()
+                    .filter(|(_, first_seen)| *first_seen >= cutoff)
+                    .map(|(holder, _)| *holder)
+                    .collect();
+                Some(ArchiveCandidateRecord {
+                    mint_address: mint_address.clone(),
+                    market_cap: latest.stats.market_cap,
+                    new_holders: (!spells.is_empty()).then_some(new_holders.len() as u64),
+                })
+            })
+            .collect())
+    }
+
+    async fn insert_snapshot(
+        &self,
+        mint_address: &str,
+        rows: &[(String, u64, Pubkey)],
+        stats: &TokenStats,
+        timestamp: &str,
+        checkpoint_timestamp: &str,
+    ) -> Result<()> {
+        let at = parse_timestamp(timestamp)?;
+        let checkpoint_at = parse_timestamp(checkpoint_timestamp)?;
+        let mut data = self.data.lock().await;
+        data.add_snapshot(mint_address, rows, stats, at, checkpoint_at);
+        if let Some(token) = data.tokens.get_mut(mint_address) {
+            token.last_stats_update = timestamp.to_string();
+            token.last_metrics_update = timestamp.to_string();
+        }
+        Ok(())
+    }
+
+    async fn import_snapshot(&self, mint_address: &str, rows: &[(String, u64, Pubkey)], stats: &TokenStats, timestamp: &str) -> Result<()> {
+        let at = parse_timestamp(timestamp)?;
+        let mut data = self.data.lock().await;
+        data.add_snapshot(mint_address, rows, stats, at, at);
+        Ok(())
+    }
+
+    // Spells are worked out from the snapshots when read
+    async fn rebuild_holder_spells(&self, _mint_address: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn find_snapshot(&self, mint_address: &str, at: i64) -> Result<Option<SnapshotRecord>> {
+        let data = self.data.lock().await;
+        Ok(data.snapshots(mint_address)
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.record.timestamp <= at)
+            .map(|snapshot| snapshot.record.clone()))
+    }
+
+    async fn token_metrics(&self, mint_address: &str) -> Result<TokenHolderStats> {
+        let data = self.data.lock().await;
+        let snapshot = data.latest(mint_address)?;
+        let decimals = snapshot.stats.decimals;
+        let mut metrics = snapshot.metrics.clone().into_token_stats(mint_address, snapshot.stats.clone());
+        metrics.insider_holdings = data.label_holdings(mint_address, INSIDER_LABEL, decimals);
+        metrics.sniper_holdings = data.label_holdings(mint_address, SNIPER_LABEL, decimals);
+        metrics.launch = data.launches
+            .get(mint_address)
+            .cloned()
+            .map(|row| launch_info(row, &metrics.sniper_holdings, decimals));
+        Ok(metrics)
+    }
+
+    async fn top_holders(&self, mint_address: &str, limit: u64) -> Result<Vec<(String, f64)>> {
+        let data = self.data.lock().await;
+        let mut holders = data.snapshots(mint_address)
+            .last()
+            .map(|snapshot| snapshot.balances.clone())
+            .unwrap_or_default();
+        largest_first(&mut holders);
+        holders.truncate(limit as usize);
+        Ok(holders)
+    }
+
+    async fn largest_holders(&self, limit: u64) -> Result<Vec<(String, f64)>> {
+        let data = self.data.lock().await;
+        let mut totals: HashMap<&str, f64> = HashMap::new();
+        for snapshot in data.snapshots.values().filter_map(|snapshots| snapshots.last()) {
+            for (holder, balance) in &snapshot.balances {
+                *totals.entry(holder.as_str()).or_default() += balance;
+            }
+        }
+        let mut holders: Vec<(String, f64)> = totals.into_iter()
+            .map(|(holder, total)| (holder.to_string(), total))
+            .collect();
+        largest_first(&mut holders);
+        holders.truncate(limit as usize);
+        Ok(holders)
+    }
+
+    async fn insert_excluded_accounts(&self, accounts: &[(String, String, String)]) -> Result<()> {
+        let mut data = self.data.lock().await;
+        data.excluded.extend(accounts.iter().map(|(address, category, _)| (address.clone(), category.clone())));
+        Ok(())
+    }
+
+    async fn default_thresholds(&self) -> Result<ThresholdConfig> {
+        let data = self.data.lock().await;
+        Ok(ThresholdConfig {
+            mint_address: None,
+            thresholds: data.thresholds.get(DEFAULT_KEY).cloned().unwrap_or_default(),
+            source: ConfigSource::Default,
+        })
+    }
+
+    async fn token_thresholds(&self, mint_address: &str) -> Result<ThresholdConfig> {
+        let data = self.data.lock().await;
+        let (thresholds, source) = token_config(&data.thresholds, mint_address);
+        Ok(ThresholdConfig {
+            mint_address: Some(mint_address.to_string()),
+            thresholds,
+            source,
+        })
+    }
+
+    async fn set_thresholds(&self, mint_address: Option<&str>, thresholds: &[f64]) -> Result<()> {
+        let mut data = self.data.lock().await;
+        data.thresholds.insert(mint_address.unwrap_or(DEFAULT_KEY).to_string(), thresholds.to_vec());
+        Ok(())
+    }
+
+    async fn default_buckets(&self) -> Result<ConcentrationBucketConfig> {
+        let data = self.data.lock().await;
+        Ok(ConcentrationBucketConfig {
+            mint_address: None,
+            buckets: data.buckets.get(DEFAULT_KEY).cloned().unwrap_or_default(),
+            source: ConfigSource::Default,
+        })
+    }
+
+    async fn token_buckets(&self, mint_address: &str) -> Result<ConcentrationBucketConfig> {
+        let data = self.data.lock().await;
+        let (buckets, source) = token_config(&data.buckets, mint_address);
+        Ok(ConcentrationBucketConfig {
+            mint_address: Some(mint_address.to_string()),
+            buckets,
+            source,
+        })
+    }
+
+    async fn set_buckets(&self, mint_address: Option<&str>, buckets: &[u32]) -> Result<()> {
+        let mut data = self.data.lock().await;
+        data.buckets.insert(mint_address.unwrap_or(DEFAULT_KEY).to_string(), buckets.to_vec());
+        Ok(())
+    }
+
+    async fn tokens_pending_launch_analysis(&self) -> Result<Vec<String>> {
+        let data = self.data.lock().await;
+        Ok(data.tokens
+            .iter()
+            .filter(|(_, token)| token.status == "active")
+            .filter(|(mint_address, _)| {
+                data.launches.get(*mint_address).is_none_or(|launch| launch.status == "history_too_long")
+            })
+            .map(|(mint_address, _)| mint_address.clone())
+            .collect())
+    }
+
+    // Labels count towards the label holdings of the snapshots written after them
+    async fn insert_launch_analysis(&self, mint_address: &str, analysis: &LaunchAnalysis, slot_window: u64) -> Result<()> {
+        let now = format_timestamp(Utc::now());
+        let mut data = self.data.lock().await;
+        let labels = data.labels.entry(mint_address.to_string()).or_default();
+        for (label, wallets) in [(INSIDER_LABEL, &analysis.insiders), (SNIPER_LABEL, &analysis.snipers)] {
+            for (address, reason) in wallets {
+                labels.retain(|wallet| wallet.label != label || wallet.address != *address);
+                labels.push(WalletLabel {
+                    address: address.clone(),
+                    label: label.to_string(),
+                    reason: reason.clone(),
+                    added_at: now.clone(),
+                });
+            }
+        }
+        data.launches.insert(mint_address.to_string(), LaunchAnalysisRecord {
+            analyzed_at: now,
+            status: analysis.status.to_string(),
+            creation_signature: analysis.creation_signature.clone(),
+            launch_slot: analysis.launch_slot,
+            window_slots: slot_window,
+            insiders: analysis.insiders.len() as u32,
+            snipers: analysis.snipers.len() as u32,
+            sniper_acquired: analysis.sniper_acquired,
+        });
+        Ok(())
+    }
+
+    // Raw points are the snapshots, the hourly and daily rollups keep the newest
+    // snapshot of each bucket. Auto picks the finest tier whose first bucket
+    // covers `from`, as resolve_resolution does.
+    async fn history(&self, mint_address: &str, from: i64, to: i64, resolution: Resolution) -> Result<TokenHistory> {
+        let data = self.data.lock().await;
+        let snapshots = data.snapshots(mint_address);
+        let resolution = match resolution {
+            Resolution::Auto => candidate_resolutions(from, to)
+                .into_iter()
+                .find(|tier| snapshots.first().is_some_and(|first| tier.bucket_start(first.record.timestamp) <= from))
+                .unwrap_or(Resolution::Daily),
+            other => other,
+        };
+
+        let mut buckets: BTreeMap<i64, &MemorySnapshot> = BTreeMap::new();
+        for snapshot in snapshots {
+            buckets.insert(resolution.bucket_start(snapshot.record.timestamp), snapshot);
+        }
+        let points = buckets.into_iter()
+            .filter(|(bucket, _)| (from..=to).contains(bucket))
+            .map(|(bucket, snapshot)| {
+                let mut point = HistoryPoint::new(format_unix(bucket));
+                point.price = Some(snapshot.stats.price);
+                point.supply = Some(snapshot.stats.supply);
+                point.market_cap = Some(snapshot.stats.market_cap);
+                if let Some(distribution) = &snapshot.metrics.distribution {
+                    point.hhi = Some(distribution.hhi);
+                    point.distribution_score = Some(distribution.distribution_score);
+                    point.gini = Some(distribution.gini);
+                    point.nakamoto_coefficient = Some(distribution.nakamoto_coefficient);
+                    point.total_holders = Some(distribution.total_count as u64);
+                }
+                point.concentration_metrics = snapshot.metrics.concentration.clone();
+                point.holder_counts = snapshot.metrics.holder_thresholds
+                    .iter()
+                    .map(|threshold| HolderCountPoint {
+                        usd_threshold: threshold.usd_threshold,
+                        holder_count: threshold.holder_count,
+                    })
+                    .collect();
+                point
+            })
+            .collect();
+
+        Ok(TokenHistory {
+            mint_address: mint_address.to_string(),
+            resolution,
+            from,
+            to,
+            points,
+        })
+    }
+
+    // Same as get_concentration, from the latest snapshot's balances
+    async fn concentration(&self, mint_address: &str, top_n: &[u32]) -> Result<TokenConcentration> {
+        let data = self.data.lock().await;
+        let snapshot = data.latest(mint_address)?;
+        let mut balances: Vec<f64> = snapshot.balances.iter().map(|(_, balance)| *balance).collect();
+        balances.sort_by(|a, b| b.total_cmp(a));
+        let supply = snapshot.stats.supply;
+        let holders_for = |share: f64| {
+            let mut held = 0.0;
+            balances.iter()
+                .position(|balance| {
+                    held += balance;
+                    held >= supply * share
+                })
+                .map(|index| index as u64 + 1)
+        };
+
+        Ok(TokenConcentration {
+            mint_address: mint_address.to_string(),
+            timestamp: format_unix(snapshot.record.timestamp),
+            total_holders: balances.len() as u64,
+            concentration_metrics: metrics::concentration(&balances, supply, top_n),
+            holders_for_50pct: holders_for(0.5),
+            holders_for_90pct: holders_for(0.9),
+        })
+    }
+
+    async fn lorenz_curve(&self, mint_address: &str, record: &SnapshotRecord, points: u32) -> Result<LorenzCurve> {
+        let data = self.data.lock().await;
+        let snapshot = data.snapshot(mint_address, record)?;
+        let mut balances: Vec<f64> = snapshot.balances.iter().map(|(_, balance)| *balance).collect();
+        balances.sort_by(f64::total_cmp);
+        let total: f64 = balances.iter().sum();
+        let holders = balances.len();
+
+        Ok(LorenzCurve {
+            mint_address: mint_address.to_string(),
+            timestamp: record.timestamp,
+            total_holders: holders as u64,
+            points: if holders == 0 {
+                Vec::new()
+            } else {
+                (0..=points as usize)
+                    .map(|k| LorenzPoint {
+                        holder_share: k as f64 / points as f64,
+                        supply_share: balances[..k * holders / points as usize].iter().sum::<f64>() / total,
+                    })
+                    .collect()
+            },
+        })
+    }
+
+    async fn balance_histogram(&self, mint_address: &str, record: &SnapshotRecord, bins_per_decade: u32) -> Result<BalanceHistogram> {
+        let data = self.data.lock().await;
+        let snapshot = data.snapshot(mint_address, record)?;
+        let token_scale = 1.0 / 10f64.powi(record.decimals as i32);
+
+        Ok(BalanceHistogram {
+            mint_address: mint_address.to_string(),
+            timestamp: record.timestamp,
+            price: record.price,
+            bins_per_decade,
+            tokens: log_histogram(&snapshot.balances, token_scale, bins_per_decade),
+            usd: log_histogram(&snapshot.balances, token_scale * record.price, bins_per_decade),
+        })
+    }
+
+    async fn holder_age(&self, mint_address: &str, record: &SnapshotRecord) -> Result<HolderAge> {
+        let data = self.data.lock().await;
+        let snapshot = data.snapshot(mint_address, record)?;
+        // Holders of the snapshot are in the latest spell they started by then
+        let snapshots = data.snapshots(mint_address);
+        let taken = snapshots.partition_point(|earlier| earlier.record.timestamp <= record.timestamp);
+        let first_seen: HashMap<&str, i64> = holder_spells(&snapshots[..taken]).into_iter().collect();
+        let mut buckets: BTreeMap<u64, (u64, f64)> = BTreeMap::new();
+        for (holder, balance) in &snapshot.balances {
+            if let Some(seen) = first_seen.get(holder.as_str()) {
+                let bucket = buckets.entry(distribution::age_bucket(record.timestamp - seen)).or_default();
+                bucket.0 += 1;
+                bucket.1 += balance;
+            }
+        }
+        let rows = buckets.into_iter()
+            .map(|(bucket, (holders, balance))| (bucket, holders, balance))
+            .collect();
+        Ok(distribution::holder_age(mint_address, record, rows))
+    }
+
+    // Ranked over every owner, excluded ones included, like get_holders
+    async fn holders(&self, mint_address: &str, record: &SnapshotRecord, after_rank: u64, limit: u64, min_usd: f64) -> Result<HolderList> {
+        let data = self.data.lock().await;
+        let mut owners = data.snapshot(mint_address, record)?.owners.clone();
+        largest_first(&mut owners);
+        let total_holders = owners.len() as u64;
+
+        let rows = if min_usd > 0.0 && record.price <= 0.0 {
+            Vec::new()
+        } else {
+            let min_balance = if min_usd > 0.0 { min_usd / record.price * 10f64.powi(record.decimals as i32) } else { 0.0 };
+            owners.into_iter()
+                .enumerate()
+                .map(|(index, (holder_address, balance))| HolderRankRecord {
+                    rank: index as u64 + 1,
+                    category: data.excluded.get(&holder_address).cloned().unwrap_or_default(),
+                    holder_address,
+                    balance,
+                })
+                .filter(|row| row.rank > after_rank && row.balance >= min_balance)
+                .take(limit as usize)
+                .collect()
+        };
+        Ok(holders::holder_list(mint_address, record, total_holders, rows, limit))
+    }
+
+    async fn wallet_holding(&self, mint_address: &str, holder_address: &str, limit: u64) -> Result<WalletHolding> {
+        let data = self.data.lock().await;
+        let history = last_snapshots(data.snapshots(mint_address), limit)
+            .iter()
+            .filter_map(|snapshot| wallet_snapshot(snapshot, &snapshot.owners, holder_address))
+            .map(holders::wallet_snapshot)
+            .collect();
+
+        Ok(WalletHolding {
+            mint_address: mint_address.to_string(),
+            holder_address: holder_address.to_string(),
+            excluded_category: data.excluded.get(holder_address).cloned(),
+            history,
+        })
+    }
+
+    // From the latest balances of each mint, excluded owners left out, like LATEST_HOLDER_BALANCES_SQL
+    async fn wallet_overlap(&self, mints: &[String], limit: u64) -> Result<WalletOverlap> {
+        let data = self.data.lock().await;
+        let mut held: BTreeMap<&str, Vec<(&str, f64, &SnapshotRecord)>> = BTreeMap::new();
+        for mint_address in mints {
+            let Some(snapshot) = data.snapshots(mint_address).last() else {
+                continue;
+            };
+            for (holder, balance) in &snapshot.balances {
+                held.entry(holder.as_str()).or_default().push((mint_address.as_str(), *balance, &snapshot.record));
+            }
+        }
+        let union_holders = held.len() as u64;
+        let shared: Vec<(&str, Vec<(&str, f64, &SnapshotRecord)>)> = held.into_iter()
+            .filter(|(_, holdings)| holdings.len() == mints.len())
+            .collect();
+
+        let mut supply_shares: Vec<(String, f64)> = Vec::new();
+        for mint_address in mints {
+            let mut total = None;
+            for (_, balance, record) in shared.iter().flat_map(|(_, holdings)| holdings).filter(|(mint, ..)| mint == mint_address) {
+                let (held, supply) = total.get_or_insert((0.0, record.supply));
+                *held += balance;
+                *supply = record.supply;
+            }
+            if let Some((held, supply)) = total {
+                supply_shares.push((mint_address.clone(), held / supply * 100.0));
+            }
+        }
+
+        let mut holders: Vec<SharedHolderRecord> = shared.iter()
+            .map(|(holder, holdings)| SharedHolderRecord {
+                holder_address: holder.to_string(),
+                mints: holdings.iter().map(|(mint, ..)| mint.to_string()).collect(),
+                balances: holdings.iter().map(|(_, balance, record)| balance / 10f64.powi(record.decimals as i32)).collect(),
+                shares: holdings.iter().map(|(_, balance, record)| balance / record.supply * 100.0).collect(),
+            })
+            .collect();
+        holders.sort_by(|a, b| {
+            b.shares.iter().sum::<f64>()
+                .total_cmp(&a.shares.iter().sum::<f64>())
+                .then_with(|| a.holder_address.cmp(&b.holder_address))
+        });
+        holders.truncate(limit as usize);
+
+        Ok(overlap::wallet_overlap(mints, (shared.len() as u64, union_holders), supply_shares, holders))
+    }
+
+    async fn multi_token_wallets(&self, min_tokens: u64, limit: u64) -> Result<MultiTokenWallets> {
+        let data = self.data.lock().await;
+        let mut held: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for (mint_address, snapshots) in &data.snapshots {
+            for (holder, _) in snapshots.last().map_or(&[][..], |snapshot| &snapshot.balances) {
+                held.entry(holder.as_str()).or_default().push(mint_address.clone());
+            }
+        }
+        let mut wallets: Vec<MultiTokenWallet> = held.into_iter()
+            .filter(|(_, mints)| mints.len() as u64 >= min_tokens)
+            .map(|(holder, mut mints)| {
+                mints.sort();
+                MultiTokenWallet {
+                    holder_address: holder.to_string(),
+                    token_count: mints.len() as u64,
+                    mints,
+                }
+            })
+            .collect();
+        let total_wallets = wallets.len() as u64;
+        wallets.sort_by(|a, b| b.token_count.cmp(&a.token_count).then_with(|| a.holder_address.cmp(&b.holder_address)));
+        wallets.truncate(limit as usize);
+
+        Ok(MultiTokenWallets {
+            min_tokens,
+            total_wallets,
+            wallets,
+        })
+    }
+
+    // Active tokens the wallet holds in their latest snapshot, excluded owners left out
+    async fn wallet_portfolio(&self, holder_address: &str, snapshots: u64) -> Result<WalletPortfolio> {
+        let data = self.data.lock().await;
+        let mut latest = Vec::new();
+        let mut histories = HashMap::new();
+        for (mint_address, _) in data.tokens.iter().filter(|(_, token)| token.status == "active") {
+            let Some(row) = data.snapshots(mint_address)
+                .last()
+                .and_then(|snapshot| wallet_snapshot(snapshot, &snapshot.balances, holder_address))
+                .filter(|row| row.balance > 0.0)
+            else {
+                continue;
+            };
+            latest.push(PortfolioTokenRecord {
+                mint_address: mint_address.clone(),
+                timestamp: row.timestamp,
+                price: row.price,
+                supply: row.supply,
+                decimals: row.decimals,
+                total_holders: row.total_holders,
+                balance: row.balance,
+                rank: row.rank,
+            });
+            let history = last_snapshots(data.snapshots(mint_address), snapshots)
+                .iter()
+                .filter_map(|snapshot| wallet_snapshot(snapshot, &snapshot.balances, holder_address))
+                .map(holders::wallet_snapshot)
+                .collect();
+            histories.insert(mint_address.clone(), history);
+        }
+
+        Ok(holders::wallet_portfolio(holder_address, data.excluded.get(holder_address).cloned(), latest, histories))
+    }
+
+    async fn wallet_labels(&self, mint_address: &str) -> Result<Vec<WalletLabel>> {
+        let data = self.data.lock().await;
+        let mut labels = data.labels.get(mint_address).cloned().unwrap_or_default();
+        labels.sort_by(|a, b| a.label.cmp(&b.label).then_with(|| a.added_at.cmp(&b.added_at)));
+        Ok(labels)
+    }
+
+    // Laid out like the ClickHouse export, Parquet isn't available
+    async fn export(&self, dataset: ExportDataset, format: ExportFormat, mint_address: &str, from: i64, to: i64) -> Result<ExportStream> {
+        let data = self.data.lock().await;
+        let snapshots: Vec<&MemorySnapshot> = data.snapshots(mint_address)
+            .iter()
+            .filter(|snapshot| (from..=to).contains(&snapshot.record.timestamp))
+            .collect();
+        let rows: Vec<Vec<Value>> = match dataset {
+            ExportDataset::Holders => {
+                check_holder_export_range(mint_address, from, to, snapshots.len())?;
+                let mut rows = Vec::new();
+                for snapshot in snapshots {
+                    let mut balances = snapshot.balances.clone();
+                    largest_first(&mut balances);
+                    let timestamp = format_unix(snapshot.record.timestamp);
+                    rows.extend(balances.into_iter().map(|(holder, balance)| vec![json!(timestamp), json!(holder), json!(balance)]));
+                }
+                rows
+            }
+            ExportDataset::Stats => snapshots.iter()
+                .map(|snapshot| vec![
+                    json!(format_unix(snapshot.record.timestamp)),
+                    json!(format_unix(snapshot.record.checkpoint_timestamp)),
+                    json!(snapshot.stats.price),
+                    json!(snapshot.stats.supply),
+                    json!(snapshot.stats.market_cap),
+                    json!(snapshot.stats.decimals),
+                ])
+                .collect(),
+            ExportDataset::Metrics => snapshots.iter()
+                .filter_map(|snapshot| snapshot.metrics.distribution.as_ref().map(|distribution| (snapshot, distribution)))
+                .map(|(snapshot, distribution)| {
+                    let percentiles = &distribution.percentiles;
+                    let concentration: serde_json::Map<String, Value> = snapshot.metrics.concentration
+                        .iter()
+                        .map(|metric| (metric.top_n.to_string(), json!(metric.percentage)))
+                        .collect();
+                    let holder_counts: serde_json::Map<String, Value> = snapshot.metrics.holder_thresholds
+                        .iter()
+                        .map(|threshold| (threshold.usd_threshold.to_string(), json!(threshold.holder_count)))
+                        .collect();
+                    vec![
+                        json!(format_unix(snapshot.record.timestamp)),
+                        json!(distribution.total_count),
+                        json!(distribution.hhi),
+                        json!(distribution.distribution_score),
+                        json!(distribution.gini),
+                        json!(distribution.nakamoto_coefficient),
+                        json!(distribution.theil_index),
+                        json!(distribution.mean_balance),
+                        json!(distribution.median_balance),
+                        json!(percentiles.p10),
+                        json!(percentiles.p25),
+                        json!(percentiles.p50),
+                        json!(percentiles.p75),
+                        json!(percentiles.p90),
+                        json!(percentiles.p95),
+                        json!(percentiles.p99),
+                        json!(distribution.lorenz_curve),
+                        Value::Object(concentration),
+                        Value::Object(holder_counts),
+                    ]
+                })
+                .collect(),
+        };
+        export_rows(dataset, format, mint_address, rows)
+    }
+
+    async fn enqueue_job(&self, mint_address: &str, kind: &str) -> Result<String> {
+        let mut data = self.data.lock().await;
+        let job_id = format!("job-{}", data.jobs.len() + 1);
+        data.jobs.push(SnapshotJobRecord {
+            job_id: job_id.clone(),
+            mint_address: mint_address.to_string(),
+            kind: kind.to_string(),
+            status: "queued".to_string(),
+            created_at: Utc::now().timestamp_millis(),
+            started_at: 0,
+            finished_at: 0,
+            attempts: 0,
+            max_attempts: MAX_ATTEMPTS,
+            run_after: 0,
+            lease_owner: String::new(),
+            lease_expires_at: 0,
+            holders: 0,
+            rows_written: 0,
+            snapshot_timestamp: NEVER.to_string(),
+            error: String::new(),
+        });
+        Ok(job_id)
+    }
+
+    async fn job(&self, job_id: &str) -> Result<Option<SnapshotJob>> {
+        let data = self.data.lock().await;
+        Ok(data.jobs
+            .iter()
+            .find(|job| job.job_id == job_id)
+            .cloned()
+            .map(jobs::to_snapshot_job))
+    }
+
+    async fn jobs(&self, status: Option<&str>, mint_address: Option<&str>, limit: u64) -> Result<Vec<SnapshotJob>> {
+        let data = self.data.lock().await;
+        Ok(data.jobs
+            .iter()
+            .rev()
+            .filter(|job| status.is_none_or(|status| job.status == status))
+            .filter(|job| mint_address.is_none_or(|mint_address| job.mint_address == mint_address))
+            .take(limit as usize)
+            .cloned()
+            .map(jobs::to_snapshot_job)
+            .collect())
+    }
+
+    async fn claimable_jobs(&self, limit: u64) -> Result<Vec<ClaimableJobRecord>> {
+        let now = Utc::now().timestamp_millis();
+        let data = self.data.lock().await;
+        let running: HashSet<&str> = data.jobs
+            .iter()
+            .filter(|job| job.status == "running" && job.lease_expires_at >= now)
+            .map(|job| job.mint_address.as_str())
+            .collect();
+        Ok(data.jobs
+            .iter()
+            .filter(|job| is_claimable(job, now) && !running.contains(job.mint_address.as_str()))
+            .take(limit as usize)
+            .map(|job| ClaimableJobRecord {
+                job_id: job.job_id.clone(),
+                mint_address: job.mint_address.clone(),
+                attempt: job.attempts + 1,
+            })
+            .collect())
+    }
+
+    // The lock makes the check and the lease one step, so exactly one claim of an attempt wins
+    async fn claim_job(&self, job_id: &str, attempt: u32, worker_id: &str, lease_secs: u64) -> Result<bool> {
+        let now = Utc::now().timestamp_millis();
+        let mut data = self.data.lock().await;
+        let Some(job) = data.jobs.iter_mut().find(|job| job.job_id == job_id) else {
+            return Ok(false);
+        };
+        if !is_claimable(job, now) || job.attempts + 1 != attempt {
+            return Ok(false);
+        }
+        job.status = "running".to_string();
+        job.started_at = now;
+        job.attempts = attempt;
+        job.lease_owner = worker_id.to_string();
+        job.lease_expires_at = now + lease_secs as i64 * 1000;
+        Ok(true)
+    }
+
+    async fn renew_lease(&self, job_id: &str, attempt: u32, worker_id: &str, lease_secs: u64) -> Result<()> {
+        let now = Utc::now().timestamp_millis();
+        let mut data = self.data.lock().await;
+        if let Some(job) = leased_job(&mut data.jobs, job_id, attempt, worker_id).filter(|job| job.status == "running") {
+            job.lease_expires_at = now + lease_secs as i64 * 1000;
+        }
+        Ok(())
+    }
+
+    async fn finish_job(&self, job_id: &str, attempt: u32, worker_id: &str, summary: &SnapshotSummary) -> Result<()> {
+        let now = Utc::now().timestamp_millis();
+        let mut data = self.data.lock().await;
+        if let Some(job) = leased_job(&mut data.jobs, job_id, attempt, worker_id) {
+            job.status = "done".to_string();
+            job.finished_at = now;
+            job.lease_owner = String::new();
+            job.lease_expires_at = 0;
+            job.holders = summary.holders as u64;
+            job.rows_written = summary.rows_written as u64;
+            job.snapshot_timestamp = summary.timestamp.clone();
+            job.error = String::new();
+        }
+        Ok(())
+    }
+
+    async fn fail_job(&self, job_id: &str, attempt: u32, worker_id: &str, error: &str, retry_after_secs: u64) -> Result<()> {
+        let now = Utc::now().timestamp_millis();
+        let mut data = self.data.lock().await;
+        if let Some(job) = leased_job(&mut data.jobs, job_id, attempt, worker_id) {
+            let retry = job.attempts < job.max_attempts;
+            job.status = if retry { "queued" } else { "failed" }.to_string();
+            job.finished_at = if retry { 0 } else { now };
+            job.run_after = now + retry_after_secs as i64 * 1000;
+            job.lease_owner = String::new();
+            job.lease_expires_at = 0;
+            job.error = error.to_string();
+        }
+        Ok(())
+    }
+
+    async fn fail_exhausted_leases(&self) -> Result<()> {
+        let now = Utc::now().timestamp_millis();
+        let mut data = self.data.lock().await;
+        for job in data.jobs.iter_mut() {
+            if job.status == "running" && job.lease_expires_at < now && job.attempts >= job.max_attempts {
+                job.status = "failed".to_string();
+                job.finished_at = now;
+                job.error = "lease expired on the last attempt".to_string();
+            }
+        }
+        Ok(())
+    }
+
+    async fn send_heartbeat(&self, worker_id: &str, mode: &str, active: bool) -> Result<()> {
+        let mut data = self.data.lock().await;
+        data.workers.insert(worker_id.to_string(), MemoryWorker {
+            mode: mode.to_string(),
+            active,
+            heartbeat_at: Utc::now().timestamp_millis(),
+        });
+        Ok(())
+    }
+
+    async fn live_workers(&self, timeout_secs: u64) -> Result<Vec<(String, String, String)>> {
+        let cutoff = Utc::now().timestamp_millis() - timeout_secs as i64 * 1000;
+        let data = self.data.lock().await;
+        Ok(data.workers
+            .iter()
+            .filter(|(_, worker)| worker.active && worker.heartbeat_at > cutoff)
+            .map(|(worker_id, worker)| (
+                worker_id.clone(),
+                worker.mode.clone(),
+                jobs::format_millis(worker.heartbeat_at).unwrap_or_default(),
+            ))
+            .collect())
+    }
+}
@@ -0,0 +1,71 @@
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use anyhow::Result;
+use clickhouse::Client;
+use governor::{Quota, RateLimiter, state::{NotKeyed, InMemoryState}, clock::DefaultClock};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use tokio::time::{sleep, Duration};
+use crate::config::{ClickHouseConfig, RpcConfig};
+
+// The services, API and commands as a library, main.rs runs them and the
+// integration tests in tests/ drive them with a MemoryStore
+
+pub mod types;
+pub mod services;
+pub mod api;
+pub mod db;
+pub mod cli;
+pub mod config;
+pub mod commands;
+
+pub type RpcLimiter = Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>;
+
+pub fn connect_rpc(config: &RpcConfig) -> Result<(Arc<RpcClient>, RpcLimiter)> {
+    let rpc_url = config.endpoint()?;
+    
+    let quota = NonZeroU32::new(config.requests_per_second)
+        .ok_or_else(|| anyhow::anyhow!("rpc.requests_per_second must be at least 1"))?;
+    let rpc_limiter = Arc::new(RateLimiter::direct(Quota::per_second(quota)));
+    let rpc_client = Arc::new(RpcClient::new_with_timeout_and_commitment(
+        rpc_url,
+        std::time::Duration::from_secs(config.timeout_secs),
+        CommitmentConfig::confirmed(),
+    ));
+    Ok((rpc_client, rpc_limiter))
+}
+
+pub async fn connect_to_clickhouse(config: &ClickHouseConfig) -> Result<Client> {
+    let max_retries = config.connect_retries;
+    tracing::info!("Attempting to connect to ClickHouse with URL: {}", config.url);
+    
+    let client = Client::default()
+        .with_url(&config.url)
+        .with_user(&config.user)
+        .with_password(&config.password)
+        .with_database(&config.database);
+
+    for attempt in 1..=max_retries {
+        tracing::info!("Connection attempt {} of {}", attempt, max_retries);
+        match client.query("SELECT version()").execute().await {
+            Ok(_) => {
+                tracing::info!("Successfully connected to ClickHouse");
+                // Try to get database name
+                match client.query("SELECT currentDatabase()").fetch_one::<String>().await {
+                    Ok(db_name) => tracing::info!("Connected to database: {}", db_name),
+                    Err(e) => tracing::warn!("Connected but couldn't get database name: {}", e),
+                }
+                return Ok(client);
+            }
+            Err(e) => {
+                tracing::error!("Connection attempt {} failed with error: {}", attempt, e);
+                if attempt == max_retries {
+                    return Err(anyhow::anyhow!("Failed to connect to ClickHouse after {} attempts. Last error: {}", max_retries, e));
+                }
+                tracing::warn!("Retrying in 2 seconds...");
+                sleep(Duration::from_secs(2)).await;
+            }
+        }
+    }
+    unreachable!()
+}
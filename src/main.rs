@@ -3,21 +3,20 @@ use anyhow::Result;
 use dotenv::dotenv;
 use std::env;
 use std::sync::Arc;
-use solana_client::nonblocking::rpc_client::RpcClient;
+use std::path::PathBuf;
 use tokio::net::TcpListener;
-use governor::{Quota, RateLimiter};
-use nonzero_ext::nonzero;
-use solana_sdk::commitment_config::CommitmentConfig;
-use clickhouse::Client;
-use crate::db::init::init_database;
-use tokio::time::{sleep, Duration};
+use memespread::db::init::init_database;
+use memespread::cli::{Cli, Command, ExclusionsAction};
+use clap::Parser;
+use memespread::db::retention::apply_retention;
+use memespread::config::Config;
 use poem::{
     handler, 
     Route, 
     Server, 
     get, 
     post, 
-    web::{Json, Path}, 
+    web::{Data, Json, Path}, 
     Response, 
     IntoResponse, 
     middleware::Cors, 
@@ -25,48 +24,14 @@ use poem::{
     http::StatusCode,
 };
 use serde::{Deserialize, Serialize};
-use reqwest;
-use serde_json;
 
-mod types;
-mod services;
-mod api;
-mod db;
-
-use crate::api::routes::create_router;
-use crate::services::monitor;
-
-async fn connect_to_clickhouse(max_retries: u32) -> Result<Client> {
-    let clickhouse_url = env::var("CLICKHOUSE_URL").unwrap_or_else(|_| "http://localhost:8123".to_string());
-    tracing::info!("Attempting to connect to ClickHouse with URL: {}", clickhouse_url);
-    
-    let client = Client::default()
-        .with_url(&clickhouse_url);
-
-    for attempt in 1..=max_retries {
-        tracing::info!("Connection attempt {} of {}", attempt, max_retries);
-        match client.query("SELECT version()").execute().await {
-            Ok(_) => {
-                tracing::info!("Successfully connected to ClickHouse");
-                // Try to get database name
-                match client.query("SELECT currentDatabase()").fetch_one::<String>().await {
-                    Ok(db_name) => tracing::info!("Connected to database: {}", db_name),
-                    Err(e) => tracing::warn!("Connected but couldn't get database name: {}", e),
-                }
-                return Ok(client);
-            }
-            Err(e) => {
-                tracing::error!("Connection attempt {} failed with error: {}", attempt, e);
-                if attempt == max_retries {
-                    return Err(anyhow::anyhow!("Failed to connect to ClickHouse after {} attempts. Last error: {}", max_retries, e));
-                }
-                tracing::warn!("Retrying in 2 seconds...");
-                sleep(Duration::from_secs(2)).await;
-            }
-        }
-    }
-    unreachable!()
-}
+use memespread::api::routes::create_router;
+use memespread::commands;
+use memespread::db::store::{ClickHouseStore, Store};
+use memespread::services::{self, monitor};
+use memespread::services::sharding::{self, Shard};
+use memespread::services::snapshots::SnapshotCache;
+use memespread::{connect_rpc, connect_to_clickhouse};
 
 #[derive(Deserialize, Serialize)]
 struct ContractInput {
@@ -74,9 +39,9 @@ struct ContractInput {
 }
 
 #[handler]
-async fn submit(Json(input): Json<ContractInput>) -> impl IntoResponse {
+async fn submit(Json(input): Json<ContractInput>, Data(config): Data<&Arc<Config>>) -> impl IntoResponse {
     let client = reqwest::Client::new();
-    let response = match client.post("http://localhost:8000/tokens")
+    let response = match client.post(format!("{}/tokens", config.frontend.api_url))
         .json(&serde_json::json!({
             "mint_address": input.contract
         }))
@@ -105,7 +70,7 @@ async fn submit(Json(input): Json<ContractInput>) -> impl IntoResponse {
 }
 
 #[handler]
-async fn token_details(Path(mint_address): Path<String>) -> impl IntoResponse {
+async fn token_details(Path(mint_address): Path<String>, Data(config): Data<&Arc<Config>>) -> impl IntoResponse {
     Response::builder()
         .content_type("text/html")
         .body(
@@ -277,10 +242,18 @@ async fn token_details(Path(mint_address): Path<String>) -> impl IntoResponse {
                         return absNum < 1 ? num.toFixed(6) : num.toFixed(decimals);
                     }}
 
+                    // Thresholds can be sub-dollar, those keep their own precision
+                    function formatUsd(num) {{
+                        return num < 1 ? String(num) : formatNumber(num, 0);
+                    }}
+
                     function updateHolderThresholds(data, useTotal = true) {{
                         const thresholds = data.holder_thresholds;
                         const container = document.getElementById('holder-thresholds');
                         container.innerHTML = '';
+                        // pct_of_10usd is relative to the lowest configured threshold
+                        const lowest = thresholds.length ? '>$' + formatUsd(thresholds[0].usd_threshold) : '>$10';
+                        document.getElementById('lowest-threshold-btn').textContent = lowest + ' Wallets';
                         
                         thresholds.forEach(t => {{
                             const percentage = useTotal ? t.pct_total_holders : t.pct_of_10usd;
@@ -289,13 +262,13 @@ async fn token_details(Path(mint_address): Path<String>) -> impl IntoResponse {
                             const bar = document.createElement('div');
                             bar.className = 'threshold-bar';
                             bar.innerHTML = `
-                                <div class="threshold-label">>${{formatNumber(t.usd_threshold, 0)}}</div>
+                                <div class="threshold-label">>${{formatUsd(t.usd_threshold)}}</div>
                                 <div class="bar-container">
                                     <div class="bar" style="width: ${{barWidth}}%"></div>
                                 </div>
                                                                     <div class="threshold-value">
                                      ${{formatNumber(t.holder_count, 0)}} 
-                                     (${{percentage.toFixed(1)}}% of ${{useTotal ? 'total' : '>$10'}})
+                                     (${{percentage.toFixed(1)}}% of ${{useTotal ? 'total' : lowest}})
                                 </div>
                             `;
                             container.appendChild(bar);
@@ -303,35 +276,59 @@ async fn token_details(Path(mint_address): Path<String>) -> impl IntoResponse {
                     }}
 
                                         function updateConcentrationBars(data) {{
-                        const bars = document.querySelectorAll('#concentration-bars .threshold-bar');
-                        if (!bars.length) return;
-
-                        // Update existing bars with concentration metrics
-                        data.concentration_metrics.forEach((m, i) => {{
-                            if (bars[i]) {{
-                                const bar = bars[i].querySelector('.bar');
-                                const value = bars[i].querySelector('.threshold-value');
-                                if (bar) bar.style.width = m.percentage + '%';
-                                if (value) value.textContent = m.percentage.toFixed(2) + '%';
-                            }}
-                        }});
+                        const container = document.getElementById('concentration-bars');
+                        const metrics = data.concentration_metrics;
+                        if (!container || !metrics.length) return;
+
+                        // Buckets are configurable per token, so the bars follow the response
+                        const rows = metrics.map(m => ({{
+                            label: m.top_n === 1 ? '#1 Top Holder' : `Top ${{m.top_n}} Holders`,
+                            percentage: m.percentage,
+                        }}));
+                        const last = metrics[metrics.length - 1];
+                        rows.push({{ label: `${{last.top_n + 1}}+ Holders &infin;`, percentage: 100 - last.percentage }});
+
+                        container.innerHTML = rows.map(r => `
+                            <div class="threshold-bar">
+                                <div class="threshold-label">${{r.label}}</div>
+                                <div class="bar-container"><div class="bar" style="width: ${{r.percentage}}%"></div></div>
+                                <div class="threshold-value">${{r.percentage.toFixed(2)}}%</div>
+                            </div>
+                        `).join('');
+                    }}
 
-                        // Update the remaining holders bar (last bar)
-                        const lastBar = bars[bars.length - 1];
-                        if (lastBar && data.concentration_metrics.length > 0) {{
-                            const lastMetric = data.concentration_metrics[data.concentration_metrics.length - 1];
-                            const remainingPercentage = 100 - lastMetric.percentage;
-                            const bar = lastBar.querySelector('.bar');
-                            const value = lastBar.querySelector('.threshold-value');
-                            if (bar) bar.style.width = remainingPercentage + '%';
-                            if (value) value.textContent = remainingPercentage.toFixed(2) + '%';
+                    // Time since each current holder first held the token, weighted by count or balance
+                    function updateHolderAge(age, byHolders = true) {{
+                        const container = document.getElementById('holder-age');
+                        if (!container || !age) return;
+                        container.innerHTML = age.buckets.map(b => {{
+                            const percentage = byHolders ? b.holder_pct : b.balance_pct;
+                            const value = byHolders ? formatNumber(b.holders, 0) : formatNumber(b.balance);
+                            return `
+                                <div class="threshold-bar">
+                                    <div class="threshold-label">${{b.bucket}}</div>
+                                    <div class="bar-container"><div class="bar" style="width: ${{percentage}}%"></div></div>
+                                    <div class="threshold-value">${{value}} (${{percentage.toFixed(1)}}%)</div>
+                                </div>
+                            `;
+                        }}).join('');
+                    }}
+
+                    async function loadHolderAge() {{
+                        try {{
+                            const res = await fetch('{api_url}/tokens/' + encodeURIComponent('{}') + '/age');
+                            if (!res.ok) return;
+                            window.holderAge = await res.json();
+                            updateHolderAge(window.holderAge, true);
+                        }} catch (error) {{
+                            console.error('Error loading holder age:', error);
                         }}
                     }}
 
                     async function loadTokenDetails() {{
                         const contentDiv = document.getElementById('content');
                         try {{
-                            const res = await fetch('http://localhost:8000/tokens/' + encodeURIComponent('{}'));
+                            const res = await fetch('{api_url}/tokens/' + encodeURIComponent('{}'));
                             if (!res.ok) throw new Error(`HTTP error! status: ${{res.status}}`);
                             const data = await res.text();
                             try {{
@@ -362,7 +359,7 @@ async fn token_details(Path(mint_address): Path<String>) -> impl IntoResponse {
                                             <h2>Holder Thresholds</h2>
                                             <div class="toggle-container">
                                                 <button class="toggle-btn active" onclick="this.classList.add('active'); this.nextElementSibling.classList.remove('active'); updateHolderThresholds(window.tokenData, true)">All Wallets</button>
-                                                <button class="toggle-btn" onclick="this.classList.add('active'); this.previousElementSibling.classList.remove('active'); updateHolderThresholds(window.tokenData, false)">>$10 Wallets</button>
+                                                <button id="lowest-threshold-btn" class="toggle-btn" onclick="this.classList.add('active'); this.previousElementSibling.classList.remove('active'); updateHolderThresholds(window.tokenData, false)">>$10 Wallets</button>
                                             </div>
                                             <div id="holder-thresholds"></div>
                                         </div>
@@ -410,6 +407,16 @@ async fn token_details(Path(mint_address): Path<String>) -> impl IntoResponse {
                                         </div>
                                     </div>
 
+                                    <!-- Holder Age Hero -->
+                                    <div class="hero-section">
+                                        <h2>Holder Age</h2>
+                                        <div class="toggle-container">
+                                            <button class="toggle-btn active" onclick="this.classList.add('active'); this.nextElementSibling.classList.remove('active'); updateHolderAge(window.holderAge, true)">By Holders</button>
+                                            <button class="toggle-btn" onclick="this.classList.add('active'); this.previousElementSibling.classList.remove('active'); updateHolderAge(window.holderAge, false)">By Balance</button>
+                                        </div>
+                                        <div id="holder-age"></div>
+                                    </div>
+
                                     <!-- Distribution Stats Hero -->
                                     <div class="hero-section">
                                         <h2>Distribution Stats</h2>
@@ -430,6 +437,26 @@ async fn token_details(Path(mint_address): Path<String>) -> impl IntoResponse {
                                                 <div class="metric-label">Median Balance</div>
                                                 <div class="metric-value">${{formatNumber(jsonData.distribution_stats.median_balance)}}</div>
                                             </div>
+                                            <div class="metric-card">
+                                                <div class="metric-label">Holders</div>
+                                                <div class="metric-value">${{formatNumber(jsonData.distribution_stats.total_count, 0)}}</div>
+                                            </div>
+                                            <div class="metric-card">
+                                                <div class="metric-label">Gini</div>
+                                                <div class="metric-value">${{jsonData.distribution_stats.gini.toFixed(4)}}</div>
+                                            </div>
+                                            <div class="metric-card">
+                                                <div class="metric-label">Nakamoto Coefficient</div>
+                                                <div class="metric-value">${{jsonData.distribution_stats.nakamoto_coefficient}}</div>
+                                            </div>
+                                            <div class="metric-card">
+                                                <div class="metric-label">Theil Index</div>
+                                                <div class="metric-value">${{jsonData.distribution_stats.theil_index.toFixed(4)}}</div>
+                                            </div>
+                                            <div class="metric-card">
+                                                <div class="metric-label">p10 / p50 / p90 / p99 Balance</div>
+                                                <div class="metric-value">${{['p10', 'p50', 'p90', 'p99'].map(p => formatNumber(jsonData.distribution_stats.percentiles[p])).join(' / ')}}</div>
+                                            </div>
                                         </div>
                                     </div>
                                 `;
@@ -438,6 +465,7 @@ async fn token_details(Path(mint_address): Path<String>) -> impl IntoResponse {
                                                 window.tokenData = jsonData;
                                                 updateHolderThresholds(jsonData, true);
                                                 updateConcentrationBars(jsonData);
+                                                loadHolderAge();
                                 
                             }} catch (parseError) {{
                                 contentDiv.className = 'error';
@@ -452,13 +480,14 @@ async fn token_details(Path(mint_address): Path<String>) -> impl IntoResponse {
                 </script>
             </body>
             </html>"#,
-            mint_address, mint_address
+            mint_address, mint_address, mint_address,
+            api_url = config.frontend.api_url,
         )
     )
 }
 
 #[handler]
-async fn index() -> impl IntoResponse {
+async fn index(Data(config): Data<&Arc<Config>>) -> impl IntoResponse {
     Response::builder()
         .content_type("text/html")
         .body(
@@ -583,7 +612,7 @@ async fn index() -> impl IntoResponse {
                     async function loadTokenList() {
                         const listDiv = document.getElementById('token-list');
                         try {
-                            const res = await fetch('http://localhost:8000/tokens');
+                            const res = await fetch('__API_URL__/tokens');
                             if (!res.ok) throw new Error(`HTTP error! status: ${res.status}`);
                             const tokens = await res.json();
                             
@@ -596,6 +625,7 @@ async fn index() -> impl IntoResponse {
                             const tokenListHtml = tokens.map(token => `
                                 <a href="/token/${token.mint_address}" class="token-item">
                                     <div class="token-address">${token.mint_address}</div>
+                                    ${token.status === 'paused' ? '<div class="token-time">Paused</div>' : ''}
                                     <div class="token-time">
                                         Last updated: ${timeAgo(token.last_stats_update)}
                                     </div>
@@ -619,7 +649,7 @@ async fn index() -> impl IntoResponse {
                         resultDiv.textContent = 'Processing...';
                         
                         try {
-                            const res = await fetch('http://localhost:8000/tokens', {
+                            const res = await fetch('__API_URL__/tokens', {
                                 method: 'POST',
                                 headers: {'Content-Type': 'application/json'},
                                 body: JSON.stringify({mint_address: contract})
@@ -630,7 +660,7 @@ async fn index() -> impl IntoResponse {
                             try {
                                 const jsonData = JSON.parse(data);
                                 resultDiv.className = 'success';
-                                if (jsonData.status === 'monitoring_started') {
+                                if (jsonData.status === 'monitoring_started' || jsonData.status === 'monitoring_resumed') {
                                     resultDiv.innerHTML = `
                                         <div style="padding: 15px; background: #e8f5e9; border-radius: 4px; border: 1px solid #c8e6c9; color: #2e7d32;">
                                             <strong>✓ Success:</strong> ${jsonData.message}
@@ -670,6 +700,7 @@ async fn index() -> impl IntoResponse {
                 </script>
             </body>
             </html>"#
+            .replace("__API_URL__", &config.frontend.api_url)
         )
 }
 
@@ -680,18 +711,60 @@ async fn api_hello() -> &'static str {
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    tracing_subscriber::fmt::init();
+    // Logs go to stderr, so the output of the one-shot commands can be piped
+    tracing_subscriber::fmt().with_writer(std::io::stderr).init();
     
     dotenv().ok();
-    let api_key = env::var("HELIUS_API_KEY").expect("HELIUS_API_KEY must be set");
-    let rpc_url = format!("https://rpc.helius.xyz/?api-key={}", api_key);
-    
-    let rpc_limiter = Arc::new(RateLimiter::direct(Quota::per_second(nonzero!(5u32))));
-    let rpc_client = Arc::new(RpcClient::new_with_timeout_and_commitment(
-        rpc_url.clone(),
-        std::time::Duration::from_secs(60),
-        CommitmentConfig::confirmed(),
-    ));
+    let cli = Cli::parse();
+    let config_path = cli.config.or_else(|| env::var_os("MEMESPREAD_CONFIG").map(PathBuf::from));
+    let config = Arc::new(Config::load(config_path.as_deref())?);
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => run(RunMode::Serve, config).await,
+        Command::Api => run(RunMode::Api, config).await,
+        Command::Worker => run(RunMode::Worker, config).await,
+        Command::Add { mint_address } => commands::add(&config, &mint_address).await,
+        Command::Snapshot { mint_address, timeout } => commands::snapshot(&config, &mint_address, timeout).await,
+        Command::Recompute { mint_address, from, to } => commands::recompute(&config, mint_address.as_deref(), from, to).await,
+        Command::Export(args) => commands::export(&config, args).await,
+        Command::Replay(args) => commands::replay(&config, args).await,
+        Command::CheckMetrics { mint_address, at, tolerance } => commands::check_metrics(&config, &mint_address, at, tolerance).await,
+        Command::ImportSnapshots { mint_address, file, force } => commands::import_snapshots(&config, &mint_address, &file, force).await,
+        Command::Exclusions { action: ExclusionsAction::Import { file } } => commands::import_exclusions(&config, &file).await,
+        Command::Migrate { dry_run, action } => commands::migrate(&config, dry_run, action).await,
+    }
+}
+
+// Which parts of the service this process runs. `Serve` runs everything in one
+// process, `Api` only answers requests and `Worker` only takes snapshots, so
+// workers can be scaled out separately behind one or more API instances.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RunMode {
+    Serve,
+    Api,
+    Worker,
+}
+
+impl RunMode {
+    fn name(self) -> &'static str {
+        match self {
+            RunMode::Serve => "serve",
+            RunMode::Api => "api",
+            RunMode::Worker => "worker",
+        }
+    }
+
+    fn runs_api(self) -> bool {
+        self != RunMode::Worker
+    }
+
+    fn runs_worker(self) -> bool {
+        self != RunMode::Api
+    }
+}
+
+async fn run(mode: RunMode, config: Arc<Config>) -> Result<()> {
+    let (rpc_client, rpc_limiter) = connect_rpc(&config.rpc)?;
     
     // Test RPC connection at startup
     match rpc_client.get_version().await {
@@ -699,76 +772,153 @@ async fn main() -> Result<(), anyhow::Error> {
         Err(e) => tracing::error!("Failed to connect to RPC: {:?}", e),
     };
     
+    let snapshot_cache = Arc::new(SnapshotCache::new(config.monitor.checkpoint_every));
+
     // Connect to ClickHouse with retries
-    let client = connect_to_clickhouse(5).await?;
+    let client = connect_to_clickhouse(&config.clickhouse).await?;
 
     // Initialize database tables
-    init_database(&client).await?;
-
-    let state = (rpc_client.clone(), rpc_limiter.clone(), client.clone());
-    let app = create_router(state.clone());
-
-    let addr = SocketAddr::from(([0, 0, 0, 0], 8000));
-    tracing::info!("Listening on {}", addr);
-    
-    let listener = TcpListener::bind(addr).await?;
-
-    // Start the monitoring service in a separate task
-    let monitor_handle = tokio::spawn({
-        let client = client.clone();
-        let rpc_client = rpc_client.clone();
-        let rate_limiter = rpc_limiter.clone();
-        async move {
-            tracing::info!("Starting monitoring service...");
-            monitor::start_monitoring(client, rpc_client, rate_limiter).await;
-        }
-    });
-
-    // Start the excluded accounts service in a separate task
-    let excluded_accounts_handle = tokio::spawn({
-        let rpc = rpc_client.clone();
-        let rate_limiter = rpc_limiter.clone();
-        let ch_client = client.clone();
-        async move {
-            tracing::info!("Starting excluded accounts service...");
-            services::excluded_accounts::schedule_exclusion_updates(
-                rpc,
-                rate_limiter,
-                ch_client,
-            ).await;
-        }
-    });
-
-    // Frontend routes
-    let frontend_routes = Route::new()
-        .at("/", get(index))
-        .at("/token/:mint_address", get(token_details))
-        .at("/tokens", post(submit))
-        .with(Cors::new()
-            .allow_origin_regex(".*")  // Allow all origins in development
-            .allow_methods(vec!["GET", "POST"])
-            .allow_headers(vec!["Content-Type"]));
-    let frontend_server = Server::new(poem::listener::TcpListener::bind("0.0.0.0:3000")).run(frontend_routes);
-
-    let frontend_handle = tokio::spawn(frontend_server);
-
-    // Run both the API server and monitoring service concurrently
-    tokio::select! {
-        result = axum::serve(listener, app.into_make_service()) => {
-            if let Err(e) = result {
+    init_database(&client, config.clickhouse.auto_migrate).await?;
+    apply_retention(&client, &config.retention).await?;
+
+    let store: Arc<dyn Store> = Arc::new(ClickHouseStore::new(client, config.clickhouse.clone()));
+    let shard = Arc::new(Shard::new(config.workers.id.clone()));
+    tracing::info!("Running in {} mode as {}", mode.name(), shard.worker_id());
+    let mut services: Vec<(&'static str, tokio::task::JoinHandle<()>)> = Vec::new();
+
+    if mode.runs_api() {
+        let state = (rpc_client.clone(), rpc_limiter.clone(), store.clone(), config.clone());
+        let app = create_router(state.clone());
+
+        let addr = SocketAddr::from(([0, 0, 0, 0], config.api.port));
+        tracing::info!("Listening on {}", addr);
+        let listener = TcpListener::bind(addr).await?;
+        services.push(("API server", tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app.into_make_service()).await {
                 tracing::error!("Failed to serve API: {:?}", e);
             }
+        })));
+
+        // Frontend routes
+        let frontend_routes = Route::new()
+            .at("/", get(index))
+            .at("/token/:mint_address", get(token_details))
+            .at("/tokens", post(submit))
+            .with(Cors::new()
+                .allow_origin_regex(".*")  // Allow all origins in development
+                .allow_methods(vec!["GET", "POST"])
+                .allow_headers(vec!["Content-Type"]))
+            .data(config.clone());
+        let frontend_addr = format!("0.0.0.0:{}", config.frontend.port);
+        let frontend_server = Server::new(poem::listener::TcpListener::bind(frontend_addr)).run(frontend_routes);
+        services.push(("Frontend server", tokio::spawn(async move {
+            if let Err(e) = frontend_server.await {
+                tracing::error!("Failed to serve frontend: {:?}", e);
+            }
+        })));
+    }
+
+    if mode.runs_worker() {
+        // Join the worker ring, every worker takes the mints it owns
+        services.push(("Worker heartbeat", tokio::spawn({
+            let store = store.clone();
+            let shard = shard.clone();
+            let cache = snapshot_cache.clone();
+            let config = config.clone();
+            async move {
+                sharding::run_heartbeat(store, shard, mode.name(), cache, config).await;
+            }
+        })));
+
+        // Start the monitoring service in a separate task
+        services.push(("Monitoring service", tokio::spawn({
+            let store = store.clone();
+            let rpc_client = rpc_client.clone();
+            let rate_limiter = rpc_limiter.clone();
+            let cache = snapshot_cache.clone();
+            let shard = shard.clone();
+            let config = config.clone();
+            async move {
+                tracing::info!("Starting monitoring service...");
+                monitor::start_monitoring(store, rpc_client, rate_limiter, config, cache, shard).await;
+            }
+        })));
+
+        // Run snapshot jobs queued by the schedule and the API
+        services.push(("Snapshot job service", tokio::spawn({
+            let store = store.clone();
+            let rpc_client = rpc_client.clone();
+            let rate_limiter = rpc_limiter.clone();
+            let cache = snapshot_cache.clone();
+            let shard = shard.clone();
+            let config = config.clone();
+            async move {
+                tracing::info!("Starting snapshot job service...");
+                services::jobs::run_snapshot_jobs(store, rpc_client, rate_limiter, cache, shard, config).await;
+            }
+        })));
+
+        // Start the excluded accounts service in a separate task
+        services.push(("Excluded accounts service", tokio::spawn({
+            let rpc = rpc_client.clone();
+            let rate_limiter = rpc_limiter.clone();
+            let store = store.clone();
+            let shard = shard.clone();
+            let config = config.clone();
+            async move {
+                tracing::info!("Starting excluded accounts service...");
+                services::excluded_accounts::schedule_exclusion_updates(
+                    rpc,
+                    rate_limiter,
+                    store,
+                    shard,
+                    config,
+                ).await;
+            }
+        })));
+
+        // Label insiders and snipers of tokens that haven't been analyzed yet
+        services.push(("Launch analysis service", tokio::spawn({
+            let rpc = rpc_client.clone();
+            let rate_limiter = rpc_limiter.clone();
+            let store = store.clone();
+            let shard = shard.clone();
+            let config = config.clone();
+            async move {
+                tracing::info!("Starting launch analysis service...");
+                services::launch::schedule_launch_analysis(rpc, rate_limiter, store, shard, config).await;
+            }
+        })));
+
+        // Archive tokens that fell below the market cap / activity floors, if configured
+        if config.archive.enabled() {
+            let store = store.clone();
+            let shard = shard.clone();
+            let config = config.clone();
+            tokio::spawn(async move {
+                tracing::info!("Starting auto-archive service...");
+                services::lifecycle::schedule_auto_archive(store, shard, config).await;
+            });
         }
-        _ = monitor_handle => {
-            tracing::info!("Monitoring service finished");
+    }
+
+    // Run until a service stops or the process is interrupted
+    let handles = services.iter_mut().map(|(_, handle)| handle);
+    tokio::select! {
+        (_, finished, _) = futures::future::select_all(handles) => {
+            tracing::info!("{} finished", services[finished].0);
         }
-        _ = excluded_accounts_handle => {
-            tracing::info!("Excluded accounts service finished");
+        _ = tokio::signal::ctrl_c() => {
+            tracing::info!("Shutting down");
         }
-        _ = frontend_handle => {
-            tracing::info!("Frontend server finished");
+    }
+
+    // Leave the ring right away instead of waiting for the heartbeat to time out
+    if mode.runs_worker() {
+        if let Err(e) = store.send_heartbeat(shard.worker_id(), mode.name(), false).await {
+            tracing::error!("Failed to leave the worker ring: {}", e);
         }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,117 @@
+use anyhow::Result;
+use clickhouse::Client;
+use crate::db::models::TopHoldersShareRecord;
+use crate::db::queries::LATEST_HOLDER_BALANCES_SQL;
+use crate::types::models::{ConcentrationBucketConfig, ConcentrationMetric, ConfigSource, TokenConcentration};
+
+// Upper bound on top-N values per request or per token config
+pub const MAX_BUCKETS: usize = 20;
+
+// Newest bucket list stored for a key, '' being the global default
+async fn stored_buckets(client: &Client, key: &str) -> Result<Vec<u32>> {
+    let buckets: Option<Vec<u32>> = client
+        .query("
+            SELECT argMax(buckets, updated_at)
+            FROM token_concentration_config
+            WHERE mint_address = ?
+            GROUP BY mint_address
+        ")
+        .bind(key)
+        .fetch_optional()
+        .await?;
+    Ok(buckets.unwrap_or_default())
+}
+
+pub async fn get_default_buckets(client: &Client) -> Result<ConcentrationBucketConfig> {
+    Ok(ConcentrationBucketConfig {
+        mint_address: None,
+        buckets: stored_buckets(client, "").await?,
+        source: ConfigSource::Default,
+    })
+}
+
+// Buckets applied to a token's snapshots: its override, else the default
+pub async fn get_token_buckets(client: &Client, mint_address: &str) -> Result<ConcentrationBucketConfig> {
+    let buckets = stored_buckets(client, mint_address).await?;
+    if !buckets.is_empty() {
+        return Ok(ConcentrationBucketConfig {
+            mint_address: Some(mint_address.to_string()),
+            buckets,
+            source: ConfigSource::Token,
+        });
+    }
+
+    Ok(ConcentrationBucketConfig {
+        mint_address: Some(mint_address.to_string()),
+        buckets: stored_buckets(client, "").await?,
+        source: ConfigSource::Default,
+    })
+}
+
+// Sorts and dedupes the list, rejecting zero
+pub fn normalize_buckets(mut buckets: Vec<u32>) -> Result<Vec<u32>, String> {
+    if buckets.len() > MAX_BUCKETS {
+        return Err(format!("At most {} top-N buckets can be configured", MAX_BUCKETS));
+    }
+    if buckets.contains(&0) {
+        return Err("Top-N buckets must be at least 1".to_string());
+    }
+    buckets.sort_unstable();
+    buckets.dedup();
+    Ok(buckets)
+}
+
+// Applies from the next snapshot on, an empty list for a token resets it to the default
+pub async fn set_buckets(client: &Client, mint_address: Option<&str>, buckets: &[u32]) -> Result<()> {
+    client
+        .query("INSERT INTO token_concentration_config (mint_address, buckets) VALUES (?, ?)")
+        .bind(mint_address.unwrap_or(""))
+        .bind(buckets)
+        .execute()
+        .await?;
+    Ok(())
+}
+
+// Share of supply held by each top-N of the latest snapshot, computed on demand
+// rather than read from token_concentration so any N works
+pub async fn get_concentration(client: &Client, mint_address: &str, top_n: &[u32]) -> Result<TokenConcentration> {
+    let (timestamp, supply): (String, f64) = client
+        .query("SELECT toString(timestamp), supply FROM token_stats WHERE mint_address = ? ORDER BY timestamp DESC LIMIT 1")
+        .bind(mint_address)
+        .fetch_one()
+        .await?;
+
+    let share: TopHoldersShareRecord = client
+        .query(&format!("
+            SELECT
+                length(balances) AS total_holders,
+                arrayMap(n -> arraySum(arraySlice(balances, 1, n)) / ? * 100, ?) AS percentages,
+                arrayFirstIndex(x -> x >= ? * 0.5, arrayCumSum(balances)) AS holders_for_50pct,
+                arrayFirstIndex(x -> x >= ? * 0.9, arrayCumSum(balances)) AS holders_for_90pct
+            FROM (
+                SELECT arrayReverseSort(groupArray(balance)) AS balances
+                FROM ({})
+                WHERE mint_address = ?
+            )
+        ", LATEST_HOLDER_BALANCES_SQL))
+        .bind(supply)
+        .bind(top_n)
+        .bind(supply)
+        .bind(supply)
+        .bind(mint_address)
+        .fetch_one()
+        .await?;
+
+    Ok(TokenConcentration {
+        mint_address: mint_address.to_string(),
+        timestamp,
+        total_holders: share.total_holders,
+        concentration_metrics: top_n.iter()
+            .zip(share.percentages)
+            .map(|(n, percentage)| ConcentrationMetric { top_n: *n as i32, percentage })
+            .collect(),
+        // arrayFirstIndex returns 0 when no prefix reaches the share
+        holders_for_50pct: (share.holders_for_50pct > 0).then_some(share.holders_for_50pct),
+        holders_for_90pct: (share.holders_for_90pct > 0).then_some(share.holders_for_90pct),
+    })
+}
@@ -0,0 +1,209 @@
+use anyhow::Result;
+use clickhouse::Client;
+use crate::db::models::{LorenzRecord, SnapshotRecord};
+use crate::db::queries::SNAPSHOT_HOLDER_BALANCES_SQL;
+use crate::types::models::{BalanceHistogram, HistogramBucket, HolderAge, HolderAgeBucket, LorenzCurve, LorenzPoint};
+
+pub const MAX_LORENZ_POINTS: u32 = 1000;
+pub const MAX_BINS_PER_DECADE: u32 = 10;
+
+// Upper bound of each holder age bucket in seconds, the last one is open ended
+const AGE_BUCKETS: &[(&str, i64)] = &[
+    ("<1h", 3600),
+    ("1-24h", 24 * 3600),
+    ("1-7d", 7 * 24 * 3600),
+    (">7d", i64::MAX),
+];
+
+pub async fn get_lorenz_curve(
+    client: &Client,
+    mint_address: &str,
+    snapshot: &SnapshotRecord,
+    points: u32,
+) -> Result<LorenzCurve> {
+    let lorenz: LorenzRecord = client
+        .query(&format!("
+            SELECT
+                length(balances) AS total_holders,
+                arrayMap(k -> k / ?, range(? + 1)) AS holder_shares,
+                arrayMap(
+                    k -> arraySum(arraySlice(balances, 1, intDiv(k * length(balances), ?))) / arraySum(balances),
+                    range(? + 1)
+                ) AS supply_shares
+            FROM (
+                SELECT arraySort(groupArray(balance)) AS balances
+                FROM ({})
+            )
+        ", SNAPSHOT_HOLDER_BALANCES_SQL))
+        .bind(points)
+        .bind(points)
+        .bind(points)
+        .bind(points)
+        .bind(mint_address)
+        .bind(snapshot.checkpoint_timestamp)
+        .bind(snapshot.timestamp)
+        .fetch_one()
+        .await?;
+
+    Ok(LorenzCurve {
+        mint_address: mint_address.to_string(),
+        timestamp: snapshot.timestamp,
+        total_holders: lorenz.total_holders,
+        // An empty snapshot divides by zero, report no curve instead of NaNs
+        points: if lorenz.total_holders == 0 {
+            Vec::new()
+        } else {
+            lorenz.holder_shares.into_iter()
+                .zip(lorenz.supply_shares)
+                .map(|(holder_share, supply_share)| LorenzPoint { holder_share, supply_share })
+                .collect()
+        },
+    })
+}
+
+// Bucket i covers [10^(i / bins_per_decade), 10^((i + 1) / bins_per_decade)) of
+// balance * scale, so scale turns raw amounts into tokens or USD
+async fn log_histogram(
+    client: &Client,
+    mint_address: &str,
+    snapshot: &SnapshotRecord,
+    scale: f64,
+    bins_per_decade: u32,
+) -> Result<Vec<HistogramBucket>> {
+    let bins: Vec<(i64, u64, f64)> = client
+        .query(&format!("
+            SELECT
+                toInt64(floor(log10(value) * ?)) AS bin,
+                count(),
+                sum(value)
+            FROM (
+                SELECT balance * ? AS value
+                FROM ({})
+            )
+            WHERE value > 0
+            GROUP BY bin
+            ORDER BY bin
+        ", SNAPSHOT_HOLDER_BALANCES_SQL))
+        .bind(bins_per_decade)
+        .bind(scale)
+        .bind(mint_address)
+        .bind(snapshot.checkpoint_timestamp)
+        .bind(snapshot.timestamp)
+        .fetch_all()
+        .await?;
+
+    Ok(histogram_buckets(bins, bins_per_decade))
+}
+
+// (bin, holder_count, total) rows in bin order, see log_histogram
+pub fn histogram_buckets(bins: Vec<(i64, u64, f64)>, bins_per_decade: u32) -> Vec<HistogramBucket> {
+    let width = bins_per_decade as f64;
+    bins.into_iter()
+        .map(|(bin, holder_count, total)| HistogramBucket {
+            lower: 10f64.powf(bin as f64 / width),
+            upper: 10f64.powf((bin + 1) as f64 / width),
+            holder_count,
+            total,
+        })
+        .collect()
+}
+
+// Bin of one value, as floor(log10(value) * bins_per_decade) in log_histogram
+pub fn histogram_bin(value: f64, bins_per_decade: u32) -> i64 {
+    (value.log10() * bins_per_decade as f64).floor() as i64
+}
+
+pub async fn get_balance_histogram(
+    client: &Client,
+    mint_address: &str,
+    snapshot: &SnapshotRecord,
+    bins_per_decade: u32,
+) -> Result<BalanceHistogram> {
+    let token_scale = 1.0 / 10f64.powi(snapshot.decimals as i32);
+    let tokens = log_histogram(client, mint_address, snapshot, token_scale, bins_per_decade).await?;
+    let usd = log_histogram(client, mint_address, snapshot, token_scale * snapshot.price, bins_per_decade).await?;
+
+    Ok(BalanceHistogram {
+        mint_address: mint_address.to_string(),
+        timestamp: snapshot.timestamp,
+        price: snapshot.price,
+        bins_per_decade,
+        tokens,
+        usd,
+    })
+}
+
+// Age of each holder's current spell, the snapshots in a row it held the token
+// in, so a wallet that sold out and bought back counts from its return
+pub async fn get_holder_age(
+    client: &Client,
+    mint_address: &str,
+    snapshot: &SnapshotRecord,
+) -> Result<HolderAge> {
+    let bounds: Vec<i64> = AGE_BUCKETS.iter().map(|(_, upper)| *upper).collect();
+    let rows: Vec<(u64, u64, f64)> = client
+        .query(&format!("
+            SELECT
+                toUInt64(arrayFirstIndex(upper -> age < upper, ?)) AS bucket,
+                count(),
+                sum(balance)
+            FROM (
+                SELECT
+                    ? - toInt64(toUnixTimestamp(fs.first_seen)) AS age,
+                    sb.balance AS balance
+                FROM ({}) sb
+                INNER JOIN (
+                    SELECT holder_address, max(first_seen) AS first_seen
+                    FROM token_holder_spells
+                    WHERE mint_address = ? AND first_seen <= toDateTime(?, 'UTC')
+                    GROUP BY holder_address
+                ) fs ON sb.holder_address = fs.holder_address
+            )
+            GROUP BY bucket
+        ", SNAPSHOT_HOLDER_BALANCES_SQL))
+        .bind(bounds)
+        .bind(snapshot.timestamp)
+        .bind(mint_address)
+        .bind(snapshot.checkpoint_timestamp)
+        .bind(snapshot.timestamp)
+        .bind(mint_address)
+        .bind(snapshot.timestamp)
+        .fetch_all()
+        .await?;
+
+    Ok(holder_age(mint_address, snapshot, rows))
+}
+
+// 1-based index into AGE_BUCKETS of a holder first seen `age` seconds before the snapshot
+pub fn age_bucket(age: i64) -> u64 {
+    AGE_BUCKETS.iter().position(|(_, upper)| age < *upper).map_or(0, |index| index as u64 + 1)
+}
+
+// From (bucket, holders, raw balance) rows, bucket as returned by age_bucket
+pub fn holder_age(mint_address: &str, snapshot: &SnapshotRecord, rows: Vec<(u64, u64, f64)>) -> HolderAge {
+    let total_holders: u64 = rows.iter().map(|(_, holders, _)| holders).sum();
+    let total_balance: f64 = rows.iter().map(|(_, _, balance)| balance).sum();
+    let scale = 10f64.powi(snapshot.decimals as i32);
+    let buckets = AGE_BUCKETS.iter().enumerate()
+        .map(|(i, (bucket, _))| {
+            let (holders, balance) = rows.iter()
+                .find(|(index, _, _)| *index == i as u64 + 1)
+                .map(|(_, holders, balance)| (*holders, *balance))
+                .unwrap_or_default();
+            HolderAgeBucket {
+                bucket: bucket.to_string(),
+                holders,
+                holder_pct: if total_holders > 0 { holders as f64 / total_holders as f64 * 100.0 } else { 0.0 },
+                balance: balance / scale,
+                balance_pct: if total_balance > 0.0 { balance / total_balance * 100.0 } else { 0.0 },
+            }
+        })
+        .collect();
+
+    HolderAge {
+        mint_address: mint_address.to_string(),
+        timestamp: snapshot.timestamp,
+        total_holders,
+        buckets,
+    }
+}
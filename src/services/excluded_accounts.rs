@@ -5,6 +5,10 @@ use solana_sdk::pubkey::Pubkey;
 use governor::{RateLimiter, state::{NotKeyed, InMemoryState}, clock::DefaultClock};
 use clickhouse::Client;
 use std::str::FromStr;
+use crate::config::{Config, ExclusionsConfig};
+use crate::db::queries::LATEST_HOLDER_BALANCES_SQL;
+use crate::db::store::Store;
+use crate::services::sharding::Shard;
 
 pub const PROGRAM_IDS: &[&str] = &[
     "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK", // Raydium concentrated
@@ -32,37 +36,65 @@ pub const EXCLUDED_OWNERS: &[&str] = &[
 
 ]; 
 
+// Largest owners summed over the latest snapshot of every token
+pub async fn get_largest_holders(clickhouse_client: &Client, limit: u64) -> Result<Vec<(String, f64)>, anyhow::Error> {
+    let large_holders = clickhouse_client
+        .query(&format!("
+            SELECT holder_address, sum(balance) AS total
+            FROM ({})
+            GROUP BY holder_address
+            ORDER BY total DESC
+            LIMIT ?
+        ", LATEST_HOLDER_BALANCES_SQL))
+        .bind(limit)
+        .fetch_all()
+        .await?;
+    Ok(large_holders)
+}
+
 pub async fn update_excluded_accounts(
     client: &Arc<RpcClient>,
     rate_limiter: &Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
-    clickhouse_client: &Client,
+    store: &dyn Store,
+    config: &ExclusionsConfig,
 ) -> Result<(), anyhow::Error> {
-    // First, insert known exclusions
-    for &address in EXCLUDED_OWNERS {
-        clickhouse_client
-            .query("
-                INSERT INTO excluded_accounts (address, category, description)
-                VALUES (?, 'exchange', 'Known CEX/LP')
-            ")
-            .bind(address)
-            .execute()
-            .await?;
-    }
+    insert_known_exclusions(store).await?;
 
-    // Get top 300 holders across all monitored tokens
-    let large_holders: Vec<(String, f64)> = clickhouse_client
-        .query("
-            SELECT DISTINCT holder_address, sum(balance) AS total
-            FROM token_holder_balances
-            GROUP BY holder_address
-            ORDER BY total DESC
-            LIMIT 300
-        ")
-        .fetch_all()
-        .await?;
+    // Get the top holders across all monitored tokens
+    let large_holders = store.largest_holders(config.top_holders).await?;
+    exclude_program_accounts(client, rate_limiter, store, &large_holders, config).await
+}
+
+pub async fn check_new_token_exclusions(
+    client: &Arc<RpcClient>,
+    rate_limiter: &Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    store: &dyn Store,
+    mint_address: &str,
+    config: &ExclusionsConfig,
+) -> Result<(), anyhow::Error> {
+    insert_known_exclusions(store).await?;
+
+    // Get top holders for just this token
+    let large_holders = store.top_holders(mint_address, config.top_holders).await?;
+    exclude_program_accounts(client, rate_limiter, store, &large_holders, config).await
+}
+
+async fn insert_known_exclusions(store: &dyn Store) -> Result<(), anyhow::Error> {
+    let known: Vec<(String, String, String)> = EXCLUDED_OWNERS.iter()
+        .map(|address| (address.to_string(), "exchange".to_string(), "Known CEX/LP".to_string()))
+        .collect();
+    store.insert_excluded_accounts(&known).await
+}
 
-    // Check if they're program accounts in batches
-    for chunk in large_holders.chunks(25) {
+// Check if they're program accounts in batches
+async fn exclude_program_accounts(
+    client: &Arc<RpcClient>,
+    rate_limiter: &Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    store: &dyn Store,
+    large_holders: &[(String, f64)],
+    config: &ExclusionsConfig,
+) -> Result<(), anyhow::Error> {
+    for chunk in large_holders.chunks(config.batch_size) {
         rate_limiter.until_ready().await;
         
         let addresses: Vec<Pubkey> = chunk.iter()
@@ -70,21 +102,13 @@ pub async fn update_excluded_accounts(
             .collect();
             
         if let Ok(accounts) = client.get_multiple_accounts(&addresses).await {
-            for (account, (address, _)) in accounts.iter().zip(chunk.iter()) {
-                if let Some(acc) = account {
-                    if PROGRAM_IDS.contains(&acc.owner.to_string().as_str()) {
-                        clickhouse_client
-                            .query("
-                                INSERT INTO excluded_accounts (address, category, description)
-                                VALUES (?, 'program', ?)
-                            ")
-                            .bind(address)
-                            .bind(format!("Owned by {}", acc.owner))
-                            .execute()
-                            .await?;
-                    }
-                }
-            }
+            let programs: Vec<(String, String, String)> = accounts.iter()
+                .zip(chunk.iter())
+                .filter_map(|(account, (address, _))| account.as_ref().map(|acc| (address, acc.owner)))
+                .filter(|(_, owner)| PROGRAM_IDS.contains(&owner.to_string().as_str()))
+                .map(|(address, owner)| (address.clone(), "program".to_string(), format!("Owned by {}", owner)))
+                .collect();
+            store.insert_excluded_accounts(&programs).await?;
         }
         tokio::time::sleep(Duration::from_millis(100)).await;
     }
@@ -92,65 +116,50 @@ pub async fn update_excluded_accounts(
     Ok(())
 }
 
-pub async fn check_new_token_exclusions(
-    client: &Arc<RpcClient>,
-    rate_limiter: &Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+// One `address,category[,description]` per line, as accepted by `exclusions import`.
+// Blank lines, `#` comments and an `address,...` header are skipped.
+pub fn parse_exclusions(text: &str) -> Result<Vec<(String, String, String)>, String> {
+    let mut accounts = Vec::new();
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.splitn(3, ',').map(str::trim);
+        let address = fields.next().unwrap_or_default();
+        if line_no == 0 && address == "address" {
+            continue;
+        }
+        if Pubkey::from_str(address).is_err() {
+            return Err(format!("Line {}: invalid address {}", line_no + 1, address));
+        }
+        let category = fields.next().unwrap_or_default();
+        if category.is_empty() {
+            return Err(format!("Line {}: missing category for {}", line_no + 1, address));
+        }
+        let description = fields.next().unwrap_or_default();
+        accounts.push((address.to_string(), category.to_string(), description.to_string()));
+    }
+    Ok(accounts)
+}
+
+// Only affects snapshots taken afterwards, `recompute` applies them to stored ones
+pub async fn import_excluded_accounts(
     clickhouse_client: &Client,
-    mint_address: &str,
+    accounts: &[(String, String, String)],
 ) -> Result<(), anyhow::Error> {
-    // First, insert known exclusions
-    for &address in EXCLUDED_OWNERS {
+    for (address, category, description) in accounts {
         clickhouse_client
             .query("
                 INSERT INTO excluded_accounts (address, category, description)
-                VALUES (?, 'exchange', 'Known CEX/LP')
+                VALUES (?, ?, ?)
             ")
             .bind(address)
+            .bind(category)
+            .bind(description)
             .execute()
             .await?;
     }
-
-    // Get top holders for just this token
-    let large_holders: Vec<(String, f64)> = clickhouse_client
-        .query("
-            SELECT DISTINCT holder_address, balance
-            FROM token_holder_balances
-            WHERE mint_address = ?
-            ORDER BY balance DESC
-            LIMIT 300
-        ")
-        .bind(mint_address)
-        .fetch_all()
-        .await?;
-
-    // Check if they're program accounts in batches
-    for chunk in large_holders.chunks(25) {
-        rate_limiter.until_ready().await;
-        
-        let addresses: Vec<Pubkey> = chunk.iter()
-            .filter_map(|(addr, _)| Pubkey::from_str(addr).ok())
-            .collect();
-            
-        if let Ok(accounts) = client.get_multiple_accounts(&addresses).await {
-            for (account, (address, _)) in accounts.iter().zip(chunk.iter()) {
-                if let Some(acc) = account {
-                    if PROGRAM_IDS.contains(&acc.owner.to_string().as_str()) {
-                        clickhouse_client
-                            .query("
-                                INSERT INTO excluded_accounts (address, category, description)
-                                VALUES (?, 'program', ?)
-                            ")
-                            .bind(address)
-                            .bind(format!("Owned by {}", acc.owner))
-                            .execute()
-                            .await?;
-                    }
-                }
-            }
-        }
-        tokio::time::sleep(Duration::from_millis(100)).await;
-    }
-
     Ok(())
 }
 
@@ -158,12 +167,19 @@ pub async fn check_new_token_exclusions(
 pub async fn schedule_exclusion_updates(
     client: Arc<RpcClient>,
     rate_limiter: Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
-    clickhouse_client: Client,
+    store: Arc<dyn Store>,
+    shard: Arc<Shard>,
+    config: Arc<Config>,
 ) {
-    let mut interval = tokio::time::interval(Duration::from_secs(24 * 60 * 60));
+    let config = &config.exclusions;
+    let mut interval = tokio::time::interval(Duration::from_secs(config.update_interval_secs));
     loop {
         interval.tick().await;
-        if let Err(e) = update_excluded_accounts(&client, &rate_limiter, &clickhouse_client).await {
+        // One worker refreshes the global list for everyone
+        if !shard.is_leader() {
+            continue;
+        }
+        if let Err(e) = update_excluded_accounts(&client, &rate_limiter, store.as_ref(), config).await {
             tracing::error!("Failed to update excluded accounts: {}", e);
         }
     }
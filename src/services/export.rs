@@ -0,0 +1,381 @@
+use std::str::FromStr;
+use anyhow::Result;
+use bytes::Bytes;
+use futures::stream::{BoxStream, StreamExt};
+use crate::config::ClickHouseConfig;
+use crate::db::models::SnapshotRecord;
+use crate::db::queries::SNAPSHOT_HOLDER_BALANCES_SQL;
+
+pub type ExportStream = BoxStream<'static, Result<Bytes, reqwest::Error>>;
+
+// Every snapshot of a holders export is a subquery of the one statement
+pub const MAX_HOLDER_EXPORT_SNAPSHOTS: usize = 500;
+
+// The range holds more snapshots than one holders export takes
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct ExportRangeTooLarge(pub String);
+
+// Holders are exported per snapshot, so the range is capped by its number of snapshots
+pub fn check_holder_export_range(mint_address: &str, from: i64, to: i64, snapshots: usize) -> Result<(), ExportRangeTooLarge> {
+    if snapshots > MAX_HOLDER_EXPORT_SNAPSHOTS {
+        return Err(ExportRangeTooLarge(format!(
+            "More than {} snapshots of {} between {} and {}, export holders over a shorter range",
+            MAX_HOLDER_EXPORT_SNAPSHOTS, mint_address, from, to
+        )));
+    }
+    Ok(())
+}
+
+// The store can't write the format, Parquet only comes out of ClickHouse
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct ExportFormatUnavailable(pub String);
+
+// Raw data of a mint over a time range, streamed in one of ClickHouse's output
+// formats straight from its HTTP interface, so nothing is buffered here:
+// - holders: the balance of every holder as of each snapshot, rebuilt from its
+//   checkpoint and deltas like SNAPSHOT_HOLDER_BALANCES_SQL does for the metrics
+// - stats: token_stats rows, one per snapshot
+// - metrics: token_distribution rows with the snapshot's top-N concentration and
+//   holder counts per USD threshold as maps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportDataset {
+    Holders,
+    Stats,
+    Metrics,
+}
+
+impl FromStr for ExportDataset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "holders" => Ok(ExportDataset::Holders),
+            "stats" => Ok(ExportDataset::Stats),
+            "metrics" => Ok(ExportDataset::Metrics),
+            other => Err(format!("unknown dataset {}, expected holders, stats or metrics", other)),
+        }
+    }
+}
+
+impl ExportDataset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportDataset::Holders => "holders",
+            ExportDataset::Stats => "stats",
+            ExportDataset::Metrics => "metrics",
+        }
+    }
+
+    // Columns of the dataset's query, in order
+    pub fn columns(&self) -> &'static [&'static str] {
+        match self {
+            ExportDataset::Holders => &["timestamp", "holder_address", "balance"],
+            ExportDataset::Stats => &["timestamp", "checkpoint_timestamp", "price", "supply", "market_cap", "decimals"],
+            ExportDataset::Metrics => &[
+                "timestamp", "total_holders", "hhi", "distribution_score", "gini", "nakamoto_coefficient",
+                "theil_index", "mean_balance", "median_balance", "p10", "p25", "p50", "p75", "p90", "p95", "p99",
+                "lorenz_curve", "concentration", "holder_counts",
+            ],
+        }
+    }
+
+    fn sql(&self, snapshots: &[SnapshotRecord]) -> String {
+        match self {
+            ExportDataset::Holders => holders_sql(snapshots),
+            ExportDataset::Stats => EXPORT_STATS_SQL.to_string(),
+            ExportDataset::Metrics => EXPORT_METRICS_SQL.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+    Parquet,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "ndjson" => Ok(ExportFormat::Ndjson),
+            "parquet" => Ok(ExportFormat::Parquet),
+            other => Err(format!("unknown format {}, expected csv, ndjson or parquet", other)),
+        }
+    }
+}
+
+impl ExportFormat {
+    fn clickhouse_format(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSVWithNames",
+            ExportFormat::Ndjson => "JSONEachRow",
+            ExportFormat::Parquet => "Parquet",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+// Parameters are bound by ClickHouse from the param_* URL arguments
+const EMPTY_HOLDERS_SQL: &str = "
+    SELECT toDateTime(0, 'UTC') AS timestamp, '' AS holder_address, toFloat64(0) AS balance
+    WHERE 0
+";
+
+const EXPORT_STATS_SQL: &str = "
+    SELECT timestamp, checkpoint_timestamp, price, supply, market_cap, decimals
+    FROM token_stats FINAL
+    WHERE mint_address = {mint_address:String}
+        AND timestamp BETWEEN toDateTime({from:Int64}, 'UTC') AND toDateTime({to:Int64}, 'UTC')
+    ORDER BY timestamp
+";
+
+const EXPORT_METRICS_SQL: &str = "
+    SELECT
+        d.timestamp AS timestamp,
+        d.total_holders AS total_holders,
+        d.hhi AS hhi,
+        d.distribution_score AS distribution_score,
+        d.gini AS gini,
+        d.nakamoto_coefficient AS nakamoto_coefficient,
+        d.theil_index AS theil_index,
+        d.mean_balance AS mean_balance,
+        d.median_balance AS median_balance,
+        d.p10 AS p10,
+        d.p25 AS p25,
+        d.p50 AS p50,
+        d.p75 AS p75,
+        d.p90 AS p90,
+        d.p95 AS p95,
+        d.p99 AS p99,
+        d.lorenz_curve AS lorenz_curve,
+        c.concentration AS concentration,
+        h.holder_counts AS holder_counts
+    FROM (
+        SELECT *
+        FROM token_distribution FINAL
+        WHERE mint_address = {mint_address:String}
+            AND timestamp BETWEEN toDateTime({from:Int64}, 'UTC') AND toDateTime({to:Int64}, 'UTC')
+    ) d
+    LEFT JOIN (
+        SELECT timestamp, CAST((groupArray(top_n), groupArray(percentage)), 'Map(UInt32, Float64)') AS concentration
+        FROM token_concentration FINAL
+        WHERE mint_address = {mint_address:String}
+            AND timestamp BETWEEN toDateTime({from:Int64}, 'UTC') AND toDateTime({to:Int64}, 'UTC')
+        GROUP BY timestamp
+    ) c ON c.timestamp = d.timestamp
+    LEFT JOIN (
+        SELECT timestamp, CAST((groupArray(usd_threshold), groupArray(holder_count)), 'Map(Float64, UInt64)') AS holder_counts
+        FROM token_holder_counts FINAL
+        WHERE mint_address = {mint_address:String}
+            AND timestamp BETWEEN toDateTime({from:Int64}, 'UTC') AND toDateTime({to:Int64}, 'UTC')
+        GROUP BY timestamp
+    ) h ON h.timestamp = d.timestamp
+    ORDER BY timestamp
+";
+
+// SNAPSHOT_HOLDER_BALANCES_SQL of each snapshot, oldest first, as one UNION ALL
+// so the export stays a single CSV or Parquet file. The snapshot bounds are
+// integers from token_stats and go into the SQL as literals.
+fn holders_sql(snapshots: &[SnapshotRecord]) -> String {
+    if snapshots.is_empty() {
+        return EMPTY_HOLDERS_SQL.to_string();
+    }
+    let selects: Vec<String> = snapshots
+        .iter()
+        .map(|snapshot| {
+            let binds = [
+                "{mint_address:String}".to_string(),
+                snapshot.checkpoint_timestamp.to_string(),
+                snapshot.timestamp.to_string(),
+            ];
+            let mut parts = SNAPSHOT_HOLDER_BALANCES_SQL.split('?');
+            let mut sql = parts.next().unwrap_or_default().to_string();
+            for (bind, part) in binds.iter().zip(parts) {
+                sql.push_str(bind);
+                sql.push_str(part);
+            }
+            format!(
+                "SELECT toDateTime({}, 'UTC') AS timestamp, holder_address, balance FROM ({})",
+                snapshot.timestamp, sql
+            )
+        })
+        .collect();
+    format!(
+        "SELECT timestamp, holder_address, balance FROM ({}) ORDER BY timestamp, balance DESC, holder_address",
+        selects.join(" UNION ALL ")
+    )
+}
+
+// Sent with the user, password and database the clickhouse::Client connects
+// with. `snapshots` are the ones of a holders export, see MAX_HOLDER_EXPORT_SNAPSHOTS.
+// Errors in the query come back before the first byte. The stream ends early if
+// the connection drops, ClickHouse then writes the exception into the output.
+pub async fn export_stream(
+    config: &ClickHouseConfig,
+    dataset: ExportDataset,
+    format: ExportFormat,
+    mint_address: &str,
+    range: (i64, i64),
+    snapshots: &[SnapshotRecord],
+) -> Result<ExportStream> {
+    let query = format!("{} FORMAT {}", dataset.sql(snapshots), format.clickhouse_format());
+    let (from, to) = range;
+    let response = reqwest::Client::new()
+        .post(&config.url)
+        .header("X-ClickHouse-User", &config.user)
+        .header("X-ClickHouse-Key", &config.password)
+        .query(&[
+            ("database", config.database.clone()),
+            ("max_query_size", (query.len() + 1024).to_string()),
+            ("param_mint_address", mint_address.to_string()),
+            ("param_from", from.to_string()),
+            ("param_to", to.to_string()),
+        ])
+        .body(query)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error = response.text().await.unwrap_or_default();
+        return Err(anyhow::anyhow!("ClickHouse returned {}: {}", status, error.trim()));
+    }
+
+    tracing::info!("Exporting {} of {} as {}", dataset.name(), mint_address, format.extension());
+    Ok(futures::stream::try_unfold(response, |mut response| async move {
+        Ok(response.chunk().await?.map(|chunk| (chunk, response)))
+    }).boxed())
+}
+
+// Rows of the dataset's columns written the way CSVWithNames and JSONEachRow
+// lay them out, for stores that don't read through ClickHouse. In CSV strings
+// are quoted and arrays and maps are written as JSON.
+pub fn export_rows(
+    dataset: ExportDataset,
+    format: ExportFormat,
+    mint_address: &str,
+    rows: Vec<Vec<serde_json::Value>>,
+) -> Result<ExportStream> {
+    let columns = dataset.columns();
+    let rows: Vec<Vec<serde_json::Value>> = rows.into_iter()
+        .map(|row| row.into_iter().map(whole_numbers).collect())
+        .collect();
+    let mut out = String::new();
+    match format {
+        ExportFormat::Csv => {
+            let header: Vec<String> = columns.iter().map(|column| csv_field(&(*column).into())).collect();
+            out.push_str(&header.join(","));
+            out.push('\n');
+            for row in &rows {
+                let fields: Vec<String> = row.iter().map(csv_field).collect();
+                out.push_str(&fields.join(","));
+                out.push('\n');
+            }
+        }
+        ExportFormat::Ndjson => {
+            for row in &rows {
+                let fields: Vec<String> = columns.iter()
+                    .zip(row)
+                    .map(|(column, value)| format!("{}:{}", serde_json::Value::from(*column), value))
+                    .collect();
+                out.push_str(&format!("{{{}}}\n", fields.join(",")));
+            }
+        }
+        ExportFormat::Parquet => {
+            return Err(ExportFormatUnavailable("Parquet exports need ClickHouse, export as csv or ndjson".to_string()).into());
+        }
+    }
+
+    tracing::info!("Exporting {} of {} as {}", dataset.name(), mint_address, format.extension());
+    Ok(futures::stream::once(async move { Ok(Bytes::from(out)) }).boxed())
+}
+
+// ClickHouse writes floats without a fraction as integers, 500 rather than 500.0
+fn whole_numbers(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Number(number) => match number.as_f64() {
+            Some(float) if number.is_f64() && float.fract() == 0.0 && float.abs() < 1e15 => (float as i64).into(),
+            _ => serde_json::Value::Number(number),
+        },
+        serde_json::Value::Array(values) => values.into_iter().map(whole_numbers).collect(),
+        serde_json::Value::Object(fields) => fields.into_iter().map(|(key, value)| (key, whole_numbers(value))).collect(),
+        other => other,
+    }
+}
+
+fn csv_field(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => format!("\"{}\"", text.replace('"', "\"\"")),
+        serde_json::Value::Number(number) => number.to_string(),
+        other => csv_field(&other.to_string().into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(checkpoint_timestamp: i64, timestamp: i64) -> SnapshotRecord {
+        SnapshotRecord { timestamp, checkpoint_timestamp, price: 1.0, supply: 1.0, decimals: 0 }
+    }
+
+    #[test]
+    fn holders_are_exported_per_snapshot() {
+        let sql = holders_sql(&[snapshot(100, 100), snapshot(100, 160)]);
+        assert_eq!(sql.matches("UNION ALL").count(), 1);
+        assert!(!sql.contains('?'));
+        assert_eq!(sql.matches("thb.mint_address = {mint_address:String}").count(), 2);
+        assert!(sql.contains("BETWEEN toDateTime(100, 'UTC') AND toDateTime(100, 'UTC')"));
+        assert!(sql.contains("BETWEEN toDateTime(100, 'UTC') AND toDateTime(160, 'UTC')"));
+        assert!(sql.contains("SELECT toDateTime(160, 'UTC') AS timestamp"));
+    }
+
+    #[test]
+    fn empty_range_has_the_columns_and_no_rows() {
+        assert_eq!(holders_sql(&[]), EMPTY_HOLDERS_SQL);
+    }
+
+    async fn exported(format: ExportFormat, rows: Vec<Vec<serde_json::Value>>) -> String {
+        let mut stream = export_rows(ExportDataset::Holders, format, "mint", rows).unwrap();
+        let mut out = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            out.extend_from_slice(&chunk.unwrap());
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[tokio::test]
+    async fn rows_are_written_like_clickhouse_formats() {
+        let rows = || vec![vec!["2024-06-10 06:00:00".into(), "a\"b".into(), 500.0.into()]];
+        assert_eq!(
+            exported(ExportFormat::Csv, rows()).await,
+            "\"timestamp\",\"holder_address\",\"balance\"\n\"2024-06-10 06:00:00\",\"a\"\"b\",500\n"
+        );
+        assert_eq!(
+            exported(ExportFormat::Ndjson, rows()).await,
+            "{\"timestamp\":\"2024-06-10 06:00:00\",\"holder_address\":\"a\\\"b\",\"balance\":500}\n"
+        );
+        assert!(export_rows(ExportDataset::Holders, ExportFormat::Parquet, "mint", rows()).is_err());
+    }
+}
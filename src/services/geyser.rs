@@ -0,0 +1,660 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+use anyhow::Result;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use governor::{RateLimiter, state::{NotKeyed, InMemoryState}, clock::DefaultClock};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{program_pack::Pack, pubkey::Pubkey};
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+use crate::config::{Config, GeyserConfig};
+use crate::db::store::Store;
+use crate::services::sharding::Shard;
+use crate::services::snapshots::SnapshotCache;
+use crate::services::token::{fetch_and_sort_holders, write_snapshot};
+
+// Minimal subset of the Yellowstone `geyser.proto` messages. Prost skips unknown
+// fields, so only what we send or read is declared here, with the upstream tags.
+pub mod proto {
+    use std::collections::HashMap;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeRequest {
+        #[prost(map = "string, message", tag = "1")]
+        pub accounts: HashMap<String, SubscribeRequestFilterAccounts>,
+        #[prost(enumeration = "CommitmentLevel", optional, tag = "6")]
+        pub commitment: Option<i32>,
+        #[prost(message, optional, tag = "9")]
+        pub ping: Option<SubscribeRequestPing>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeRequestFilterAccounts {
+        #[prost(string, repeated, tag = "2")]
+        pub account: Vec<String>,
+        #[prost(string, repeated, tag = "3")]
+        pub owner: Vec<String>,
+        #[prost(message, repeated, tag = "4")]
+        pub filters: Vec<SubscribeRequestFilterAccountsFilter>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeRequestFilterAccountsFilter {
+        #[prost(oneof = "AccountsFilter", tags = "1, 2")]
+        pub filter: Option<AccountsFilter>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum AccountsFilter {
+        #[prost(message, tag = "1")]
+        Memcmp(SubscribeRequestFilterAccountsFilterMemcmp),
+        #[prost(uint64, tag = "2")]
+        Datasize(u64),
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeRequestFilterAccountsFilterMemcmp {
+        #[prost(uint64, tag = "1")]
+        pub offset: u64,
+        #[prost(oneof = "MemcmpData", tags = "3")]
+        pub data: Option<MemcmpData>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum MemcmpData {
+        #[prost(string, tag = "3")]
+        Base58(String),
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeRequestPing {
+        #[prost(int32, tag = "1")]
+        pub id: i32,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum CommitmentLevel {
+        Processed = 0,
+        Confirmed = 1,
+        Finalized = 2,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeUpdate {
+        #[prost(oneof = "UpdateOneof", tags = "2, 6")]
+        pub update_oneof: Option<UpdateOneof>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum UpdateOneof {
+        #[prost(message, tag = "2")]
+        Account(SubscribeUpdateAccount),
+        #[prost(message, tag = "6")]
+        Ping(SubscribeUpdatePing),
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeUpdateAccount {
+        #[prost(message, optional, tag = "1")]
+        pub account: Option<SubscribeUpdateAccountInfo>,
+        #[prost(uint64, tag = "2")]
+        pub slot: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeUpdateAccountInfo {
+        #[prost(bytes = "vec", tag = "1")]
+        pub pubkey: Vec<u8>,
+        #[prost(bytes = "vec", tag = "3")]
+        pub owner: Vec<u8>,
+        #[prost(bytes = "vec", tag = "6")]
+        pub data: Vec<u8>,
+        #[prost(uint64, tag = "7")]
+        pub write_version: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeUpdatePing {}
+}
+
+use proto::{
+    AccountsFilter, CommitmentLevel, MemcmpData, SubscribeRequest,
+    SubscribeRequestFilterAccounts, SubscribeRequestFilterAccountsFilter,
+    SubscribeRequestFilterAccountsFilterMemcmp, SubscribeRequestPing, SubscribeUpdate,
+    SubscribeUpdateAccount, UpdateOneof,
+};
+
+// (holders sorted by amount, supply, decimals)
+type BookSnapshot = (Vec<(String, u64, Pubkey)>, u64, u8);
+
+const SUBSCRIBE_PATH: &str = "/geyser.Geyser/Subscribe";
+const MINTS_FILTER: &str = "mints";
+
+#[derive(Debug, Default)]
+struct MintBook {
+    supply: u64,
+    decimals: u8,
+    seeded: bool,
+    // token account -> (owner, amount, (slot, write_version))
+    accounts: HashMap<String, (Pubkey, u64, (u64, u64))>,
+    // accounts updated by the stream before the RPC seed landed
+    touched: HashSet<String>,
+}
+
+// Holder state for every monitored mint, kept up to date from the account stream
+#[derive(Debug, Default)]
+pub struct HolderBook {
+    mints: HashMap<String, MintBook>,
+}
+
+impl HolderBook {
+    pub fn mint_addresses(&self) -> Vec<String> {
+        self.mints.keys().cloned().collect()
+    }
+
+    pub fn track(&mut self, mint_address: &str) {
+        self.mints.entry(mint_address.to_string()).or_default();
+    }
+
+    pub fn untrack(&mut self, mint_address: &str) {
+        self.mints.remove(mint_address);
+    }
+
+    pub fn unseeded(&self) -> Vec<String> {
+        self.mints.iter()
+            .filter(|(_, book)| !book.seeded)
+            .map(|(mint, _)| mint.clone())
+            .collect()
+    }
+
+    pub fn mark_all_unseeded(&mut self) {
+        for book in self.mints.values_mut() {
+            book.seeded = false;
+            book.touched.clear();
+        }
+    }
+
+    // Merge an RPC scan into the book without clobbering fresher stream updates
+    pub fn seed(&mut self, mint_address: &str, supply: u64, decimals: u8, holders: Vec<(String, u64, Pubkey)>) {
+        let Some(book) = self.mints.get_mut(mint_address) else {
+            return;
+        };
+        let mut accounts: HashMap<String, (Pubkey, u64, (u64, u64))> = holders.into_iter()
+            .filter(|(token_account, _, _)| !book.touched.contains(token_account))
+            .map(|(token_account, amount, owner)| (token_account, (owner, amount, (0, 0))))
+            .collect();
+        for token_account in book.touched.drain() {
+            if let Some(entry) = book.accounts.get(&token_account) {
+                accounts.insert(token_account, *entry);
+            }
+        }
+        book.accounts = accounts;
+        book.supply = supply;
+        book.decimals = decimals;
+        book.seeded = true;
+    }
+
+    pub fn apply(&mut self, update: &SubscribeUpdateAccount) {
+        let Some(info) = update.account.as_ref() else {
+            return;
+        };
+        let version = (update.slot, info.write_version);
+        let Ok(pubkey) = Pubkey::try_from(info.pubkey.as_slice()) else {
+            return;
+        };
+
+        if info.data.len() == Mint::LEN {
+            if let (Some(book), Ok(mint)) = (self.mints.get_mut(&pubkey.to_string()), Mint::unpack(&info.data)) {
+                book.supply = mint.supply;
+                book.decimals = mint.decimals;
+            }
+            return;
+        }
+
+        let Ok(token_account) = TokenAccount::unpack(&info.data) else {
+            return;
+        };
+        let Some(book) = self.mints.get_mut(&token_account.mint.to_string()) else {
+            return;
+        };
+        let key = pubkey.to_string();
+        if let Some((_, _, seen)) = book.accounts.get(&key) {
+            if *seen > version {
+                return;
+            }
+        }
+        if !book.seeded {
+            book.touched.insert(key.clone());
+        }
+        // Same cut-off as fetch_and_sort_holders(.., min_balance = 1)
+        if token_account.amount > 1 && token_account.state == AccountState::Initialized {
+            book.accounts.insert(key, (token_account.owner, token_account.amount, version));
+        } else {
+            book.accounts.remove(&key);
+        }
+    }
+
+    // Holders sorted by amount, in the shape the snapshot writer expects
+    pub fn holders(&self, mint_address: &str) -> Option<BookSnapshot> {
+        let book = self.mints.get(mint_address).filter(|book| book.seeded)?;
+        let mut holders: Vec<(String, u64, Pubkey)> = book.accounts.iter()
+            .map(|(token_account, (owner, amount, _))| (token_account.clone(), *amount, *owner))
+            .collect();
+        holders.sort_by_key(|h| std::cmp::Reverse(h.1));
+        Some((holders, book.supply, book.decimals))
+    }
+}
+
+pub fn build_subscribe_request(mint_addresses: &[String]) -> SubscribeRequest {
+    let mut accounts: HashMap<String, SubscribeRequestFilterAccounts> = mint_addresses.iter()
+        .map(|mint| {
+            (mint.clone(), SubscribeRequestFilterAccounts {
+                account: vec![],
+                owner: vec![spl_token::ID.to_string()],
+                filters: vec![
+                    SubscribeRequestFilterAccountsFilter {
+                        filter: Some(AccountsFilter::Datasize(TokenAccount::LEN as u64)),
+                    },
+                    SubscribeRequestFilterAccountsFilter {
+                        filter: Some(AccountsFilter::Memcmp(SubscribeRequestFilterAccountsFilterMemcmp {
+                            offset: 0,
+                            data: Some(MemcmpData::Base58(mint.clone())),
+                        })),
+                    },
+                ],
+            })
+        })
+        .collect();
+
+    // Mint accounts themselves, so supply changes don't need an RPC call
+    if !mint_addresses.is_empty() {
+        accounts.insert(MINTS_FILTER.to_string(), SubscribeRequestFilterAccounts {
+            account: mint_addresses.to_vec(),
+            owner: vec![],
+            filters: vec![],
+        });
+    }
+
+    SubscribeRequest {
+        accounts,
+        commitment: Some(CommitmentLevel::Confirmed as i32),
+        ping: None,
+    }
+}
+
+async fn connect(config: &GeyserConfig) -> Result<Channel> {
+    let url = config.endpoint.clone().unwrap_or_default();
+    let mut endpoint = Endpoint::from_shared(url.clone())?
+        .connect_timeout(Duration::from_secs(10))
+        .http2_keep_alive_interval(Duration::from_secs(30))
+        .keep_alive_while_idle(true);
+    if url.starts_with("https://") {
+        endpoint = endpoint.tls_config(ClientTlsConfig::new())?;
+    }
+    Ok(endpoint.connect().await?)
+}
+
+// Pumps one subscription until the stream ends or errors
+async fn run_subscription(
+    config: &GeyserConfig,
+    book: &Arc<Mutex<HolderBook>>,
+    requests: &Arc<Mutex<Option<UnboundedSender<SubscribeRequest>>>>,
+) -> Result<()> {
+    let channel = connect(config).await?;
+    let mut grpc = tonic::client::Grpc::new(channel);
+    grpc.ready().await?;
+
+    let (tx, rx) = unbounded();
+    let mints = book.lock().await.mint_addresses();
+    tx.unbounded_send(build_subscribe_request(&mints))?;
+
+    let mut request = tonic::Request::new(rx);
+    if let Some(token) = &config.x_token {
+        request.metadata_mut().insert("x-token", token.parse()?);
+    }
+
+    let codec = tonic::codec::ProstCodec::<SubscribeRequest, SubscribeUpdate>::default();
+    let mut stream = grpc
+        .streaming(request, PathAndQuery::from_static(SUBSCRIBE_PATH), codec)
+        .await?
+        .into_inner();
+    tracing::info!("Geyser subscription established for {} mints", mints.len());
+    *requests.lock().await = Some(tx.clone());
+
+    while let Some(update) = stream.message().await? {
+        match update.update_oneof {
+            Some(UpdateOneof::Account(account)) => book.lock().await.apply(&account),
+            Some(UpdateOneof::Ping(_)) => {
+                tx.unbounded_send(SubscribeRequest {
+                    ping: Some(SubscribeRequestPing { id: 1 }),
+                    ..Default::default()
+                })?;
+            }
+            None => {}
+        }
+    }
+
+    Ok(())
+}
+
+async fn seed_mint(
+    rpc_client: &Arc<RpcClient>,
+    rate_limiter: &Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    book: &Arc<Mutex<HolderBook>>,
+    mint_address: &str,
+) -> Result<()> {
+    let mint_pubkey = Pubkey::from_str(mint_address)?;
+    rate_limiter.until_ready().await;
+    let mint_account = rpc_client.get_account(&mint_pubkey).await?;
+    let mint_data = Mint::unpack(&mint_account.data)?;
+    let holders = fetch_and_sort_holders(rpc_client, rate_limiter, &mint_pubkey, 1).await?;
+    tracing::info!("Seeded {} holders for {} from RPC", holders.len(), mint_address);
+
+    book.lock().await.seed(mint_address, mint_data.supply, mint_data.decimals, holders);
+    Ok(())
+}
+
+// Picks up added/removed monitored tokens in this worker's shard and pushes
+// the new filter set upstream
+async fn sync_monitored_mints(
+    store: &dyn Store,
+    cache: &SnapshotCache,
+    shard: &Shard,
+    book: &Arc<Mutex<HolderBook>>,
+    requests: &Arc<Mutex<Option<UnboundedSender<SubscribeRequest>>>>,
+) -> Result<()> {
+    let monitored: HashSet<String> = store.monitored_tokens(&["active".to_string()]).await?
+        .into_iter()
+        .map(|token| token.mint_address)
+        .filter(|mint| shard.owns(mint))
+        .collect();
+
+    let mut book = book.lock().await;
+    let tracked: HashSet<String> = book.mint_addresses().into_iter().collect();
+    if tracked == monitored {
+        return Ok(());
+    }
+    for mint in tracked.difference(&monitored) {
+        book.untrack(mint);
+        cache.forget(mint).await;
+    }
+    for mint in monitored.difference(&tracked) {
+        book.track(mint);
+    }
+
+    let mints = book.mint_addresses();
+    if let Some(tx) = requests.lock().await.as_ref() {
+        // A new request on the open stream replaces the previous filters
+        if tx.unbounded_send(build_subscribe_request(&mints)).is_err() {
+            tracing::warn!("Geyser stream is down, filters will be applied on reconnect");
+        }
+    }
+    tracing::info!("Geyser now tracking {} mints", mints.len());
+    Ok(())
+}
+
+pub async fn start_geyser_ingestion(
+    store: Arc<dyn Store>,
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    config: Arc<Config>,
+    cache: Arc<SnapshotCache>,
+    shard: Arc<Shard>,
+) {
+    let geyser = config.geyser.clone();
+    tracing::info!("Starting Geyser ingestion from {}", geyser.endpoint.as_deref().unwrap_or_default());
+    let book = Arc::new(Mutex::new(HolderBook::default()));
+    let requests: Arc<Mutex<Option<UnboundedSender<SubscribeRequest>>>> = Arc::new(Mutex::new(None));
+
+    if let Err(e) = sync_monitored_mints(store.as_ref(), &cache, &shard, &book, &requests).await {
+        tracing::error!("Failed to load monitored tokens: {:?}", e);
+    }
+
+    let stream_handle = tokio::spawn({
+        let book = book.clone();
+        let requests = requests.clone();
+        let geyser = geyser.clone();
+        async move {
+            let mut backoff = Duration::from_secs(1);
+            loop {
+                match run_subscription(&geyser, &book, &requests).await {
+                    Ok(()) => tracing::warn!("Geyser stream closed by server"),
+                    Err(e) => tracing::error!("Geyser stream failed: {:?}", e),
+                }
+                *requests.lock().await = None;
+                // Anything missed while disconnected is picked up by re-seeding
+                book.lock().await.mark_all_unseeded();
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(Duration::from_secs(60));
+            }
+        }
+    });
+
+    let mut snapshot_timer = tokio::time::interval(Duration::from_secs(config.monitor.stats_interval_secs));
+    let mut last_resync = Instant::now();
+
+    loop {
+        snapshot_timer.tick().await;
+        if stream_handle.is_finished() {
+            tracing::error!("Geyser stream task exited");
+            return;
+        }
+
+        if let Err(e) = sync_monitored_mints(store.as_ref(), &cache, &shard, &book, &requests).await {
+            tracing::error!("Failed to sync monitored tokens: {:?}", e);
+        }
+
+        if last_resync.elapsed() >= Duration::from_secs(geyser.resync_secs) {
+            book.lock().await.mark_all_unseeded();
+            last_resync = Instant::now();
+        }
+
+        let unseeded = book.lock().await.unseeded();
+        for mint_address in unseeded {
+            if let Err(e) = seed_mint(&rpc_client, &rate_limiter, &book, &mint_address).await {
+                tracing::error!("Failed to seed holders for {}: {:?}", mint_address, e);
+            }
+        }
+
+        let mints = book.lock().await.mint_addresses();
+        for mint_address in mints {
+            let Some((holders, supply, decimals)) = book.lock().await.holders(&mint_address) else {
+                continue;
+            };
+            match write_snapshot(store.as_ref(), &cache, &mint_address, &holders, supply, decimals).await {
+                Ok(_) => tracing::debug!("Wrote Geyser snapshot for {}", mint_address),
+                Err(e) => tracing::error!("Failed to write Geyser snapshot for {}: {:?}", mint_address, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use futures::StreamExt;
+    use tonic::codegen::{http, BoxFuture, BoxStream, Context, Poll, Service};
+    use tonic::transport::{Body, Server};
+    use super::*;
+    use super::proto::SubscribeUpdateAccountInfo;
+
+    fn account_update(token_account: &Pubkey, mint: &Pubkey, owner: &Pubkey, amount: u64, slot: u64) -> SubscribeUpdateAccount {
+        let mut data = vec![0; TokenAccount::LEN];
+        TokenAccount {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        }.pack_into_slice(&mut data);
+        SubscribeUpdateAccount {
+            account: Some(SubscribeUpdateAccountInfo {
+                pubkey: token_account.to_bytes().to_vec(),
+                owner: spl_token::ID.to_bytes().to_vec(),
+                data,
+                write_version: 1,
+            }),
+            slot,
+        }
+    }
+
+    // A tracked and seeded mint with one token account holding 100
+    fn seeded_book() -> (HolderBook, Pubkey, Pubkey, Pubkey) {
+        let (mint, token_account, owner) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut book = HolderBook::default();
+        book.track(&mint.to_string());
+        book.seed(&mint.to_string(), 1000, 6, vec![(token_account.to_string(), 100, owner)]);
+        (book, mint, token_account, owner)
+    }
+
+    fn holders(book: &HolderBook, mint: &Pubkey) -> Vec<(String, u64, Pubkey)> {
+        book.holders(&mint.to_string()).unwrap().0
+    }
+
+    #[test]
+    fn update_overrides_the_seed() {
+        let (mut book, mint, token_account, owner) = seeded_book();
+        book.apply(&account_update(&token_account, &mint, &owner, 250, 10));
+        assert_eq!(holders(&book, &mint), vec![(token_account.to_string(), 250, owner)]);
+    }
+
+    #[test]
+    fn stale_slot_is_ignored() {
+        let (mut book, mint, token_account, owner) = seeded_book();
+        book.apply(&account_update(&token_account, &mint, &owner, 250, 10));
+        book.apply(&account_update(&token_account, &mint, &owner, 50, 9));
+        assert_eq!(holders(&book, &mint), vec![(token_account.to_string(), 250, owner)]);
+    }
+
+    #[test]
+    fn owner_change_moves_the_balance() {
+        let (mut book, mint, token_account, _) = seeded_book();
+        let new_owner = Pubkey::new_unique();
+        book.apply(&account_update(&token_account, &mint, &new_owner, 100, 10));
+        assert_eq!(holders(&book, &mint), vec![(token_account.to_string(), 100, new_owner)]);
+    }
+
+    #[test]
+    fn zero_balance_removes_the_holder() {
+        let (mut book, mint, token_account, owner) = seeded_book();
+        book.apply(&account_update(&token_account, &mint, &owner, 0, 10));
+        assert!(holders(&book, &mint).is_empty());
+    }
+
+    #[test]
+    fn seed_keeps_updates_streamed_before_it() {
+        let (mint, token_account, owner) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut book = HolderBook::default();
+        book.track(&mint.to_string());
+        book.apply(&account_update(&token_account, &mint, &owner, 250, 10));
+        assert!(book.holders(&mint.to_string()).is_none());
+
+        let other = (Pubkey::new_unique().to_string(), 40, Pubkey::new_unique());
+        book.seed(&mint.to_string(), 1000, 6, vec![(token_account.to_string(), 100, owner), other.clone()]);
+        assert_eq!(holders(&book, &mint), vec![(token_account.to_string(), 250, owner), other]);
+    }
+
+    // (x-token, first request) of each subscription
+    type Subscriptions = Arc<Mutex<Vec<(Option<String>, SubscribeRequest)>>>;
+
+    // Answers a subscription with fixed updates, then ends the stream
+    #[derive(Clone)]
+    struct MockGeyser {
+        updates: Vec<SubscribeUpdate>,
+        requests: Subscriptions,
+    }
+
+    impl tonic::server::NamedService for MockGeyser {
+        const NAME: &'static str = "geyser.Geyser";
+    }
+
+    impl tonic::server::StreamingService<SubscribeRequest> for MockGeyser {
+        type Response = SubscribeUpdate;
+        type ResponseStream = BoxStream<SubscribeUpdate>;
+        type Future = BoxFuture<tonic::Response<Self::ResponseStream>, tonic::Status>;
+
+        fn call(&mut self, request: tonic::Request<tonic::Streaming<SubscribeRequest>>) -> Self::Future {
+            let service = self.clone();
+            Box::pin(async move {
+                let x_token = request.metadata().get("x-token").and_then(|v| v.to_str().ok()).map(str::to_string);
+                let mut stream = request.into_inner();
+                if let Some(Ok(subscribe)) = stream.next().await {
+                    service.requests.lock().await.push((x_token, subscribe));
+                }
+                let updates = futures::stream::iter(service.updates.into_iter().map(Ok));
+                Ok(tonic::Response::new(Box::pin(updates) as Self::ResponseStream))
+            })
+        }
+    }
+
+    impl Service<http::Request<Body>> for MockGeyser {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+            let service = self.clone();
+            Box::pin(async move {
+                assert_eq!(request.uri().path(), SUBSCRIBE_PATH);
+                let codec = tonic::codec::ProstCodec::<SubscribeUpdate, SubscribeRequest>::default();
+                Ok(tonic::server::Grpc::new(codec).streaming(service, request).await)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn subscription_feeds_the_book() {
+        let (mint, token_account, owner) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (other_account, other_owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let account = |update| SubscribeUpdate { update_oneof: Some(UpdateOneof::Account(update)) };
+        let mock = MockGeyser {
+            updates: vec![
+                account(account_update(&token_account, &mint, &owner, 300, 20)),
+                account(account_update(&other_account, &mint, &other_owner, 80, 21)),
+                account(account_update(&token_account, &mint, &owner, 999, 19)),
+                // Not a monitored mint
+                account(account_update(&Pubkey::new_unique(), &Pubkey::new_unique(), &owner, 5, 22)),
+            ],
+            requests: Arc::new(Mutex::new(Vec::new())),
+        };
+        let requests = mock.requests.clone();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let incoming = futures::stream::unfold(listener, |listener| async move {
+            Some((listener.accept().await.map(|(socket, _)| socket), listener))
+        });
+        tokio::spawn(Server::builder().add_service(mock).serve_with_incoming(incoming));
+
+        let book = Arc::new(Mutex::new(HolderBook::default()));
+        book.lock().await.track(&mint.to_string());
+        let config = GeyserConfig {
+            endpoint: Some(endpoint),
+            x_token: Some("secret".to_string()),
+            ..GeyserConfig::default()
+        };
+        run_subscription(&config, &book, &Arc::new(Mutex::new(None))).await.unwrap();
+
+        let requests = requests.lock().await;
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].0.as_deref(), Some("secret"));
+        assert_eq!(requests[0].1, build_subscribe_request(&[mint.to_string()]));
+
+        // The RPC scan is older than the streamed updates
+        let mut book = book.lock().await;
+        book.seed(&mint.to_string(), 1000, 6, vec![(token_account.to_string(), 100, owner)]);
+        assert_eq!(holders(&book, &mint), vec![
+            (token_account.to_string(), 300, owner),
+            (other_account.to_string(), 80, other_owner),
+        ]);
+    }
+}
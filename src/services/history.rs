@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+use anyhow::Result;
+use clickhouse::Client;
+use crate::types::models::{ConcentrationMetric, HistoryPoint, HolderCountPoint, Resolution, TokenHistory};
+
+// Raw snapshots are capped to a few days per request and hourly buckets to a
+// few months, past that the next tier down is used even if finer data exists
+const MAX_RAW_RANGE_SECS: i64 = 3 * 24 * 3600;
+const MAX_HOURLY_RANGE_SECS: i64 = 180 * 24 * 3600;
+
+impl Resolution {
+    fn table(&self, base: &str) -> String {
+        match self {
+            Resolution::Hourly => format!("{}_hourly", base),
+            Resolution::Daily => format!("{}_daily", base),
+            _ => base.to_string(),
+        }
+    }
+
+    fn time_column(&self) -> &'static str {
+        match self {
+            Resolution::Hourly | Resolution::Daily => "bucket",
+            _ => "timestamp",
+        }
+    }
+
+    // Start of the rollup bucket a snapshot at `timestamp` lands in, like
+    // toStartOfHour / toStartOfDay
+    pub fn bucket_start(&self, timestamp: i64) -> i64 {
+        match self {
+            Resolution::Hourly => timestamp - timestamp.rem_euclid(3600),
+            Resolution::Daily => timestamp - timestamp.rem_euclid(24 * 3600),
+            _ => timestamp,
+        }
+    }
+}
+
+// Tiers to try for the range, finest first. Daily is used when none covers `from`.
+pub fn candidate_resolutions(from: i64, to: i64) -> Vec<Resolution> {
+    let range = to - from;
+    let mut candidates = Vec::new();
+    if range <= MAX_RAW_RANGE_SECS {
+        candidates.push(Resolution::Raw);
+    }
+    if range <= MAX_HOURLY_RANGE_SECS {
+        candidates.push(Resolution::Hourly);
+    }
+    candidates
+}
+
+// Oldest retained row for the mint in a tier, None when the tier is empty for it
+async fn earliest_in_tier(client: &Client, mint_address: &str, resolution: Resolution) -> Result<Option<i64>> {
+    let (earliest, rows): (i64, u64) = client
+        .query(&format!(
+            "SELECT toInt64(min({col})), count() FROM {table} WHERE mint_address = ?",
+            col = resolution.time_column(),
+            table = resolution.table("token_stats"),
+        ))
+        .bind(mint_address)
+        .fetch_one()
+        .await?;
+    Ok((rows > 0).then_some(earliest))
+}
+
+// Picks the finest tier that still covers `from` and isn't too dense for the range
+pub async fn resolve_resolution(client: &Client, mint_address: &str, from: i64, to: i64) -> Result<Resolution> {
+    for resolution in candidate_resolutions(from, to) {
+        if let Some(earliest) = earliest_in_tier(client, mint_address, resolution).await? {
+            if earliest <= from {
+                return Ok(resolution);
+            }
+        }
+    }
+
+    Ok(Resolution::Daily)
+}
+
+pub async fn get_token_history(
+    client: &Client,
+    mint_address: &str,
+    from: i64,
+    to: i64,
+    resolution: Resolution,
+) -> Result<TokenHistory> {
+    let resolution = match resolution {
+        Resolution::Auto => resolve_resolution(client, mint_address, from, to).await?,
+        other => other,
+    };
+    let col = resolution.time_column();
+    let mut points: BTreeMap<String, HistoryPoint> = BTreeMap::new();
+
+    let stats: Vec<(String, f64, f64, f64)> = client
+        .query(&format!(
+            "SELECT toString({col}), price, supply, market_cap
+             FROM {table} FINAL
+             WHERE mint_address = ? AND {col} BETWEEN toDateTime(?, 'UTC') AND toDateTime(?, 'UTC')
+             ORDER BY {col}",
+            col = col,
+            table = resolution.table("token_stats"),
+        ))
+        .bind(mint_address)
+        .bind(from)
+        .bind(to)
+        .fetch_all()
+        .await?;
+    for (timestamp, price, supply, market_cap) in stats {
+        let point = points.entry(timestamp.clone()).or_insert_with(|| HistoryPoint::new(timestamp));
+        point.price = Some(price);
+        point.supply = Some(supply);
+        point.market_cap = Some(market_cap);
+    }
+
+    let distribution: Vec<(String, f64, f64, f64, u64, u64)> = client
+        .query(&format!(
+            "SELECT toString({col}), hhi, distribution_score, gini, nakamoto_coefficient, total_holders
+             FROM {table} FINAL
+             WHERE mint_address = ? AND {col} BETWEEN toDateTime(?, 'UTC') AND toDateTime(?, 'UTC')",
+            col = col,
+            table = resolution.table("token_distribution"),
+        ))
+        .bind(mint_address)
+        .bind(from)
+        .bind(to)
+        .fetch_all()
+        .await?;
+    for (timestamp, hhi, distribution_score, gini, nakamoto_coefficient, total_holders) in distribution {
+        let point = points.entry(timestamp.clone()).or_insert_with(|| HistoryPoint::new(timestamp));
+        point.hhi = Some(hhi);
+        point.distribution_score = Some(distribution_score);
+        point.gini = Some(gini);
+        point.nakamoto_coefficient = Some(nakamoto_coefficient);
+        point.total_holders = Some(total_holders);
+    }
+
+    let concentration: Vec<(String, u32, f64)> = client
+        .query(&format!(
+            "SELECT toString({col}), top_n, percentage
+             FROM {table} FINAL
+             WHERE mint_address = ? AND {col} BETWEEN toDateTime(?, 'UTC') AND toDateTime(?, 'UTC')
+             ORDER BY top_n",
+            col = col,
+            table = resolution.table("token_concentration"),
+        ))
+        .bind(mint_address)
+        .bind(from)
+        .bind(to)
+        .fetch_all()
+        .await?;
+    for (timestamp, top_n, percentage) in concentration {
+        let point = points.entry(timestamp.clone()).or_insert_with(|| HistoryPoint::new(timestamp));
+        point.concentration_metrics.push(ConcentrationMetric { top_n: top_n as i32, percentage });
+    }
+
+    let holder_counts: Vec<(String, f64, u64)> = client
+        .query(&format!(
+            "SELECT toString({col}), usd_threshold, holder_count
+             FROM {table} FINAL
+             WHERE mint_address = ? AND {col} BETWEEN toDateTime(?, 'UTC') AND toDateTime(?, 'UTC')
+             ORDER BY usd_threshold",
+            col = col,
+            table = resolution.table("token_holder_counts"),
+        ))
+        .bind(mint_address)
+        .bind(from)
+        .bind(to)
+        .fetch_all()
+        .await?;
+    for (timestamp, usd_threshold, holder_count) in holder_counts {
+        let point = points.entry(timestamp.clone()).or_insert_with(|| HistoryPoint::new(timestamp));
+        point.holder_counts.push(HolderCountPoint { usd_threshold, holder_count });
+    }
+
+    Ok(TokenHistory {
+        mint_address: mint_address.to_string(),
+        resolution,
+        from,
+        to,
+        points: points.into_values().collect(),
+    })
+}
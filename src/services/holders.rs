@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use anyhow::Result;
+use clickhouse::Client;
+use crate::db::models::{HolderRankRecord, PortfolioSnapshotRecord, PortfolioTokenRecord, SnapshotRecord, WalletSnapshotRecord};
+use crate::db::queries::{LATEST_HOLDER_BALANCES_SQL, SNAPSHOT_OWNER_BALANCES_SQL};
+use crate::types::models::{HolderEntry, HolderList, PortfolioToken, WalletHolding, WalletPortfolio, WalletSnapshot};
+
+pub const MAX_HOLDERS_PAGE: u64 = 1000;
+pub const MAX_WALLET_SNAPSHOTS: u64 = 500;
+pub const MAX_PORTFOLIO_SNAPSHOTS: u64 = 50;
+
+// Latest category per excluded address, as a subquery to join on
+const EXCLUDED_CATEGORIES_SQL: &str = "
+    SELECT address, argMax(category, added_at) AS category
+    FROM excluded_accounts
+    GROUP BY address
+";
+
+// Pages are keyed on the snapshot and the last rank returned, so they stay
+// consistent while new snapshots come in
+pub fn encode_cursor(timestamp: i64, rank: u64) -> String {
+    format!("{}:{}", timestamp, rank)
+}
+
+pub fn decode_cursor(cursor: &str) -> Option<(i64, u64)> {
+    let (timestamp, rank) = cursor.split_once(':')?;
+    Some((timestamp.parse().ok()?, rank.parse().ok()?))
+}
+
+pub async fn get_excluded_category(client: &Client, address: &str) -> Result<Option<String>> {
+    let category: Option<String> = client
+        .query(&format!("SELECT category FROM ({}) WHERE address = ?", EXCLUDED_CATEGORIES_SQL))
+        .bind(address)
+        .fetch_optional()
+        .await?;
+    Ok(category)
+}
+
+pub async fn get_holders(
+    client: &Client,
+    mint_address: &str,
+    snapshot: &SnapshotRecord,
+    after_rank: u64,
+    limit: u64,
+    min_usd: f64,
+) -> Result<HolderList> {
+    let token_scale = 10f64.powi(snapshot.decimals as i32);
+
+    let total_holders: u64 = client
+        .query(&format!("SELECT count() FROM ({})", SNAPSHOT_OWNER_BALANCES_SQL))
+        .bind(mint_address)
+        .bind(snapshot.checkpoint_timestamp)
+        .bind(snapshot.timestamp)
+        .fetch_one()
+        .await?;
+
+    // Without a price no balance has a USD value to compare against
+    let rows: Vec<HolderRankRecord> = if min_usd > 0.0 && snapshot.price <= 0.0 {
+        Vec::new()
+    } else {
+        let min_balance = if min_usd > 0.0 { min_usd / snapshot.price * token_scale } else { 0.0 };
+        client
+            .query(&format!("
+                SELECT rank, holder_address, balance, category
+                FROM (
+                    SELECT
+                        row_number() OVER (ORDER BY b.balance DESC, b.holder_address ASC) AS rank,
+                        b.holder_address AS holder_address,
+                        b.balance AS balance,
+                        ea.category AS category
+                    FROM ({}) b
+                    LEFT JOIN ({}) ea ON b.holder_address = ea.address
+                )
+                WHERE rank > ? AND balance >= ?
+                ORDER BY rank
+                LIMIT ?
+            ", SNAPSHOT_OWNER_BALANCES_SQL, EXCLUDED_CATEGORIES_SQL))
+            .bind(mint_address)
+            .bind(snapshot.checkpoint_timestamp)
+            .bind(snapshot.timestamp)
+            .bind(after_rank)
+            .bind(min_balance)
+            .bind(limit)
+            .fetch_all()
+            .await?
+    };
+
+    Ok(holder_list(mint_address, snapshot, total_holders, rows, limit))
+}
+
+// One page of at most `limit` ranked rows, category empty for holders that aren't excluded
+pub fn holder_list(
+    mint_address: &str,
+    snapshot: &SnapshotRecord,
+    total_holders: u64,
+    rows: Vec<HolderRankRecord>,
+    limit: u64,
+) -> HolderList {
+    let token_scale = 10f64.powi(snapshot.decimals as i32);
+    let next_cursor = match rows.last() {
+        Some(last) if rows.len() as u64 == limit => Some(encode_cursor(snapshot.timestamp, last.rank)),
+        _ => None,
+    };
+
+    let holders = rows.into_iter()
+        .map(|row| {
+            let balance = row.balance / token_scale;
+            HolderEntry {
+                rank: row.rank,
+                holder_address: row.holder_address,
+                balance,
+                usd_value: balance * snapshot.price,
+                share_of_supply: if snapshot.supply > 0.0 { row.balance / snapshot.supply * 100.0 } else { 0.0 },
+                excluded_category: (!row.category.is_empty()).then_some(row.category),
+            }
+        })
+        .collect();
+
+    HolderList {
+        mint_address: mint_address.to_string(),
+        timestamp: snapshot.timestamp,
+        total_holders,
+        holders,
+        next_cursor,
+    }
+}
+
+// Balance and rank of one wallet over the token's last `limit` snapshots, oldest first.
+// Ranks use the same ordering as get_holders.
+pub async fn get_wallet_holding(
+    client: &Client,
+    mint_address: &str,
+    holder_address: &str,
+    limit: u64,
+) -> Result<WalletHolding> {
+    let rows: Vec<WalletSnapshotRecord> = client
+        .query("
+            WITH snapshots AS (
+                SELECT mint_address, timestamp, checkpoint_timestamp, price, supply, decimals
+                FROM token_stats
+                WHERE mint_address = ?
+                ORDER BY timestamp DESC
+                LIMIT ?
+            ),
+            owner_amounts AS (
+                SELECT mint_address, holder_address, timestamp, toFloat64(sum(amount)) AS amount
+                FROM token_holders
+                WHERE mint_address = ?
+                    AND timestamp >= (SELECT min(checkpoint_timestamp) FROM snapshots)
+                GROUP BY mint_address, holder_address, timestamp
+            ),
+            owner_balances AS (
+                SELECT
+                    s.timestamp AS timestamp,
+                    any(s.price) AS price,
+                    any(s.supply) AS supply,
+                    any(s.decimals) AS decimals,
+                    oa.holder_address AS holder_address,
+                    argMax(oa.amount, oa.timestamp) AS balance
+                FROM snapshots s
+                INNER JOIN owner_amounts oa ON s.mint_address = oa.mint_address
+                WHERE oa.timestamp >= s.checkpoint_timestamp AND oa.timestamp <= s.timestamp
+                GROUP BY s.timestamp, oa.holder_address
+                HAVING balance > 0
+            )
+            SELECT
+                toInt64(timestamp) AS timestamp,
+                price,
+                supply,
+                decimals,
+                total_holders,
+                wallet_balance AS balance,
+                arrayCount(
+                    (b, h) -> b > wallet_balance OR (b = wallet_balance AND h < ?),
+                    balances,
+                    holders
+                ) + 1 AS rank
+            FROM (
+                SELECT
+                    timestamp,
+                    any(price) AS price,
+                    any(supply) AS supply,
+                    any(decimals) AS decimals,
+                    count() AS total_holders,
+                    sumIf(balance, holder_address = ?) AS wallet_balance,
+                    groupArray(balance) AS balances,
+                    groupArray(holder_address) AS holders
+                FROM owner_balances
+                GROUP BY timestamp
+            )
+            ORDER BY timestamp
+        ")
+        .bind(mint_address)
+        .bind(limit)
+        .bind(mint_address)
+        .bind(holder_address)
+        .bind(holder_address)
+        .fetch_all()
+        .await?;
+
+    Ok(WalletHolding {
+        mint_address: mint_address.to_string(),
+        holder_address: holder_address.to_string(),
+        excluded_category: get_excluded_category(client, holder_address).await?,
+        history: rows.into_iter().map(wallet_snapshot).collect(),
+    })
+}
+
+// Raw balance and supply in the row, rank ignored where the wallet held nothing
+pub fn wallet_snapshot(row: WalletSnapshotRecord) -> WalletSnapshot {
+    let balance = row.balance / 10f64.powi(row.decimals as i32);
+    WalletSnapshot {
+        timestamp: row.timestamp,
+        balance,
+        usd_value: balance * row.price,
+        share_of_supply: if row.supply > 0.0 { row.balance / row.supply * 100.0 } else { 0.0 },
+        rank: (row.balance > 0.0).then_some(row.rank),
+        total_holders: row.total_holders,
+    }
+}
+
+// Every active token the wallet holds in its latest snapshot, largest USD value
+// first. One grouped query ranks the wallet in each token it holds, a second one
+// reads the history of just those tokens.
+pub async fn get_wallet_portfolio(client: &Client, holder_address: &str, snapshots: u64) -> Result<WalletPortfolio> {
+    let latest: Vec<PortfolioTokenRecord> = client
+        .query(&format!("
+            WITH wallet AS (
+                SELECT lb.mint_address AS mint_address, lb.balance AS balance
+                FROM ({latest}) lb
+                INNER JOIN (
+                    SELECT mint_address
+                    FROM monitored_tokens FINAL
+                    WHERE status = 'active'
+                ) mt ON lb.mint_address = mt.mint_address
+                WHERE lb.holder_address = ?
+            )
+            SELECT
+                b.mint_address AS mint_address,
+                toInt64(any(ts.timestamp)) AS timestamp,
+                any(ts.price) AS price,
+                any(ts.supply) AS supply,
+                any(ts.decimals) AS decimals,
+                count() AS total_holders,
+                any(w.balance) AS balance,
+                countIf(b.balance > w.balance OR (b.balance = w.balance AND b.holder_address < ?)) + 1 AS rank
+            FROM ({latest}) b
+            INNER JOIN wallet w ON b.mint_address = w.mint_address
+            INNER JOIN (
+                SELECT
+                    mint_address,
+                    max(timestamp) AS timestamp,
+                    argMax(price, timestamp) AS price,
+                    argMax(supply, timestamp) AS supply,
+                    argMax(decimals, timestamp) AS decimals
+                FROM token_stats
+                WHERE mint_address IN (SELECT mint_address FROM wallet)
+                GROUP BY mint_address
+            ) ts ON b.mint_address = ts.mint_address
+            WHERE b.mint_address IN (SELECT mint_address FROM wallet)
+            GROUP BY b.mint_address
+        ", latest = LATEST_HOLDER_BALANCES_SQL))
+        .bind(holder_address)
+        .bind(holder_address)
+        .fetch_all()
+        .await?;
+
+    let mints: Vec<&str> = latest.iter().map(|row| row.mint_address.as_str()).collect();
+    let histories = get_wallet_histories(client, &mints, holder_address, snapshots).await?;
+    let excluded_category = get_excluded_category(client, holder_address).await?;
+    Ok(wallet_portfolio(holder_address, excluded_category, latest, histories))
+}
+
+// The wallet's latest holding per token with the token's history
+pub fn wallet_portfolio(
+    holder_address: &str,
+    excluded_category: Option<String>,
+    latest: Vec<PortfolioTokenRecord>,
+    mut histories: HashMap<String, Vec<WalletSnapshot>>,
+) -> WalletPortfolio {
+    let mut tokens: Vec<PortfolioToken> = latest.into_iter()
+        .map(|row| {
+            let balance = row.balance / 10f64.powi(row.decimals as i32);
+            PortfolioToken {
+                timestamp: row.timestamp,
+                balance,
+                usd_value: balance * row.price,
+                share_of_supply: if row.supply > 0.0 { row.balance / row.supply * 100.0 } else { 0.0 },
+                rank: row.rank,
+                total_holders: row.total_holders,
+                percentile: (row.total_holders - row.rank + 1) as f64 / row.total_holders as f64 * 100.0,
+                history: histories.remove(&row.mint_address).unwrap_or_default(),
+                mint_address: row.mint_address,
+            }
+        })
+        .collect();
+    tokens.sort_by(|a, b| b.usd_value.total_cmp(&a.usd_value));
+
+    WalletPortfolio {
+        holder_address: holder_address.to_string(),
+        excluded_category,
+        total_usd_value: tokens.iter().map(|t| t.usd_value).sum(),
+        tokens,
+    }
+}
+
+// Balance and rank of the wallet over the last `limit` snapshots of each of the
+// mints, oldest first, from the same balances as LATEST_HOLDER_BALANCES_SQL
+async fn get_wallet_histories(
+    client: &Client,
+    mints: &[&str],
+    holder_address: &str,
+    limit: u64,
+) -> Result<HashMap<String, Vec<WalletSnapshot>>> {
+    if mints.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let rows: Vec<PortfolioSnapshotRecord> = client
+        .query("
+            WITH snapshots AS (
+                SELECT mint_address, timestamp, checkpoint_timestamp, price, supply, decimals
+                FROM token_stats
+                WHERE mint_address IN ?
+                ORDER BY timestamp DESC
+                LIMIT ? BY mint_address
+            ),
+            owner_balances AS (
+                SELECT
+                    s.mint_address AS mint_address,
+                    s.timestamp AS timestamp,
+                    any(s.price) AS price,
+                    any(s.supply) AS supply,
+                    any(s.decimals) AS decimals,
+                    thb.holder_address AS holder_address,
+                    argMax(thb.balance, thb.timestamp) AS balance
+                FROM snapshots s
+                INNER JOIN (
+                    SELECT mint_address, holder_address, timestamp, balance
+                    FROM token_holder_balances
+                    WHERE mint_address IN ?
+                        AND timestamp >= (SELECT min(checkpoint_timestamp) FROM snapshots)
+                ) thb ON s.mint_address = thb.mint_address
+                WHERE thb.timestamp >= s.checkpoint_timestamp AND thb.timestamp <= s.timestamp
+                GROUP BY s.mint_address, s.timestamp, thb.holder_address
+                HAVING balance > 0
+            )
+            SELECT
+                mint_address,
+                toInt64(timestamp) AS timestamp,
+                price,
+                supply,
+                decimals,
+                total_holders,
+                wallet_balance AS balance,
+                arrayCount(
+                    (b, h) -> b > wallet_balance OR (b = wallet_balance AND h < ?),
+                    balances,
+                    holders
+                ) + 1 AS rank
+            FROM (
+                SELECT
+                    mint_address,
+                    timestamp,
+                    any(price) AS price,
+                    any(supply) AS supply,
+                    any(decimals) AS decimals,
+                    count() AS total_holders,
+                    sumIf(balance, holder_address = ?) AS wallet_balance,
+                    groupArray(balance) AS balances,
+                    groupArray(holder_address) AS holders
+                FROM owner_balances
+                GROUP BY mint_address, timestamp
+            )
+            ORDER BY mint_address, timestamp
+        ")
+        .bind(mints)
+        .bind(limit)
+        .bind(mints)
+        .bind(holder_address)
+        .bind(holder_address)
+        .fetch_all()
+        .await?;
+
+    let mut histories: HashMap<String, Vec<WalletSnapshot>> = HashMap::new();
+    for row in rows {
+        histories.entry(row.mint_address).or_default().push(wallet_snapshot(WalletSnapshotRecord {
+            timestamp: row.timestamp,
+            price: row.price,
+            supply: row.supply,
+            decimals: row.decimals,
+            total_holders: row.total_holders,
+            balance: row.balance,
+            rank: row.rank,
+        }));
+    }
+    Ok(histories)
+}
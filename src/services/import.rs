@@ -0,0 +1,101 @@
+use std::collections::{BTreeMap, HashSet};
+use std::str::FromStr;
+use anyhow::Result;
+use chrono::DateTime;
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use crate::db::store::Store;
+use crate::services::token::snapshot_stats;
+use crate::types::models::TokenStats;
+
+// One snapshot per line of an import file:
+// {"timestamp": 1717000000, "price": 0.0042, "supply": 1000000000000000, "decimals": 6,
+//  "holders": [{"owner": "...", "amount": 1500000000, "token_account": "..."}]}
+// Amounts and supply are raw, token_account is optional.
+#[derive(Deserialize)]
+struct ImportLine {
+    timestamp: i64,
+    price: f64,
+    supply: u64,
+    decimals: u8,
+    holders: Vec<ImportHolder>,
+}
+
+#[derive(Deserialize)]
+struct ImportHolder {
+    owner: String,
+    amount: u64,
+    token_account: Option<String>,
+}
+
+pub struct HistoricalSnapshot {
+    // Unix seconds
+    pub timestamp: i64,
+    pub stats: TokenStats,
+    pub holders: Vec<(String, u64, Pubkey)>,
+}
+
+// Snapshots come back sorted by timestamp
+pub fn parse_snapshot_import(text: &str) -> Result<Vec<HistoricalSnapshot>, String> {
+    let mut snapshots = Vec::new();
+    let mut timestamps = HashSet::new();
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let snapshot: ImportLine = serde_json::from_str(line)
+            .map_err(|e| format!("Line {}: {}", line_no + 1, e))?;
+        if snapshot.timestamp <= 0 || !timestamps.insert(snapshot.timestamp) {
+            return Err(format!("Line {}: timestamp {} is invalid or repeated", line_no + 1, snapshot.timestamp));
+        }
+        if !(snapshot.price.is_finite() && snapshot.price > 0.0) {
+            return Err(format!("Line {}: price must be positive", line_no + 1));
+        }
+
+        // Token accounts listed twice are summed, owners without one get a single row
+        let mut holders: BTreeMap<(Pubkey, String), u64> = BTreeMap::new();
+        for holder in snapshot.holders {
+            let owner = Pubkey::from_str(&holder.owner)
+                .map_err(|_| format!("Line {}: invalid owner {}", line_no + 1, holder.owner))?;
+            // Both end up in the holders INSERT, so only addresses are accepted
+            let token_account = holder.token_account.unwrap_or(holder.owner);
+            Pubkey::from_str(&token_account)
+                .map_err(|_| format!("Line {}: invalid token account {}", line_no + 1, token_account))?;
+            *holders.entry((owner, token_account)).or_default() += holder.amount;
+        }
+        let holders: Vec<(String, u64, Pubkey)> = holders
+            .into_iter()
+            .filter(|(_, amount)| *amount > 0)
+            .map(|((owner, token_account), amount)| (token_account, amount, owner))
+            .collect();
+        if holders.is_empty() {
+            return Err(format!("Line {}: no holders with a balance", line_no + 1));
+        }
+
+        snapshots.push(HistoricalSnapshot {
+            timestamp: snapshot.timestamp,
+            stats: snapshot_stats(snapshot.price, snapshot.supply, snapshot.decimals),
+            holders,
+        });
+    }
+    snapshots.sort_by_key(|snapshot| snapshot.timestamp);
+    Ok(snapshots)
+}
+
+// Every snapshot is written as its own checkpoint, see Store::import_snapshot,
+// then the mint's holder spells are rebuilt. Returns the holder rows written.
+pub async fn write_historical_snapshots(store: &dyn Store, mint_address: &str, snapshots: &[HistoricalSnapshot]) -> Result<usize> {
+    let mut rows = 0;
+    for snapshot in snapshots {
+        let timestamp = DateTime::from_timestamp(snapshot.timestamp, 0)
+            .ok_or_else(|| anyhow::anyhow!("Timestamp {} is out of range", snapshot.timestamp))?
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        store.import_snapshot(mint_address, &snapshot.holders, &snapshot.stats, &timestamp).await?;
+        rows += snapshot.holders.len();
+        tracing::info!("Imported snapshot of {} at {}, {} holder rows", mint_address, timestamp, snapshot.holders.len());
+    }
+    store.rebuild_holder_spells(mint_address).await?;
+    Ok(rows)
+}
@@ -0,0 +1,457 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use anyhow::Result;
+use chrono::{TimeZone, Utc};
+use clickhouse::Client;
+use futures::stream::StreamExt;
+use governor::{RateLimiter, state::{NotKeyed, InMemoryState}, clock::DefaultClock};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use tokio::time::Duration;
+use crate::config::{Config, JobsConfig};
+use crate::db::models::{ClaimableJobRecord, JobClaimRecord, SnapshotJobRecord};
+use crate::services::sharding::Shard;
+use crate::services::snapshots::SnapshotCache;
+use crate::db::store::Store;
+use crate::services::token::{update_token_metrics, SnapshotSummary};
+use crate::types::models::SnapshotJob;
+
+pub const JOB_STATUSES: &[&str] = &["queued", "running", "done", "failed"];
+pub const MAX_JOBS_PAGE: u64 = 1000;
+
+pub async fn enqueue_snapshot_job(client: &Client, mint_address: &str, kind: &str) -> Result<String> {
+    let job_id: String = client
+        .query("SELECT toString(generateUUIDv4())")
+        .fetch_one()
+        .await?;
+    client
+        .query("
+            INSERT INTO snapshot_jobs (job_id, mint_address, kind, status, created_at)
+            VALUES (?, ?, ?, 'queued', now64(3, 'UTC'))
+        ")
+        .bind(&job_id)
+        .bind(mint_address)
+        .bind(kind)
+        .execute()
+        .await?;
+    tracing::debug!("Queued {} snapshot job {} for {}", kind, job_id, mint_address);
+    Ok(job_id)
+}
+
+// A refresh outside the schedule. A job of the mint that is still queued or
+// running is returned instead of queueing another one behind it.
+pub async fn request_refresh(store: &dyn Store, mint_address: &str) -> Result<String> {
+    for status in ["queued", "running"] {
+        if let Some(job) = store.jobs(Some(status), Some(mint_address), 1).await?.into_iter().next() {
+            return Ok(job.job_id);
+        }
+    }
+    store.enqueue_job(mint_address, "refresh").await
+}
+
+pub fn format_millis(millis: i64) -> Option<String> {
+    if millis <= 0 {
+        return None;
+    }
+    Utc.timestamp_millis_opt(millis)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S%.3f").to_string())
+}
+
+const SNAPSHOT_JOB_COLUMNS: &str = "
+    j.job_id AS job_id,
+    j.mint_address AS mint_address,
+    j.kind AS kind,
+    j.status AS status,
+    toUnixTimestamp64Milli(j.created_at) AS created_at,
+    toUnixTimestamp64Milli(j.started_at) AS started_at,
+    toUnixTimestamp64Milli(j.finished_at) AS finished_at,
+    j.attempts AS attempts,
+    j.max_attempts AS max_attempts,
+    toUnixTimestamp64Milli(j.run_after) AS run_after,
+    j.lease_owner AS lease_owner,
+    toUnixTimestamp64Milli(j.lease_expires_at) AS lease_expires_at,
+    j.holders AS holders,
+    j.rows_written AS rows_written,
+    toString(j.snapshot_timestamp) AS snapshot_timestamp,
+    j.error AS error
+";
+
+pub fn to_snapshot_job(job: SnapshotJobRecord) -> SnapshotJob {
+    let done = job.status == "done";
+    let running = job.status == "running";
+    SnapshotJob {
+        created_at: format_millis(job.created_at).unwrap_or_default(),
+        started_at: format_millis(job.started_at),
+        finished_at: format_millis(job.finished_at),
+        queued_ms: (job.started_at > 0).then(|| job.started_at - job.created_at),
+        run_ms: (job.started_at > 0 && job.finished_at > 0).then(|| job.finished_at - job.started_at),
+        attempts: job.attempts,
+        max_attempts: job.max_attempts,
+        next_attempt_at: if job.status == "queued" { format_millis(job.run_after) } else { None },
+        lease_owner: running.then_some(job.lease_owner),
+        lease_expires_at: if running { format_millis(job.lease_expires_at) } else { None },
+        holders: done.then_some(job.holders),
+        rows_written: done.then_some(job.rows_written),
+        snapshot_timestamp: done.then_some(job.snapshot_timestamp),
+        error: (!job.error.is_empty()).then_some(job.error),
+        job_id: job.job_id,
+        mint_address: job.mint_address,
+        kind: job.kind,
+        status: job.status,
+    }
+}
+
+pub async fn get_snapshot_job(client: &Client, job_id: &str) -> Result<Option<SnapshotJob>> {
+    let record: Option<SnapshotJobRecord> = client
+        .query(&format!("
+            SELECT {}
+            FROM snapshot_jobs j FINAL
+            WHERE j.job_id = ?
+            LIMIT 1
+        ", SNAPSHOT_JOB_COLUMNS))
+        .bind(job_id)
+        .fetch_optional()
+        .await?;
+
+    Ok(record.map(to_snapshot_job))
+}
+
+// Newest first, empty filters match every job
+pub async fn list_snapshot_jobs(
+    client: &Client,
+    status: Option<&str>,
+    mint_address: Option<&str>,
+    limit: u64,
+) -> Result<Vec<SnapshotJob>> {
+    let records: Vec<SnapshotJobRecord> = client
+        .query(&format!("
+            SELECT {}
+            FROM snapshot_jobs j FINAL
+            WHERE (? = '' OR j.status = ?) AND (? = '' OR j.mint_address = ?)
+            ORDER BY j.created_at DESC
+            LIMIT ?
+        ", SNAPSHOT_JOB_COLUMNS))
+        .bind(status.unwrap_or_default())
+        .bind(status.unwrap_or_default())
+        .bind(mint_address.unwrap_or_default())
+        .bind(mint_address.unwrap_or_default())
+        .bind(limit)
+        .fetch_all()
+        .await?;
+
+    Ok(records.into_iter().map(to_snapshot_job).collect())
+}
+
+// Writes a claim for the job's next attempt. Claims of an attempt share a
+// deduplication token, so ClickHouse stores the first one and drops the others:
+// the stored row names the only worker that may run the attempt. The winner
+// takes a lease on the job, everyone else leaves it alone.
+pub async fn claim_job(client: &Client, job_id: &str, attempt: u32, worker_id: &str, lease_secs: u64) -> Result<bool> {
+    client
+        .clone()
+        .with_option("insert_deduplicate", "1")
+        .with_option("insert_deduplication_token", format!("{}:{}", job_id, attempt))
+        .query("INSERT INTO snapshot_job_claims (job_id, attempt, worker_id) VALUES (?, ?, ?)")
+        .bind(job_id)
+        .bind(attempt)
+        .bind(worker_id)
+        .execute()
+        .await?;
+
+    // Earliest first, in case a claim outlived the deduplication window
+    let claim: JobClaimRecord = client
+        .query("
+            SELECT
+                argMin(worker_id, claimed_at) AS worker_id,
+                toInt64(dateDiff('second', min(claimed_at), now64(6, 'UTC'))) AS age_secs
+            FROM snapshot_job_claims
+            WHERE job_id = ? AND attempt = ?
+        ")
+        .bind(job_id)
+        .bind(attempt)
+        .fetch_one()
+        .await?;
+    if claim.worker_id != worker_id {
+        if claim.age_secs > lease_secs as i64 {
+            skip_abandoned_attempt(client, job_id, attempt).await?;
+        }
+        return Ok(false);
+    }
+
+    client
+        .query("
+            INSERT INTO snapshot_jobs
+            SELECT * REPLACE (
+                'running' AS status,
+                now64(3, 'UTC') AS started_at,
+                ? AS attempts,
+                ? AS lease_owner,
+                now64(3, 'UTC') + toIntervalSecond(?) AS lease_expires_at,
+                now64(3, 'UTC') AS updated_at
+            )
+            FROM snapshot_jobs FINAL
+            WHERE job_id = ?
+        ")
+        .bind(attempt)
+        .bind(worker_id)
+        .bind(lease_secs)
+        .bind(job_id)
+        .execute()
+        .await?;
+    Ok(true)
+}
+
+// The winner of an attempt died before taking its lease, so nobody else can
+// claim it. The attempt counts as used and the job moves on to the next one.
+async fn skip_abandoned_attempt(client: &Client, job_id: &str, attempt: u32) -> Result<()> {
+    tracing::warn!("Snapshot job {} attempt {} was claimed but never started", job_id, attempt);
+    client
+        .query("
+            INSERT INTO snapshot_jobs
+            SELECT * REPLACE (
+                if(? < max_attempts, 'queued', 'failed') AS status,
+                if(? < max_attempts, toDateTime64(0, 3, 'UTC'), now64(3, 'UTC')) AS finished_at,
+                ? AS attempts,
+                '' AS lease_owner,
+                toDateTime64(0, 3, 'UTC') AS lease_expires_at,
+                'claimed attempt never started' AS error,
+                now64(3, 'UTC') AS updated_at
+            )
+            FROM snapshot_jobs FINAL
+            WHERE job_id = ? AND attempts = ?
+                AND (status = 'queued' OR (status = 'running' AND lease_expires_at < now64(3, 'UTC')))
+        ")
+        .bind(attempt)
+        .bind(attempt)
+        .bind(attempt)
+        .bind(job_id)
+        .bind(attempt - 1)
+        .execute()
+        .await?;
+    Ok(())
+}
+
+// The lease checks keep a worker that lost its lease from overwriting the new owner's state
+pub async fn renew_lease(client: &Client, job_id: &str, attempt: u32, worker_id: &str, lease_secs: u64) -> Result<()> {
+    client
+        .query("
+            INSERT INTO snapshot_jobs
+            SELECT * REPLACE (
+                now64(3, 'UTC') + toIntervalSecond(?) AS lease_expires_at,
+                now64(3, 'UTC') AS updated_at
+            )
+            FROM snapshot_jobs FINAL
+            WHERE job_id = ? AND status = 'running' AND lease_owner = ? AND attempts = ?
+        ")
+        .bind(lease_secs)
+        .bind(job_id)
+        .bind(worker_id)
+        .bind(attempt)
+        .execute()
+        .await?;
+    Ok(())
+}
+
+pub async fn mark_done(client: &Client, job_id: &str, attempt: u32, worker_id: &str, summary: &SnapshotSummary) -> Result<()> {
+    client
+        .query("
+            INSERT INTO snapshot_jobs
+            SELECT * REPLACE (
+                'done' AS status,
+                now64(3, 'UTC') AS finished_at,
+                '' AS lease_owner,
+                toDateTime64(0, 3, 'UTC') AS lease_expires_at,
+                ? AS holders,
+                ? AS rows_written,
+                toDateTime(?, 'UTC') AS snapshot_timestamp,
+                '' AS error,
+                now64(3, 'UTC') AS updated_at
+            )
+            FROM snapshot_jobs FINAL
+            WHERE job_id = ? AND lease_owner = ? AND attempts = ?
+        ")
+        .bind(summary.holders as u64)
+        .bind(summary.rows_written as u64)
+        .bind(&summary.timestamp)
+        .bind(job_id)
+        .bind(worker_id)
+        .bind(attempt)
+        .execute()
+        .await?;
+    Ok(())
+}
+
+fn backoff_secs(config: &JobsConfig, attempt: u32) -> u64 {
+    config.base_backoff_secs
+        .saturating_mul(1u64 << attempt.saturating_sub(1).min(16))
+        .min(config.max_backoff_secs)
+}
+
+// Requeued to run after the backoff until max_attempts, then failed for good
+pub async fn mark_failed(client: &Client, job_id: &str, attempt: u32, worker_id: &str, error: &str, retry_after_secs: u64) -> Result<()> {
+    client
+        .query("
+            INSERT INTO snapshot_jobs
+            SELECT * REPLACE (
+                if(attempts < max_attempts, 'queued', 'failed') AS status,
+                if(attempts < max_attempts, toDateTime64(0, 3, 'UTC'), now64(3, 'UTC')) AS finished_at,
+                now64(3, 'UTC') + toIntervalSecond(?) AS run_after,
+                '' AS lease_owner,
+                toDateTime64(0, 3, 'UTC') AS lease_expires_at,
+                ? AS error,
+                now64(3, 'UTC') AS updated_at
+            )
+            FROM snapshot_jobs FINAL
+            WHERE job_id = ? AND lease_owner = ? AND attempts = ?
+        ")
+        .bind(retry_after_secs)
+        .bind(error)
+        .bind(job_id)
+        .bind(worker_id)
+        .bind(attempt)
+        .execute()
+        .await?;
+    Ok(())
+}
+
+// Jobs whose worker died on the last attempt won't be picked up again
+pub async fn fail_exhausted_leases(client: &Client) -> Result<()> {
+    client
+        .query("
+            INSERT INTO snapshot_jobs
+            SELECT * REPLACE (
+                'failed' AS status,
+                now64(3, 'UTC') AS finished_at,
+                'lease expired on the last attempt' AS error,
+                now64(3, 'UTC') AS updated_at
+            )
+            FROM snapshot_jobs FINAL
+            WHERE status = 'running' AND lease_expires_at < now64(3, 'UTC') AND attempts >= max_attempts
+        ")
+        .execute()
+        .await?;
+    Ok(())
+}
+
+// Queued jobs that are due and running jobs whose lease expired, oldest first.
+// Mints with a job still running are left until it is done.
+pub async fn claimable_jobs(client: &Client, limit: u64) -> Result<Vec<ClaimableJobRecord>> {
+    let jobs = client
+        .query("
+            SELECT job_id, mint_address, attempts + 1 AS attempt
+            FROM snapshot_jobs FINAL
+            WHERE ((status = 'queued' AND run_after <= now64(3, 'UTC'))
+                OR (status = 'running' AND lease_expires_at < now64(3, 'UTC') AND attempts < max_attempts))
+                AND mint_address NOT IN (
+                    SELECT mint_address FROM snapshot_jobs FINAL
+                    WHERE status = 'running' AND lease_expires_at >= now64(3, 'UTC')
+                )
+            ORDER BY created_at
+            LIMIT ?
+        ")
+        .bind(limit)
+        .fetch_all::<ClaimableJobRecord>()
+        .await?;
+    Ok(jobs)
+}
+
+// Only jobs for mints in this worker's shard are taken, the snapshot cache
+// only knows about those. One job per mint per round, the others wait for the
+// next poll rather than queue up on the mint's write lock.
+async fn shard_jobs(store: &dyn Store, config: &JobsConfig, shard: &Shard) -> Result<Vec<ClaimableJobRecord>> {
+    let mut mints = HashSet::new();
+    Ok(store.claimable_jobs(config.claim_scan_limit)
+        .await?
+        .into_iter()
+        .filter(|job| shard.owns(&job.mint_address) && mints.insert(job.mint_address.clone()))
+        .take(config.concurrency)
+        .collect())
+}
+
+async fn run_job(
+    store: &dyn Store,
+    client: &Arc<RpcClient>,
+    rate_limiter: &Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    cache: &SnapshotCache,
+    shard: &Shard,
+    config: &JobsConfig,
+    job: &ClaimableJobRecord,
+) -> Result<()> {
+    let worker_id = shard.worker_id();
+    let (job_id, mint_address, attempt) = (job.job_id.as_str(), job.mint_address.as_str(), job.attempt);
+    if !store.claim_job(job_id, attempt, worker_id, config.lease_secs).await? {
+        return Ok(());
+    }
+
+    let snapshot = update_token_metrics(client, rate_limiter, mint_address, store, cache);
+    tokio::pin!(snapshot);
+    let mut renew = tokio::time::interval(Duration::from_secs(config.lease_secs / 3));
+    renew.tick().await;
+    let result = loop {
+        tokio::select! {
+            result = &mut snapshot => break result,
+            _ = renew.tick() => {
+                if let Err(e) = store.renew_lease(job_id, attempt, worker_id, config.lease_secs).await {
+                    tracing::warn!("Failed to renew lease on snapshot job {}: {}", job_id, e);
+                }
+            }
+        }
+    };
+    // The mint moved to another worker while the snapshot ran
+    if !shard.owns(mint_address) {
+        cache.forget(mint_address).await;
+    }
+
+    match result {
+        Ok(summary) => {
+            tracing::info!("Snapshot job {} for {} done, {} holders", job_id, mint_address, summary.holders);
+            store.finish_job(job_id, attempt, worker_id, &summary).await
+        }
+        Err(e) => {
+            tracing::error!("Snapshot job {} for {} failed on attempt {}: {:?}", job_id, mint_address, attempt, e);
+            store.fail_job(job_id, attempt, worker_id, &e.to_string(), backoff_secs(config, attempt)).await
+        }
+    }
+}
+
+// Polls the queue and runs whatever this worker manages to claim. Any number of
+// workers can run this against the same database, each taking its shard's
+// jobs. Snapshots are taken over RPC whatever the ingest mode.
+pub async fn run_snapshot_jobs(
+    store: Arc<dyn Store>,
+    client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    cache: Arc<SnapshotCache>,
+    shard: Arc<Shard>,
+    config: Arc<Config>,
+) {
+    tracing::info!("Snapshot job worker {} started", shard.worker_id());
+    let config = &config.jobs;
+    let mut interval = tokio::time::interval(Duration::from_secs(config.poll_interval_secs));
+    loop {
+        interval.tick().await;
+        if let Err(e) = store.fail_exhausted_leases().await {
+            tracing::error!("Failed to expire snapshot job leases: {}", e);
+        }
+        let jobs = match shard_jobs(store.as_ref(), config, &shard).await {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                tracing::error!("Failed to read queued snapshot jobs: {}", e);
+                continue;
+            }
+        };
+
+        futures::stream::iter(jobs)
+            .map(|job| {
+                let (store, client, rate_limiter, cache, shard) = (store.as_ref(), &client, &rate_limiter, &cache, &shard);
+                async move {
+                    if let Err(e) = run_job(store, client, rate_limiter, cache, shard, config, &job).await {
+                        tracing::error!("Failed to record snapshot job {}: {}", job.job_id, e);
+                    }
+                }
+            })
+            .buffer_unordered(config.concurrency)
+            .collect::<Vec<_>>()
+            .await;
+    }
+}
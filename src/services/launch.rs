@@ -0,0 +1,541 @@
+use std::collections::{BTreeSet, HashMap};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Result;
+use clickhouse::Client;
+use governor::{RateLimiter, state::{NotKeyed, InMemoryState}, clock::DefaultClock};
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_client::GetConfirmedSignaturesForAddress2Config,
+    rpc_config::{RpcBlockConfig, RpcTransactionConfig},
+    rpc_response::RpcConfirmedTransactionStatusWithSignature,
+};
+use solana_sdk::{program_pack::Pack, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, TransactionDetails, UiTransactionEncoding};
+use spl_token::state::Mint;
+use crate::config::Config;
+use crate::db::models::{LaunchAnalysisRecord, WalletLabelRow};
+use crate::db::store::Store;
+use crate::services::sharding::Shard;
+use crate::types::models::{LabelHoldingPoint, LabelHoldings, LaunchInfo, WalletLabel};
+
+// getSignaturesForAddress page size
+const SIGNATURE_PAGE: usize = 1000;
+// Signature history is walked back a page at a time for this many pages, older
+// tokens are searched for by slot instead
+const MAX_SIGNATURE_PAGES: usize = 20;
+// Earliest transactions searched for the initial distribution
+const EARLY_TX_COUNT: usize = 20;
+// Transactions fetched from the start of the history, enough to cover the
+// launch window of busy launches
+const LAUNCH_TX_COUNT: usize = 500;
+pub const INSIDER_LABEL: &str = "insider";
+pub const SNIPER_LABEL: &str = "sniper";
+
+// Oldest `count` transactions touching the mint, oldest first. Young mints are
+// walked back from the newest transaction. For older ones the slots are bisected
+// for the last block with less than a page of the mint's history before it, which
+// takes around 30 probes of a few calls each. None if the node has no history
+// from before the creation.
+pub async fn earliest_signatures(
+    client: &Arc<RpcClient>,
+    rate_limiter: &Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    mint_pubkey: &Pubkey,
+    count: usize,
+) -> Result<Option<Vec<RpcConfirmedTransactionStatusWithSignature>>> {
+    let mut before = None;
+    let mut tail: Vec<RpcConfirmedTransactionStatusWithSignature> = Vec::new();
+
+    for _ in 0..MAX_SIGNATURE_PAGES {
+        let page = signatures_before(client, rate_limiter, mint_pubkey, before).await?;
+        let exhausted = page.len() < SIGNATURE_PAGE;
+        before = match page.last() {
+            Some(last) => Some(Signature::from_str(&last.signature)?),
+            None => None,
+        };
+        // Keep the last pages around in case the final one has fewer than `count`
+        tail.extend(page);
+        if tail.len() > count + SIGNATURE_PAGE {
+            tail.drain(..tail.len() - count - SIGNATURE_PAGE);
+        }
+
+        if exhausted || before.is_none() {
+            return Ok(Some(oldest_first(tail, count)));
+        }
+    }
+
+    // The creation is somewhere before the oldest transaction seen so far
+    let mut hi = tail.last().map(|s| s.slot).unwrap_or_default();
+    rate_limiter.until_ready().await;
+    let mut lo = client.get_first_available_block().await?;
+    tracing::info!("Searching slots {} to {} for the creation of {}", lo, hi, mint_pubkey);
+    let mut earliest = None;
+    while lo <= hi {
+        let mid = lo + (hi - lo) / 2;
+        let Some(anchor) = block_anchor(client, rate_limiter, mid).await? else {
+            hi = mid.saturating_sub(1);
+            if mid == 0 {
+                break;
+            }
+            continue;
+        };
+        let page = signatures_before(client, rate_limiter, mint_pubkey, Some(anchor)).await?;
+        if page.len() < SIGNATURE_PAGE {
+            // The whole history before this block, look for a later one
+            earliest = Some(page);
+            lo = mid + 1;
+        } else if mid == 0 {
+            break;
+        } else {
+            hi = mid - 1;
+        }
+    }
+
+    Ok(earliest
+        .filter(|page| !page.is_empty())
+        .map(|page| oldest_first(page, count)))
+}
+
+// One page of the mint's signatures before the given transaction, newest first
+async fn signatures_before(
+    client: &Arc<RpcClient>,
+    rate_limiter: &Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    mint_pubkey: &Pubkey,
+    before: Option<Signature>,
+) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+    rate_limiter.until_ready().await;
+    let page = client
+        .get_signatures_for_address_with_config(mint_pubkey, GetConfirmedSignaturesForAddress2Config {
+            before,
+            until: None,
+            limit: Some(SIGNATURE_PAGE),
+            commitment: None,
+        })
+        .await?;
+    Ok(page)
+}
+
+// First transaction of the first block at or after `slot`. `before` takes any
+// transaction's signature, so this bounds the mint's history by slot.
+async fn block_anchor(
+    client: &Arc<RpcClient>,
+    rate_limiter: &Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    slot: u64,
+) -> Result<Option<Signature>> {
+    rate_limiter.until_ready().await;
+    let Some(block_slot) = client.get_blocks_with_limit(slot, 1).await?.first().copied() else {
+        return Ok(None);
+    };
+    rate_limiter.until_ready().await;
+    let block = client
+        .get_block_with_config(block_slot, RpcBlockConfig {
+            encoding: None,
+            transaction_details: Some(TransactionDetails::Signatures),
+            rewards: Some(false),
+            commitment: None,
+            max_supported_transaction_version: Some(0),
+        })
+        .await?;
+    match block.signatures.as_deref().and_then(|signatures| signatures.first()) {
+        Some(signature) => Ok(Some(Signature::from_str(signature)?)),
+        None => Ok(None),
+    }
+}
+
+fn oldest_first(
+    newest_first: Vec<RpcConfirmedTransactionStatusWithSignature>,
+    count: usize,
+) -> Vec<RpcConfirmedTransactionStatusWithSignature> {
+    let mut earliest: Vec<_> = newest_first.into_iter().rev().filter(|s| s.err.is_none()).collect();
+    earliest.truncate(count);
+    earliest
+}
+
+pub async fn fetch_transaction(
+    client: &Arc<RpcClient>,
+    rate_limiter: &Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    signature: &str,
+) -> Result<EncodedConfirmedTransactionWithStatusMeta> {
+    rate_limiter.until_ready().await;
+    let tx = client
+        .get_transaction_with_config(&Signature::from_str(signature)?, RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment: None,
+            max_supported_transaction_version: Some(0),
+        })
+        .await?;
+    Ok(tx)
+}
+
+pub fn transaction_signers(tx: &EncodedConfirmedTransactionWithStatusMeta) -> Vec<Pubkey> {
+    match tx.transaction.transaction.decode() {
+        Some(decoded) => {
+            let signers = decoded.message.header().num_required_signatures as usize;
+            decoded.message.static_account_keys().iter().take(signers).copied().collect()
+        }
+        None => Vec::new(),
+    }
+}
+
+// Net change of the mint's balance per owner in one transaction, in raw amounts
+pub fn token_balance_changes(tx: &EncodedConfirmedTransactionWithStatusMeta, mint_address: &str) -> HashMap<String, i128> {
+    let mut changes: HashMap<String, i128> = HashMap::new();
+    let Some(meta) = &tx.transaction.meta else {
+        return changes;
+    };
+
+    let pre: Option<Vec<_>> = meta.pre_token_balances.clone().into();
+    let post: Option<Vec<_>> = meta.post_token_balances.clone().into();
+    for (balances, sign) in [(pre.unwrap_or_default(), -1i128), (post.unwrap_or_default(), 1i128)] {
+        for balance in balances.into_iter().filter(|b| b.mint == mint_address) {
+            let owner: Option<String> = balance.owner.into();
+            let (Some(owner), Ok(amount)) = (owner, balance.ui_token_amount.amount.parse::<i128>()) else {
+                continue;
+            };
+            *changes.entry(owner).or_default() += sign * amount;
+        }
+    }
+    changes.retain(|_, change| *change != 0);
+    changes
+}
+
+pub async fn insert_labels(
+    clickhouse_client: &Client,
+    mint_address: &str,
+    label: &str,
+    wallets: &[(String, String)],
+) -> Result<()> {
+    if wallets.is_empty() {
+        return Ok(());
+    }
+    // One batch, so the wallets of a label land in a single part
+    let mut insert = clickhouse_client.insert("wallet_labels")?;
+    for (address, reason) in wallets {
+        insert.write(&WalletLabelRow { mint_address, address, label, reason }).await?;
+    }
+    insert.end().await?;
+    Ok(())
+}
+
+// Outcome of one launch analysis, stored in token_launch_analysis
+#[derive(Debug, Clone, Default)]
+pub struct LaunchAnalysis {
+    pub creation_signature: String,
+    pub launch_slot: u64,
+    pub insiders: Vec<(String, String)>,
+    pub snipers: Vec<(String, String)>,
+    // Raw amount the snipers received inside the launch window
+    pub sniper_acquired: f64,
+    pub status: &'static str,
+}
+
+// One row per wallet, keeping the first reason found
+fn dedupe_wallets(wallets: &mut Vec<(String, String)>) {
+    let mut seen = BTreeSet::new();
+    wallets.retain(|(address, _)| seen.insert(address.clone()));
+}
+
+// Walks the start of the mint's history once. The mint authority, the creation
+// transaction's signers and whoever received tokens in the creation transaction
+// or in the creator's other early transactions are labelled insiders. Everyone
+// else who bought within `slot_window` slots of the creation is a sniper.
+pub async fn analyze_launch(
+    client: &Arc<RpcClient>,
+    rate_limiter: &Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    store: &dyn Store,
+    mint_address: &str,
+    slot_window: u64,
+) -> Result<LaunchAnalysis> {
+    let mint_pubkey = Pubkey::from_str(mint_address)?;
+    let mut analysis = LaunchAnalysis { status: "done", ..Default::default() };
+
+    rate_limiter.until_ready().await;
+    let mint_account = client.get_account(&mint_pubkey).await?;
+    // Token-2022 mints carry extensions after the base layout
+    if let Some(data) = mint_account.data.get(..Mint::LEN) {
+        if let Ok(mint) = Mint::unpack_from_slice(data) {
+            if let Some(authority) = Option::<Pubkey>::from(mint.mint_authority) {
+                analysis.insiders.push((authority.to_string(), "mint_authority".to_string()));
+            }
+        }
+    }
+
+    match earliest_signatures(client, rate_limiter, &mint_pubkey, LAUNCH_TX_COUNT).await? {
+        Some(signatures) if !signatures.is_empty() => {
+            analysis.creation_signature = signatures[0].signature.clone();
+            analysis.launch_slot = signatures[0].slot;
+            let mut creators: BTreeSet<Pubkey> = BTreeSet::new();
+            let mut early_buys: Vec<(String, String, i128)> = Vec::new();
+
+            for (i, status) in signatures.iter().enumerate() {
+                let in_window = status.slot <= analysis.launch_slot + slot_window;
+                if i >= EARLY_TX_COUNT && !in_window {
+                    break;
+                }
+                let tx = match fetch_transaction(client, rate_limiter, &status.signature).await {
+                    Ok(tx) => tx,
+                    Err(e) => {
+                        tracing::warn!("Failed to fetch transaction {} of {}: {}", status.signature, mint_address, e);
+                        continue;
+                    }
+                };
+                let signers = transaction_signers(&tx);
+                let changes = token_balance_changes(&tx, mint_address);
+                if i == 0 {
+                    for signer in &signers {
+                        analysis.insiders.push((signer.to_string(), format!("creation_signer:{}", status.signature)));
+                    }
+                    creators.extend(signers.iter().copied());
+                }
+
+                if i == 0 || (i < EARLY_TX_COUNT && signers.iter().any(|s| creators.contains(s))) {
+                    for (owner, change) in changes {
+                        if change > 0 {
+                            analysis.insiders.push((owner, format!("initial_recipient:{}", status.signature)));
+                        }
+                    }
+                } else if in_window {
+                    let offset = status.slot - analysis.launch_slot;
+                    for (owner, change) in changes {
+                        if change > 0 {
+                            early_buys.push((owner, format!("slot+{}:{}", offset, status.signature), change));
+                        }
+                    }
+                }
+            }
+
+            let insiders: BTreeSet<&str> = analysis.insiders.iter().map(|(address, _)| address.as_str()).collect();
+            for (owner, reason, change) in early_buys {
+                if insiders.contains(owner.as_str()) {
+                    continue;
+                }
+                analysis.sniper_acquired += change as f64;
+                analysis.snipers.push((owner, reason));
+            }
+        }
+        Some(_) => tracing::warn!("No transactions found for {}", mint_address),
+        None => {
+            analysis.status = "history_unavailable";
+            tracing::warn!(
+                "The RPC node has no history from the creation of {}, only labelling the mint authority",
+                mint_address
+            );
+        }
+    }
+
+    dedupe_wallets(&mut analysis.insiders);
+    dedupe_wallets(&mut analysis.snipers);
+
+    store.insert_launch_analysis(mint_address, &analysis, slot_window).await?;
+
+    tracing::info!(
+        "Labelled {} insider and {} sniper wallets for {}",
+        analysis.insiders.len(),
+        analysis.snipers.len(),
+        mint_address
+    );
+    Ok(analysis)
+}
+
+// The labelled wallets first, then the analysis row marking the mint as analyzed
+pub async fn store_launch_analysis(
+    clickhouse_client: &Client,
+    mint_address: &str,
+    analysis: &LaunchAnalysis,
+    slot_window: u64,
+) -> Result<()> {
+    insert_labels(clickhouse_client, mint_address, INSIDER_LABEL, &analysis.insiders).await?;
+    insert_labels(clickhouse_client, mint_address, SNIPER_LABEL, &analysis.snipers).await?;
+    clickhouse_client
+        .query("
+            INSERT INTO token_launch_analysis
+                (mint_address, creation_signature, launch_slot, window_slots, insiders, snipers, sniper_acquired, status)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        ")
+        .bind(mint_address)
+        .bind(&analysis.creation_signature)
+        .bind(analysis.launch_slot)
+        .bind(slot_window)
+        .bind(analysis.insiders.len() as u32)
+        .bind(analysis.snipers.len() as u32)
+        .bind(analysis.sniper_acquired)
+        .bind(analysis.status)
+        .execute()
+        .await?;
+    Ok(())
+}
+
+// Active tokens still to be analyzed, see schedule_launch_analysis
+pub async fn get_tokens_pending_launch_analysis(clickhouse_client: &Client) -> Result<Vec<String>> {
+    let pending = clickhouse_client
+        .query("
+            SELECT mint_address FROM monitored_tokens FINAL
+            WHERE status = 'active'
+                AND mint_address NOT IN (
+                    SELECT mint_address FROM token_launch_analysis FINAL
+                    WHERE status != 'history_too_long'
+                )
+        ")
+        .fetch_all()
+        .await?;
+    Ok(pending)
+}
+
+// Picks up monitored tokens that were never analyzed, e.g. ones added before
+// launch analysis existed or whose analysis failed, one at a time, each worker
+// taking the ones in its shard. Tokens given up on as history_too_long before
+// old histories were searched by slot are analyzed again.
+pub async fn schedule_launch_analysis(
+    client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    store: Arc<dyn Store>,
+    shard: Arc<Shard>,
+    config: Arc<Config>,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.launch.check_interval_secs));
+    loop {
+        interval.tick().await;
+        let pending = match store.tokens_pending_launch_analysis().await {
+            Ok(pending) => pending,
+            Err(e) => {
+                tracing::error!("Failed to find tokens pending launch analysis: {}", e);
+                continue;
+            }
+        };
+
+        for mint_address in pending.into_iter().filter(|mint| shard.owns(mint)) {
+            if let Err(e) = analyze_launch(&client, &rate_limiter, store.as_ref(), &mint_address, config.launch.slot_window).await {
+                tracing::error!("Failed to analyze launch of {}: {}", mint_address, e);
+            }
+        }
+    }
+}
+
+pub async fn get_wallet_labels(clickhouse_client: &Client, mint_address: &str) -> Result<Vec<WalletLabel>> {
+    let labels = clickhouse_client
+        .query("
+            SELECT address, label, reason, toString(added_at)
+            FROM wallet_labels FINAL
+            WHERE mint_address = ?
+            ORDER BY label, added_at
+        ")
+        .bind(mint_address)
+        .fetch_all::<(String, String, String, String)>()
+        .await?;
+
+    Ok(labels.into_iter()
+        .map(|(address, label, reason, added_at)| WalletLabel { address, label, reason, added_at })
+        .collect())
+}
+
+// Balances are converted to tokens with the mint's decimals
+pub async fn get_label_holdings(
+    clickhouse_client: &Client,
+    mint_address: &str,
+    label: &str,
+    decimals: u8,
+) -> Result<LabelHoldings> {
+    let labelled_wallets: u64 = clickhouse_client
+        .query("SELECT uniqExact(address) FROM wallet_labels WHERE mint_address = ? AND label = ?")
+        .bind(mint_address)
+        .bind(label)
+        .fetch_one()
+        .await?;
+
+    // No row for the latest snapshot means none of the wallets holds anything
+    let latest: Option<(u64, f64, f64)> = clickhouse_client
+        .query("
+            SELECT holders, balance, supply_share
+            FROM token_label_holdings
+            WHERE mint_address = ? AND label = ?
+                AND timestamp = (SELECT max(timestamp) FROM token_stats WHERE mint_address = ?)
+            LIMIT 1
+        ")
+        .bind(mint_address)
+        .bind(label)
+        .bind(mint_address)
+        .fetch_optional()
+        .await?;
+
+    let history: Vec<(String, u64, f64, f64)> = clickhouse_client
+        .query("
+            SELECT toString(bucket), holders, balance, supply_share
+            FROM token_label_holdings_hourly FINAL
+            WHERE mint_address = ? AND label = ? AND bucket >= subtractDays(now(), 7)
+            ORDER BY bucket
+        ")
+        .bind(mint_address)
+        .bind(label)
+        .fetch_all()
+        .await?;
+
+    Ok(label_holdings(label, labelled_wallets, latest.unwrap_or_default(), history, decimals))
+}
+
+// From the latest and hourly (holders, raw balance, supply_share) of the label
+pub fn label_holdings(
+    label: &str,
+    labelled_wallets: u64,
+    (holders, balance, supply_share): (u64, f64, f64),
+    history: Vec<(String, u64, f64, f64)>,
+    decimals: u8,
+) -> LabelHoldings {
+    let scale = 10f64.powi(decimals as i32);
+    LabelHoldings {
+        label: label.to_string(),
+        labelled_wallets,
+        holders,
+        balance: balance / scale,
+        supply_share,
+        history: history.into_iter()
+            .map(|(timestamp, holders, balance, supply_share)| LabelHoldingPoint {
+                timestamp,
+                holders,
+                balance: balance / scale,
+                supply_share,
+            })
+            .collect(),
+    }
+}
+
+// Latest launch analysis of the mint, compared against what its snipers hold now
+pub async fn get_launch_info(
+    clickhouse_client: &Client,
+    mint_address: &str,
+    sniper_holdings: &LabelHoldings,
+    decimals: u8,
+) -> Result<Option<LaunchInfo>> {
+    let row: Option<LaunchAnalysisRecord> = clickhouse_client
+        .query("
+            SELECT toString(analyzed_at) as analyzed_at, status, creation_signature, launch_slot, window_slots,
+                insiders, snipers, sniper_acquired
+            FROM token_launch_analysis FINAL
+            WHERE mint_address = ?
+            LIMIT 1
+        ")
+        .bind(mint_address)
+        .fetch_optional()
+        .await?;
+
+    Ok(row.map(|row| launch_info(row, sniper_holdings, decimals)))
+}
+
+pub fn launch_info(row: LaunchAnalysisRecord, sniper_holdings: &LabelHoldings, decimals: u8) -> LaunchInfo {
+    let sniper_acquired = row.sniper_acquired / 10f64.powi(decimals as i32);
+    LaunchInfo {
+        analyzed_at: row.analyzed_at,
+        status: row.status,
+        creation_signature: row.creation_signature,
+        launch_slot: row.launch_slot,
+        window_slots: row.window_slots,
+        insiders: row.insiders,
+        snipers: row.snipers,
+        sniper_acquired,
+        retained_pct: if sniper_acquired > 0.0 {
+            sniper_holdings.balance / sniper_acquired * 100.0
+        } else {
+            0.0
+        },
+    }
+}
@@ -0,0 +1,173 @@
+use std::sync::Arc;
+use anyhow::Result;
+use clickhouse::Client;
+use governor::{RateLimiter, state::{NotKeyed, InMemoryState}, clock::DefaultClock};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use tokio::time::Duration;
+use crate::config::{ArchiveConfig, Config, ExclusionsConfig};
+use crate::db::models::ArchiveCandidateRecord;
+use crate::db::store::Store;
+use crate::services::excluded_accounts::check_new_token_exclusions;
+use crate::services::sharding::Shard;
+use crate::types::models::TokenStatus;
+
+pub const TOKEN_STATUSES: &[&str] = &["active", "paused", "archived"];
+
+// Largest POST /tokens/bulk request, and how many of its mints are added at once
+pub const MAX_BULK_TOKENS: usize = 500;
+pub const BULK_CONCURRENCY: usize = 8;
+
+pub fn validate_status(status: &str) -> Result<(), String> {
+    if TOKEN_STATUSES.contains(&status) {
+        Ok(())
+    } else {
+        Err(format!("Unknown status {}, expected one of {}", status, TOKEN_STATUSES.join(", ")))
+    }
+}
+
+pub async fn get_token_status(client: &Client, mint_address: &str) -> Result<Option<TokenStatus>> {
+    let status = client
+        .query("
+            SELECT mint_address, status, status_reason, toString(status_updated_at)
+            FROM monitored_tokens FINAL
+            WHERE mint_address = ?
+            LIMIT 1
+        ")
+        .bind(mint_address)
+        .fetch_optional::<(String, String, String, String)>()
+        .await?;
+
+    Ok(status.map(|(mint_address, status, reason, updated_at)| TokenStatus {
+        mint_address,
+        status,
+        reason,
+        updated_at,
+    }))
+}
+
+// Waits for the mutation so the new status is visible to the next read
+pub async fn set_token_status(client: &Client, mint_address: &str, status: &str, reason: &str) -> Result<()> {
+    client
+        .query("
+            ALTER TABLE monitored_tokens
+            UPDATE status = ?, status_reason = ?, status_updated_at = now()
+            WHERE mint_address = ?
+            SETTINGS mutations_sync = 1
+        ")
+        .bind(status)
+        .bind(reason)
+        .bind(mint_address)
+        .execute()
+        .await?;
+    tracing::info!("Set status of {} to {} ({})", mint_address, status, reason);
+    Ok(())
+}
+
+pub enum AddedToken {
+    AlreadyMonitored,
+    Resumed { job_id: String },
+    Started { job_id: String },
+}
+
+// Starts monitoring a token and queues its first snapshot. Paused and archived
+// tokens are resumed, `via` ends up in their status reason. The mint is expected
+// to be validated by the caller.
+pub async fn add_token(
+    rpc_client: &Arc<RpcClient>,
+    rate_limiter: &Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    store: &dyn Store,
+    mint_address: &str,
+    via: &str,
+    config: &ExclusionsConfig,
+) -> Result<AddedToken> {
+    match store.token_status(mint_address).await? {
+        Some(token) if token.status == "active" => return Ok(AddedToken::AlreadyMonitored),
+        Some(_) => {
+            store.set_token_status(mint_address, "active", &format!("resumed via {}", via)).await?;
+            let job_id = store.enqueue_job(mint_address, "refresh").await?;
+            return Ok(AddedToken::Resumed { job_id });
+        }
+        None => {}
+    }
+
+    store.insert_monitored_token(mint_address).await?;
+
+    // Check for excluded accounts for this new token
+    if let Err(e) = check_new_token_exclusions(rpc_client, rate_limiter, store, mint_address, config).await {
+        tracing::error!("Failed to check excluded accounts for new token: {}", e);
+    }
+
+    let job_id = store.enqueue_job(mint_address, "refresh").await?;
+    Ok(AddedToken::Started { job_id })
+}
+
+// Active tokens older than the window with their latest market cap and number of
+// new holders over the window, wallets that came back after selling out included.
+// Missing rows join as NULL rather than 0: tokens without a snapshot yet are left
+// out, and new_holders stays NULL until the token has holder spells.
+pub async fn get_archive_candidates(client: &Client, window_hours: u32) -> Result<Vec<ArchiveCandidateRecord>> {
+    let candidates: Vec<ArchiveCandidateRecord> = client
+        .clone()
+        .with_option("join_use_nulls", "1")
+        .query("
+            SELECT mt.mint_address AS mint_address, assumeNotNull(ts.latest_market_cap) AS market_cap, age.new_holders AS new_holders
+            FROM (
+                SELECT mint_address FROM monitored_tokens FINAL
+                WHERE status = 'active' AND created_at < subtractHours(now(), ?)
+            ) mt
+            LEFT JOIN (
+                SELECT mint_address, argMax(market_cap, timestamp) AS latest_market_cap
+                FROM token_stats
+                GROUP BY mint_address
+            ) ts ON mt.mint_address = ts.mint_address
+            LEFT JOIN (
+                SELECT mint_address, uniqExactIf(holder_address, first_seen >= subtractHours(now(), ?)) AS new_holders
+                FROM token_holder_spells
+                GROUP BY mint_address
+            ) age ON mt.mint_address = age.mint_address
+            WHERE ts.latest_market_cap IS NOT NULL
+        ")
+        .bind(window_hours)
+        .bind(window_hours)
+        .fetch_all()
+        .await?;
+    Ok(candidates)
+}
+
+// Archives the candidates whose latest market cap or number of new holders over
+// the window is below the policy's floor. The new holder floor only applies once
+// the token has holder spells.
+pub async fn archive_inactive_tokens(store: &dyn Store, policy: &ArchiveConfig) -> Result<Vec<String>> {
+    let mut archived = Vec::new();
+    for ArchiveCandidateRecord { mint_address, market_cap, new_holders } in store.archive_candidates(policy.window_hours).await? {
+        let reason = match (policy.min_market_cap, policy.min_new_holders) {
+            (Some(floor), _) if market_cap < floor => {
+                format!("auto: market cap ${:.0} below ${:.0}", market_cap, floor)
+            }
+            (_, Some(floor)) if new_holders.is_some_and(|count| count < floor) => format!(
+                "auto: {} new holders in {}h, below {}",
+                new_holders.unwrap_or_default(), policy.window_hours, floor
+            ),
+            _ => continue,
+        };
+        store.set_token_status(&mint_address, "archived", &reason).await?;
+        archived.push(mint_address);
+    }
+    Ok(archived)
+}
+
+pub async fn schedule_auto_archive(store: Arc<dyn Store>, shard: Arc<Shard>, config: Arc<Config>) {
+    let policy = &config.archive;
+    let mut interval = tokio::time::interval(Duration::from_secs(policy.check_interval_secs));
+    loop {
+        interval.tick().await;
+        if !shard.is_leader() {
+            continue;
+        }
+        match archive_inactive_tokens(store.as_ref(), policy).await {
+            Ok(archived) if !archived.is_empty() => tracing::info!("Auto-archived {} tokens", archived.len()),
+            Ok(_) => {}
+            Err(e) => tracing::error!("Failed to auto-archive tokens: {}", e),
+        }
+    }
+}
@@ -0,0 +1,530 @@
+use std::collections::{HashMap, HashSet};
+use anyhow::Result;
+use clickhouse::Client;
+use solana_sdk::pubkey::Pubkey;
+use crate::db::models::{SnapshotRecord, TokenConcentrationMetricRecord, TokenDistributionMetricRecord, TokenHolderThresholdRecord};
+use crate::db::queries::SNAPSHOT_HOLDER_BALANCES_SQL;
+use crate::services::launch::{INSIDER_LABEL, SNIPER_LABEL};
+use crate::types::models::{BalancePercentiles, ConcentrationMetric, DistributionStats, HolderThreshold, LabelHoldings, TokenHolderStats, TokenStats};
+
+// The metric views of db::schema in Rust, for snapshots that aren't in
+// ClickHouse. Every function takes the balances the views read from
+// token_holder_balances: one positive raw amount per owner, excluded accounts
+// left out, in any order. Keep both in sync, `memespread check-metrics` compares
+// them on a stored snapshot.
+
+// Same as the seeds of token_threshold_config and token_concentration_config
+pub const DEFAULT_THRESHOLDS: &[f64] = &[10.0, 100.0, 1000.0, 10000.0, 100000.0];
+pub const DEFAULT_BUCKETS: &[u32] = &[1, 10, 25, 50, 100, 250];
+
+#[derive(Debug, Clone)]
+pub struct SnapshotMetrics {
+    // (usd_threshold, token_amount) as in token_thresholds
+    pub thresholds: Vec<(f64, f64)>,
+    pub holder_thresholds: Vec<HolderThreshold>,
+    pub concentration: Vec<ConcentrationMetric>,
+    pub distribution: Option<DistributionStats>,
+}
+
+// token_holder_balances_mv: amounts summed per owner, minus excluded owners, as (owner, balance)
+pub fn owner_balances(holders: &[(String, u64, Pubkey)], excluded: &HashSet<String>) -> Vec<(String, f64)> {
+    let mut owners: HashMap<String, u64> = HashMap::new();
+    for (_, amount, owner) in holders {
+        *owners.entry(owner.to_string()).or_default() += amount;
+    }
+    owners
+        .into_iter()
+        .filter(|(owner, balance)| *balance > 0 && !excluded.contains(owner))
+        .map(|(owner, balance)| (owner, balance as f64))
+        .collect()
+}
+
+pub fn compute_metrics(balances: &[f64], stats: &TokenStats, thresholds: &[f64], buckets: &[u32]) -> SnapshotMetrics {
+    SnapshotMetrics {
+        thresholds: token_thresholds(stats.price, thresholds),
+        holder_thresholds: holder_counts(balances, stats, thresholds),
+        concentration: concentration(balances, stats.supply, buckets),
+        distribution: distribution(balances, stats.supply),
+    }
+}
+
+// token_thresholds_mv
+pub fn token_thresholds(price: f64, thresholds: &[f64]) -> Vec<(f64, f64)> {
+    if price <= 0.0 {
+        return Vec::new();
+    }
+    thresholds.iter().map(|usd_threshold| (*usd_threshold, usd_threshold / price)).collect()
+}
+
+// token_holder_counts_mv, pct_of_10usd is relative to the lowest threshold
+pub fn holder_counts(balances: &[f64], stats: &TokenStats, thresholds: &[f64]) -> Vec<HolderThreshold> {
+    if stats.price <= 0.0 || balances.is_empty() {
+        return Vec::new();
+    }
+    let scale = 10f64.powi(stats.decimals as i32);
+    let usd_values: Vec<f64> = balances.iter().map(|balance| balance / scale * stats.price).collect();
+    let total_holders = usd_values.len() as u64;
+    let lowest = thresholds.iter().copied().fold(f64::INFINITY, f64::min);
+    let above_lowest = usd_values.iter().filter(|usd| **usd >= lowest).count() as u64;
+
+    thresholds
+        .iter()
+        .map(|usd_threshold| {
+            let slice: Vec<f64> = usd_values.iter().copied().filter(|usd| usd >= usd_threshold).collect();
+            let holder_count = slice.len() as u64;
+            HolderThreshold {
+                usd_threshold: *usd_threshold,
+                holder_count,
+                total_holders,
+                pct_total_holders: ratio(holder_count as f64, total_holders as f64) * 100.0,
+                pct_of_10usd: ratio(holder_count as f64, above_lowest as f64) * 100.0,
+                mcap_per_holder: ratio(stats.market_cap, holder_count as f64),
+                slice_value_usd: total(slice.into_iter()),
+            }
+        })
+        .collect()
+}
+
+// token_concentration_mv: share of the supply held by the N largest holders
+pub fn concentration(balances: &[f64], supply: f64, buckets: &[u32]) -> Vec<ConcentrationMetric> {
+    if balances.is_empty() {
+        return Vec::new();
+    }
+    let mut sorted = balances.to_vec();
+    sorted.sort_by(|a, b| b.total_cmp(a));
+    buckets
+        .iter()
+        .map(|top_n| ConcentrationMetric {
+            top_n: *top_n as i32,
+            percentage: total(sorted.iter().copied().take(*top_n as usize)) / supply * 100.0,
+        })
+        .collect()
+}
+
+// token_distribution_mv. Ranks are 1-based in ascending order of balance, the
+// percentiles follow quantileExact, which takes the element at floor(level * n).
+pub fn distribution(balances: &[f64], supply: f64) -> Option<DistributionStats> {
+    if balances.is_empty() {
+        return None;
+    }
+    let mut sorted = balances.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let count = sorted.len() as f64;
+    let held = total(sorted.iter().copied());
+    let ranked = || sorted.iter().enumerate().map(|(i, balance)| ((i + 1) as f64, *balance));
+
+    let hhi = total(sorted.iter().map(|balance| (balance / supply * 100.0).powi(2)));
+    let distribution_score = (1.0 - total(ranked().map(|(rank, balance)| balance * (rank - 1.0))) / (count * held)) * 100.0;
+    let gini = 2.0 * total(ranked().map(|(rank, balance)| rank * balance)) / (count * held) - (count + 1.0) / count;
+    let theil_index = total(sorted.iter().map(|balance| balance * balance.ln())) / held - (held / count).ln();
+
+    // Fewest holders, largest first, owning more than half of the held balance
+    let mut cumulative = 0.0;
+    let nakamoto_coefficient = sorted
+        .iter()
+        .rev()
+        .position(|balance| {
+            cumulative += balance;
+            cumulative > held * 0.5
+        })
+        .map_or(0, |index| index as u64 + 1);
+
+    let lorenz_curve = (0..=20)
+        .map(|k| total(sorted[..k * sorted.len() / 20].iter().copied()) / held)
+        .collect();
+
+    let quantile = |level: f64| sorted[((level * count) as usize).min(sorted.len() - 1)];
+    Some(DistributionStats {
+        total_count: sorted.len(),
+        hhi,
+        distribution_score,
+        median_balance: quantile(0.5),
+        mean_balance: held / count,
+        gini,
+        nakamoto_coefficient,
+        theil_index,
+        percentiles: BalancePercentiles {
+            p10: quantile(0.1),
+            p25: quantile(0.25),
+            p50: quantile(0.5),
+            p75: quantile(0.75),
+            p90: quantile(0.9),
+            p95: quantile(0.95),
+            p99: quantile(0.99),
+        },
+        lorenz_curve,
+    })
+}
+
+impl SnapshotMetrics {
+    // Same shape as the metrics read from ClickHouse. Label holdings and the
+    // launch aren't part of a snapshot's metrics, the store fills them in.
+    pub fn into_token_stats(self, mint_address: &str, stats: TokenStats) -> TokenHolderStats {
+        let no_holdings = |label: &str| LabelHoldings {
+            label: label.to_string(),
+            labelled_wallets: 0,
+            holders: 0,
+            balance: 0.0,
+            supply_share: 0.0,
+            history: Vec::new(),
+        };
+        TokenHolderStats {
+            mint_address: mint_address.to_string(),
+            token_stats: stats,
+            distribution_stats: self.distribution.unwrap_or_default(),
+            holder_thresholds: self.holder_thresholds,
+            concentration_metrics: self.concentration,
+            insider_holdings: no_holdings(INSIDER_LABEL),
+            sniper_holdings: no_holdings(SNIPER_LABEL),
+            launch: None,
+        }
+    }
+}
+
+// Float sums start at -0.0 in Rust, ClickHouse's at 0
+fn total(values: impl Iterator<Item = f64>) -> f64 {
+    values.fold(0.0, |sum, value| sum + value)
+}
+
+// Division as the views do it, with coalesce(x / nullIf(y, 0), 0)
+fn ratio(numerator: f64, denominator: f64) -> f64 {
+    if denominator == 0.0 {
+        0.0
+    } else {
+        numerator / denominator
+    }
+}
+
+// Recomputes a stored snapshot in Rust and lists every value that differs from
+// what the views stored by more than the relative tolerance. Thresholds and
+// buckets are the ones stored with the snapshot, balances already have the
+// exclusions of the time applied.
+pub async fn compare_with_stored(
+    client: &Client,
+    mint_address: &str,
+    snapshot: &SnapshotRecord,
+    tolerance: f64,
+) -> Result<Vec<String>> {
+    let balances: Vec<f64> = client
+        .query(SNAPSHOT_HOLDER_BALANCES_SQL)
+        .bind(mint_address)
+        .bind(snapshot.checkpoint_timestamp)
+        .bind(snapshot.timestamp)
+        .fetch_all::<(String, f64)>()
+        .await?
+        .into_iter()
+        .map(|(_, balance)| balance)
+        .collect();
+
+    let stored_thresholds: Vec<(f64, f64)> = client
+        .query("
+            SELECT usd_threshold, token_amount
+            FROM token_thresholds FINAL
+            WHERE mint_address = ? AND timestamp = toDateTime(?, 'UTC')
+            ORDER BY usd_threshold
+        ")
+        .bind(mint_address)
+        .bind(snapshot.timestamp)
+        .fetch_all()
+        .await?;
+    let stored_counts: Vec<TokenHolderThresholdRecord> = client
+        .query("
+            SELECT mint_address, timestamp, usd_threshold, holder_count, total_holders,
+                pct_total_holders, pct_of_10usd, mcap_per_holder, slice_value_usd
+            FROM token_holder_counts FINAL
+            WHERE mint_address = ? AND timestamp = toDateTime(?, 'UTC')
+            ORDER BY usd_threshold
+        ")
+        .bind(mint_address)
+        .bind(snapshot.timestamp)
+        .fetch_all()
+        .await?;
+    let stored_concentration: Vec<TokenConcentrationMetricRecord> = client
+        .query("
+            SELECT mint_address, timestamp, top_n, percentage
+            FROM token_concentration FINAL
+            WHERE mint_address = ? AND timestamp = toDateTime(?, 'UTC')
+            ORDER BY top_n
+        ")
+        .bind(mint_address)
+        .bind(snapshot.timestamp)
+        .fetch_all()
+        .await?;
+    let stored_distribution: Option<TokenDistributionMetricRecord> = client
+        .query("
+            SELECT mint_address, timestamp, hhi, distribution_score, median_balance, total_holders,
+                mean_balance, gini, nakamoto_coefficient, theil_index,
+                p10, p25, p50, p75, p90, p95, p99, lorenz_curve
+            FROM token_distribution FINAL
+            WHERE mint_address = ? AND timestamp = toDateTime(?, 'UTC')
+        ")
+        .bind(mint_address)
+        .bind(snapshot.timestamp)
+        .fetch_optional()
+        .await?;
+
+    let stats = TokenStats {
+        price: snapshot.price,
+        supply: snapshot.supply,
+        market_cap: snapshot.price * snapshot.supply,
+        decimals: snapshot.decimals,
+    };
+    let thresholds: Vec<f64> = stored_counts.iter().map(|row| row.usd_threshold).collect();
+    let buckets: Vec<u32> = stored_concentration.iter().map(|row| row.top_n).collect();
+    let computed = compute_metrics(&balances, &stats, &thresholds, &buckets);
+
+    let mut diff = Diff { tolerance, mismatches: Vec::new() };
+    for ((usd_threshold, stored), (_, computed)) in stored_thresholds.iter().zip(&computed.thresholds) {
+        diff.check(&format!("thresholds[${}]", usd_threshold), "token_amount", *stored, *computed);
+    }
+    for (stored, computed) in stored_counts.iter().zip(&computed.holder_thresholds) {
+        let key = format!("holder_counts[${}]", stored.usd_threshold);
+        diff.check(&key, "holder_count", stored.holder_count as f64, computed.holder_count as f64);
+        diff.check(&key, "total_holders", stored.total_holders as f64, computed.total_holders as f64);
+        diff.check(&key, "pct_total_holders", stored.pct_total_holders, computed.pct_total_holders);
+        diff.check(&key, "pct_of_10usd", stored.pct_of_10usd, computed.pct_of_10usd);
+        diff.check(&key, "mcap_per_holder", stored.mcap_per_holder, computed.mcap_per_holder);
+        diff.check(&key, "slice_value_usd", stored.slice_value_usd, computed.slice_value_usd);
+    }
+    if !stored_counts.is_empty() && computed.holder_thresholds.is_empty() {
+        diff.mismatches.push("holder_counts: stored, but none computed".to_string());
+    }
+    for (stored, computed) in stored_concentration.iter().zip(&computed.concentration) {
+        diff.check(&format!("concentration[top {}]", stored.top_n), "percentage", stored.percentage, computed.percentage);
+    }
+    if !stored_concentration.is_empty() && computed.concentration.is_empty() {
+        diff.mismatches.push("concentration: stored, but none computed".to_string());
+    }
+
+    match (&stored_distribution, &computed.distribution) {
+        (Some(stored), Some(computed)) => {
+            let key = "distribution";
+            diff.check(key, "total_holders", stored.total_holders as f64, computed.total_count as f64);
+            diff.check(key, "hhi", stored.hhi, computed.hhi);
+            diff.check(key, "distribution_score", stored.distribution_score, computed.distribution_score);
+            diff.check(key, "median_balance", stored.median_balance, computed.median_balance);
+            diff.check(key, "mean_balance", stored.mean_balance, computed.mean_balance);
+            diff.check(key, "gini", stored.gini, computed.gini);
+            diff.check(key, "nakamoto_coefficient", stored.nakamoto_coefficient as f64, computed.nakamoto_coefficient as f64);
+            diff.check(key, "theil_index", stored.theil_index, computed.theil_index);
+            let percentiles = &computed.percentiles;
+            diff.check(key, "p10", stored.p10, percentiles.p10);
+            diff.check(key, "p25", stored.p25, percentiles.p25);
+            diff.check(key, "p50", stored.p50, percentiles.p50);
+            diff.check(key, "p75", stored.p75, percentiles.p75);
+            diff.check(key, "p90", stored.p90, percentiles.p90);
+            diff.check(key, "p95", stored.p95, percentiles.p95);
+            diff.check(key, "p99", stored.p99, percentiles.p99);
+            for (i, (stored, computed)) in stored.lorenz_curve.iter().zip(&computed.lorenz_curve).enumerate() {
+                diff.check(key, &format!("lorenz_curve[{}]", i), *stored, *computed);
+            }
+        }
+        (None, None) => {}
+        (Some(_), None) => diff.mismatches.push("distribution: stored, but none computed".to_string()),
+        (None, Some(_)) => diff.mismatches.push("distribution: computed, but none stored".to_string()),
+    }
+
+    Ok(diff.mismatches)
+}
+
+struct Diff {
+    tolerance: f64,
+    mismatches: Vec<String>,
+}
+
+impl Diff {
+    fn check(&mut self, key: &str, field: &str, stored: f64, computed: f64) {
+        let scale = stored.abs().max(computed.abs()).max(1.0);
+        if (stored - computed).abs() > self.tolerance * scale {
+            self.mismatches.push(format!("{}.{}: stored {}, computed {}", key, field, stored, computed));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use serde_json::Value;
+    use super::*;
+
+    // Snapshots with the values the metric views give for them, worked out from
+    // the view definitions in db::schema
+    const SNAPSHOTS: &str = include_str!("../../tests/fixtures/metrics_snapshots.json");
+
+    fn assert_close(what: &str, expected: f64, actual: f64) {
+        let scale = expected.abs().max(actual.abs()).max(1.0);
+        assert!((expected - actual).abs() <= 1e-9 * scale, "{}: expected {}, got {}", what, expected, actual);
+    }
+
+    fn floats(value: &Value) -> Vec<f64> {
+        value.as_array().unwrap().iter().map(|v| v.as_f64().unwrap()).collect()
+    }
+
+    #[test]
+    fn matches_fixture_snapshots() {
+        let snapshots: Vec<Value> = serde_json::from_str(SNAPSHOTS).unwrap();
+        for snapshot in &snapshots {
+            let name = snapshot["name"].as_str().unwrap();
+            let stats = TokenStats {
+                price: snapshot["price"].as_f64().unwrap(),
+                supply: snapshot["supply"].as_f64().unwrap(),
+                market_cap: snapshot["market_cap"].as_f64().unwrap(),
+                decimals: snapshot["decimals"].as_u64().unwrap() as u8,
+            };
+            let thresholds = floats(&snapshot["thresholds"]);
+            let buckets: Vec<u32> = floats(&snapshot["buckets"]).into_iter().map(|b| b as u32).collect();
+            let metrics = compute_metrics(&floats(&snapshot["balances"]), &stats, &thresholds, &buckets);
+            let expected = &snapshot["expected"];
+
+            let expected_thresholds = expected["thresholds"].as_array().unwrap();
+            assert_eq!(metrics.thresholds.len(), expected_thresholds.len(), "{}", name);
+            for ((usd, amount), row) in metrics.thresholds.iter().zip(expected_thresholds) {
+                assert_close(&format!("{} thresholds[{}]", name, usd), row[1].as_f64().unwrap(), *amount);
+            }
+
+            let expected_counts = expected["holder_counts"].as_array().unwrap();
+            assert_eq!(metrics.holder_thresholds.len(), expected_counts.len(), "{}", name);
+            for (row, want) in metrics.holder_thresholds.iter().zip(expected_counts) {
+                let key = format!("{} holder_counts[{}]", name, row.usd_threshold);
+                assert_eq!(row.holder_count, want["holder_count"].as_u64().unwrap(), "{}", key);
+                assert_eq!(row.total_holders, want["total_holders"].as_u64().unwrap(), "{}", key);
+                assert_close(&key, want["pct_total_holders"].as_f64().unwrap(), row.pct_total_holders);
+                assert_close(&key, want["pct_of_10usd"].as_f64().unwrap(), row.pct_of_10usd);
+                assert_close(&key, want["mcap_per_holder"].as_f64().unwrap(), row.mcap_per_holder);
+                assert_close(&key, want["slice_value_usd"].as_f64().unwrap(), row.slice_value_usd);
+            }
+
+            let expected_concentration = expected["concentration"].as_array().unwrap();
+            assert_eq!(metrics.concentration.len(), expected_concentration.len(), "{}", name);
+            for (row, want) in metrics.concentration.iter().zip(expected_concentration) {
+                assert_eq!(row.top_n as u64, want["top_n"].as_u64().unwrap(), "{}", name);
+                assert_close(&format!("{} concentration[{}]", name, row.top_n), want["percentage"].as_f64().unwrap(), row.percentage);
+            }
+
+            let want = &expected["distribution"];
+            let distribution = metrics.distribution.unwrap();
+            assert_eq!(distribution.total_count as u64, want["total_holders"].as_u64().unwrap(), "{}", name);
+            assert_eq!(distribution.nakamoto_coefficient, want["nakamoto_coefficient"].as_u64().unwrap(), "{}", name);
+            for (field, actual) in [
+                ("hhi", distribution.hhi),
+                ("distribution_score", distribution.distribution_score),
+                ("median_balance", distribution.median_balance),
+                ("mean_balance", distribution.mean_balance),
+                ("gini", distribution.gini),
+                ("theil_index", distribution.theil_index),
+            ] {
+                assert_close(&format!("{} {}", name, field), want[field].as_f64().unwrap(), actual);
+            }
+            let percentiles = &distribution.percentiles;
+            let actual = [percentiles.p10, percentiles.p25, percentiles.p50, percentiles.p75, percentiles.p90, percentiles.p95, percentiles.p99];
+            for (want, actual) in floats(&want["percentiles"]).into_iter().zip(actual) {
+                assert_close(&format!("{} percentiles", name), want, actual);
+            }
+            let lorenz = floats(&want["lorenz_curve"]);
+            assert_eq!(distribution.lorenz_curve.len(), lorenz.len(), "{}", name);
+            for (i, (want, actual)) in lorenz.into_iter().zip(&distribution.lorenz_curve).enumerate() {
+                assert_close(&format!("{} lorenz_curve[{}]", name, i), want, *actual);
+            }
+        }
+    }
+
+    #[test]
+    fn owner_balances_sums_accounts_and_drops_excluded_and_empty_owners() {
+        let (a, b, c, d) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let holders = vec![
+            ("a1".to_string(), 600, a),
+            ("a2".to_string(), 400, a),
+            ("b1".to_string(), 0, b),
+            ("c1".to_string(), 1000, c),
+            ("d1".to_string(), 250, d),
+        ];
+        let excluded = HashSet::from([c.to_string()]);
+        let mut balances = owner_balances(&holders, &excluded);
+        balances.sort_by(|x, y| x.1.total_cmp(&y.1));
+        assert_eq!(balances, vec![(d.to_string(), 250.0), (a.to_string(), 1000.0)]);
+    }
+
+    #[test]
+    fn empty_snapshot_has_no_metrics() {
+        let stats = TokenStats { price: 1.0, supply: 1000.0, market_cap: 1000.0, decimals: 0 };
+        let metrics = compute_metrics(&[], &stats, DEFAULT_THRESHOLDS, DEFAULT_BUCKETS);
+        assert!(metrics.holder_thresholds.is_empty());
+        assert!(metrics.concentration.is_empty());
+        assert!(metrics.distribution.is_none());
+    }
+
+    // Positive raw balances and a supply at least as large as what they hold
+    fn snapshot() -> impl Strategy<Value = (Vec<f64>, f64)> {
+        (prop::collection::vec(1u64..1_000_000_000_000, 1..300), 1.0f64..3.0).prop_map(|(balances, factor)| {
+            let balances: Vec<f64> = balances.into_iter().map(|b| b as f64).collect();
+            let supply = balances.iter().sum::<f64>() * factor;
+            (balances, supply)
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn distribution_is_bounded((balances, supply) in snapshot()) {
+            let distribution = distribution(&balances, supply).unwrap();
+            prop_assert!(distribution.gini >= -1e-9 && distribution.gini <= 1.0 + 1e-9, "gini {}", distribution.gini);
+            prop_assert!(distribution.theil_index >= -1e-9, "theil {}", distribution.theil_index);
+            prop_assert!(distribution.nakamoto_coefficient >= 1);
+            prop_assert!(distribution.nakamoto_coefficient as usize <= balances.len());
+            prop_assert!(distribution.hhi <= 10000.0 + 1e-6);
+
+            let lorenz = &distribution.lorenz_curve;
+            prop_assert_eq!(lorenz.len(), 21);
+            prop_assert_eq!(lorenz[0], 0.0);
+            prop_assert!((lorenz[20] - 1.0).abs() < 1e-9, "lorenz ends at {}", lorenz[20]);
+            prop_assert!(lorenz.windows(2).all(|w| w[0] <= w[1] + 1e-12), "lorenz not monotone {:?}", lorenz);
+            // The poorest k% never hold more than k% of the balance
+            for (k, share) in lorenz.iter().enumerate() {
+                prop_assert!(*share <= k as f64 / 20.0 + 1e-9, "lorenz[{}] = {}", k, share);
+            }
+
+            let p = &distribution.percentiles;
+            prop_assert!(p.p10 <= p.p25 && p.p25 <= p.p50 && p.p50 <= p.p75 && p.p75 <= p.p90 && p.p90 <= p.p95 && p.p95 <= p.p99);
+        }
+
+        #[test]
+        fn concentration_is_a_growing_share((balances, supply) in snapshot(), mut buckets in prop::collection::vec(1u32..500, 1..8)) {
+            buckets.sort_unstable();
+            let rows = concentration(&balances, supply, &buckets);
+            prop_assert_eq!(rows.len(), buckets.len());
+            for row in &rows {
+                prop_assert!(row.percentage > 0.0 && row.percentage <= 100.0 + 1e-9, "top {} holds {}%", row.top_n, row.percentage);
+            }
+            prop_assert!(rows.windows(2).all(|w| w[0].percentage <= w[1].percentage + 1e-9));
+        }
+
+        #[test]
+        fn holder_counts_shrink_with_the_threshold((balances, supply) in snapshot(), price in 1e-9f64..10.0, decimals in 0u8..10) {
+            let stats = TokenStats { price, supply, market_cap: price * supply / 10f64.powi(decimals as i32), decimals };
+            let rows = holder_counts(&balances, &stats, DEFAULT_THRESHOLDS);
+            prop_assert_eq!(rows.len(), DEFAULT_THRESHOLDS.len());
+            for row in &rows {
+                prop_assert_eq!(row.total_holders as usize, balances.len());
+                prop_assert!(row.holder_count <= row.total_holders);
+                prop_assert!(row.pct_total_holders <= 100.0 + 1e-9 && row.pct_of_10usd <= 100.0 + 1e-9);
+            }
+            prop_assert!(rows.windows(2).all(|w| w[0].holder_count >= w[1].holder_count && w[0].slice_value_usd >= w[1].slice_value_usd));
+        }
+
+        #[test]
+        fn owner_balances_keep_the_held_total(amounts in prop::collection::vec((0usize..20, 0u64..1_000_000), 0..100)) {
+            let owners: Vec<Pubkey> = (0..20).map(|_| Pubkey::new_unique()).collect();
+            let holders: Vec<(String, u64, Pubkey)> = amounts
+                .iter()
+                .enumerate()
+                .map(|(i, (owner, amount))| (i.to_string(), *amount, owners[*owner]))
+                .collect();
+            let excluded = HashSet::from([owners[0].to_string()]);
+            let balances: Vec<f64> = owner_balances(&holders, &excluded).into_iter().map(|(_, balance)| balance).collect();
+
+            let held: u64 = amounts.iter().filter(|(owner, _)| *owner != 0).map(|(_, amount)| amount).sum();
+            prop_assert_eq!(balances.iter().sum::<f64>(), held as f64);
+            prop_assert!(balances.iter().all(|balance| *balance > 0.0));
+            prop_assert!(balances.len() < owners.len());
+        }
+    }
+}
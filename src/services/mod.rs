@@ -1,3 +1,19 @@
 pub mod token;
 pub mod monitor;
-pub mod excluded_accounts;
\ No newline at end of file
+pub mod excluded_accounts;
+pub mod geyser;
+pub mod snapshots;
+pub mod history;pub mod thresholds;
+pub mod concentration;
+pub mod distribution;
+pub mod holders;
+pub mod overlap;
+pub mod launch;
+pub mod lifecycle;
+pub mod jobs;
+pub mod sharding;
+pub mod recompute;
+pub mod replay;
+pub mod metrics;
+pub mod export;
+pub mod import;
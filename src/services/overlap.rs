@@ -0,0 +1,148 @@
+use anyhow::Result;
+use clickhouse::Client;
+use crate::db::models::{MultiTokenWalletRecord, SharedHolderRecord};
+use crate::db::queries::{LATEST_HOLDER_BALANCES_SQL, LATEST_SUPPLY_SQL};
+use crate::types::models::{MintBalance, MintShare, MultiTokenWallet, MultiTokenWallets, SharedHolder, WalletOverlap};
+
+pub const MAX_OVERLAP_MINTS: usize = 10;
+pub const MAX_OVERLAP_HOLDERS: u64 = 1000;
+
+// Holders of every one of the mints, binds the mints and their count
+fn shared_holders_sql() -> String {
+    format!("
+        SELECT holder_address
+        FROM ({})
+        WHERE mint_address IN ?
+        GROUP BY holder_address
+        HAVING count() = ?
+    ", LATEST_HOLDER_BALANCES_SQL)
+}
+
+pub async fn get_wallet_overlap(client: &Client, mints: &[String], limit: u64) -> Result<WalletOverlap> {
+    let (shared_holders, union_holders): (u64, u64) = client
+        .query(&format!("
+            SELECT countIf(held = ?), count()
+            FROM (
+                SELECT holder_address, count() AS held
+                FROM ({})
+                WHERE mint_address IN ?
+                GROUP BY holder_address
+            )
+        ", LATEST_HOLDER_BALANCES_SQL))
+        .bind(mints.len())
+        .bind(mints)
+        .fetch_one()
+        .await?;
+
+    let supply_shares: Vec<(String, f64)> = client
+        .query(&format!("
+            SELECT lb.mint_address, sum(lb.balance) / any(s.supply) * 100
+            FROM ({}) lb
+            INNER JOIN ({}) s ON lb.mint_address = s.mint_address
+            WHERE lb.mint_address IN ? AND lb.holder_address IN ({})
+            GROUP BY lb.mint_address
+        ", LATEST_HOLDER_BALANCES_SQL, LATEST_SUPPLY_SQL, shared_holders_sql()))
+        .bind(mints)
+        .bind(mints)
+        .bind(mints.len())
+        .fetch_all()
+        .await?;
+
+    // Largest combined holders first
+    let holders: Vec<SharedHolderRecord> = client
+        .query(&format!("
+            SELECT
+                lb.holder_address AS holder_address,
+                groupArray(lb.mint_address) AS mints,
+                groupArray(lb.balance / pow(10, s.decimals)) AS balances,
+                groupArray(lb.balance / s.supply * 100) AS shares
+            FROM ({}) lb
+            INNER JOIN ({}) s ON lb.mint_address = s.mint_address
+            WHERE lb.mint_address IN ? AND lb.holder_address IN ({})
+            GROUP BY lb.holder_address
+            ORDER BY arraySum(shares) DESC, holder_address
+            LIMIT ?
+        ", LATEST_HOLDER_BALANCES_SQL, LATEST_SUPPLY_SQL, shared_holders_sql()))
+        .bind(mints)
+        .bind(mints)
+        .bind(mints.len())
+        .bind(limit)
+        .fetch_all()
+        .await?;
+
+    Ok(wallet_overlap(mints, (shared_holders, union_holders), supply_shares, holders))
+}
+
+// From the shared and union holder counts, the share of each mint's supply the
+// shared holders hold and the largest of them
+pub fn wallet_overlap(
+    mints: &[String],
+    (shared_holders, union_holders): (u64, u64),
+    supply_shares: Vec<(String, f64)>,
+    holders: Vec<SharedHolderRecord>,
+) -> WalletOverlap {
+    WalletOverlap {
+        mints: mints.to_vec(),
+        shared_holders,
+        union_holders,
+        jaccard: if union_holders > 0 { shared_holders as f64 / union_holders as f64 } else { 0.0 },
+        supply_shares: mints.iter()
+            .map(|mint| MintShare {
+                mint_address: mint.clone(),
+                shared_supply_share: supply_shares.iter()
+                    .find(|(m, _)| m == mint)
+                    .map_or(0.0, |(_, share)| *share),
+            })
+            .collect(),
+        holders: holders.into_iter()
+            .map(|row| SharedHolder {
+                holder_address: row.holder_address,
+                balances: row.mints.into_iter()
+                    .zip(row.balances)
+                    .zip(row.shares)
+                    .map(|((mint_address, balance), share_of_supply)| MintBalance { mint_address, balance, share_of_supply })
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+// Wallets holding at least `min_tokens` of the monitored tokens, most tokens first
+pub async fn get_multi_token_wallets(client: &Client, min_tokens: u64, limit: u64) -> Result<MultiTokenWallets> {
+    let per_holder = format!("
+        SELECT holder_address, count() AS token_count, groupArray(mint_address) AS mints
+        FROM ({})
+        GROUP BY holder_address
+        HAVING token_count >= ?
+    ", LATEST_HOLDER_BALANCES_SQL);
+
+    let total_wallets: u64 = client
+        .query(&format!("SELECT count() FROM ({})", per_holder))
+        .bind(min_tokens)
+        .fetch_one()
+        .await?;
+
+    let wallets: Vec<MultiTokenWalletRecord> = client
+        .query(&format!("
+            SELECT holder_address, token_count, mints
+            FROM ({})
+            ORDER BY token_count DESC, holder_address
+            LIMIT ?
+        ", per_holder))
+        .bind(min_tokens)
+        .bind(limit)
+        .fetch_all()
+        .await?;
+
+    Ok(MultiTokenWallets {
+        min_tokens,
+        total_wallets,
+        wallets: wallets.into_iter()
+            .map(|row| MultiTokenWallet {
+                holder_address: row.holder_address,
+                token_count: row.token_count,
+                mints: row.mints,
+            })
+            .collect(),
+    })
+}
@@ -0,0 +1,167 @@
+use anyhow::Result;
+use clickhouse::Client;
+use crate::db::migrations::{applied_at, view_select, TOKEN_ACCOUNT_KEY_VERSION};
+use crate::db::schema::{
+    ROLLUP_SOURCES,
+    ROLLUP_TIERS,
+    TOKEN_HOLDER_BALANCES_MV_SQL,
+    TOKEN_THRESHOLDS_MV_SQL,
+    TOKEN_HOLDER_COUNTS_MV_SQL,
+    TOKEN_CONCENTRATION_MV_SQL,
+    TOKEN_DISTRIBUTION_MV_SQL,
+    TOKEN_LABEL_HOLDINGS_MV_SQL,
+    TOKEN_HOLDER_SPELLS_SQL,
+};
+
+// Metric tables rebuilt from token_stats and the views that fill them
+pub const METRIC_VIEWS: &[(&str, &str)] = &[
+    ("token_thresholds", TOKEN_THRESHOLDS_MV_SQL),
+    ("token_holder_counts", TOKEN_HOLDER_COUNTS_MV_SQL),
+    ("token_concentration", TOKEN_CONCENTRATION_MV_SQL),
+    ("token_distribution", TOKEN_DISTRIBUTION_MV_SQL),
+    ("token_label_holdings", TOKEN_LABEL_HOLDINGS_MV_SQL),
+];
+
+// Binds from, to (unix seconds) and the mint address twice, '' for every mint
+const RANGE_FILTER_SQL: &str = "
+    timestamp BETWEEN toDateTime(?, 'UTC') AND toDateTime(?, 'UTC')
+    AND (? = '' OR mint_address = ?)
+";
+
+// Replays the views over the stored snapshots taken between `from` and `to`, so
+// exclusions, thresholds, concentration buckets and labels changed since apply
+// to them. Holder balances are rebuilt from the oldest checkpoint the snapshots
+// build on. Returns the number of snapshots recomputed.
+// token_holders merged an owner's token accounts into one row before
+// TOKEN_ACCOUNT_KEY_VERSION, so snapshots building on older checkpoints are
+// refused rather than rebuilt with part of those owners' balances.
+pub async fn recompute_metrics(client: &Client, mint_address: Option<&str>, from: i64, to: i64) -> Result<u64> {
+    let mint = mint_address.unwrap_or_default();
+    let (snapshots, checkpoint_from): (u64, i64) = client
+        .query(&format!(
+            "SELECT count(), toInt64(min(checkpoint_timestamp)) FROM token_stats WHERE {}",
+            RANGE_FILTER_SQL
+        ))
+        .bind(from)
+        .bind(to)
+        .bind(mint)
+        .bind(mint)
+        .fetch_one()
+        .await?;
+    if snapshots == 0 {
+        return Ok(0);
+    }
+    let keyed_since = applied_at(client, TOKEN_ACCOUNT_KEY_VERSION)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Migration {} is not applied, run `memespread migrate` first", TOKEN_ACCOUNT_KEY_VERSION))?;
+    check_recompute_range(checkpoint_from, keyed_since)?;
+
+    tracing::info!("Rebuilding holder balances since {}", checkpoint_from);
+    delete_range(client, "token_holder_balances", "timestamp", mint, checkpoint_from, to).await?;
+    let balances_sql = view_select(TOKEN_HOLDER_BALANCES_MV_SQL).replace(
+        "FROM token_holders th",
+        &format!("FROM (SELECT * FROM token_holders WHERE {}) th", RANGE_FILTER_SQL),
+    );
+    client
+        .query(&format!("INSERT INTO token_holder_balances {}", balances_sql))
+        .bind(checkpoint_from)
+        .bind(to)
+        .bind(mint)
+        .bind(mint)
+        .execute()
+        .await?;
+
+    for (table, view_sql) in METRIC_VIEWS {
+        tracing::info!("Recomputing {} for {} snapshots", table, snapshots);
+        delete_range(client, table, "timestamp", mint, from, to).await?;
+        // The rollup views fire on the insert below and fill the buckets back in
+        if ROLLUP_SOURCES.contains(table) {
+            for tier in ROLLUP_TIERS {
+                let rollup = format!("{}_{}", table, tier);
+                delete_range(client, &rollup, "snapshot_timestamp", mint, from, to).await?;
+            }
+        }
+
+        let metric_sql = view_select(view_sql).replace(
+            "FROM token_stats ts",
+            &format!("FROM (SELECT * FROM token_stats WHERE {}) ts", RANGE_FILTER_SQL),
+        );
+        client
+            .query(&format!("INSERT INTO {} {}", table, metric_sql))
+            .bind(from)
+            .bind(to)
+            .bind(mint)
+            .bind(mint)
+            .execute()
+            .await?;
+    }
+
+    // A spell can start before `from`, so the mint's spells are rebuilt whole
+    tracing::info!("Rebuilding holder spells");
+    rebuild_holder_spells(client, mint_address).await?;
+
+    Ok(snapshots)
+}
+
+// Replaces the holding spells of a mint, or of every mint, with the ones of its
+// stored snapshots
+pub async fn rebuild_holder_spells(client: &Client, mint_address: Option<&str>) -> Result<()> {
+    let mint = mint_address.unwrap_or_default();
+    client
+        .query("ALTER TABLE token_holder_spells DELETE WHERE ? = '' OR mint_address = ? SETTINGS mutations_sync = 1")
+        .bind(mint)
+        .bind(mint)
+        .execute()
+        .await?;
+    let spells_sql = TOKEN_HOLDER_SPELLS_SQL.replace(
+        "FROM token_holder_balances FINAL",
+        "FROM (SELECT * FROM token_holder_balances FINAL WHERE ? = '' OR mint_address = ?)",
+    );
+    client
+        .query(&format!("INSERT INTO token_holder_spells {}", spells_sql))
+        .bind(mint)
+        .bind(mint)
+        .execute()
+        .await?;
+    Ok(())
+}
+
+fn check_recompute_range(checkpoint_from: i64, keyed_since: i64) -> Result<()> {
+    if checkpoint_from < keyed_since {
+        return Err(anyhow::anyhow!(
+            "The snapshots build on a checkpoint at {}, before migration {} at {} kept every token account of an owner. \
+             Their holder rows may have lost accounts, recompute from the first checkpoint after {} instead",
+            checkpoint_from, TOKEN_ACCOUNT_KEY_VERSION, keyed_since, keyed_since
+        ));
+    }
+    Ok(())
+}
+
+async fn delete_range(client: &Client, table: &str, column: &str, mint: &str, from: i64, to: i64) -> Result<()> {
+    client
+        .query(&format!(
+            "ALTER TABLE {} DELETE WHERE {} SETTINGS mutations_sync = 1",
+            table,
+            RANGE_FILTER_SQL.replace("timestamp BETWEEN", &format!("{} BETWEEN", column))
+        ))
+        .bind(from)
+        .bind(to)
+        .bind(mint)
+        .bind(mint)
+        .execute()
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_checkpoints_from_before_the_account_key() {
+        assert!(check_recompute_range(1_718_000_000, 1_718_000_000).is_ok());
+        assert!(check_recompute_range(1_718_000_060, 1_718_000_000).is_ok());
+        let error = check_recompute_range(1_717_999_999, 1_718_000_000).unwrap_err().to_string();
+        assert!(error.contains("migration 17"), "{}", error);
+    }
+}
@@ -0,0 +1,198 @@
+use std::path::Path;
+use std::str::FromStr;
+use anyhow::Result;
+use base64::Engine;
+use clickhouse::Client;
+use serde_json::Value;
+use solana_client::rpc_response::RpcKeyedAccount;
+use solana_sdk::{account::Account, program_pack::Pack, pubkey::Pubkey};
+use spl_token::state::Account as TokenAccount;
+use crate::db::migrations::view_select;
+use crate::db::operations::{insert_token_holders, insert_token_stats_row};
+use crate::db::schema::TOKEN_HOLDER_BALANCES_MV_SQL;
+use crate::services::recompute::METRIC_VIEWS;
+use crate::services::snapshots::SnapshotCache;
+use crate::services::token::get_token_metrics;
+use crate::types::models::{TokenHolderStats, TokenStats};
+
+// Tables a replayed snapshot is written to, shadowed by temporary tables of the
+// same name so the views' SQL runs unchanged without storing anything
+const REPLAY_TABLES: &[&str] = &[
+    "token_stats",
+    "token_holders",
+    "token_holder_balances",
+    "token_thresholds",
+    "token_holder_counts",
+    "token_concentration",
+    "token_distribution",
+    "token_label_holdings",
+];
+
+// Token account dumps, all holding what getProgramAccounts returns:
+// - json: the JSON-RPC response or its `result`, with base64 encoded account data
+// - csv: `pubkey,data` lines with base64 data, extra columns are ignored
+// - base64: `<pubkey> <data>` lines
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DumpFormat {
+    Json,
+    Csv,
+    Base64,
+}
+
+impl FromStr for DumpFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(DumpFormat::Json),
+            "csv" => Ok(DumpFormat::Csv),
+            "base64" => Ok(DumpFormat::Base64),
+            other => Err(format!("unknown format {}, expected json, csv or base64", other)),
+        }
+    }
+}
+
+impl DumpFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(DumpFormat::Json),
+            "csv" => Some(DumpFormat::Csv),
+            "b64" | "base64" | "txt" => Some(DumpFormat::Base64),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse_dump(text: &str, format: DumpFormat) -> Result<Vec<(Pubkey, Account)>, String> {
+    match format {
+        DumpFormat::Json => parse_json_dump(text),
+        DumpFormat::Csv => parse_line_dump(text, |line| line.split(',').map(str::trim).collect()),
+        DumpFormat::Base64 => parse_line_dump(text, |line| line.split_whitespace().collect()),
+    }
+}
+
+fn parse_json_dump(text: &str) -> Result<Vec<(Pubkey, Account)>, String> {
+    let json: Value = serde_json::from_str(text).map_err(|e| format!("Invalid JSON: {}", e))?;
+    // The response itself, its result, or the result with context
+    let result = match json {
+        Value::Object(mut response) if response.contains_key("result") => response.remove("result").unwrap_or_default(),
+        other => other,
+    };
+    let result = match result {
+        Value::Object(mut with_context) if with_context.contains_key("value") => with_context.remove("value").unwrap_or_default(),
+        other => other,
+    };
+
+    let keyed: Vec<RpcKeyedAccount> = serde_json::from_value(result)
+        .map_err(|e| format!("Expected a list of {{pubkey, account}} objects: {}", e))?;
+    keyed
+        .into_iter()
+        .map(|keyed| {
+            let pubkey = Pubkey::from_str(&keyed.pubkey)
+                .map_err(|_| format!("Invalid pubkey {}", keyed.pubkey))?;
+            let account = keyed.account.decode::<Account>()
+                .ok_or_else(|| format!("Can't decode the data of {}, dump it with base64 encoding", keyed.pubkey))?;
+            Ok((pubkey, account))
+        })
+        .collect()
+}
+
+// Only the data is known, so the accounts are given to the token program
+fn parse_line_dump(text: &str, split: impl Fn(&str) -> Vec<&str>) -> Result<Vec<(Pubkey, Account)>, String> {
+    let mut accounts = Vec::new();
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = split(line);
+        if line_no == 0 && fields.first() == Some(&"pubkey") {
+            continue;
+        }
+        let (pubkey, data) = match fields.as_slice() {
+            [pubkey, data, ..] => (*pubkey, *data),
+            _ => return Err(format!("Line {}: expected a pubkey and base64 data", line_no + 1)),
+        };
+        let pubkey = Pubkey::from_str(pubkey)
+            .map_err(|_| format!("Line {}: invalid pubkey {}", line_no + 1, pubkey))?;
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(data)
+            .map_err(|e| format!("Line {}: invalid base64 data: {}", line_no + 1, e))?;
+        accounts.push((pubkey, Account {
+            data,
+            owner: spl_token::ID,
+            ..Default::default()
+        }));
+    }
+    Ok(accounts)
+}
+
+// The mint every token account in the dump belongs to
+pub fn dump_mint(accounts: &[(Pubkey, Account)]) -> Result<Pubkey, String> {
+    let mut mints: Vec<Pubkey> = accounts
+        .iter()
+        .filter(|(_, account)| account.data.len() == TokenAccount::LEN)
+        .filter_map(|(_, account)| TokenAccount::unpack(&account.data).ok())
+        .map(|token_account| token_account.mint)
+        .collect();
+    mints.sort();
+    mints.dedup();
+    match mints.as_slice() {
+        [mint] => Ok(*mint),
+        [] => Err("The dump holds no token accounts".to_string()),
+        _ => Err(format!("The dump holds token accounts of {} mints, pick one with --mint", mints.len())),
+    }
+}
+
+// Sum of every token account of the mint, which is its supply if the dump is complete
+pub fn dump_supply(accounts: &[(Pubkey, Account)], mint_pubkey: &Pubkey) -> u64 {
+    accounts
+        .iter()
+        .filter(|(_, account)| account.data.len() == TokenAccount::LEN)
+        .filter_map(|(_, account)| TokenAccount::unpack(&account.data).ok())
+        .filter(|token_account| token_account.mint == *mint_pubkey)
+        .map(|token_account| token_account.amount)
+        .sum()
+}
+
+// Runs a snapshot through the exclusion and metric views without storing it:
+// the holders and stats go to temporary tables of a ClickHouse session, the
+// views' SELECTs are replayed over them, and the metrics are read back the same
+// way the API reads stored ones. Exclusions, thresholds, buckets and labels are
+// the stored ones.
+pub async fn replay_snapshot(
+    client: &Client,
+    mint_address: &str,
+    holders: &[(String, u64, Pubkey)],
+    stats: &TokenStats,
+    timestamp: &str,
+) -> Result<TokenHolderStats> {
+    let session_id = format!("memespread-replay-{}-{}", std::process::id(), chrono::Utc::now().timestamp_millis());
+    let session = client.clone().with_option("session_id", session_id);
+
+    for table in REPLAY_TABLES {
+        session
+            .query(&format!("CREATE TEMPORARY TABLE {} ENGINE = Memory AS SELECT * FROM {} LIMIT 0", table, table))
+            .execute()
+            .await?;
+    }
+
+    // A first snapshot, so every holder is written as a checkpoint
+    let owners = SnapshotCache::group_by_owner(holders);
+    let delta = SnapshotCache::new(1).diff(mint_address, &owners, timestamp).await;
+    insert_token_holders(&session, mint_address, &delta.rows, timestamp).await?;
+    session
+        .query(&format!("INSERT INTO token_holder_balances {}", view_select(TOKEN_HOLDER_BALANCES_MV_SQL)))
+        .execute()
+        .await?;
+    insert_token_stats_row(&session, mint_address, timestamp, &delta.checkpoint_timestamp, stats).await?;
+    for (table, view_sql) in METRIC_VIEWS {
+        session
+            .query(&format!("INSERT INTO {} {}", table, view_select(view_sql)))
+            .execute()
+            .await?;
+    }
+
+    tracing::info!("Replayed {} holder rows of {}", delta.rows.len(), mint_address);
+    get_token_metrics(&session, mint_address).await
+}
@@ -0,0 +1,152 @@
+use std::sync::{Arc, RwLock};
+use anyhow::Result;
+use clickhouse::Client;
+use tokio::time::Duration;
+use crate::config::Config;
+use crate::db::store::Store;
+use crate::services::snapshots::SnapshotCache;
+
+// Global jobs (exclusion updates, auto-archival) run on the owner of this key
+const LEADER_KEY: &str = "__leader__";
+
+// FNV-1a with a splitmix finalizer: stable across builds, unlike DefaultHasher,
+// so workers running different versions agree on the owners
+fn score(worker_id: &str, key: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in worker_id.bytes().chain(std::iter::once(0)).chain(key.bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash ^= hash >> 30;
+    hash = hash.wrapping_mul(0xbf58476d1ce4e5b9);
+    hash ^= hash >> 27;
+    hash = hash.wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+// Rendezvous hashing: the worker with the highest score for the key owns it, so
+// a worker joining or leaving only moves the keys it wins or held
+pub fn owner<'a>(workers: &'a [String], key: &str) -> Option<&'a str> {
+    workers.iter()
+        .max_by_key(|worker| (score(worker, key), worker.as_str()))
+        .map(|worker| worker.as_str())
+}
+
+pub fn leader(workers: &[String]) -> Option<&str> {
+    owner(workers, LEADER_KEY)
+}
+
+// This worker's view of the live workers. Until the first heartbeat it only
+// knows itself, so a single process owns every token.
+pub struct Shard {
+    worker_id: String,
+    workers: RwLock<Vec<String>>,
+}
+
+impl Shard {
+    pub fn new(worker_id: String) -> Self {
+        Self {
+            workers: RwLock::new(vec![worker_id.clone()]),
+            worker_id,
+        }
+    }
+
+    pub fn worker_id(&self) -> &str {
+        &self.worker_id
+    }
+
+    pub fn workers(&self) -> Vec<String> {
+        self.workers.read().expect("shard lock poisoned").clone()
+    }
+
+    pub fn owns(&self, mint_address: &str) -> bool {
+        let workers = self.workers.read().expect("shard lock poisoned");
+        owner(&workers, mint_address) == Some(self.worker_id.as_str())
+    }
+
+    pub fn is_leader(&self) -> bool {
+        let workers = self.workers.read().expect("shard lock poisoned");
+        leader(&workers) == Some(self.worker_id.as_str())
+    }
+
+    // Returns whether the membership changed
+    fn update(&self, mut workers: Vec<String>) -> bool {
+        if !workers.contains(&self.worker_id) {
+            workers.push(self.worker_id.clone());
+        }
+        workers.sort();
+        let mut current = self.workers.write().expect("shard lock poisoned");
+        if *current == workers {
+            return false;
+        }
+        *current = workers;
+        true
+    }
+}
+
+pub async fn send_heartbeat(client: &Client, worker_id: &str, mode: &str, active: bool) -> Result<()> {
+    client
+        .query("
+            INSERT INTO worker_heartbeats (worker_id, mode, active, heartbeat_at)
+            VALUES (?, ?, ?, now64(3, 'UTC'))
+        ")
+        .bind(worker_id)
+        .bind(mode)
+        .bind(active as u8)
+        .execute()
+        .await?;
+    Ok(())
+}
+
+// Workers that sent a heartbeat within the timeout and didn't leave
+pub async fn live_workers(client: &Client, timeout_secs: u64) -> Result<Vec<(String, String, String)>> {
+    let workers = client
+        .query("
+            SELECT worker_id, mode, toString(heartbeat_at)
+            FROM worker_heartbeats FINAL
+            WHERE active = 1 AND heartbeat_at > now64(3, 'UTC') - toIntervalSecond(?)
+            ORDER BY worker_id
+        ")
+        .bind(timeout_secs)
+        .fetch_all()
+        .await?;
+    Ok(workers)
+}
+
+// Keeps this worker in the ring and follows the others. When the membership
+// changes, cached snapshots of mints that moved away are dropped, so the mint
+// starts from a fresh checkpoint if it ever comes back.
+pub async fn run_heartbeat(
+    store: Arc<dyn Store>,
+    shard: Arc<Shard>,
+    mode: &'static str,
+    cache: Arc<SnapshotCache>,
+    config: Arc<Config>,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.workers.heartbeat_secs));
+    loop {
+        interval.tick().await;
+        if let Err(e) = store.send_heartbeat(shard.worker_id(), mode, true).await {
+            tracing::error!("Failed to send worker heartbeat: {}", e);
+            continue;
+        }
+        let workers = match store.live_workers(config.workers.timeout_secs).await {
+            Ok(workers) => workers.into_iter().map(|(worker_id, _, _)| worker_id).collect(),
+            Err(e) => {
+                tracing::error!("Failed to read worker heartbeats: {}", e);
+                continue;
+            }
+        };
+
+        if shard.update(workers) {
+            let workers = shard.workers();
+            tracing::info!(
+                "Worker ring changed, {} workers: {}{}",
+                workers.len(),
+                workers.join(", "),
+                if shard.is_leader() { " (leader)" } else { "" }
+            );
+            cache.retain(|mint_address| shard.owns(mint_address)).await;
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+// Token accounts grouped by owner: owner -> [(token_account, amount)]
+pub type OwnerAccounts = HashMap<Pubkey, Vec<(String, u64)>>;
+
+#[derive(Debug)]
+struct LastSnapshot {
+    owners: OwnerAccounts,
+    timestamp: String,
+    checkpoint_timestamp: String,
+    since_checkpoint: u32,
+}
+
+// What to write for one snapshot: either every holder (checkpoint) or only the
+// owners whose token accounts changed, with a zero row for owners that left
+#[derive(Debug)]
+pub struct SnapshotDelta {
+    pub rows: Vec<(String, u64, Pubkey)>,
+    pub checkpoint_timestamp: String,
+    pub is_checkpoint: bool,
+}
+
+// Last written holder set per mint, so each cycle only has to store changes.
+// token_holder_balances sums per owner, so a changed owner is always rewritten
+// with all of its token accounts.
+// A writer holds the mint's lock from diff to commit, so a job and the Geyser
+// stream can't both build a delta on the same last snapshot.
+#[derive(Debug)]
+pub struct SnapshotCache {
+    checkpoint_every: u32,
+    mints: Mutex<HashMap<String, LastSnapshot>>,
+    writers: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+impl SnapshotCache {
+    pub fn new(checkpoint_every: u32) -> Self {
+        Self {
+            checkpoint_every: checkpoint_every.max(1),
+            mints: Mutex::new(HashMap::new()),
+            writers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn lock(&self, mint_address: &str) -> OwnedMutexGuard<()> {
+        let writer = self.writers.lock().await.entry(mint_address.to_string()).or_default().clone();
+        writer.lock_owned().await
+    }
+
+    // Timestamp of the last committed snapshot
+    pub async fn last_timestamp(&self, mint_address: &str) -> Option<String> {
+        self.mints.lock().await.get(mint_address).map(|last| last.timestamp.clone())
+    }
+
+    pub fn group_by_owner(holders: &[(String, u64, Pubkey)]) -> OwnerAccounts {
+        let mut owners: OwnerAccounts = HashMap::new();
+        for (token_account, amount, owner) in holders {
+            owners.entry(*owner).or_default().push((token_account.clone(), *amount));
+        }
+        for accounts in owners.values_mut() {
+            accounts.sort();
+        }
+        owners
+    }
+
+    pub async fn diff(&self, mint_address: &str, owners: &OwnerAccounts, timestamp: &str) -> SnapshotDelta {
+        let mints = self.mints.lock().await;
+        let last = match mints.get(mint_address) {
+            Some(last) if last.since_checkpoint < self.checkpoint_every => last,
+            _ => {
+                return SnapshotDelta {
+                    rows: flatten(owners.iter()),
+                    checkpoint_timestamp: timestamp.to_string(),
+                    is_checkpoint: true,
+                };
+            }
+        };
+
+        let mut rows = flatten(owners.iter().filter(|(owner, accounts)| last.owners.get(owner) != Some(*accounts)));
+        for (owner, accounts) in &last.owners {
+            if !owners.contains_key(owner) {
+                if let Some((token_account, _)) = accounts.first() {
+                    rows.push((token_account.clone(), 0, *owner));
+                }
+            }
+        }
+
+        SnapshotDelta {
+            rows,
+            checkpoint_timestamp: last.checkpoint_timestamp.clone(),
+            is_checkpoint: false,
+        }
+    }
+
+    // Only called once the snapshot is stored, so a failed write is retried as part of the next delta
+    pub async fn commit(&self, mint_address: &str, owners: OwnerAccounts, timestamp: &str, delta: &SnapshotDelta) {
+        let mut mints = self.mints.lock().await;
+        let since_checkpoint = match (delta.is_checkpoint, mints.get(mint_address)) {
+            (false, Some(last)) => last.since_checkpoint + 1,
+            _ => 1,
+        };
+        mints.insert(mint_address.to_string(), LastSnapshot {
+            owners,
+            timestamp: timestamp.to_string(),
+            checkpoint_timestamp: delta.checkpoint_timestamp.clone(),
+            since_checkpoint,
+        });
+    }
+
+    pub async fn forget(&self, mint_address: &str) {
+        self.mints.lock().await.remove(mint_address);
+    }
+
+    pub async fn retain(&self, keep: impl Fn(&str) -> bool) {
+        self.mints.lock().await.retain(|mint_address, _| keep(mint_address));
+    }
+}
+
+fn flatten<'a>(owners: impl Iterator<Item = (&'a Pubkey, &'a Vec<(String, u64)>)>) -> Vec<(String, u64, Pubkey)> {
+    owners
+        .flat_map(|(owner, accounts)| {
+            accounts.iter().map(move |(token_account, amount)| (token_account.clone(), *amount, *owner))
+        })
+        .collect()
+}
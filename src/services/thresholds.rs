@@ -0,0 +1,71 @@
+use anyhow::Result;
+use clickhouse::Client;
+use crate::types::models::{ThresholdConfig, ConfigSource};
+
+// Upper bound on thresholds per token, each one is a row per snapshot
+pub const MAX_THRESHOLDS: usize = 20;
+
+// Newest threshold list stored for a key, '' being the global default
+async fn stored_thresholds(client: &Client, key: &str) -> Result<Vec<f64>> {
+    let thresholds: Option<Vec<f64>> = client
+        .query("
+            SELECT argMax(thresholds, updated_at)
+            FROM token_threshold_config
+            WHERE mint_address = ?
+            GROUP BY mint_address
+        ")
+        .bind(key)
+        .fetch_optional()
+        .await?;
+    Ok(thresholds.unwrap_or_default())
+}
+
+pub async fn get_default_thresholds(client: &Client) -> Result<ThresholdConfig> {
+    Ok(ThresholdConfig {
+        mint_address: None,
+        thresholds: stored_thresholds(client, "").await?,
+        source: ConfigSource::Default,
+    })
+}
+
+// Thresholds applied to a token's snapshots: its override, else the default
+pub async fn get_token_thresholds(client: &Client, mint_address: &str) -> Result<ThresholdConfig> {
+    let thresholds = stored_thresholds(client, mint_address).await?;
+    if !thresholds.is_empty() {
+        return Ok(ThresholdConfig {
+            mint_address: Some(mint_address.to_string()),
+            thresholds,
+            source: ConfigSource::Token,
+        });
+    }
+
+    Ok(ThresholdConfig {
+        mint_address: Some(mint_address.to_string()),
+        thresholds: stored_thresholds(client, "").await?,
+        source: ConfigSource::Default,
+    })
+}
+
+// Sorts and dedupes the list, rejecting anything that isn't a positive amount
+pub fn normalize_thresholds(mut thresholds: Vec<f64>) -> Result<Vec<f64>, String> {
+    if thresholds.len() > MAX_THRESHOLDS {
+        return Err(format!("At most {} thresholds can be configured", MAX_THRESHOLDS));
+    }
+    if let Some(bad) = thresholds.iter().find(|t| !t.is_finite() || **t <= 0.0) {
+        return Err(format!("Invalid threshold {}, thresholds must be positive USD amounts", bad));
+    }
+    thresholds.sort_by(|a, b| a.total_cmp(b));
+    thresholds.dedup();
+    Ok(thresholds)
+}
+
+// Applies from the next snapshot on, an empty list for a token resets it to the default
+pub async fn set_thresholds(client: &Client, mint_address: Option<&str>, thresholds: &[f64]) -> Result<()> {
+    client
+        .query("INSERT INTO token_threshold_config (mint_address, thresholds) VALUES (?, ?)")
+        .bind(mint_address.unwrap_or(""))
+        .bind(thresholds)
+        .execute()
+        .await?;
+    Ok(())
+}
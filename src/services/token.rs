@@ -4,6 +4,7 @@ use solana_client::{
     rpc_filter::{Memcmp, MemcmpEncodedBytes},
 };
 use solana_sdk::{
+    account::Account,
     program_pack::Pack,
     pubkey::Pubkey,
 };
@@ -12,16 +13,19 @@ use std::sync::Arc;
 use std::str::FromStr;
 use governor::{RateLimiter, state::{NotKeyed, InMemoryState}, clock::DefaultClock};
 use rayon::prelude::*;
-use crate::types::models::{TokenHolderStats, HolderThreshold, ConcentrationMetric, TokenStats, DistributionStats};
+use crate::types::models::{TokenHolderStats, HolderThreshold, ConcentrationMetric, TokenStats, DistributionStats, BalancePercentiles};
 use serde_json::Value;
 use tracing::info;
 use clickhouse::Client;
 use solana_account_decoder::UiAccountEncoding;
 use crate::db::models::{TokenStatsRecord, TokenHolderThresholdRecord, TokenConcentrationMetricRecord, TokenDistributionMetricRecord};
-use crate::db::operations::{insert_token_stats, insert_token_holders};
+use crate::db::store::Store;
+use crate::services::snapshots::SnapshotCache;
+use crate::services::launch::{get_label_holdings, get_launch_info, INSIDER_LABEL, SNIPER_LABEL};
+use chrono::Utc;
 
 
-async fn fetch_and_sort_holders(
+pub async fn fetch_and_sort_holders(
     client: &Arc<RpcClient>,
     rate_limiter: &Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
     mint_pubkey: &Pubkey,
@@ -47,11 +51,19 @@ async fn fetch_and_sort_holders(
     let accounts = client.get_program_accounts_with_config(&spl_token::ID, config).await?;
     info!("Found {} total token accounts", accounts.len());
 
+    Ok(decode_holders(accounts, mint_pubkey, min_balance))
+}
+
+// Initialized token accounts of the mint holding more than `min_balance`, largest
+// first. Also applies the RPC filters, so offline dumps can be passed as they are.
+pub fn decode_holders(accounts: Vec<(Pubkey, Account)>, mint_pubkey: &Pubkey, min_balance: u64) -> Vec<(String, u64, Pubkey)> {
     let mut holders = accounts
         .into_par_iter()
+        .filter(|(_, account)| account.data.len() == TokenAccount::LEN)
         .filter_map(|(pubkey, account)| {
             TokenAccount::unpack(&account.data).ok()
                 .filter(|token_account| {
+                    token_account.mint == *mint_pubkey &&
                     token_account.amount > min_balance && 
                     token_account.state == spl_token::state::AccountState::Initialized
                 })
@@ -59,8 +71,8 @@ async fn fetch_and_sort_holders(
         })
         .collect::<Vec<_>>();
     
-    holders.sort_by(|a, b| b.1.cmp(&a.1));
-    Ok(holders)
+    holders.sort_by_key(|h| std::cmp::Reverse(h.1));
+    holders
 }
 
 
@@ -95,7 +107,31 @@ pub async fn get_token_metrics(
 
     // Get distribution metrics
     let distribution: Option<TokenDistributionMetricRecord> = clickhouse_client
-        .query("SELECT mint_address, timestamp, hhi, distribution_score FROM token_distribution WHERE mint_address = ? ORDER BY timestamp DESC LIMIT 1")
+        .query("
+            SELECT
+                mint_address,
+                timestamp,
+                hhi,
+                distribution_score,
+                median_balance,
+                total_holders,
+                mean_balance,
+                gini,
+                nakamoto_coefficient,
+                theil_index,
+                p10,
+                p25,
+                p50,
+                p75,
+                p90,
+                p95,
+                p99,
+                lorenz_curve
+            FROM token_distribution
+            WHERE mint_address = ?
+            ORDER BY timestamp DESC
+            LIMIT 1
+        ")
         .bind(mint_address)
         .fetch_optional()
         .await?;
@@ -116,10 +152,11 @@ pub async fn get_token_metrics(
                 slice_value_usd
             FROM token_holder_counts 
             WHERE mint_address = ? 
-            ORDER BY timestamp DESC, usd_threshold ASC
-            LIMIT 5
+                AND timestamp = (SELECT max(timestamp) FROM token_holder_counts WHERE mint_address = ?)
+            ORDER BY usd_threshold ASC
         ")
         .bind(mint_address)
+        .bind(mint_address)
         .fetch_all()
         .await?;
 
@@ -133,10 +170,11 @@ pub async fn get_token_metrics(
                 percentage
             FROM token_concentration 
             WHERE mint_address = ? 
-            ORDER BY timestamp DESC, top_n ASC
-            LIMIT 6
+                AND timestamp = (SELECT max(timestamp) FROM token_concentration WHERE mint_address = ?)
+            ORDER BY top_n ASC
         ")
         .bind(mint_address)
+        .bind(mint_address)
         .fetch_all()
         .await?;
 
@@ -149,14 +187,18 @@ pub async fn get_token_metrics(
 
     let holder_thresholds = thresholds.into_iter().map(|t| HolderThreshold {
         usd_threshold: t.usd_threshold,
-        holder_count: t.holder_count as u64,
-        total_holders: t.total_holders as u64,
+        holder_count: t.holder_count,
+        total_holders: t.total_holders,
         pct_total_holders: t.pct_total_holders,
         pct_of_10usd: t.pct_of_10usd,
         mcap_per_holder: t.mcap_per_holder,
         slice_value_usd: t.slice_value_usd
     }).collect();
 
+    let insider_holdings = get_label_holdings(clickhouse_client, mint_address, INSIDER_LABEL, stats.decimals).await?;
+    let sniper_holdings = get_label_holdings(clickhouse_client, mint_address, SNIPER_LABEL, stats.decimals).await?;
+    let launch = get_launch_info(clickhouse_client, mint_address, &sniper_holdings, stats.decimals).await?;
+
     Ok(TokenHolderStats {
         mint_address: mint_address.to_string(),
         token_stats: TokenStats {
@@ -165,18 +207,37 @@ pub async fn get_token_metrics(
             market_cap: stats.market_cap,
             decimals: stats.decimals,
         },
-        distribution_stats: DistributionStats {
-            total_count: 0,
-            hhi: distribution.as_ref().map_or(0.0, |d| d.hhi),
-            distribution_score: distribution.as_ref().map_or(0.0, |d| d.distribution_score),
-            median_balance: 0.0,
-            mean_balance: 0.0,
+        distribution_stats: match distribution {
+            Some(d) => DistributionStats {
+                total_count: d.total_holders as usize,
+                hhi: d.hhi,
+                distribution_score: d.distribution_score,
+                median_balance: d.median_balance,
+                mean_balance: d.mean_balance,
+                gini: d.gini,
+                nakamoto_coefficient: d.nakamoto_coefficient,
+                theil_index: d.theil_index,
+                percentiles: BalancePercentiles {
+                    p10: d.p10,
+                    p25: d.p25,
+                    p50: d.p50,
+                    p75: d.p75,
+                    p90: d.p90,
+                    p95: d.p95,
+                    p99: d.p99,
+                },
+                lorenz_curve: d.lorenz_curve,
+            },
+            None => DistributionStats::default(),
         },
         holder_thresholds,
         concentration_metrics: concentration.into_iter().map(|c| ConcentrationMetric {
             top_n: c.top_n as i32,
             percentage: c.percentage,
         }).collect(),
+        insider_holdings,
+        sniper_holdings,
+        launch,
     })
 }
 
@@ -184,31 +245,87 @@ pub async fn update_token_metrics(
     client: &Arc<RpcClient>,
     rate_limiter: &Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
     mint_address: &str,
-    clickhouse_client: &Client,
-) -> Result<()> {
+    store: &dyn Store,
+    cache: &SnapshotCache,
+) -> Result<SnapshotSummary> {
     // Fetch holders first
     let mint_pubkey = Pubkey::from_str(mint_address)?;
     let mint_account = client.get_account(&mint_pubkey).await?;
     let mint_data = spl_token::state::Mint::unpack(&mint_account.data)?;
 
     let holders = fetch_and_sort_holders(client, rate_limiter, &mint_pubkey, 1).await?;
-    
-    // Insert holders first
-    insert_token_holders(clickhouse_client, mint_address, &holders).await?;
 
-    // Get price and other metrics
+    write_snapshot(store, cache, mint_address, &holders, mint_data.supply, mint_data.decimals).await
+}
+
+#[derive(Debug, Clone)]
+pub struct SnapshotSummary {
+    pub timestamp: String,
+    // Distinct owners, excluded accounts included
+    pub holders: usize,
+    pub rows_written: usize,
+}
+
+// Shared by the RPC and Geyser ingestion modes: only holders that changed since
+// the cached snapshot are written, with a full checkpoint every N snapshots
+pub async fn write_snapshot(
+    store: &dyn Store,
+    cache: &SnapshotCache,
+    mint_address: &str,
+    holders: &[(String, u64, Pubkey)],
+    supply: u64,
+    decimals: u8,
+) -> Result<SnapshotSummary> {
+    let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
     let price = get_token_price(mint_address).await?;
-    let market_cap = price * mint_data.supply as f64;
+    let stats = snapshot_stats(price, supply, decimals);
+    write_snapshot_at(store, cache, mint_address, holders, &stats, &timestamp).await
+}
 
-    // Insert stats (it will use the timestamp from holders)
-    insert_token_stats(
-        clickhouse_client,
-        mint_address,
+pub fn snapshot_stats(price: f64, supply: u64, decimals: u8) -> TokenStats {
+    TokenStats {
         price,
-        mint_data.supply as f64,
-        market_cap,
-        mint_data.decimals as u8,
-    ).await?;
+        supply: supply as f64,
+        market_cap: price * supply as f64,
+        decimals,
+    }
+}
+
+// Writes a snapshot with the given stats and timestamp, e.g. one replayed from a dump.
+// Snapshots of a mint are written one at a time and in order, a delta older than
+// the last one would be read into it.
+pub async fn write_snapshot_at(
+    store: &dyn Store,
+    cache: &SnapshotCache,
+    mint_address: &str,
+    holders: &[(String, u64, Pubkey)],
+    stats: &TokenStats,
+    timestamp: &str,
+) -> Result<SnapshotSummary> {
+    let _writer = cache.lock(mint_address).await;
+    if let Some(last) = cache.last_timestamp(mint_address).await.filter(|last| last.as_str() > timestamp) {
+        return Err(anyhow::anyhow!("Snapshot of {} at {} is older than the last one at {}", mint_address, timestamp, last));
+    }
+    let owners = SnapshotCache::group_by_owner(holders);
+    let delta = cache.diff(mint_address, &owners, timestamp).await;
+
+    // Holders first, then the stats: the metric views fire on the stats insert
+    // and rebuild the holder set from the checkpoint up to this timestamp
+    store.insert_snapshot(mint_address, &delta.rows, stats, timestamp, &delta.checkpoint_timestamp).await?;
+
+    tracing::info!(
+        "Wrote {} snapshot for {} ({} of {} holder rows)",
+        if delta.is_checkpoint { "checkpoint" } else { "delta" },
+        mint_address,
+        delta.rows.len(),
+        holders.len()
+    );
+    let summary = SnapshotSummary {
+        timestamp: timestamp.to_string(),
+        holders: owners.len(),
+        rows_written: delta.rows.len(),
+    };
+    cache.commit(mint_address, owners, timestamp, &delta).await;
 
-    Ok(())
+    Ok(summary)
 }
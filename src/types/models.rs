@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TokenQuery {
     pub mint_address: String,
@@ -13,6 +14,100 @@ pub struct TokenHolderStats {
     pub distribution_stats: DistributionStats,
     pub holder_thresholds: Vec<HolderThreshold>,
     pub concentration_metrics: Vec<ConcentrationMetric>,
+    pub insider_holdings: LabelHoldings,
+    pub sniper_holdings: LabelHoldings,
+    pub launch: Option<LaunchInfo>,
+}
+
+// What the launch analysis found. retained_pct is how much of what the snipers
+// bought in the launch window they still hold.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LaunchInfo {
+    pub analyzed_at: String,
+    pub status: String,
+    pub creation_signature: String,
+    pub launch_slot: u64,
+    pub window_slots: u64,
+    pub insiders: u32,
+    pub snipers: u32,
+    pub sniper_acquired: f64,
+    pub retained_pct: f64,
+}
+
+// queued_ms is the wait before a worker last picked the job up, run_ms how long that attempt ran
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotJob {
+    pub job_id: String,
+    pub mint_address: String,
+    // 'refresh' from the API, 'stats' / 'metrics' from the monitoring schedule
+    pub kind: String,
+    pub status: String,
+    pub created_at: String,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+    pub queued_ms: Option<i64>,
+    pub run_ms: Option<i64>,
+    pub attempts: u32,
+    pub max_attempts: u32,
+    // Set while a failed job waits for its retry
+    pub next_attempt_at: Option<String>,
+    pub lease_owner: Option<String>,
+    pub lease_expires_at: Option<String>,
+    pub holders: Option<u64>,
+    pub rows_written: Option<u64>,
+    pub snapshot_timestamp: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletLabel {
+    pub address: String,
+    pub label: String,
+    pub reason: String,
+    pub added_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenStatus {
+    pub mint_address: String,
+    pub status: String,
+    pub reason: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonitoredToken {
+    pub mint_address: String,
+    pub status: String,
+    pub last_stats_update: String,
+    pub last_metrics_update: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Worker {
+    pub worker_id: String,
+    pub mode: String,
+    pub heartbeat_at: String,
+    pub leader: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabelHoldingPoint {
+    pub timestamp: String,
+    pub holders: u64,
+    pub balance: f64,
+    pub supply_share: f64,
+}
+
+// Supply held by the wallets carrying one label, now and per hour over the last week
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabelHoldings {
+    pub label: String,
+    pub labelled_wallets: u64,
+    pub holders: u64,
+    pub balance: f64,
+    pub supply_share: f64,
+    pub history: Vec<LabelHoldingPoint>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,13 +118,30 @@ pub struct TokenStats {
     pub decimals: u8,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DistributionStats {
     pub total_count: usize,
     pub hhi: f64,
     pub distribution_score: f64,
     pub median_balance: f64,
     pub mean_balance: f64,
+    pub gini: f64,
+    pub nakamoto_coefficient: u64,
+    pub theil_index: f64,
+    pub percentiles: BalancePercentiles,
+    // Cumulative share of the held balance at 0%, 5%, ..., 100% of holders
+    pub lorenz_curve: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BalancePercentiles {
+    pub p10: f64,
+    pub p25: f64,
+    pub p50: f64,
+    pub p75: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,12 +161,266 @@ pub struct ConcentrationMetric {
     pub percentage: f64,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TimestampedHolderThresholds {
     pub timestamp: DateTime<Utc>,
     pub thresholds: Vec<HolderThreshold>
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolution {
+    Auto,
+    Raw,
+    Hourly,
+    Daily,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HolderCountPoint {
+    pub usd_threshold: f64,
+    pub holder_count: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryPoint {
+    pub timestamp: String,
+    pub price: Option<f64>,
+    pub supply: Option<f64>,
+    pub market_cap: Option<f64>,
+    pub hhi: Option<f64>,
+    pub distribution_score: Option<f64>,
+    pub gini: Option<f64>,
+    pub nakamoto_coefficient: Option<u64>,
+    pub total_holders: Option<u64>,
+    pub concentration_metrics: Vec<ConcentrationMetric>,
+    pub holder_counts: Vec<HolderCountPoint>,
+}
+
+impl HistoryPoint {
+    pub fn new(timestamp: String) -> Self {
+        Self {
+            timestamp,
+            price: None,
+            supply: None,
+            market_cap: None,
+            hhi: None,
+            distribution_score: None,
+            gini: None,
+            nakamoto_coefficient: None,
+            total_holders: None,
+            concentration_metrics: Vec::new(),
+            holder_counts: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenHistory {
+    pub mint_address: String,
+    pub resolution: Resolution,
+    pub from: i64,
+    pub to: i64,
+    pub points: Vec<HistoryPoint>,
+}
+
+// Whether a per-token setting comes from the token's override or the global default
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigSource {
+    Token,
+    Default,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThresholdConfig {
+    pub mint_address: Option<String>,
+    pub thresholds: Vec<f64>,
+    pub source: ConfigSource,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConcentrationBucketConfig {
+    pub mint_address: Option<String>,
+    pub buckets: Vec<u32>,
+    pub source: ConfigSource,
+}
+
+// Concentration of the latest snapshot for arbitrary top-N values
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenConcentration {
+    pub mint_address: String,
+    pub timestamp: String,
+    pub total_holders: u64,
+    pub concentration_metrics: Vec<ConcentrationMetric>,
+    // Fewest holders that together own 50% / 90% of supply, None if all
+    // non-excluded holders together own less than that
+    pub holders_for_50pct: Option<u64>,
+    pub holders_for_90pct: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LorenzPoint {
+    pub holder_share: f64,
+    pub supply_share: f64,
+}
+
+// Holders sorted from smallest to largest balance, supply_share is the
+// cumulative share of the non-excluded balance held by the first holder_share
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LorenzCurve {
+    pub mint_address: String,
+    pub timestamp: i64,
+    pub total_holders: u64,
+    pub points: Vec<LorenzPoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistogramBucket {
+    pub lower: f64,
+    pub upper: f64,
+    pub holder_count: u64,
+    pub total: f64,
+}
+
+// Log-scale histogram of holder balances, once in tokens and once in USD at the snapshot price
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalanceHistogram {
+    pub mint_address: String,
+    pub timestamp: i64,
+    pub price: f64,
+    pub bins_per_decade: u32,
+    pub tokens: Vec<HistogramBucket>,
+    pub usd: Vec<HistogramBucket>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HolderAgeBucket {
+    pub bucket: String,
+    pub holders: u64,
+    pub holder_pct: f64,
+    pub balance: f64,
+    pub balance_pct: f64,
+}
+
+// Holders of a snapshot grouped by how long before it they first held the token,
+// by count and by balance in tokens
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HolderAge {
+    pub mint_address: String,
+    pub timestamp: i64,
+    pub total_holders: u64,
+    pub buckets: Vec<HolderAgeBucket>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HolderEntry {
+    pub rank: u64,
+    pub holder_address: String,
+    pub balance: f64,
+    pub usd_value: f64,
+    pub share_of_supply: f64,
+    // Set for accounts left out of the metrics (exchanges, LPs, ...)
+    pub excluded_category: Option<String>,
+}
+
+// One page of holders of a snapshot, ranked by balance including excluded accounts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HolderList {
+    pub mint_address: String,
+    pub timestamp: i64,
+    pub total_holders: u64,
+    pub holders: Vec<HolderEntry>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletSnapshot {
+    pub timestamp: i64,
+    pub balance: f64,
+    pub usd_value: f64,
+    pub share_of_supply: f64,
+    // None for snapshots where the wallet held nothing
+    pub rank: Option<u64>,
+    pub total_holders: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletHolding {
+    pub mint_address: String,
+    pub holder_address: String,
+    pub excluded_category: Option<String>,
+    pub history: Vec<WalletSnapshot>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MintBalance {
+    pub mint_address: String,
+    pub balance: f64,
+    pub share_of_supply: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedHolder {
+    pub holder_address: String,
+    pub balances: Vec<MintBalance>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MintShare {
+    pub mint_address: String,
+    // Percentage of the mint's supply held by the shared holders together
+    pub shared_supply_share: f64,
+}
+
+// Holders of all of the given mints in their latest snapshots, excluded accounts left out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletOverlap {
+    pub mints: Vec<String>,
+    pub shared_holders: u64,
+    pub union_holders: u64,
+    pub jaccard: f64,
+    pub supply_shares: Vec<MintShare>,
+    pub holders: Vec<SharedHolder>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultiTokenWallet {
+    pub holder_address: String,
+    pub token_count: u64,
+    pub mints: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultiTokenWallets {
+    pub min_tokens: u64,
+    pub total_wallets: u64,
+    pub wallets: Vec<MultiTokenWallet>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortfolioToken {
+    pub mint_address: String,
+    pub timestamp: i64,
+    pub balance: f64,
+    pub usd_value: f64,
+    pub share_of_supply: f64,
+    pub rank: u64,
+    pub total_holders: u64,
+    // Share of the token's holders with a smaller or equal balance
+    pub percentile: f64,
+    pub history: Vec<WalletSnapshot>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletPortfolio {
+    pub holder_address: String,
+    pub excluded_category: Option<String>,
+    pub total_usd_value: f64,
+    pub tokens: Vec<PortfolioToken>,
+}
+
 //#[derive(Clone, Debug, Serialize, Deserialize)]
 //pub struct HistoricalStats {
 //    pub timestamp: DateTime<Utc>,
@@ -0,0 +1,364 @@
+// Drives the router and the monitoring schedule against MemoryStore, no
+// ClickHouse or RPC node involved
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::time::Duration;
+use governor::{Quota, RateLimiter};
+use serde_json::{json, Value};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use tokio::net::TcpListener;
+use memespread::api::routes::create_router;
+use memespread::config::Config;
+use memespread::db::store::{MemoryStore, Store};
+use memespread::services::metrics::{DEFAULT_BUCKETS, DEFAULT_THRESHOLDS};
+use memespread::services::monitor::start_monitoring;
+use memespread::services::overlap::MAX_OVERLAP_HOLDERS;
+use memespread::services::sharding::Shard;
+use memespread::services::snapshots::SnapshotCache;
+use memespread::services::token::SnapshotSummary;
+use memespread::types::models::TokenStats;
+use memespread::RpcLimiter;
+
+const MINT: &str = "So11111111111111111111111111111111111111112";
+
+struct TestApp {
+    base: String,
+    http: reqwest::Client,
+    store: Arc<dyn Store>,
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: RpcLimiter,
+    config: Arc<Config>,
+}
+
+impl TestApp {
+    async fn start() -> Self {
+        let store: Arc<dyn Store> = Arc::new(MemoryStore::new(&[], DEFAULT_THRESHOLDS.to_vec(), DEFAULT_BUCKETS.to_vec()));
+        // Nothing listens there, the routes under test never reach the node
+        let rpc_client = Arc::new(RpcClient::new("http://127.0.0.1:1".to_string()));
+        let rate_limiter = Arc::new(RateLimiter::direct(Quota::per_second(NonZeroU32::new(1000).unwrap())));
+        let config = Arc::new(Config::default());
+
+        let app = create_router((rpc_client.clone(), rate_limiter.clone(), store.clone(), config.clone()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, app.into_make_service()).await.unwrap();
+        });
+
+        Self { base, http: reqwest::Client::new(), store, rpc_client, rate_limiter, config }
+    }
+
+    async fn get(&self, path: &str) -> (u16, Value) {
+        let response = self.http.get(format!("{}{}", self.base, path)).send().await.unwrap();
+        (response.status().as_u16(), response.json().await.unwrap_or(Value::Null))
+    }
+
+    async fn send(&self, method: reqwest::Method, path: &str, body: Value) -> (u16, Value) {
+        let response = self.http.request(method, format!("{}{}", self.base, path)).json(&body).send().await.unwrap();
+        (response.status().as_u16(), response.json().await.unwrap_or(Value::Null))
+    }
+
+    // Three owners holding 50%, 30% and 20% of a 1000 token supply
+    async fn insert_snapshot(&self) {
+        let owners = [(Pubkey::new_unique(), 500), (Pubkey::new_unique(), 300), (Pubkey::new_unique(), 200)];
+        self.insert_holdings(MINT, &owners).await;
+    }
+
+    // A checkpoint of one token account per owner, at $2 and a 1000 token supply
+    async fn insert_holdings(&self, mint_address: &str, owners: &[(Pubkey, u64)]) {
+        self.insert_holdings_at(mint_address, owners, chrono::Utc::now().timestamp()).await;
+    }
+
+    async fn insert_holdings_at(&self, mint_address: &str, owners: &[(Pubkey, u64)], at: i64) {
+        let rows: Vec<(String, u64, Pubkey)> = owners
+            .iter()
+            .map(|(owner, amount)| (Pubkey::new_unique().to_string(), *amount, *owner))
+            .collect();
+        let stats = TokenStats { price: 2.0, supply: 1000.0, market_cap: 2000.0, decimals: 0 };
+        let timestamp = chrono::DateTime::from_timestamp(at, 0).unwrap().format("%Y-%m-%d %H:%M:%S").to_string();
+        self.store.insert_snapshot(mint_address, &rows, &stats, &timestamp, &timestamp).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn serves_a_monitored_token() {
+    let app = TestApp::start().await;
+
+    let (status, _) = app.get(&format!("/tokens/{}", MINT)).await;
+    assert_eq!(status, 404);
+
+    app.store.insert_monitored_token(MINT).await.unwrap();
+    app.insert_snapshot().await;
+
+    let (status, tokens) = app.get("/tokens").await;
+    assert_eq!(status, 200);
+    assert_eq!(tokens.as_array().unwrap().len(), 1);
+    assert_eq!(tokens[0]["mint_address"], MINT);
+    assert_eq!(tokens[0]["status"], "active");
+
+    let (status, stats) = app.get(&format!("/tokens/{}", MINT)).await;
+    assert_eq!(status, 200);
+    assert_eq!(stats["token_stats"]["supply"], 1000.0);
+    assert_eq!(stats["distribution_stats"]["total_count"], 3);
+    assert_eq!(stats["distribution_stats"]["nakamoto_coefficient"], 2);
+
+    let (status, concentration) = app.get(&format!("/tokens/{}/concentration?n=1,2", MINT)).await;
+    assert_eq!(status, 200);
+    assert_eq!(concentration["total_holders"], 3);
+    assert_eq!(concentration["concentration_metrics"][0]["percentage"], 50.0);
+    assert_eq!(concentration["concentration_metrics"][1]["percentage"], 80.0);
+    assert_eq!(concentration["holders_for_50pct"], 1);
+    assert_eq!(concentration["holders_for_90pct"], 3);
+
+    let (status, token) = app.send(reqwest::Method::PATCH, &format!("/tokens/{}", MINT), json!({ "status": "paused" })).await;
+    assert_eq!(status, 200);
+    assert_eq!(token["status"], "paused");
+    let (_, tokens) = app.get("/tokens?status=active").await;
+    assert!(tokens.as_array().unwrap().is_empty());
+
+    let (status, _) = app.send(reqwest::Method::PATCH, &format!("/tokens/{}", MINT), json!({ "status": "frozen" })).await;
+    assert_eq!(status, 400);
+}
+
+#[tokio::test]
+async fn serves_snapshot_analytics() {
+    let app = TestApp::start().await;
+    app.store.insert_monitored_token(MINT).await.unwrap();
+    app.insert_snapshot().await;
+
+    let (status, lorenz) = app.get(&format!("/tokens/{}/lorenz?points=2", MINT)).await;
+    assert_eq!(status, 200);
+    assert_eq!(lorenz["total_holders"], 3);
+    let supply_shares: Vec<f64> = lorenz["points"].as_array().unwrap().iter().map(|p| p["supply_share"].as_f64().unwrap()).collect();
+    assert_eq!(supply_shares, vec![0.0, 0.2, 1.0]);
+
+    // 200 to 500 tokens all fall in [100, 1000), $400 to $1000 across two decades
+    let (status, histogram) = app.get(&format!("/tokens/{}/histogram?bins_per_decade=1", MINT)).await;
+    assert_eq!(status, 200);
+    assert_eq!(histogram["tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(histogram["tokens"][0]["holder_count"], 3);
+    let usd_counts: Vec<u64> = histogram["usd"].as_array().unwrap().iter().map(|b| b["holder_count"].as_u64().unwrap()).collect();
+    assert_eq!(usd_counts, vec![2, 1]);
+
+    let (status, age) = app.get(&format!("/tokens/{}/age", MINT)).await;
+    assert_eq!(status, 200);
+    assert_eq!(age["total_holders"], 3);
+    assert_eq!(age["buckets"][0]["bucket"], "<1h");
+    assert_eq!(age["buckets"][0]["holders"], 3);
+
+    let (status, page) = app.get(&format!("/tokens/{}/holders?limit=2", MINT)).await;
+    assert_eq!(status, 200);
+    assert_eq!(page["total_holders"], 3);
+    let balances: Vec<f64> = page["holders"].as_array().unwrap().iter().map(|h| h["balance"].as_f64().unwrap()).collect();
+    assert_eq!(balances, vec![500.0, 300.0]);
+    let cursor = page["next_cursor"].as_str().unwrap().to_string();
+    let (_, page) = app.get(&format!("/tokens/{}/holders?limit=2&cursor={}", MINT, cursor)).await;
+    assert_eq!(page["holders"][0]["rank"], 3);
+    assert_eq!(page["next_cursor"], Value::Null);
+
+    let (status, history) = app.get(&format!("/tokens/{}/history?resolution=raw", MINT)).await;
+    assert_eq!(status, 200);
+    assert_eq!(history["points"].as_array().unwrap().len(), 1);
+    assert_eq!(history["points"][0]["total_holders"], 3);
+
+    let (status, labels) = app.get(&format!("/tokens/{}/labels", MINT)).await;
+    assert_eq!(status, 200);
+    assert_eq!(labels, json!([]));
+
+    let response = app.http.get(format!("{}/tokens/{}/export/holders?format=csv", app.base, MINT)).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let csv = response.text().await.unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "\"timestamp\",\"holder_address\",\"balance\"");
+    assert_eq!(lines.len(), 4);
+    assert!(lines[1].ends_with(",500"));
+
+    let (status, _) = app.get(&format!("/tokens/{}/export/stats?format=parquet", MINT)).await;
+    assert_eq!(status, 400);
+}
+
+// A wallet missing from a snapshot starts a new spell when it comes back
+#[tokio::test]
+async fn holder_age_restarts_when_a_wallet_returns() {
+    let app = TestApp::start().await;
+    app.store.insert_monitored_token(MINT).await.unwrap();
+    let (returning, staying) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let now = chrono::Utc::now().timestamp();
+    let (joined, left, returned) = (now - 10 * 86400, now - 2 * 86400, now - 1800);
+    app.insert_holdings_at(MINT, &[(returning, 100), (staying, 100)], joined).await;
+    app.insert_holdings_at(MINT, &[(staying, 100)], left).await;
+    app.insert_holdings_at(MINT, &[(returning, 100), (staying, 100)], returned).await;
+
+    let holders = |age: &Value| -> Vec<u64> {
+        age["buckets"].as_array().unwrap().iter().map(|b| b["holders"].as_u64().unwrap()).collect()
+    };
+    let (status, age) = app.get(&format!("/tokens/{}/age", MINT)).await;
+    assert_eq!(status, 200);
+    assert_eq!(age["total_holders"], 2);
+    assert_eq!(holders(&age), vec![1, 0, 0, 1]);
+
+    let (_, age) = app.get(&format!("/tokens/{}/age?timestamp={}", MINT, left)).await;
+    assert_eq!(holders(&age), vec![0, 0, 0, 1]);
+}
+
+#[tokio::test]
+async fn serves_wallets_across_tokens() {
+    const OTHER_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+    let app = TestApp::start().await;
+    let (shared, only_first, only_other) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    app.store.insert_excluded_accounts(&[(only_other.to_string(), "exchange".to_string(), String::new())]).await.unwrap();
+    for mint in [MINT, OTHER_MINT] {
+        app.store.insert_monitored_token(mint).await.unwrap();
+    }
+    app.insert_holdings(MINT, &[(shared, 600), (only_first, 400)]).await;
+    app.insert_holdings(OTHER_MINT, &[(shared, 100), (only_other, 900)]).await;
+
+    let (status, holding) = app.get(&format!("/tokens/{}/holders/{}", OTHER_MINT, shared)).await;
+    assert_eq!(status, 200);
+    // The excluded owner is ranked ahead, as in the holder list
+    assert_eq!(holding["history"][0]["rank"], 2);
+    assert_eq!(holding["history"][0]["total_holders"], 2);
+    let (_, holding) = app.get(&format!("/tokens/{}/holders/{}", OTHER_MINT, only_other)).await;
+    assert_eq!(holding["excluded_category"], "exchange");
+
+    let (status, portfolio) = app.get(&format!("/wallets/{}", shared)).await;
+    assert_eq!(status, 200);
+    assert_eq!(portfolio["tokens"].as_array().unwrap().len(), 2);
+    assert_eq!(portfolio["tokens"][0]["mint_address"], MINT);
+    assert_eq!(portfolio["total_usd_value"], 1400.0);
+
+    let (status, overlap) = app.get(&format!("/overlap?mints={},{}", MINT, OTHER_MINT)).await;
+    assert_eq!(status, 200);
+    // The excluded owner isn't counted
+    assert_eq!(overlap["shared_holders"], 1);
+    assert_eq!(overlap["union_holders"], 2);
+    assert_eq!(overlap["holders"][0]["holder_address"], shared.to_string());
+    let other_share = overlap["supply_shares"].as_array().unwrap().iter().find(|s| s["mint_address"] == OTHER_MINT).unwrap();
+    assert_eq!(other_share["shared_supply_share"], 10.0);
+
+    let (status, wallets) = app.get("/overlap/wallets?min_tokens=2").await;
+    assert_eq!(status, 200);
+    assert_eq!(wallets["total_wallets"], 1);
+    assert_eq!(wallets["wallets"][0]["holder_address"], shared.to_string());
+}
+
+#[tokio::test]
+async fn rejects_overlap_limits_out_of_range() {
+    const OTHER_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+    let app = TestApp::start().await;
+    app.store.insert_monitored_token(MINT).await.unwrap();
+    app.store.insert_monitored_token(OTHER_MINT).await.unwrap();
+
+    for limit in [0, MAX_OVERLAP_HOLDERS + 1] {
+        let (status, error) = app.get(&format!("/overlap?mints={},{}&limit={}", MINT, OTHER_MINT, limit)).await;
+        assert_eq!(status, 400, "limit={}", limit);
+        assert!(error["error"].as_str().unwrap().contains("between 1 and 1000"), "{}", error);
+    }
+}
+
+#[tokio::test]
+async fn rejects_multi_token_wallet_limits_out_of_range() {
+    let app = TestApp::start().await;
+
+    for limit in [0, MAX_OVERLAP_HOLDERS + 1] {
+        let (status, error) = app.get(&format!("/overlap/wallets?limit={}", limit)).await;
+        assert_eq!(status, 400, "limit={}", limit);
+        assert!(error["error"].as_str().unwrap().contains("between 1 and 1000"), "{}", error);
+    }
+}
+
+#[tokio::test]
+async fn stores_thresholds() {
+    let app = TestApp::start().await;
+    app.store.insert_monitored_token(MINT).await.unwrap();
+
+    let (status, thresholds) = app.send(reqwest::Method::PUT, "/thresholds", json!({ "thresholds": [50.0, 5.0] })).await;
+    assert_eq!(status, 200);
+    assert_eq!(thresholds["thresholds"], json!([5.0, 50.0]));
+
+    let (_, thresholds) = app.get(&format!("/tokens/{}/thresholds", MINT)).await;
+    assert_eq!(thresholds["thresholds"], json!([5.0, 50.0]));
+    assert_eq!(thresholds["source"], "default");
+
+    app.send(reqwest::Method::PUT, &format!("/tokens/{}/thresholds", MINT), json!({ "thresholds": [1.0] })).await;
+    let (_, thresholds) = app.get(&format!("/tokens/{}/thresholds", MINT)).await;
+    assert_eq!(thresholds["thresholds"], json!([1.0]));
+    assert_eq!(thresholds["source"], "token");
+}
+
+#[tokio::test]
+async fn monitor_tick_queues_one_job_per_token() {
+    let app = TestApp::start().await;
+    app.store.insert_monitored_token(MINT).await.unwrap();
+    let paused = Pubkey::new_unique().to_string();
+    app.store.insert_monitored_token(&paused).await.unwrap();
+    app.store.set_token_status(&paused, "paused", "test").await.unwrap();
+
+    let monitor = tokio::spawn(start_monitoring(
+        app.store.clone(),
+        app.rpc_client.clone(),
+        app.rate_limiter.clone(),
+        app.config.clone(),
+        Arc::new(SnapshotCache::new(app.config.monitor.checkpoint_every)),
+        Arc::new(Shard::new("worker-1".to_string())),
+    ));
+    // Both timers fire on start, the second finds the first one's job pending
+    let mut jobs = Value::Null;
+    for _ in 0..50 {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        jobs = app.get("/jobs").await.1;
+        if !jobs.as_array().unwrap().is_empty() {
+            break;
+        }
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    monitor.abort();
+
+    let (status, jobs_now) = app.get("/jobs").await;
+    assert_eq!(status, 200);
+    assert_eq!(jobs_now, jobs);
+    let jobs = jobs.as_array().unwrap();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0]["mint_address"], MINT);
+    assert_eq!(jobs[0]["status"], "queued");
+    assert!(jobs[0]["kind"] == "stats" || jobs[0]["kind"] == "metrics");
+
+    let (status, job) = app.get(&format!("/jobs/{}", jobs[0]["job_id"].as_str().unwrap())).await;
+    assert_eq!(status, 200);
+    assert_eq!(job["job_id"], jobs[0]["job_id"]);
+
+    // A refresh reuses the queued job rather than piling up another one
+    for _ in 0..2 {
+        let (status, job) = app.send(reqwest::Method::POST, &format!("/tokens/{}/refresh", MINT), Value::Null).await;
+        assert_eq!(status, 200);
+        assert_eq!(job["job_id"], jobs[0]["job_id"]);
+    }
+    let (_, queued) = app.get(&format!("/jobs?status=queued&mint_address={}", MINT)).await;
+    assert_eq!(queued.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn refresh_queues_a_job_once() {
+    let app = TestApp::start().await;
+    app.store.insert_monitored_token(MINT).await.unwrap();
+
+    let (status, job) = app.send(reqwest::Method::POST, &format!("/tokens/{}/refresh", MINT), Value::Null).await;
+    assert_eq!(status, 200);
+    assert_eq!(job["kind"], "refresh");
+    assert_eq!(job["status"], "queued");
+
+    // Still the same job once a worker runs it
+    let job_id = job["job_id"].as_str().unwrap().to_string();
+    assert!(app.store.claim_job(&job_id, 1, "worker-1", 120).await.unwrap());
+    let (_, job) = app.send(reqwest::Method::POST, &format!("/tokens/{}/refresh", MINT), Value::Null).await;
+    assert_eq!(job["job_id"], job_id.as_str());
+    assert_eq!(job["status"], "running");
+
+    let summary = SnapshotSummary { timestamp: "2024-06-10 06:00:00".to_string(), holders: 3, rows_written: 3 };
+    app.store.finish_job(&job_id, 1, "worker-1", &summary).await.unwrap();
+    let (_, job) = app.send(reqwest::Method::POST, &format!("/tokens/{}/refresh", MINT), Value::Null).await;
+    assert_ne!(job["job_id"], job_id.as_str());
+    let (_, jobs) = app.get(&format!("/jobs?mint_address={}", MINT)).await;
+    assert_eq!(jobs.as_array().unwrap().len(), 2);
+}
@@ -0,0 +1,70 @@
+// Claims and leases of the snapshot job queue against MemoryStore
+use std::sync::Arc;
+use memespread::db::store::{MemoryStore, Store};
+use memespread::services::metrics::{DEFAULT_BUCKETS, DEFAULT_THRESHOLDS};
+use memespread::services::token::SnapshotSummary;
+
+const MINT: &str = "So11111111111111111111111111111111111111112";
+
+fn store() -> Arc<dyn Store> {
+    Arc::new(MemoryStore::new(&[], DEFAULT_THRESHOLDS.to_vec(), DEFAULT_BUCKETS.to_vec()))
+}
+
+#[tokio::test]
+async fn one_worker_wins_each_attempt() {
+    let store = store();
+    let job_id = store.enqueue_job(MINT, "refresh").await.unwrap();
+    let attempt = store.claimable_jobs(10).await.unwrap()[0].attempt;
+    assert_eq!(attempt, 1);
+
+    let claims = (0..16).map(|worker| {
+        let (store, job_id) = (store.clone(), job_id.clone());
+        tokio::spawn(async move { store.claim_job(&job_id, attempt, &format!("worker-{}", worker), 120).await.unwrap() })
+    });
+    let won = futures::future::join_all(claims).await.into_iter().filter(|won| *won.as_ref().unwrap()).count();
+    assert_eq!(won, 1);
+
+    let job = store.job(&job_id).await.unwrap().unwrap();
+    assert_eq!(job.status, "running");
+    assert_eq!(job.attempts, 1);
+    assert!(store.claimable_jobs(10).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn only_the_lease_owner_updates_the_job() {
+    let store = store();
+    let job_id = store.enqueue_job(MINT, "stats").await.unwrap();
+    assert!(store.claim_job(&job_id, 1, "worker-1", 120).await.unwrap());
+
+    let summary = SnapshotSummary { timestamp: "2024-06-10 06:00:00".to_string(), holders: 10, rows_written: 10 };
+    store.finish_job(&job_id, 1, "worker-2", &summary).await.unwrap();
+    assert_eq!(store.job(&job_id).await.unwrap().unwrap().status, "running");
+
+    // A failed attempt is requeued for the next one
+    store.fail_job(&job_id, 1, "worker-1", "rpc timeout", 0).await.unwrap();
+    let job = store.job(&job_id).await.unwrap().unwrap();
+    assert_eq!(job.status, "queued");
+    assert_eq!(job.error.as_deref(), Some("rpc timeout"));
+    assert!(!store.claim_job(&job_id, 1, "worker-2", 120).await.unwrap());
+    assert!(store.claim_job(&job_id, 2, "worker-2", 120).await.unwrap());
+
+    store.finish_job(&job_id, 2, "worker-2", &summary).await.unwrap();
+    let job = store.job(&job_id).await.unwrap().unwrap();
+    assert_eq!(job.status, "done");
+    assert_eq!(job.holders, Some(10));
+}
+
+#[tokio::test]
+async fn a_mint_runs_one_job_at_a_time() {
+    let store = store();
+    let refresh = store.enqueue_job(MINT, "refresh").await.unwrap();
+    let stats = store.enqueue_job(MINT, "stats").await.unwrap();
+    assert!(store.claim_job(&refresh, 1, "worker-1", 120).await.unwrap());
+    assert!(store.claimable_jobs(10).await.unwrap().is_empty());
+
+    let summary = SnapshotSummary { timestamp: "2024-06-10 06:00:00".to_string(), holders: 10, rows_written: 10 };
+    store.finish_job(&refresh, 1, "worker-1", &summary).await.unwrap();
+    let claimable = store.claimable_jobs(10).await.unwrap();
+    assert_eq!(claimable.len(), 1);
+    assert_eq!(claimable[0].job_id, stats);
+}
@@ -0,0 +1,96 @@
+// Replays a getProgramAccounts dump through the decode, exclusion and metrics
+// path of `replay --offline`, without any RPC node or database
+use std::str::FromStr;
+use solana_sdk::pubkey::Pubkey;
+use memespread::db::store::{MemoryStore, Store};
+use memespread::services::replay::{dump_mint, dump_supply, parse_dump, DumpFormat};
+use memespread::services::snapshots::SnapshotCache;
+use memespread::services::token::{decode_holders, snapshot_stats, write_snapshot_at};
+use memespread::types::models::TokenHolderStats;
+
+// Token accounts of MINT plus one of another mint. owner-a holds two accounts
+// (300 + 100), owner-b 300, owner-c 200, owner-d 100 and the pool 949. A frozen
+// account of 50 and a dust account of 1 are dropped by the decoder.
+const DUMP: &str = include_str!("fixtures/replay_accounts.json");
+
+const MINT: &str = "FqUwnBMN1shpeqKVm7W5fN73tvrjVr19TQFFgkoFFzhq";
+const OWNER_A: &str = "B3CqBtedWy3t4V9zv4u99dVhXwgERJkPAoW6TK1EyWNe";
+const OWNER_D: &str = "Ej9geEPGdUVaH7fvpSbAJNTF5djY2hUZf3VwQ4EP1iQz";
+const POOL_OWNER: &str = "FfUG2Lw1NtchrjapR4u77dvxee84K9m479CyaeHAqmdk";
+
+fn assert_close(what: &str, expected: f64, actual: f64) {
+    assert!((expected - actual).abs() <= 1e-9 * expected.abs().max(1.0), "{}: expected {}, got {}", what, expected, actual);
+}
+
+async fn replay(store: &MemoryStore, cache: &SnapshotCache, holders: &[(String, u64, Pubkey)], timestamp: &str) -> TokenHolderStats {
+    let stats = snapshot_stats(0.5, 2000, 0);
+    write_snapshot_at(store, cache, MINT, holders, &stats, timestamp).await.unwrap();
+    store.token_metrics(MINT).await.unwrap()
+}
+
+#[test]
+fn decodes_the_dump() {
+    let accounts = parse_dump(DUMP, DumpFormat::Json).unwrap();
+    assert_eq!(accounts.len(), 9);
+    assert!(dump_mint(&accounts).unwrap_err().contains("2 mints"));
+
+    let mint_pubkey = Pubkey::from_str(MINT).unwrap();
+    assert_eq!(dump_supply(&accounts, &mint_pubkey), 2000);
+
+    let holders = decode_holders(accounts, &mint_pubkey, 1);
+    let amounts: Vec<u64> = holders.iter().map(|(_, amount, _)| *amount).collect();
+    assert_eq!(amounts, vec![949, 300, 300, 200, 100, 100]);
+    assert_eq!(holders[0].2.to_string(), POOL_OWNER);
+    assert_eq!(holders.iter().filter(|(_, _, owner)| owner.to_string() == OWNER_A).count(), 2);
+}
+
+#[tokio::test]
+async fn computes_the_metrics_of_the_dump() {
+    let mint_pubkey = Pubkey::from_str(MINT).unwrap();
+    let holders = decode_holders(parse_dump(DUMP, DumpFormat::Json).unwrap(), &mint_pubkey, 1);
+    let excluded = [(POOL_OWNER.to_string(), "exchange".to_string(), String::new())];
+    let store = MemoryStore::new(&excluded, vec![10.0, 100.0, 1000.0], vec![1, 2, 10]);
+    let cache = SnapshotCache::new(60);
+
+    // Owner balances 400, 300, 200 and 100 of a 2000 supply at $0.5, so $200 to $50
+    let metrics = replay(&store, &cache, &holders, "2024-06-10 06:00:00").await;
+    assert_close("market_cap", 1000.0, metrics.token_stats.market_cap);
+
+    let counts: Vec<(u64, f64, f64)> = metrics.holder_thresholds
+        .iter()
+        .map(|h| (h.holder_count, h.slice_value_usd, h.mcap_per_holder))
+        .collect();
+    assert_eq!(counts.len(), 3);
+    assert_eq!((counts[0].0, counts[1].0, counts[2].0), (4, 3, 0));
+    assert_close("slice_value_usd at $10", 500.0, counts[0].1);
+    assert_close("slice_value_usd at $100", 450.0, counts[1].1);
+    assert_close("mcap_per_holder at $100", 1000.0 / 3.0, counts[1].2);
+    assert_close("pct_total_holders at $100", 75.0, metrics.holder_thresholds[1].pct_total_holders);
+
+    let concentration: Vec<(i32, f64)> = metrics.concentration_metrics.iter().map(|m| (m.top_n, m.percentage)).collect();
+    assert_eq!(concentration.iter().map(|(top_n, _)| *top_n).collect::<Vec<_>>(), vec![1, 2, 10]);
+    assert_close("top 1", 20.0, concentration[0].1);
+    assert_close("top 2", 35.0, concentration[1].1);
+    assert_close("top 10", 50.0, concentration[2].1);
+
+    let distribution = &metrics.distribution_stats;
+    assert_eq!(distribution.total_count, 4);
+    assert_eq!(distribution.nakamoto_coefficient, 2);
+    assert_close("hhi", 750.0, distribution.hhi);
+    assert_close("distribution_score", 50.0, distribution.distribution_score);
+    assert_close("gini", 0.25, distribution.gini);
+    assert_close("theil_index", 0.1064401352862232, distribution.theil_index);
+    assert_close("mean_balance", 250.0, distribution.mean_balance);
+    assert_close("median_balance", 300.0, distribution.median_balance);
+    assert_close("p10", 100.0, distribution.percentiles.p10);
+    assert_close("p90", 400.0, distribution.percentiles.p90);
+    assert_close("lorenz at 25%", 0.1, distribution.lorenz_curve[5]);
+    assert_close("lorenz at 100%", 1.0, distribution.lorenz_curve[20]);
+
+    // owner-d sells out, the next snapshot is a delta on top of the first
+    let holders: Vec<_> = holders.into_iter().filter(|(_, _, owner)| owner.to_string() != OWNER_D).collect();
+    let metrics = replay(&store, &cache, &holders, "2024-06-10 07:00:00").await;
+    assert_eq!(metrics.distribution_stats.total_count, 3);
+    assert_close("mean_balance", 300.0, metrics.distribution_stats.mean_balance);
+    assert_close("top 10", 45.0, metrics.concentration_metrics[2].percentage);
+}
@@ -0,0 +1,54 @@
+// Concurrent snapshot writers of one mint against MemoryStore
+use std::sync::Arc;
+use std::time::Duration;
+use solana_sdk::pubkey::Pubkey;
+use memespread::db::store::{MemoryStore, Store};
+use memespread::services::metrics::{DEFAULT_BUCKETS, DEFAULT_THRESHOLDS};
+use memespread::services::snapshots::SnapshotCache;
+use memespread::services::token::{snapshot_stats, write_snapshot_at};
+
+fn holders(owners: &[(Pubkey, u64)]) -> Vec<(String, u64, Pubkey)> {
+    owners.iter().map(|(owner, amount)| (format!("{}-account", owner), *amount, *owner)).collect()
+}
+
+// A job and the Geyser stream writing the same mint at once. Both deltas must
+// build on the snapshot written before them, whichever gets there first.
+#[tokio::test]
+async fn concurrent_writes_rebuild_the_last_snapshot() {
+    let store: Arc<dyn Store> = Arc::new(MemoryStore::new(&[], DEFAULT_THRESHOLDS.to_vec(), DEFAULT_BUCKETS.to_vec()));
+    let cache = Arc::new(SnapshotCache::new(60));
+    let stats = snapshot_stats(1.0, 1000, 0);
+    let mint = Pubkey::new_unique().to_string();
+    let (a, b, c) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    write_snapshot_at(store.as_ref(), &cache, &mint, &holders(&[(a, 100), (b, 100)]), &stats, "2024-06-10 06:00:00").await.unwrap();
+
+    // The writers wait for the mint's lock, then run one after the other
+    let locked = cache.lock(&mint).await;
+    let write = |owners: Vec<(String, u64, Pubkey)>, timestamp: &'static str| {
+        let (store, cache, mint, stats) = (store.clone(), cache.clone(), mint.clone(), stats.clone());
+        tokio::spawn(async move { write_snapshot_at(store.as_ref(), &cache, &mint, &owners, &stats, timestamp).await })
+    };
+    // b sells half at 06:01, then buys back and c comes in by 06:02
+    let earlier = write(holders(&[(a, 100), (b, 50)]), "2024-06-10 06:01:00");
+    let later = write(holders(&[(a, 100), (b, 100), (c, 70)]), "2024-06-10 06:02:00");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!earlier.is_finished() && !later.is_finished());
+    drop(locked);
+
+    let (earlier, later) = (earlier.await.unwrap(), later.await.unwrap());
+    later.unwrap();
+    // Refused when the later snapshot got in first
+    if let Err(e) = earlier {
+        assert!(e.to_string().contains("older than the last one"), "{}", e);
+    }
+
+    // Unchanged since 06:02, so only rebuilt from the stored rows
+    let last = holders(&[(a, 100), (b, 100), (c, 70)]);
+    let summary = write_snapshot_at(store.as_ref(), &cache, &mint, &last, &stats, "2024-06-10 06:03:00").await.unwrap();
+    assert_eq!(summary.rows_written, 0);
+    let mut top = store.top_holders(&mint, 10).await.unwrap();
+    top.sort_by(|x, y| x.0.cmp(&y.0));
+    let mut expected = vec![(a.to_string(), 100.0), (b.to_string(), 100.0), (c.to_string(), 70.0)];
+    expected.sort_by(|x, y| x.0.cmp(&y.0));
+    assert_eq!(top, expected);
+}